@@ -0,0 +1,157 @@
+//! Assembles the challenge in challenge/ into a program image and a memory
+//! image in OUT_DIR, which main.rs and the tests include.
+
+// The assembler itself, which only needs std
+#[allow(dead_code)]
+#[path = "src/leg_computer.rs"]
+mod leg_computer;
+#[allow(dead_code)]
+#[path = "src/leg_computer_parse.rs"]
+mod leg_computer_parse;
+#[allow(dead_code)]
+#[path = "src/leg_stdlib.rs"]
+mod leg_stdlib;
+
+use leg_computer_parse::assemble_program;
+use leg_computer_parse::generate_code;
+use std::path::Path;
+
+/// The challenge sources, in the order they are laid out in the program.
+const SOURCES: [&str; 4] = [
+    "challenge/main.leg",
+    "challenge/copy_list.leg",
+    "challenge/xor_list_check.leg",
+    "challenge/quicksort.leg",
+];
+/// The checker that compares the input with the flag as is, for
+/// `Checker::Plain`.
+const PLAIN: &str = "challenge/plain.leg";
+const MEMORY: &str = "challenge/memory.leg";
+const FLAG: &str = "flag.txt";
+
+fn read(path: &str) -> Result<String, String> {
+    println!("cargo:rerun-if-changed={}", path);
+    std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// The xor of the flag against its sorted self, which the challenge compares
+/// the input against.
+fn solution(flag: &[u8]) -> Vec<u8> {
+    let mut sorted = flag.to_vec();
+    sorted.sort_unstable();
+    flag.iter().zip(&sorted).map(|(a, b)| a ^ b).collect()
+}
+
+/// The bytes of one line of the memory layout.
+fn parse_bytes(s: &str, flag: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        if let Some(string) = rest.strip_prefix('"') {
+            let end = string.find('"').ok_or("Unterminated string")?;
+            bytes.extend(string[..end].bytes());
+            rest = &string[end + 1..];
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            match &rest[..end] {
+                "solution" => bytes.extend(solution(flag)),
+                word => bytes.push(
+                    word.parse()
+                        .map_err(|_| format!("Invalid byte: {}", word))?,
+                ),
+            }
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(bytes)
+}
+
+fn memory_image(layout: &str, flag: &[u8]) -> Result<Vec<u8>, String> {
+    let mut memory = vec![0; 256];
+    let mut used = vec![false; 256];
+    for (i, line) in layout.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: String| format!("{} line {}: {}", MEMORY, i + 1, msg);
+        let colon = line
+            .find(':')
+            .ok_or_else(|| err("Expected ADDR: BYTES".to_string()))?;
+        let addr: usize = line[..colon]
+            .trim()
+            .parse()
+            .map_err(|_| err(format!("Invalid address: {}", &line[..colon])))?;
+        let bytes = parse_bytes(&line[colon + 1..], flag).map_err(err)?;
+        if addr + bytes.len() > 256 {
+            return Err(err(format!("{} bytes do not fit at {}", bytes.len(), addr)));
+        }
+        for (offset, byte) in bytes.into_iter().enumerate() {
+            if used[addr + offset] {
+                return Err(err(format!("{:03} is already set", addr + offset)));
+            }
+            used[addr + offset] = true;
+            memory[addr + offset] = byte;
+        }
+    }
+
+    // The input list is written from the start address at address 0 on
+    let start = memory[0] as usize;
+    if let Some(last) = used.iter().rposition(|u| *u) {
+        if start <= last {
+            return Err(format!(
+                "{}: The input list at {:03} overlaps {:03}",
+                MEMORY, start, last
+            ));
+        }
+    }
+    Ok(memory)
+}
+
+fn build() -> Result<(), String> {
+    for path in &[
+        "src/leg_computer.rs",
+        "src/leg_computer_parse.rs",
+        "src/leg_stdlib.rs",
+    ] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+
+    let source = SOURCES
+        .iter()
+        .map(|path| read(path))
+        .collect::<Result<Vec<String>, String>>()?
+        .join("\n");
+    let program = generate_code(&assemble_program(&source)?);
+    let plain = generate_code(&assemble_program(&read(PLAIN)?)?);
+    for (path, program) in &[(SOURCES[0], &program), (PLAIN, &plain)] {
+        if program.len() > 256 {
+            return Err(format!(
+                "{}: Program too large: {} bytes",
+                path,
+                program.len()
+            ));
+        }
+    }
+
+    // Exactly the bytes that `make test` feeds the challenge
+    let flag = read(FLAG)?;
+    let memory = memory_image(&read(MEMORY)?, flag.as_bytes())?;
+
+    let out_dir = std::env::var("OUT_DIR").map_err(|e| format!("OUT_DIR: {}", e))?;
+    let write = |name: &str, bytes: &[u8]| {
+        let path = Path::new(&out_dir).join(name);
+        std::fs::write(&path, bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    write("challenge.bin", &program)?;
+    write("plain.bin", &plain)?;
+    write("memory.bin", &memory)
+}
+
+fn main() {
+    if let Err(e) = build() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,30 @@
+//! Command line helpers shared between the binaries.
+#![allow(dead_code)]
+
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::read_image;
+use evil_electronic_enigma::ImageLoader;
+use evil_electronic_enigma::LegComputer;
+
+/// Load a machine from the command line arguments `PROGRAM [MEMORY]` or
+/// `--asm SOURCE [MEMORY]`, or fail with `usage`. Images are read in the
+/// format their file name suggests, see `ImageFormat::from_path`.
+pub fn load_computer(args: &[String], usage: &str) -> Result<LegComputer, String> {
+    let (program, memory_path) = match args {
+        [flag, source] | [flag, source, _] if flag == "--asm" => {
+            let source =
+                std::fs::read_to_string(source).map_err(|e| format!("{}: {}", source, e))?;
+            (generate_code(&assemble_program(&source)?), args.get(2))
+        }
+        [program] | [program, _] => (read_image(program)?, args.get(1)),
+        _ => return Err(usage.to_string()),
+    };
+    let memory = match memory_path {
+        Some(path) => read_image(path)?,
+        None => Vec::new(),
+    };
+
+    let loader = ImageLoader::new(program, memory)?;
+    Ok(LegComputer::new(loader.program, loader.memory))
+}
@@ -0,0 +1,38 @@
+use evil_electronic_enigma::compile;
+use evil_electronic_enigma::generate_code;
+
+const USAGE: &str = "\
+Usage: leg-cc [--image OUT] SOURCE
+
+Compile a program in the small LEG language and print the result as assembly,
+or write it as a program image to OUT.";
+
+fn run() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (image, source) = match args.as_slice() {
+        [flag, image, source] if flag == "--image" => (Some(image), source),
+        [source] if !source.starts_with('-') => (None, source),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let text = std::fs::read_to_string(source).map_err(|e| format!("{}: {}", source, e))?;
+    let program = compile(&text).map_err(|e| format!("{}: {}", source, e))?;
+    match image {
+        Some(path) => {
+            std::fs::write(path, generate_code(&program)).map_err(|e| format!("{}: {}", path, e))?
+        }
+        None => {
+            for instruction in &program {
+                println!("{}", instruction);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use evil_electronic_enigma::Cfg;
+
+const USAGE: &str = "\
+Usage: leg-cfg [--dot] PROGRAM
+       leg-cfg [--dot] --asm SOURCE
+
+Recover the control flow graph of a program and print a summary of its
+functions, or the whole graph in Graphviz DOT format with --dot.";
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let dot = args.first().map(|a| a == "--dot").unwrap_or(false);
+    if dot {
+        args.remove(0);
+    }
+
+    let computer = match common::load_computer(&args, USAGE) {
+        Ok(computer) => computer,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let cfg = Cfg::recover(&computer.program);
+    if dot {
+        print!("{}", cfg.to_dot());
+    } else {
+        print!("{}", cfg.summary());
+    }
+}
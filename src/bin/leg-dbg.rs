@@ -0,0 +1,64 @@
+mod common;
+
+use evil_electronic_enigma::Command;
+use evil_electronic_enigma::Debugger;
+use std::io::BufRead;
+use std::io::Write;
+
+const USAGE: &str = "\
+Usage: leg-dbg PROGRAM [MEMORY]
+       leg-dbg --asm SOURCE [MEMORY]
+
+PROGRAM and MEMORY are images in the format that the end of their file name
+suggests, like for `leg`: .hex, .json, .logisim, .mem, .ihex or raw bytes.
+MEMORY is padded with zeros to 256 bytes. SOURCE is LEG assembly, assembled
+before loading.";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let computer = match common::load_computer(&args, USAGE) {
+        Ok(computer) => computer,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let mut debugger = Debugger::new(computer);
+    println!("{}", debugger.location());
+
+    let stdin = std::io::stdin();
+    let mut last_command: Option<Command> = None;
+    loop {
+        print!("(leg) ");
+        std::io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let command = if line.trim().is_empty() {
+            match &last_command {
+                Some(command) => command.clone(),
+                None => continue,
+            }
+        } else {
+            match line.trim().parse::<Command>() {
+                Ok(command) => command,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            }
+        };
+
+        if command == Command::Quit {
+            break;
+        }
+        println!("{}", debugger.execute(&command));
+        last_command = Some(command);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use evil_electronic_enigma::decompile;
+use evil_electronic_enigma::Cfg;
+
+const USAGE: &str = "\
+Usage: leg-decompile PROGRAM
+       leg-decompile --asm SOURCE
+
+Print a program as C-like pseudocode, one function per CALL target.";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let computer = match common::load_computer(&args, USAGE) {
+        Ok(computer) => computer,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    print!("{}", decompile(&Cfg::recover(&computer.program)));
+}
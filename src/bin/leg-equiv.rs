@@ -0,0 +1,117 @@
+use evil_electronic_enigma::parse_range;
+use evil_electronic_enigma::Equivalence;
+use evil_electronic_enigma::EquivalenceChecker;
+use evil_electronic_enigma::SymbolicInput;
+
+const USAGE: &str = "\
+Usage: leg-equiv [OPTIONS] LEFT RIGHT MEMORY
+
+Check that two program images leave the same bytes in the output region of
+memory, for every input if the input space is small and for random inputs
+otherwise.
+
+Options:
+  --input START-END    Input region, END exclusive. Default: the list whose
+                       bounds are at addresses 0 and 1, like the challenge.
+  --domain LO-HI       Values of each input byte, inclusive. Default: 0-255
+  --output START-END   Region that must match, END exclusive. Default: 0-256
+  --steps N            Step budget per run. Default: 100000
+  --samples N          Random inputs to try. Default: 10000
+  --exhaustive N       Enumerate at most N inputs. Default: 65536
+  --seed N             Random seed. Default: 0";
+
+fn run() -> Result<bool, String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let number = |s: &str| {
+        s.parse::<usize>()
+            .map_err(|_| format!("Invalid number: {}", s))
+    };
+
+    let mut input = None;
+    let mut domain = (0, 255);
+    let mut output = (0, 256);
+    let mut options = Vec::new();
+    while args.first().map(|a| a.starts_with("--")) == Some(true) {
+        let flag = args.remove(0);
+        if args.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        let value = args.remove(0);
+        match flag.as_str() {
+            "--input" => input = Some(parse_range(&value)?),
+            "--domain" => {
+                let (lo, len) = parse_range(&value)?;
+                if len > 255 {
+                    return Err(format!("Invalid domain: {}", value));
+                }
+                domain = (lo, lo + len as u8);
+            }
+            "--output" => output = parse_range(&value)?,
+            "--steps" | "--samples" | "--exhaustive" | "--seed" => {
+                options.push((flag, number(&value)?))
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let read = |path: &str| std::fs::read(path).map_err(|e| format!("{}: {}", path, e));
+    let (left, right, memory) = match args.as_slice() {
+        [left, right, memory] => (read(left)?, read(right)?, read(memory)?),
+        _ => return Err(USAGE.to_string()),
+    };
+    if memory.len() > 256 {
+        return Err(format!("Memory image too large: {} bytes", memory.len()));
+    }
+
+    let (start, len) = match input {
+        Some(input) => input,
+        None => {
+            let start = memory.first().cloned().unwrap_or(0);
+            let end = memory.get(1).cloned().unwrap_or(0);
+            (start, end.saturating_sub(start) as usize)
+        }
+    };
+    let inputs = (start as usize..start as usize + len)
+        .map(|addr| SymbolicInput {
+            addr: addr as u8,
+            domain: (domain.0..=domain.1).collect(),
+        })
+        .collect();
+
+    let mut checker = EquivalenceChecker::new(left, right, memory, inputs);
+    checker.output = output;
+    for (flag, value) in options {
+        match flag.as_str() {
+            "--steps" => checker.max_steps = value,
+            "--samples" => checker.samples = value,
+            "--exhaustive" => checker.max_exhaustive = value,
+            _ => checker.seed = value as u64,
+        }
+    }
+
+    match checker.check() {
+        Equivalence::Equivalent { runs, exhaustive } => {
+            println!(
+                "Equivalent on {} {}inputs",
+                runs,
+                if exhaustive { "(all) " } else { "random " }
+            );
+            Ok(true)
+        }
+        Equivalence::Counterexample(counterexample) => {
+            println!("Counterexample: {}", counterexample);
+            Ok(false)
+        }
+    }
+}
+
+fn main() {
+    match run() {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use evil_electronic_enigma::flag_image;
+use evil_electronic_enigma::Checker;
+
+const USAGE: &str = "\
+Usage: leg-flag [--checker sort-xor|plain] [--memory OUT] FLAG
+
+Lay out the initial memory of the challenge for the flag in the file FLAG,
+print the layout and write the memory image to OUT. The checker defaults to
+sort-xor, the one of the challenge.";
+
+fn run() -> Result<(), String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut checker = Checker::SortXor;
+    let mut out = None;
+    while args.first().map(|a| a.starts_with("--")) == Some(true) {
+        let flag = args.remove(0);
+        if args.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        let value = args.remove(0);
+        match flag.as_str() {
+            "--checker" => checker = value.parse()?,
+            "--memory" => out = Some(value),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = match args.as_slice() {
+        [path] => path,
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let flag = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let image = flag_image(&flag, checker)?;
+    println!("{}", image.layout);
+    if let Some(out) = out {
+        std::fs::write(&out, &image.memory).map_err(|e| format!("{}: {}", out, e))?;
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+}
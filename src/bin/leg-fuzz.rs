@@ -0,0 +1,129 @@
+mod common;
+
+use evil_electronic_enigma::parse_range;
+use evil_electronic_enigma::Fuzzer;
+use evil_electronic_enigma::Target;
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+Usage: leg-fuzz [OPTIONS] PROGRAM [MEMORY]
+       leg-fuzz [OPTIONS] --asm SOURCE [MEMORY]
+
+Mutate the input region of MEMORY, keeping inputs that reach new conditional
+jump edges. Faults, timeouts and inputs that reach the target are saved to the
+output directory.
+
+Options:
+  --input START-END    Input region, END exclusive. Default: from the address
+                       stored at 0, up to 32 bytes, like the challenge.
+  --end-pointer ADDR   Store the end address of the input at ADDR. Default: 1
+                       if --input is not given.
+  --reach ADDR         Target: eip reaches ADDR
+  --expect ADDR:TEXT   Target: halt with TEXT in memory at ADDR
+  --steps N            Step budget per run. Default: 100000
+  --iterations N       Stop after N runs. Default: 1000000
+  --seed N             Random seed. Default: 0
+  --out DIR            Save findings here. Default: findings";
+
+const REPORT_INTERVAL: usize = 10_000;
+
+struct Options {
+    input: Option<(u8, usize)>,
+    end_pointer: Option<u8>,
+    target: Option<Target>,
+    steps: usize,
+    iterations: usize,
+    seed: u64,
+    out: PathBuf,
+}
+
+fn parse_options(args: &mut Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        input: None,
+        end_pointer: None,
+        target: None,
+        steps: 100_000,
+        iterations: 1_000_000,
+        seed: 0,
+        out: PathBuf::from("findings"),
+    };
+    let number = |s: &str| {
+        s.parse::<u64>()
+            .map_err(|_| format!("Invalid number: {}", s))
+    };
+    let address = |s: &str| {
+        s.parse::<u8>()
+            .map_err(|_| format!("Invalid address: {}", s))
+    };
+
+    while args.first().map(|a| a.starts_with("--") && a != "--asm") == Some(true) {
+        let flag = args.remove(0);
+        if args.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        let value = args.remove(0);
+        match flag.as_str() {
+            "--input" => options.input = Some(parse_range(&value)?),
+            "--end-pointer" => options.end_pointer = Some(address(&value)?),
+            "--reach" => options.target = Some(Target::Reach(address(&value)?)),
+            "--expect" => {
+                let i = value.find(':').ok_or_else(|| USAGE.to_string())?;
+                options.target = Some(Target::HaltWithMemory {
+                    addr: address(&value[..i])?,
+                    bytes: value.as_bytes()[i + 1..].to_vec(),
+                });
+            }
+            "--steps" => options.steps = number(&value)? as usize,
+            "--iterations" => options.iterations = number(&value)? as usize,
+            "--seed" => options.seed = number(&value)?,
+            "--out" => options.out = PathBuf::from(value),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    Ok(options)
+}
+
+fn run() -> Result<(), String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let options = parse_options(&mut args)?;
+    let computer = common::load_computer(&args, USAGE)?;
+
+    let (start, max_len, end_pointer) = match options.input {
+        Some((start, len)) => (start, len, options.end_pointer),
+        None => (computer.memory[0], 32, options.end_pointer.or(Some(1))),
+    };
+    std::fs::create_dir_all(&options.out)
+        .map_err(|e| format!("{}: {}", options.out.display(), e))?;
+
+    let mut fuzzer = Fuzzer::new(
+        computer.program,
+        computer.memory,
+        start,
+        max_len,
+        options.seed,
+    );
+    fuzzer.input_end_pointer = end_pointer;
+    fuzzer.max_steps = options.steps;
+    fuzzer.target = options.target;
+    fuzzer.output_dir = Some(options.out);
+
+    let mut remaining = options.iterations;
+    while remaining > 0 {
+        let chunk = usize::min(remaining, REPORT_INTERVAL);
+        let stats = fuzzer.run(chunk)?;
+        eprintln!("{}", stats);
+        remaining -= chunk;
+    }
+
+    for finding in &fuzzer.findings {
+        println!("{}: {:?}", finding.file_name(), finding.input);
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+}
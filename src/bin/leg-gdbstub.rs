@@ -0,0 +1,65 @@
+mod common;
+
+use evil_electronic_enigma::GdbStub;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+
+const USAGE: &str = "\
+Usage: leg-gdbstub (--tcp ADDR | --stdio) PROGRAM [MEMORY]
+       leg-gdbstub (--tcp ADDR | --stdio) --asm SOURCE [MEMORY]
+
+Serves the GDB remote serial protocol for a LEG machine, either on a TCP
+address like 127.0.0.1:1234 (one session at a time), or on stdin/stdout for
+use with `target remote | leg-gdbstub --stdio ...`.";
+
+struct Stdio;
+
+impl Read for Stdio {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        std::io::stdin().read(buf)
+    }
+}
+
+impl Write for Stdio {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stdout().flush()
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (tcp_addr, image_args) = match args.first().map(|s| s.as_str()) {
+        Some("--tcp") if args.len() > 2 => (Some(args[1].clone()), &args[2..]),
+        Some("--stdio") => (None, &args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    let computer = match common::load_computer(image_args, USAGE) {
+        Ok(computer) => computer,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let mut stub = GdbStub::new(computer);
+    match tcp_addr {
+        Some(addr) => {
+            let listener = TcpListener::bind(&addr)?;
+            eprintln!("Listening on {}", listener.local_addr()?);
+            for stream in listener.incoming() {
+                stub.serve(stream?)?;
+            }
+        }
+        None => stub.serve(Stdio)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use evil_electronic_enigma::lint;
+use evil_electronic_enigma::LINT_KINDS;
+
+fn usage() -> String {
+    format!(
+        "\
+Usage: leg-lint SOURCE...
+
+Check LEG assembly sources for common mistakes. A comment line
+`# allow: LINT, ...` suppresses lints on the next instruction, and
+`# allow-file: LINT, ...` suppresses them in the whole file.
+
+Lints: {}",
+        LINT_KINDS
+            .iter()
+            .map(|k| k.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+fn main() {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() || paths.iter().any(|p| p.starts_with('-')) {
+        eprintln!("{}", usage());
+        std::process::exit(2);
+    }
+
+    let mut clean = true;
+    for path in &paths {
+        let result = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| lint(&source));
+        match result {
+            Ok(lints) => {
+                for lint in lints {
+                    println!("{}: {}", path, lint);
+                    clean = false;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(2);
+            }
+        }
+    }
+    if !clean {
+        std::process::exit(1);
+    }
+}
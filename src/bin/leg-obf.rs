@@ -0,0 +1,127 @@
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::parse_range;
+use evil_electronic_enigma::Obfuscator;
+use evil_electronic_enigma::SymbolicInput;
+use evil_electronic_enigma::Technique;
+
+const USAGE: &str = "\
+Usage: leg-obf [OPTIONS] SOURCE
+
+Obfuscate a LEG assembly program and print the result as assembly, or write it
+as a program image. The result is checked to leave the same bytes in the
+output region of memory as the original, for random inputs. What was done is
+reported on stderr.
+
+Options:
+  --image OUT          Write a program image to OUT
+  --seed N             Random seed. Default: 0
+  --rate P             Chance of each technique at each instruction.
+                       Default: 0.3
+  --max-bytes N        Size limit of the result. Default: 256
+  --only T,...         Techniques to use, out of substitute, opaque, junk,
+                       flatten and rename. Default: all
+  --table ADDR         Put the jump table for flatten at ADDR in memory. It
+                       is printed with the report, and the program must not
+                       use those bytes. Without it, jumps are not flattened.
+  --memory FILE        Memory image to check with. Default: all zeroes
+  --input START-END    Input region, END exclusive. Default: the list whose
+                       bounds are at addresses 0 and 1, like the challenge.
+  --output START-END   Region that must match, END exclusive. Default: 0-256
+  --samples N          Random inputs to check. Default: 1000";
+
+fn run() -> Result<(), String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let number = |s: &str| {
+        s.parse::<usize>()
+            .map_err(|_| format!("Invalid number: {}", s))
+    };
+    let read = |path: &str| std::fs::read(path).map_err(|e| format!("{}: {}", path, e));
+
+    let mut obfuscator = Obfuscator::new(0);
+    let mut image = None;
+    let mut input = None;
+    while args.first().map(|a| a.starts_with("--")) == Some(true) {
+        let flag = args.remove(0);
+        if args.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        let value = args.remove(0);
+        match flag.as_str() {
+            "--image" => image = Some(value),
+            "--seed" => obfuscator.seed = number(&value)? as u64,
+            "--rate" => {
+                obfuscator.rate = value
+                    .parse()
+                    .ok()
+                    .filter(|p| (0.0..=1.0).contains(p))
+                    .ok_or(format!("Invalid rate: {}", value))?
+            }
+            "--max-bytes" => obfuscator.max_bytes = number(&value)?,
+            "--only" => {
+                obfuscator.techniques = value
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<Vec<Technique>, String>>()?
+            }
+            "--table" => {
+                let addr = number(&value)?;
+                if addr > 255 {
+                    return Err(format!("Invalid address: {}", value));
+                }
+                obfuscator.table = Some(addr as u8);
+            }
+            "--memory" => {
+                let memory = read(&value)?;
+                if memory.len() > 256 {
+                    return Err(format!("Memory image too large: {} bytes", memory.len()));
+                }
+                obfuscator.memory = memory;
+            }
+            "--input" => input = Some(parse_range(&value)?),
+            "--output" => obfuscator.output = parse_range(&value)?,
+            "--samples" => obfuscator.samples = number(&value)?,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let source = match args.as_slice() {
+        [source] => source,
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let (start, len) = match input {
+        Some(input) => input,
+        None => {
+            let start = obfuscator.memory.first().cloned().unwrap_or(0);
+            let end = obfuscator.memory.get(1).cloned().unwrap_or(0);
+            (start, end.saturating_sub(start) as usize)
+        }
+    };
+    obfuscator.inputs = (start as usize..start as usize + len)
+        .map(|addr| SymbolicInput {
+            addr: addr as u8,
+            domain: (0..=255).collect(),
+        })
+        .collect();
+
+    let text = std::fs::read_to_string(source).map_err(|e| format!("{}: {}", source, e))?;
+    let (obfuscated, report) = obfuscator.obfuscate(&assemble_program(&text)?)?;
+    match image {
+        Some(path) => std::fs::write(&path, generate_code(&obfuscated))
+            .map_err(|e| format!("{}: {}", path, e))?,
+        None => {
+            for instruction in &obfuscated {
+                println!("{}", instruction);
+            }
+        }
+    }
+    eprintln!("{}", report);
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+}
@@ -0,0 +1,39 @@
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::optimize;
+
+const USAGE: &str = "\
+Usage: leg-opt [--image OUT] SOURCE
+
+Optimize a LEG assembly program and print the result as assembly, or write it
+as a program image to OUT. The bytes saved are reported on stderr.";
+
+fn run() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (image, source) = match args.as_slice() {
+        [flag, image, source] if flag == "--image" => (Some(image), source),
+        [source] if !source.starts_with('-') => (None, source),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let text = std::fs::read_to_string(source).map_err(|e| format!("{}: {}", source, e))?;
+    let (optimized, report) = optimize(&assemble_program(&text)?)?;
+    match image {
+        Some(path) => std::fs::write(path, generate_code(&optimized))
+            .map_err(|e| format!("{}: {}", path, e))?,
+        None => {
+            for instruction in &optimized {
+                println!("{}", instruction);
+            }
+        }
+    }
+    eprintln!("{}", report);
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+}
@@ -0,0 +1,71 @@
+use evil_electronic_enigma::flag_image;
+use evil_electronic_enigma::Checker;
+use evil_electronic_enigma::FlagProver;
+
+const USAGE: &str = "\
+Usage: leg-prove [--checker sort-xor|plain] [--program IMAGE] [--alphabet CHARS]
+                 [--min LEN] [--max LEN] [--max-runs N] FLAG
+
+Find every input of the given lengths over the alphabet that the program
+accepts, with the memory laid out for the flag in the file FLAG, and tell
+whether the flag is the only one. The program defaults to the challenge, or to
+challenge/plain.leg for the plain checker, the alphabet to printable ASCII and
+the lengths to 0 up to the length of the flag.";
+
+fn run() -> Result<(), String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut checker = Checker::SortXor;
+    let mut program = None;
+    let mut alphabet = None;
+    let mut min = 0;
+    let mut max = None;
+    let mut max_runs = None;
+    while args.first().map(|a| a.starts_with("--")) == Some(true) {
+        let flag = args.remove(0);
+        if args.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        let value = args.remove(0);
+        let number = |v: &str| v.parse().map_err(|_| format!("Invalid number: {}", v));
+        match flag.as_str() {
+            "--checker" => checker = value.parse()?,
+            "--program" => {
+                program = Some(std::fs::read(&value).map_err(|e| format!("{}: {}", value, e))?)
+            }
+            "--alphabet" if value.is_empty() => return Err("Empty alphabet".to_string()),
+            "--alphabet" => alphabet = Some(value.into_bytes()),
+            "--min" => min = number(&value)?,
+            "--max" => max = Some(number(&value)?),
+            "--max-runs" => max_runs = Some(number(&value)?),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = match args.as_slice() {
+        [path] => path,
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let flag = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let program = program.unwrap_or_else(|| checker.program().to_vec());
+    let mut prover = FlagProver::new(program, flag_image(&flag, checker)?);
+    if let Some(alphabet) = alphabet {
+        prover.alphabet = alphabet;
+    }
+    if let Some(max_runs) = max_runs {
+        prover.max_runs = max_runs;
+    }
+    prover.lengths = (min, max.unwrap_or(flag.len()));
+    let report = prover.prove()?;
+    println!("{}", report);
+    if report.unique() && report.solutions == [flag] {
+        println!("The flag is the only accepted input");
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use evil_electronic_enigma::parse_range;
+use evil_electronic_enigma::TaintTracker;
+
+const USAGE: &str = "\
+Usage: leg-taint [--input START-END]... PROGRAM [MEMORY]
+       leg-taint [--input START-END]... --asm SOURCE [MEMORY]
+
+Run a program while tracking which input bytes flow into each conditional
+branch and output. The input is the memory range START-END, end exclusive,
+and may be given several times. The default is the list whose bounds are at
+addresses 0 and 1, which is where the challenge reads its input.";
+
+const MAX_STEPS: usize = 10_000_000;
+
+fn run() -> Result<String, String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut sources = Vec::new();
+    while args.first().map(|a| a == "--input").unwrap_or(false) {
+        let range = args.get(1).ok_or_else(|| USAGE.to_string())?;
+        sources.push(parse_range(range)?);
+        args.drain(0..2);
+    }
+
+    let computer = common::load_computer(&args, USAGE)?;
+    if sources.is_empty() {
+        let (start, end) = (computer.memory[0], computer.memory[1]);
+        sources.push((start, end.saturating_sub(start) as usize));
+    }
+
+    let mut tracker = TaintTracker::new(computer, &sources);
+    tracker.run(MAX_STEPS)?;
+    Ok(tracker.report.to_string())
+}
+
+fn main() {
+    match run() {
+        Ok(report) => print!("{}", report),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
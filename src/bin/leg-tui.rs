@@ -0,0 +1,107 @@
+mod common;
+
+use evil_electronic_enigma::Action;
+use evil_electronic_enigma::Tui;
+use std::io::Read;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+const USAGE: &str = "\
+Usage: leg-tui PROGRAM [MEMORY]
+       leg-tui --asm SOURCE [MEMORY]
+
+Full-screen view of a running LEG machine. Needs a terminal that understands
+ANSI escape sequences, and `stty` to switch it to raw mode.";
+
+fn stty(args: &[&str]) -> std::io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Read one key, or `None` if none was pressed within the `stty time`
+/// timeout.
+fn read_key() -> std::io::Result<Option<char>> {
+    let mut buf = [0];
+    Ok(match std::io::stdin().read(&mut buf)? {
+        0 => None,
+        _ => Some(buf[0] as char),
+    })
+}
+
+fn read_line(tui: &Tui) -> std::io::Result<String> {
+    let mut line = String::new();
+    loop {
+        print!("{}\r\n:{}", tui.render(), line);
+        std::io::stdout().flush()?;
+        match read_key()? {
+            Some('\r') | Some('\n') => return Ok(line),
+            Some('\x1b') => return Ok(String::new()),
+            Some('\x7f') => {
+                line.pop();
+            }
+            Some(c) if !c.is_control() => line.push(c),
+            _ => {}
+        }
+    }
+}
+
+fn run(tui: &mut Tui) -> std::io::Result<()> {
+    let mut dirty = true;
+    loop {
+        if dirty {
+            print!("{}", tui.render());
+            std::io::stdout().flush()?;
+        }
+        dirty = true;
+        let key = match read_key()? {
+            Some(key) => key,
+            None => {
+                dirty = false;
+                continue;
+            }
+        };
+        match tui.handle_key(key) {
+            Action::Redraw => {}
+            Action::Animate => loop {
+                print!("{}", tui.render());
+                std::io::stdout().flush()?;
+                if !tui.animate_step() || read_key()?.is_some() {
+                    break;
+                }
+            },
+            Action::Prompt => {
+                let line = read_line(tui)?;
+                if !line.is_empty() {
+                    tui.command(&line);
+                }
+            }
+            Action::Quit => return Ok(()),
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let computer = match common::load_computer(&args, USAGE) {
+        Ok(computer) => computer,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let mut tui = Tui::new(computer);
+
+    let saved = stty(&["-g"])?;
+    // Reads time out after 0.1 s, which also paces the `r` animation
+    stty(&["raw", "-echo", "min", "0", "time", "1"])?;
+    print!("\x1b[?25l");
+    let result = run(&mut tui);
+    print!("\x1b[?25h\r\n");
+    stty(&[&saved])?;
+
+    Ok(result?)
+}
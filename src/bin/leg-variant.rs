@@ -0,0 +1,90 @@
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::disassemble;
+use evil_electronic_enigma::Checker;
+use evil_electronic_enigma::VariantGenerator;
+use std::path::Path;
+
+const USAGE: &str = "\
+Usage: leg-variant [OPTIONS] SEED OUT_DIR
+
+Generate the variant of the challenge for SEED, check that it accepts the
+flag, and write program.bin, memory.bin and manifest.txt to OUT_DIR. The
+manifest is also printed.
+
+Options:
+  --flag FILE          File holding the flag. Default: flag.txt
+  --source FILE        Assembly source to vary. Default: the checker's program
+  --checker K          sort-xor or plain. Default: sort-xor
+  --samples N          Random inputs to check the variant on. Default: 200";
+
+fn run() -> Result<(), String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let read = |path: &str| std::fs::read(path).map_err(|e| format!("{}: {}", path, e));
+
+    let mut flag_path = "flag.txt".to_string();
+    let mut source = None;
+    let mut checker = Checker::SortXor;
+    let mut samples = None;
+    while args.first().map(|a| a.starts_with("--")) == Some(true) {
+        let flag = args.remove(0);
+        if args.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        let value = args.remove(0);
+        match flag.as_str() {
+            "--flag" => flag_path = value,
+            "--source" => source = Some(value),
+            "--checker" => checker = value.parse()?,
+            "--samples" => {
+                samples = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid number: {}", value))?,
+                )
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let (seed, out_dir) = match args.as_slice() {
+        [seed, out_dir] => (
+            seed.parse::<u64>()
+                .map_err(|_| format!("Invalid seed: {}", seed))?,
+            Path::new(out_dir),
+        ),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let program = match source {
+        Some(path) => {
+            let text = String::from_utf8_lossy(&read(&path)?).into_owned();
+            assemble_program(&text)?
+        }
+        None => disassemble(checker.program())?,
+    };
+    let mut generator = VariantGenerator::new(program, &read(&flag_path)?);
+    generator.checker = checker;
+    if let Some(samples) = samples {
+        generator.samples = samples;
+    }
+    let variant = generator.generate(seed)?;
+
+    std::fs::create_dir_all(out_dir).map_err(|e| format!("{}: {}", out_dir.display(), e))?;
+    let manifest = variant.manifest();
+    for (name, bytes) in &[
+        ("program.bin", &variant.program[..]),
+        ("memory.bin", &variant.memory[..]),
+        ("manifest.txt", manifest.as_bytes()),
+    ] {
+        let path = out_dir.join(name);
+        std::fs::write(&path, bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    print!("{}", manifest);
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+}
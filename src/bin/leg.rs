@@ -0,0 +1,313 @@
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::parse_range;
+use evil_electronic_enigma::read_container;
+use evil_electronic_enigma::read_image;
+use evil_electronic_enigma::write_container;
+use evil_electronic_enigma::write_image;
+use evil_electronic_enigma::ImageFormat;
+use evil_electronic_enigma::ImageLoader;
+use evil_electronic_enigma::InputStart;
+use evil_electronic_enigma::Instruction;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::LegContainer;
+use evil_electronic_enigma::Section;
+use evil_electronic_enigma::Word;
+use std::convert::TryFrom;
+use std::io::Write;
+
+const USAGE: &str = "\
+Usage: leg asm SOURCE [OUT]
+       leg disasm PROGRAM
+       leg run [OPTIONS] PROGRAM [MEMORY]
+       leg trace [OPTIONS] PROGRAM [MEMORY]
+       leg dump IMAGE [OUT]
+       leg diff IMAGE1 IMAGE2
+
+asm     Assemble SOURCE into a program image
+disasm  Print the instructions of a program image
+run     Run a program until it halts, and print the final state
+trace   Run a program, printing each instruction and the registers before it
+dump    Convert an image to the format of OUT
+diff    Print the addresses where two images differ, and exit with 1 if any do
+
+Options of run and trace:
+  --input FILE          Write FILE to memory, by default from the address at
+                        0 on with its end written to address 1, like the
+                        challenge does
+  --input-at ADDR|*ADDR Write the input at ADDR, or where ADDR points
+  --end-pointer ADDR|none
+                        Write the end of the input to ADDR, or nowhere
+  --max-steps N         Give up after N steps (default 1000000)
+  --output START-END    Print memory START to END as text instead of the
+                        final state (run only)
+  --memory-out OUT      Write the final memory to OUT (run only)
+  --json                Print the final state as JSON (run only)
+
+Images are read and written by the end of their file name: .hex for hex
+bytes, .json for a JSON array, .logisim for a Logisim-evolution v2.0 raw
+file, .mem for Verilog $readmemh, .ihex or .ihx for Intel HEX, and raw bytes
+otherwise. A .hex file that starts with ':' is read as Intel HEX, and one
+that starts with '@' or a // or /* comment as $readmemh. Images without an
+OUT are printed as hex. MEMORY is padded with zeros to 256 bytes.
+
+A file name ending in .legc is a container of a program with its initial
+memory, entry point, functions and source lines. asm writes one if OUT ends in
+.legc, and disasm, run and trace read one as PROGRAM, where MEMORY replaces
+the container's memory.";
+
+const MAX_STEPS: usize = 1_000_000;
+
+/// Options of `run` and `trace`.
+struct RunOptions {
+    input: Option<String>,
+    input_start: InputStart,
+    end_pointer: Option<Word>,
+    max_steps: usize,
+    output: Option<(Word, usize)>,
+    json: bool,
+    memory_out: Option<String>,
+    images: Vec<String>,
+}
+
+impl RunOptions {
+    fn parse(mut args: Vec<String>) -> Result<RunOptions, String> {
+        let mut options = RunOptions {
+            input: None,
+            input_start: InputStart::Pointer(0),
+            end_pointer: Some(1),
+            max_steps: MAX_STEPS,
+            output: None,
+            json: false,
+            memory_out: None,
+            images: Vec::new(),
+        };
+        while args.first().map(|a| a.starts_with("--")) == Some(true) {
+            let flag = args.remove(0);
+            if flag == "--json" {
+                options.json = true;
+                continue;
+            }
+            if args.is_empty() {
+                return Err(USAGE.to_string());
+            }
+            let value = args.remove(0);
+            match flag.as_str() {
+                "--input" => options.input = Some(value),
+                "--input-at" => options.input_start = value.parse()?,
+                "--end-pointer" if value == "none" => options.end_pointer = None,
+                "--end-pointer" => {
+                    options.end_pointer = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid end pointer: {}", value))?,
+                    )
+                }
+                "--max-steps" => {
+                    options.max_steps = value
+                        .parse()
+                        .map_err(|_| format!("Invalid step count: {}", value))?
+                }
+                "--output" => options.output = Some(parse_range(&value)?),
+                "--memory-out" => options.memory_out = Some(value),
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        match args.len() {
+            1 | 2 => options.images = args,
+            _ => return Err(USAGE.to_string()),
+        }
+        Ok(options)
+    }
+
+    fn load(&self) -> Result<LegComputer, String> {
+        let (program, data, entry) = match is_container(&self.images[0]) {
+            true => {
+                let container = read_container(&self.images[0])?;
+                (container.program, container.data, container.entry)
+            }
+            false => (read_image(&self.images[0])?, Vec::new(), 0),
+        };
+        let memory = match self.images.get(1) {
+            Some(path) => read_image(path)?,
+            None => data,
+        };
+        let mut loader = ImageLoader::new(program, memory)?;
+        loader.input_start = self.input_start;
+        loader.end_pointer = self.end_pointer;
+        let mut computer = match &self.input {
+            Some(path) => {
+                let input = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+                loader.load(&input)?
+            }
+            None => LegComputer::new(loader.program, loader.memory),
+        };
+        computer.eip = entry;
+        Ok(computer)
+    }
+}
+
+/// True if `path` names a container rather than a bare image.
+fn is_container(path: &str) -> bool {
+    path.ends_with(".legc")
+}
+
+/// Print an image to `out`, or as hex to stdout.
+fn output_image(out: Option<&String>, image: &[Word]) -> Result<(), String> {
+    match out {
+        Some(path) => write_image(path, image),
+        None => std::io::stdout()
+            .write_all(&ImageFormat::Hex.write(image))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Run until the program halts, faults or runs out of steps, calling `trace`
+/// before each step. Returns the number of steps and the reason it stopped,
+/// if not a halt.
+fn execute<F>(computer: &mut LegComputer, max_steps: usize, mut trace: F) -> (usize, Option<String>)
+where
+    F: FnMut(&LegComputer),
+{
+    for steps in 0..max_steps {
+        if computer.is_halted() {
+            return (steps, None);
+        }
+        trace(computer);
+        if let Err(e) = computer.try_step() {
+            return (steps, Some(format!("Fault at {:03}: {}", computer.eip, e)));
+        }
+    }
+    match computer.is_halted() {
+        true => (max_steps, None),
+        false => (max_steps, Some("Step limit reached".to_string())),
+    }
+}
+
+fn run_command(args: Vec<String>, trace: bool) -> Result<bool, String> {
+    let options = RunOptions::parse(args)?;
+    let mut computer = options.load()?;
+    let (steps, stop) = execute(&mut computer, options.max_steps, |computer| {
+        if trace {
+            let text = match computer.current_instruction() {
+                Ok(instruction) => instruction.to_string(),
+                Err(e) => format!("<{}>", e),
+            };
+            println!(
+                "{:03}: {:<24} {} {}",
+                computer.eip, text, computer.registers, computer.flags
+            );
+        }
+    });
+
+    match &stop {
+        Some(reason) => eprintln!("{} after {} steps", reason, steps),
+        None => eprintln!("Halted after {} steps", steps),
+    }
+    if !trace {
+        match options.output {
+            Some((start, len)) => {
+                let start = start as usize;
+                let text: String = computer.memory[start..start + len]
+                    .iter()
+                    .flat_map(|b| std::ascii::escape_default(*b))
+                    .map(char::from)
+                    .collect();
+                println!("{}", text);
+            }
+            None if options.json => println!("{}", computer.to_json()),
+            None => println!("{}", computer),
+        }
+    }
+    if let Some(path) = &options.memory_out {
+        write_image(path, &computer.memory)?;
+    }
+    Ok(stop.is_none())
+}
+
+fn run() -> Result<bool, String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let command = args.remove(0);
+    match (command.as_str(), args.as_slice()) {
+        ("asm", [source]) | ("asm", [source, _]) => {
+            let text = std::fs::read_to_string(source).map_err(|e| format!("{}: {}", source, e))?;
+            match args.get(1) {
+                Some(out) if is_container(out) => {
+                    let container = LegContainer::assemble(&text, Vec::new())
+                        .map_err(|e| format!("{}: {}", source, e))?;
+                    write_container(out, &container)?;
+                }
+                out => {
+                    let program =
+                        assemble_program(&text).map_err(|e| format!("{}: {}", source, e))?;
+                    output_image(out, &generate_code(&program))?;
+                }
+            }
+        }
+        ("disasm", [path]) => {
+            let container = match is_container(path) {
+                true => read_container(path)?,
+                false => LegContainer::new(read_image(path)?, Vec::new()),
+            };
+            let program = &container.program;
+            for (i, words) in program.chunks_exact(2).enumerate() {
+                let addr = (2 * i) as Word;
+                for symbol in container
+                    .symbols
+                    .iter()
+                    .filter(|s| s.section == Section::Program && s.addr == addr)
+                {
+                    println!("{}:", symbol.name);
+                }
+                let text = match Instruction::try_from((words[0], words[1])) {
+                    Ok(instruction) => instruction.to_string(),
+                    Err(e) => format!("<{}>", e),
+                };
+                match container.line_at(addr) {
+                    Some(line) => println!("{:03}: {:<24} line {}", addr, text, line),
+                    None => println!("{:03}: {}", addr, text),
+                }
+            }
+            if program.len() % 2 == 1 {
+                println!(
+                    "{:03}: <Trailing byte {}>",
+                    program.len() - 1,
+                    program[program.len() - 1]
+                );
+            }
+        }
+        ("run", _) => return run_command(args, false),
+        ("trace", _) => return run_command(args, true),
+        ("dump", [path]) | ("dump", [path, _]) => output_image(args.get(1), &read_image(path)?)?,
+        ("diff", [a, b]) => {
+            let (a, b) = (read_image(a)?, read_image(b)?);
+            let mut same = a.len() == b.len();
+            for (i, (x, y)) in a.iter().zip(&b).enumerate() {
+                if x != y {
+                    println!("{:03}: {:3} {:3}", i, x, y);
+                    same = false;
+                }
+            }
+            if a.len() != b.len() {
+                println!("Lengths differ: {} and {}", a.len(), b.len());
+            }
+            return Ok(same);
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(true)
+}
+
+fn main() {
+    match run() {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use super::leg_computer::Address;
+use super::leg_computer::AluFlagRef;
+use super::leg_computer::Instruction;
+use super::leg_computer::NopOpcode;
+use super::leg_computer::StackInstruction;
+use super::leg_computer::Word;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EdgeKind {
+    /// A jump taken when the flag is set. Unconditional jumps use `True`.
+    Jump(AluFlagRef),
+    /// Falling through past a conditional jump whose flag is not set
+    NotTaken(AluFlagRef),
+    /// Falling through into a block that starts at a jump target
+    Next,
+    /// From a CALL to the entry of the called function
+    Call,
+    /// From a CALL to the instruction after it, where the callee returns to
+    AfterCall,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Edge {
+    /// None if the target is computed at run time
+    pub target: Option<Address>,
+    pub kind: EdgeKind,
+}
+
+impl Edge {
+    pub fn is_unresolved(&self) -> bool {
+        self.target.is_none()
+    }
+
+    pub fn label(&self) -> String {
+        match self.kind {
+            EdgeKind::Jump(AluFlagRef::True) => String::new(),
+            EdgeKind::Jump(flag) => flag.to_string(),
+            EdgeKind::NotTaken(flag) => match flag.negate() {
+                Some(negated) => negated.to_string(),
+                None => format!("!{}", flag),
+            },
+            EdgeKind::Next => String::new(),
+            EdgeKind::Call => "call".to_string(),
+            EdgeKind::AfterCall => "return".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub start: Address,
+    pub instructions: Vec<(Address, Instruction)>,
+    /// Empty if the block ends in RET or HALT, or runs into an undecodable
+    /// instruction
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Function {
+    pub entry: Address,
+    /// Start addresses of the blocks reachable from the entry without
+    /// following calls
+    pub blocks: BTreeSet<Address>,
+    /// Entries of the functions called with a constant target
+    pub callees: BTreeSet<Address>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<Address, BasicBlock>,
+    /// Functions by entry address. Address 0 is always an entry.
+    pub functions: BTreeMap<Address, Function>,
+    /// Reachable addresses that could not be decoded
+    pub errors: Vec<(Address, String)>,
+}
+
+/// The control flow edges out of an instruction, or None if execution simply
+/// continues with the next instruction.
+fn control_flow(addr: Address, instruction: &Instruction) -> Option<Vec<Edge>> {
+    let next = addr.wrapping_add(2);
+    let jump = |flag: AluFlagRef, target: Option<Address>| {
+        let mut edges = vec![Edge {
+            target,
+            kind: EdgeKind::Jump(flag),
+        }];
+        if flag != AluFlagRef::True {
+            edges.push(Edge {
+                target: Some(next),
+                kind: EdgeKind::NotTaken(flag),
+            });
+        }
+        edges
+    };
+    let call = |target: Option<Address>| {
+        vec![
+            Edge {
+                target,
+                kind: EdgeKind::Call,
+            },
+            Edge {
+                target: Some(next),
+                kind: EdgeKind::AfterCall,
+            },
+        ]
+    };
+
+    match instruction {
+        Instruction::Jmp {
+            flag: AluFlagRef::False,
+            ..
+        }
+        | Instruction::JmpP {
+            flag: AluFlagRef::False,
+            ..
+        }
+        | Instruction::JmpR {
+            flag: AluFlagRef::False,
+            ..
+        }
+        | Instruction::JmpRP {
+            flag: AluFlagRef::False,
+            ..
+        } => None,
+
+        Instruction::Jmp { flag, addr } => Some(jump(*flag, Some(*addr))),
+        Instruction::JmpR { flag, diff } => Some(jump(*flag, Some(addr.wrapping_add(*diff)))),
+        Instruction::JmpP { flag, .. } | Instruction::JmpRP { flag, .. } => Some(jump(*flag, None)),
+
+        Instruction::Stack(StackInstruction::CallC { addr }) => Some(call(Some(*addr))),
+        Instruction::Stack(StackInstruction::CallR { diff }) => {
+            Some(call(Some(addr.wrapping_add(*diff))))
+        }
+        Instruction::Stack(StackInstruction::Call { .. }) => Some(call(None)),
+
+        Instruction::Stack(StackInstruction::Ret { .. }) | Instruction::Nop(NopOpcode::Halt) => {
+            Some(Vec::new())
+        }
+
+        _ => None,
+    }
+}
+
+fn decode(program: &[Word], addr: Address) -> Result<Instruction, String> {
+    match program.get(addr as usize..addr as usize + 2) {
+        Some(words) => Instruction::try_from((words[0], words[1])),
+        None => Err(format!("Instruction pointer out of program: {}", addr)),
+    }
+}
+
+impl Cfg {
+    /// Recover the control flow graph of everything reachable from address 0,
+    /// following constant jump and call targets.
+    pub fn recover(program: &[Word]) -> Cfg {
+        let mut decoded: BTreeMap<Address, (Instruction, Option<Vec<Edge>>)> = BTreeMap::new();
+        let mut errors = Vec::new();
+        let mut leaders = BTreeSet::new();
+        let mut entries = BTreeSet::new();
+        leaders.insert(0);
+        entries.insert(0);
+
+        let mut worklist = vec![0];
+        while let Some(addr) = worklist.pop() {
+            if decoded.contains_key(&addr) || errors.iter().any(|(a, _)| *a == addr) {
+                continue;
+            }
+            let instruction = match decode(program, addr) {
+                Ok(instruction) => instruction,
+                Err(e) => {
+                    errors.push((addr, e));
+                    continue;
+                }
+            };
+            let edges = control_flow(addr, &instruction);
+            match &edges {
+                Some(edges) => {
+                    for edge in edges {
+                        if let Some(target) = edge.target {
+                            leaders.insert(target);
+                            worklist.push(target);
+                            if edge.kind == EdgeKind::Call {
+                                entries.insert(target);
+                            }
+                        }
+                    }
+                }
+                None => worklist.push(addr.wrapping_add(2)),
+            }
+            decoded.insert(addr, (instruction, edges));
+        }
+        errors.sort();
+
+        let mut blocks = BTreeMap::new();
+        for leader in leaders.iter().filter(|l| decoded.contains_key(l)) {
+            let mut block = BasicBlock {
+                start: *leader,
+                instructions: Vec::new(),
+                edges: Vec::new(),
+            };
+            let mut addr = *leader;
+            while let Some((instruction, edges)) = decoded.get(&addr) {
+                block.instructions.push((addr, instruction.clone()));
+                if let Some(edges) = edges {
+                    block.edges = edges.clone();
+                    break;
+                }
+                addr = addr.wrapping_add(2);
+                if leaders.contains(&addr) {
+                    block.edges.push(Edge {
+                        target: Some(addr),
+                        kind: EdgeKind::Next,
+                    });
+                    break;
+                }
+            }
+            blocks.insert(*leader, block);
+        }
+
+        let functions = entries
+            .into_iter()
+            .map(|entry| {
+                let mut function = Function {
+                    entry,
+                    blocks: BTreeSet::new(),
+                    callees: BTreeSet::new(),
+                };
+                let mut stack = vec![entry];
+                while let Some(start) = stack.pop() {
+                    let block = match blocks.get(&start) {
+                        Some(block) if function.blocks.insert(start) => block,
+                        _ => continue,
+                    };
+                    for edge in &block.edges {
+                        match (edge.kind, edge.target) {
+                            (EdgeKind::Call, Some(target)) => {
+                                function.callees.insert(target);
+                            }
+                            (EdgeKind::Call, None) => {}
+                            (_, Some(target)) => stack.push(target),
+                            (_, None) => {}
+                        }
+                    }
+                }
+                (entry, function)
+            })
+            .collect();
+
+        Cfg {
+            blocks,
+            functions,
+            errors,
+        }
+    }
+
+    /// Addresses of the instructions with a jump or call target computed at
+    /// run time.
+    pub fn unresolved(&self) -> Vec<Address> {
+        self.blocks
+            .values()
+            .filter(|block| block.edges.iter().any(Edge::is_unresolved))
+            .filter_map(|block| block.instructions.last().map(|(addr, _)| *addr))
+            .collect()
+    }
+
+    /// Render as a Graphviz digraph, with one cluster per function.
+    pub fn to_dot(&self) -> String {
+        let mut out = vec![
+            "digraph cfg {".to_string(),
+            "    node [shape=box, fontname=\"monospace\"];".to_string(),
+        ];
+
+        let mut emitted = BTreeSet::new();
+        for function in self.functions.values() {
+            out.push(format!("    subgraph cluster_{:03} {{", function.entry));
+            out.push(format!("        label=\"fn {:03}\";", function.entry));
+            for start in &function.blocks {
+                if emitted.insert(*start) {
+                    let block = &self.blocks[start];
+                    let label: String = block
+                        .instructions
+                        .iter()
+                        .map(|(addr, instruction)| {
+                            format!("{:03}: {}\\l", addr, instruction).replace('"', "\\\"")
+                        })
+                        .collect();
+                    out.push(format!("        b{:03} [label=\"{}\"];", start, label));
+                }
+            }
+            out.push("    }".to_string());
+        }
+
+        for (addr, e) in &self.errors {
+            out.push(format!(
+                "    b{:03} [label=\"{:03}: {}\", color=red];",
+                addr,
+                addr,
+                e.replace('"', "\\\"")
+            ));
+        }
+
+        for block in self.blocks.values() {
+            let from = block.start;
+            for edge in &block.edges {
+                let mut attributes = Vec::new();
+                let label = edge.label();
+                if !label.is_empty() {
+                    attributes.push(format!("label=\"{}\"", label));
+                }
+                match edge.kind {
+                    EdgeKind::Call => attributes.push("style=dashed".to_string()),
+                    EdgeKind::AfterCall => attributes.push("style=dotted".to_string()),
+                    _ => {}
+                }
+                let to = match edge.target {
+                    Some(target) => format!("b{:03}", target),
+                    None => {
+                        let node = format!("unresolved_{:03}", from);
+                        out.push(format!(
+                            "    {} [label=\"?\", shape=diamond, color=red];",
+                            node
+                        ));
+                        attributes.push("color=red".to_string());
+                        node
+                    }
+                };
+                out.push(format!(
+                    "    b{:03} -> {} [{}];",
+                    from,
+                    to,
+                    attributes.join(", ")
+                ));
+            }
+        }
+
+        out.push("}".to_string());
+        out.join("\n") + "\n"
+    }
+
+    /// A human readable overview of the functions, unresolved jumps and
+    /// decoding errors.
+    pub fn summary(&self) -> String {
+        let instruction_count = |blocks: &mut dyn Iterator<Item = &Address>| -> usize {
+            blocks
+                .map(|start| self.blocks[start].instructions.len())
+                .sum()
+        };
+
+        let mut out = vec![format!(
+            "{} functions, {} blocks, {} instructions",
+            self.functions.len(),
+            self.blocks.len(),
+            instruction_count(&mut self.blocks.keys()),
+        )];
+
+        for function in self.functions.values() {
+            let callees: Vec<String> = function
+                .callees
+                .iter()
+                .map(|c| format!("{:03}", c))
+                .collect();
+            out.push(format!(
+                "fn {:03}: {} blocks, {} instructions{}",
+                function.entry,
+                function.blocks.len(),
+                instruction_count(&mut function.blocks.iter()),
+                if callees.is_empty() {
+                    String::new()
+                } else {
+                    format!(", calls {}", callees.join(" "))
+                },
+            ));
+        }
+
+        for block in self.blocks.values() {
+            if block.edges.iter().any(Edge::is_unresolved) {
+                if let Some((addr, instruction)) = block.instructions.last() {
+                    out.push(format!("unresolved {:03}: {}", addr, instruction));
+                }
+            }
+        }
+
+        for (addr, e) in &self.errors {
+            out.push(format!("error {:03}: {}", addr, e));
+        }
+
+        out.join("\n") + "\n"
+    }
+}
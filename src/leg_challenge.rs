@@ -0,0 +1,13 @@
+/// Program image of the challenge, assembled by build.rs from
+/// challenge/*.leg.
+pub const CHALLENGE_PROGRAM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/challenge.bin"));
+
+/// Program image of a checker that compares the input with the flag as is,
+/// assembled by build.rs from challenge/plain.leg. It reads the end of the
+/// solution from byte 3 and uses no stack.
+pub const PLAIN_PROGRAM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/plain.bin"));
+
+/// Initial memory of the challenge, laid out by build.rs from
+/// challenge/memory.leg and flag.txt. The input goes at the address in byte
+/// 0, and byte 1 must be set to the end of the input.
+pub const CHALLENGE_MEMORY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/memory.bin"));
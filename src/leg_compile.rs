@@ -0,0 +1,1013 @@
+use super::leg_computer::Address;
+use super::leg_computer::AluFlagRef;
+use super::leg_computer::AluOpcode;
+use super::leg_computer::Instruction;
+use super::leg_computer::NopOpcode;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use super::leg_computer::Word;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Num(Word),
+    Ident(String),
+    Punct(&'static str),
+}
+
+const PUNCTUATION: [&str; 27] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "(", ")", "{", "}", "[", "]", ",", ";", "=",
+    "+", "-", "&", "|", "^", "~", "!", "<", ">", "@",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let line_num = i + 1;
+        let line = line.split("//").next().unwrap_or("");
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            let len = if rest.starts_with(|c: char| c.is_ascii_digit()) {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                let value = if let Some(hex) = word.strip_prefix("0x") {
+                    u8::from_str_radix(hex, 16)
+                } else {
+                    word.parse()
+                };
+                let value = value
+                    .map_err(|_| format!("Line {}: Invalid byte literal: {}", line_num, word))?;
+                tokens.push((line_num, Token::Num(value)));
+                len
+            } else if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                tokens.push((line_num, Token::Ident(rest[..len].to_string())));
+                len
+            } else if rest.starts_with('\'') {
+                let mut chars = rest.char_indices().skip(1);
+                match (chars.next(), chars.next()) {
+                    (Some((_, c)), Some((end, '\''))) if c.is_ascii() => {
+                        tokens.push((line_num, Token::Num(c as Word)));
+                        end + 1
+                    }
+                    _ => return Err(format!("Line {}: Invalid character literal", line_num)),
+                }
+            } else if let Some(punct) = PUNCTUATION.iter().find(|p| rest.starts_with(*p)) {
+                tokens.push((line_num, Token::Punct(punct)));
+                punct.len()
+            } else {
+                return Err(format!(
+                    "Line {}: Unexpected character: {}",
+                    line_num,
+                    rest.chars().next().unwrap()
+                ));
+            };
+            rest = rest[len..].trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Num(Word),
+    Var(String),
+    /// `base[index]`, the memory byte at `base + index`
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Input,
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Var(String, Expr),
+    Assign(Expr, Expr),
+    If(Expr, Vec<(usize, Stmt)>, Vec<(usize, Stmt)>),
+    While(Expr, Vec<(usize, Stmt)>),
+    Break,
+    Continue,
+    Return(Option<Expr>),
+    Halt,
+    Output(Expr),
+    Expr(Expr),
+}
+
+struct FnDecl {
+    line: usize,
+    name: String,
+    params: Vec<String>,
+    body: Vec<(usize, Stmt)>,
+}
+
+#[derive(Default)]
+struct Module {
+    functions: Vec<FnDecl>,
+    externs: HashMap<String, (Address, usize)>,
+    globals: HashMap<String, Address>,
+    consts: HashMap<String, Word>,
+}
+
+const KEYWORDS: [&str; 14] = [
+    "fn", "extern", "global", "const", "var", "if", "else", "while", "break", "continue", "return",
+    "halt", "in", "out",
+];
+
+/// Binary operators by precedence, loosest first
+const BINARY_OPS: [&[&str]; 9] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+];
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map(|(line, _)| *line)
+            .unwrap_or(0)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        match self.tokens.get(self.pos) {
+            Some((line, Token::Num(value))) => {
+                Err(format!("Line {}: {}, found {}", line, message, value))
+            }
+            Some((line, Token::Ident(word))) => {
+                Err(format!("Line {}: {}, found {}", line, message, word))
+            }
+            Some((line, Token::Punct(punct))) => {
+                Err(format!("Line {}: {}, found '{}'", line, message, punct))
+            }
+            None => Err(format!(
+                "Line {}: {}, found end of input",
+                self.line(),
+                message
+            )),
+        }
+    }
+
+    fn peek_punct(&self, punct: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some((_, Token::Punct(p))) if *p == punct)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some((_, Token::Ident(w))) if w == keyword)
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        let found = self.peek_punct(punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            self.error(&format!("Expected {}", punct))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.pos) {
+            Some((_, Token::Ident(name))) if !KEYWORDS.contains(&name.as_str()) => {
+                self.pos += 1;
+                Ok(name.clone())
+            }
+            _ => self.error("Expected a name"),
+        }
+    }
+
+    fn expect_num(&mut self) -> Result<Word, String> {
+        match self.tokens.get(self.pos) {
+            Some((_, Token::Num(value))) => {
+                self.pos += 1;
+                Ok(*value)
+            }
+            _ => self.error("Expected a number"),
+        }
+    }
+
+    fn params(&mut self) -> Result<Vec<String>, String> {
+        self.expect_punct("(")?;
+        let mut params = Vec::new();
+        if !self.eat_punct(")") {
+            loop {
+                params.push(self.expect_ident()?);
+                if self.eat_punct(")") {
+                    break;
+                }
+                self.expect_punct(",")?;
+            }
+        }
+        Ok(params)
+    }
+
+    fn module(&mut self) -> Result<Module, String> {
+        let mut module = Module::default();
+        while self.pos < self.tokens.len() {
+            let line = self.line();
+            if self.eat_keyword("fn") {
+                let name = self.expect_ident()?;
+                let params = self.params()?;
+                let body = self.block()?;
+                module.functions.push(FnDecl {
+                    line,
+                    name,
+                    params,
+                    body,
+                });
+            } else if self.eat_keyword("extern") {
+                if !self.eat_keyword("fn") {
+                    return self.error("Expected fn");
+                }
+                let name = self.expect_ident()?;
+                let params = self.params()?;
+                self.expect_punct("@")?;
+                let addr = self.expect_num()?;
+                self.expect_punct(";")?;
+                module.externs.insert(name, (addr, params.len()));
+            } else if self.eat_keyword("global") {
+                let name = self.expect_ident()?;
+                self.expect_punct("@")?;
+                let addr = self.expect_num()?;
+                self.expect_punct(";")?;
+                module.globals.insert(name, addr);
+            } else if self.eat_keyword("const") {
+                let name = self.expect_ident()?;
+                self.expect_punct("=")?;
+                let value = self.expect_num()?;
+                self.expect_punct(";")?;
+                module.consts.insert(name, value);
+            } else {
+                return self.error("Expected fn, extern, global or const");
+            }
+        }
+        Ok(module)
+    }
+
+    fn block(&mut self) -> Result<Vec<(usize, Stmt)>, String> {
+        self.expect_punct("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat_punct("}") {
+            stmts.push((self.line(), self.statement()?));
+        }
+        Ok(stmts)
+    }
+
+    fn condition(&mut self) -> Result<Expr, String> {
+        self.expect_punct("(")?;
+        let cond = self.expr()?;
+        self.expect_punct(")")?;
+        Ok(cond)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        let stmt = if self.eat_keyword("if") {
+            let cond = self.condition()?;
+            let then = self.block()?;
+            let otherwise = if self.eat_keyword("else") {
+                if self.peek_keyword("if") {
+                    vec![(self.line(), self.statement()?)]
+                } else {
+                    self.block()?
+                }
+            } else {
+                Vec::new()
+            };
+            return Ok(Stmt::If(cond, then, otherwise));
+        } else if self.eat_keyword("while") {
+            let cond = self.condition()?;
+            return Ok(Stmt::While(cond, self.block()?));
+        } else if self.eat_keyword("var") {
+            let name = self.expect_ident()?;
+            self.expect_punct("=")?;
+            Stmt::Var(name, self.expr()?)
+        } else if self.eat_keyword("break") {
+            Stmt::Break
+        } else if self.eat_keyword("continue") {
+            Stmt::Continue
+        } else if self.eat_keyword("return") {
+            if self.peek_punct(";") {
+                Stmt::Return(None)
+            } else {
+                Stmt::Return(Some(self.expr()?))
+            }
+        } else if self.eat_keyword("halt") {
+            Stmt::Halt
+        } else if self.eat_keyword("out") {
+            Stmt::Output(self.condition()?)
+        } else {
+            let expr = self.expr()?;
+            if self.eat_punct("=") {
+                match expr {
+                    Expr::Var(_) | Expr::Index(_, _) => Stmt::Assign(expr, self.expr()?),
+                    _ => {
+                        self.pos -= 1;
+                        return self.error("Expected a variable or index before =");
+                    }
+                }
+            } else {
+                Stmt::Expr(expr)
+            }
+        };
+        self.expect_punct(";")?;
+        Ok(stmt)
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == BINARY_OPS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = BINARY_OPS[level].iter().find(|op| self.peek_punct(op)) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        for op in &["-", "~", "!"] {
+            if self.eat_punct(op) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        let mut expr = self.primary()?;
+        while self.eat_punct("[") {
+            let index = self.expr()?;
+            self.expect_punct("]")?;
+            expr = Expr::Index(Box::new(expr), Box::new(index));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        if self.eat_punct("(") {
+            let expr = self.expr()?;
+            self.expect_punct(")")?;
+            return Ok(expr);
+        }
+        if let Some((_, Token::Num(value))) = self.tokens.get(self.pos) {
+            self.pos += 1;
+            return Ok(Expr::Num(*value));
+        }
+        if self.eat_keyword("in") {
+            self.expect_punct("(")?;
+            self.expect_punct(")")?;
+            return Ok(Expr::Input);
+        }
+        if !matches!(self.tokens.get(self.pos), Some((_, Token::Ident(_)))) {
+            return self.error("Expected an expression");
+        }
+        let name = self.expect_ident()?;
+        if self.peek_punct("(") {
+            self.pos += 1;
+            let mut args = Vec::new();
+            if !self.eat_punct(")") {
+                loop {
+                    args.push(self.expr()?);
+                    if self.eat_punct(")") {
+                        break;
+                    }
+                    self.expect_punct(",")?;
+                }
+            }
+            Ok(Expr::Call(name, args))
+        } else {
+            Ok(Expr::Var(name))
+        }
+    }
+}
+
+/// Instructions with jump and call targets still symbolic
+enum Item {
+    Ins(Instruction),
+    Label(usize),
+    Jump(AluFlagRef, usize),
+    Call(usize),
+}
+
+enum Callee {
+    Label(usize),
+    Addr(Address),
+}
+
+/// Where a variable lives
+enum Slot {
+    /// Relative to BP: parameters above it, locals below
+    Frame(Word),
+    Global(Address),
+    Const(Word),
+}
+
+struct FnContext {
+    params: Vec<String>,
+    locals: Vec<String>,
+    is_main: bool,
+    /// Continue and break labels of the enclosing loops
+    loops: Vec<(usize, usize)>,
+}
+
+struct Compiler<'a> {
+    module: &'a Module,
+    functions: HashMap<String, (Callee, usize)>,
+    items: Vec<Item>,
+    labels: usize,
+    line: usize,
+}
+
+use RegisterRef::A;
+use RegisterRef::B;
+use RegisterRef::C;
+
+fn alu(op: AluOpcode, arg1: RegisterRef, arg2: RegisterRef, out: RegisterRef) -> Instruction {
+    Instruction::Alu {
+        op,
+        arg1,
+        arg2,
+        out,
+    }
+}
+
+fn collect_locals(body: &[(usize, Stmt)], locals: &mut Vec<(usize, String)>) {
+    for (line, stmt) in body {
+        match stmt {
+            Stmt::Var(name, _) => locals.push((*line, name.clone())),
+            Stmt::If(_, then, otherwise) => {
+                collect_locals(then, locals);
+                collect_locals(otherwise, locals);
+            }
+            Stmt::While(_, body) => collect_locals(body, locals),
+            _ => {}
+        }
+    }
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, instruction: Instruction) {
+        self.items.push(Item::Ins(instruction));
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.items.push(Item::Label(label));
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, String> {
+        Err(format!("Line {}: {}", self.line, message))
+    }
+
+    fn slot(&self, ctx: &FnContext, name: &str) -> Result<Slot, String> {
+        if let Some(i) = ctx.locals.iter().position(|l| l == name) {
+            Ok(Slot::Frame((i as Word + 1).wrapping_neg()))
+        } else if let Some(i) = ctx.params.iter().position(|p| p == name) {
+            Ok(Slot::Frame((ctx.params.len() + 1 - i) as Word))
+        } else if let Some(addr) = self.module.globals.get(name) {
+            Ok(Slot::Global(*addr))
+        } else if let Some(value) = self.module.consts.get(name) {
+            Ok(Slot::Const(*value))
+        } else if name == "mem" {
+            Ok(Slot::Const(0))
+        } else {
+            self.error(format!("Unknown variable: {}", name))
+        }
+    }
+
+    /// The value of `expr` if it is known at compile time.
+    fn constant(&self, ctx: &FnContext, expr: &Expr) -> Option<Word> {
+        match expr {
+            Expr::Num(value) => Some(*value),
+            Expr::Var(name) => match self.slot(ctx, name) {
+                Ok(Slot::Const(value)) => Some(value),
+                _ => None,
+            },
+            Expr::Unary(op, e) => {
+                let value = self.constant(ctx, e)?;
+                match *op {
+                    "-" => Some(value.wrapping_neg()),
+                    "~" => Some(!value),
+                    _ => Some((value == 0) as Word),
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.constant(ctx, lhs)?, self.constant(ctx, rhs)?);
+                match *op {
+                    "+" => Some(lhs.wrapping_add(rhs)),
+                    "-" => Some(lhs.wrapping_sub(rhs)),
+                    "&" => Some(lhs & rhs),
+                    "|" => Some(lhs | rhs),
+                    "^" => Some(lhs ^ rhs),
+                    "<<" => Some(lhs << (rhs & 7)),
+                    ">>" => Some(((lhs as i8) >> (rhs & 7)) as Word),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The address expression of `base[index]`.
+    fn address(&self, ctx: &FnContext, base: &Expr, index: &Expr) -> Expr {
+        if self.constant(ctx, base) == Some(0) {
+            index.clone()
+        } else if self.constant(ctx, index) == Some(0) {
+            base.clone()
+        } else {
+            Expr::Binary("+", Box::new(base.clone()), Box::new(index.clone()))
+        }
+    }
+
+    /// Load a constant or variable into `dest` without touching other
+    /// registers. Returns false if `expr` is not that simple.
+    fn load_simple(
+        &mut self,
+        ctx: &FnContext,
+        expr: &Expr,
+        dest: RegisterRef,
+    ) -> Result<bool, String> {
+        if let Some(val) = self.constant(ctx, expr) {
+            self.emit(Instruction::MovC { dest, val });
+            return Ok(true);
+        }
+        if let Expr::Var(name) = expr {
+            match self.slot(ctx, name)? {
+                Slot::Frame(bp_diff) => {
+                    self.emit(Instruction::Stack(StackInstruction::Load { dest, bp_diff }))
+                }
+                Slot::Global(addr) => self.emit(Instruction::Load { dest, addr }),
+                Slot::Const(val) => self.emit(Instruction::MovC { dest, val }),
+            }
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Evaluate `lhs` into A and `rhs` into B.
+    fn operands(&mut self, ctx: &mut FnContext, lhs: &Expr, rhs: &Expr) -> Result<(), String> {
+        if self.constant(ctx, rhs).is_some() || matches!(rhs, Expr::Var(_)) {
+            self.eval(ctx, lhs)?;
+            self.load_simple(ctx, rhs, B)?;
+        } else if self.constant(ctx, lhs).is_some() || matches!(lhs, Expr::Var(_)) {
+            self.eval(ctx, rhs)?;
+            self.emit(Instruction::Mov { dest: B, src: A });
+            self.load_simple(ctx, lhs, A)?;
+        } else {
+            self.eval(ctx, rhs)?;
+            self.emit(Instruction::Stack(StackInstruction::Push { src: A }));
+            self.eval(ctx, lhs)?;
+            self.emit(Instruction::Stack(StackInstruction::Pop { dest: B }));
+        }
+        Ok(())
+    }
+
+    /// Evaluate `expr` into A. May overwrite B, C and D.
+    fn eval(&mut self, ctx: &mut FnContext, expr: &Expr) -> Result<(), String> {
+        if self.load_simple(ctx, expr, A)? {
+            return Ok(());
+        }
+        match expr {
+            Expr::Num(_) | Expr::Var(_) => unreachable!(),
+            Expr::Index(base, index) => {
+                let addr = self.address(ctx, base, index);
+                match self.constant(ctx, &addr) {
+                    Some(addr) => self.emit(Instruction::Load { dest: A, addr }),
+                    None => {
+                        self.eval(ctx, &addr)?;
+                        self.emit(Instruction::LoadP {
+                            dest: A,
+                            addr_src: A,
+                        });
+                    }
+                }
+            }
+            Expr::Call(name, args) => self.call(ctx, name, args)?,
+            Expr::Input => self.emit(Instruction::Gpi { dest: A }),
+            Expr::Unary("-", e) => {
+                self.eval(ctx, e)?;
+                self.emit(Instruction::MovC { dest: B, val: 0 });
+                self.emit(alu(AluOpcode::Sub, B, A, A));
+            }
+            Expr::Unary("~", e) => {
+                self.eval(ctx, e)?;
+                self.emit(alu(AluOpcode::Neg, A, A, A));
+            }
+            Expr::Binary(op, lhs, rhs)
+                if ["+", "-"].contains(op) && self.constant(ctx, rhs) == Some(1) =>
+            {
+                self.eval(ctx, lhs)?;
+                let op = if *op == "+" {
+                    AluOpcode::Incr
+                } else {
+                    AluOpcode::Decr
+                };
+                self.emit(alu(op, A, A, A));
+            }
+            Expr::Binary(op, lhs, rhs) if ["+", "-", "&", "|", "^", "<<", ">>"].contains(op) => {
+                self.operands(ctx, lhs, rhs)?;
+                let op = match *op {
+                    "+" => AluOpcode::Add,
+                    "-" => AluOpcode::Sub,
+                    "&" => AluOpcode::And,
+                    "|" => AluOpcode::Or,
+                    "^" => AluOpcode::Xor,
+                    "<<" => AluOpcode::ShiftL,
+                    _ => AluOpcode::ShiftR,
+                };
+                self.emit(alu(op, A, B, A));
+            }
+            _ => {
+                // Comparisons and logic evaluate to 0 or 1
+                let (zero, end) = (self.new_label(), self.new_label());
+                self.branch(ctx, expr, zero, false)?;
+                self.emit(Instruction::MovC { dest: A, val: 1 });
+                self.items.push(Item::Jump(AluFlagRef::True, end));
+                self.place(zero);
+                self.emit(Instruction::MovC { dest: A, val: 0 });
+                self.place(end);
+            }
+        }
+        Ok(())
+    }
+
+    /// Jump to `target` if `expr` is nonzero, or if it is zero when not
+    /// `jump_if`. Falls through otherwise.
+    fn branch(
+        &mut self,
+        ctx: &mut FnContext,
+        expr: &Expr,
+        target: usize,
+        jump_if: bool,
+    ) -> Result<(), String> {
+        let compare = |op: &str| match op {
+            "==" => Some(AluFlagRef::Equal),
+            "!=" => Some(AluFlagRef::NotEqual),
+            "<" => Some(AluFlagRef::LessThan),
+            "<=" => Some(AluFlagRef::LessOrEqual),
+            ">" => Some(AluFlagRef::GreaterThan),
+            ">=" => Some(AluFlagRef::GreaterOrEqual),
+            _ => None,
+        };
+        match expr {
+            Expr::Unary("!", e) => self.branch(ctx, e, target, !jump_if)?,
+            Expr::Binary(op, lhs, rhs) if *op == "&&" || *op == "||" => {
+                // Short circuit: for && the first false operand decides, for ||
+                // the first true one
+                let decides = *op == "||";
+                if decides == jump_if {
+                    self.branch(ctx, lhs, target, jump_if)?;
+                    self.branch(ctx, rhs, target, jump_if)?;
+                } else {
+                    let skip = self.new_label();
+                    self.branch(ctx, lhs, skip, decides)?;
+                    self.branch(ctx, rhs, target, jump_if)?;
+                    self.place(skip);
+                }
+            }
+            Expr::Binary(op, lhs, rhs) if compare(op).is_some() => {
+                self.operands(ctx, lhs, rhs)?;
+                self.emit(alu(AluOpcode::Echo, A, B, A));
+                let flag = compare(op).unwrap();
+                let flag = if jump_if {
+                    flag
+                } else {
+                    flag.negate().unwrap()
+                };
+                self.items.push(Item::Jump(flag, target));
+            }
+            _ => {
+                self.eval(ctx, expr)?;
+                if jump_if {
+                    self.emit(Instruction::MovC { dest: B, val: 0 });
+                    self.emit(alu(AluOpcode::Echo, A, B, A));
+                    self.items.push(Item::Jump(AluFlagRef::NotEqual, target));
+                } else {
+                    self.emit(alu(AluOpcode::Echo, A, A, A));
+                    self.items.push(Item::Jump(AluFlagRef::EqZero, target));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Push the arguments in order, call, and leave the return value in A.
+    /// The callee finds the last argument at stack offset 2.
+    fn call(&mut self, ctx: &mut FnContext, name: &str, args: &[Expr]) -> Result<(), String> {
+        let arity = match self.functions.get(name) {
+            Some((_, arity)) => *arity,
+            None => return self.error(format!("Unknown function: {}", name)),
+        };
+        if arity != args.len() {
+            return self.error(format!(
+                "{} takes {} arguments, but {} were given",
+                name,
+                arity,
+                args.len()
+            ));
+        }
+        for arg in args {
+            self.eval(ctx, arg)?;
+            self.emit(Instruction::Stack(StackInstruction::Push { src: A }));
+        }
+        let call = match self.functions[name].0 {
+            Callee::Label(label) => Item::Call(label),
+            Callee::Addr(addr) => Item::Ins(Instruction::Stack(StackInstruction::CallC { addr })),
+        };
+        self.items.push(call);
+        self.emit(Instruction::Stack(StackInstruction::Pop { dest: A }));
+        for _ in args {
+            self.emit(Instruction::Stack(StackInstruction::Pop { dest: B }));
+        }
+        Ok(())
+    }
+
+    /// Store A in the variable `name`.
+    fn store(&mut self, ctx: &FnContext, name: &str) -> Result<(), String> {
+        match self.slot(ctx, name)? {
+            Slot::Frame(bp_diff) => {
+                self.emit(Instruction::Mov {
+                    dest: B,
+                    src: RegisterRef::BP,
+                });
+                match bp_diff {
+                    1 => self.emit(alu(AluOpcode::Incr, B, B, B)),
+                    255 => self.emit(alu(AluOpcode::Decr, B, B, B)),
+                    _ => {
+                        self.emit(Instruction::MovC {
+                            dest: C,
+                            val: bp_diff,
+                        });
+                        self.emit(alu(AluOpcode::Add, B, C, B));
+                    }
+                }
+                self.emit(Instruction::StoreP {
+                    src: A,
+                    addr_src: B,
+                });
+            }
+            Slot::Global(addr) => self.emit(Instruction::Store { src: A, addr }),
+            Slot::Const(_) => return self.error(format!("Cannot assign to constant {}", name)),
+        }
+        Ok(())
+    }
+
+    fn statements(&mut self, ctx: &mut FnContext, body: &[(usize, Stmt)]) -> Result<(), String> {
+        for (line, stmt) in body {
+            self.line = *line;
+            self.statement(ctx, stmt)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, ctx: &mut FnContext, stmt: &Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::Var(name, value) | Stmt::Assign(Expr::Var(name), value) => {
+                self.eval(ctx, value)?;
+                self.store(ctx, name)?;
+            }
+            Stmt::Assign(Expr::Index(base, index), value) => {
+                let addr = self.address(ctx, base, index);
+                if let Some(addr) = self.constant(ctx, &addr) {
+                    self.eval(ctx, value)?;
+                    self.emit(Instruction::Store { src: A, addr });
+                } else if matches!(addr, Expr::Var(_)) {
+                    self.eval(ctx, value)?;
+                    self.load_simple(ctx, &addr, B)?;
+                    self.emit(Instruction::StoreP {
+                        src: A,
+                        addr_src: B,
+                    });
+                } else {
+                    self.operands(ctx, &addr, value)?;
+                    self.emit(Instruction::StoreP {
+                        src: B,
+                        addr_src: A,
+                    });
+                }
+            }
+            Stmt::Assign(_, _) => unreachable!(),
+            Stmt::If(cond, then, otherwise) => {
+                let (skip, end) = (self.new_label(), self.new_label());
+                self.branch(ctx, cond, skip, false)?;
+                self.statements(ctx, then)?;
+                if !otherwise.is_empty() {
+                    self.items.push(Item::Jump(AluFlagRef::True, end));
+                }
+                self.place(skip);
+                self.statements(ctx, otherwise)?;
+                self.place(end);
+            }
+            Stmt::While(cond, body) => {
+                let (top, end) = (self.new_label(), self.new_label());
+                self.place(top);
+                self.branch(ctx, cond, end, false)?;
+                ctx.loops.push((top, end));
+                self.statements(ctx, body)?;
+                ctx.loops.pop();
+                self.items.push(Item::Jump(AluFlagRef::True, top));
+                self.place(end);
+            }
+            Stmt::Break | Stmt::Continue => match ctx.loops.last() {
+                Some((top, end)) => {
+                    let target = if let Stmt::Break = stmt { *end } else { *top };
+                    self.items.push(Item::Jump(AluFlagRef::True, target));
+                }
+                None => return self.error("break or continue outside a loop".to_string()),
+            },
+            Stmt::Return(value) => {
+                if ctx.is_main {
+                    self.emit(Instruction::Nop(NopOpcode::Halt));
+                } else {
+                    self.eval(ctx, value.as_ref().unwrap_or(&Expr::Num(0)))?;
+                    self.emit(Instruction::Stack(StackInstruction::Ret { src: A }));
+                }
+            }
+            Stmt::Halt => self.emit(Instruction::Nop(NopOpcode::Halt)),
+            Stmt::Output(value) => {
+                self.eval(ctx, value)?;
+                self.emit(Instruction::Gpo { src: A });
+            }
+            Stmt::Expr(value) => self.eval(ctx, value)?,
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, decl: &FnDecl) -> Result<(), String> {
+        self.line = decl.line;
+        let mut locals = Vec::new();
+        collect_locals(&decl.body, &mut locals);
+        for (i, (line, name)) in locals.iter().enumerate() {
+            if decl.params.contains(name) || locals[..i].iter().any(|(_, l)| l == name) {
+                return Err(format!("Line {}: {} is already declared", line, name));
+            }
+        }
+        let mut ctx = FnContext {
+            params: decl.params.clone(),
+            locals: locals.into_iter().map(|(_, name)| name).collect(),
+            is_main: decl.name == "main",
+            loops: Vec::new(),
+        };
+
+        if let (Callee::Label(label), _) = self.functions[&decl.name] {
+            self.place(label);
+        }
+        // Declarations at the start of the body push their values straight
+        // into their slots, the other slots are reserved with whatever A holds
+        let leading = decl
+            .body
+            .iter()
+            .take_while(|(_, stmt)| matches!(stmt, Stmt::Var(_, _)))
+            .count();
+        for (line, stmt) in &decl.body[..leading] {
+            if let Stmt::Var(_, value) = stmt {
+                self.line = *line;
+                self.eval(&mut ctx, value)?;
+                self.emit(Instruction::Stack(StackInstruction::Push { src: A }));
+            }
+        }
+        for _ in leading..ctx.locals.len() {
+            self.emit(Instruction::Stack(StackInstruction::Push { src: A }));
+        }
+        self.statements(&mut ctx, &decl.body[leading..])?;
+        if ctx.is_main {
+            self.emit(Instruction::Nop(NopOpcode::Halt));
+        } else {
+            self.emit(Instruction::Stack(StackInstruction::Ret { src: A }));
+        }
+        Ok(())
+    }
+}
+
+/// Compile a program in a small C-like language of byte values to LEG.
+///
+/// A program is a list of declarations:
+///
+/// - `fn name(a, b) { ... }` defines a function. `main`, if defined, is
+///   placed first so that it runs from address 0, and halts when it returns.
+/// - `extern fn name(a, b) @ ADDR;` declares a handwritten routine at ADDR.
+/// - `global name @ ADDR;` names the memory byte at ADDR.
+/// - `const NAME = VALUE;` names a constant.
+///
+/// Statements are `var x = e;`, assignments to variables and to memory as
+/// `base[i] = e;`, `if`/`else`, `while`, `break`, `continue`, `return`,
+/// `halt;` and `out(e);`. Expressions have C's operators on unsigned bytes
+/// except `*`, `/` and `%`, calls, `in()`, and `base[i]` for the memory byte
+/// at `base + i`. The exception is `>>`, which is the machine's arithmetic
+/// SHIFTR and copies the top bit. `mem[i]` is the byte at address `i`.
+/// Comments start with `//`.
+///
+/// Functions use the same convention as the handwritten routines: the caller
+/// pushes the arguments in order, so the last is at stack offset 2, and pops
+/// the return value and then the arguments after the call. Locals live below
+/// BP, and hold unspecified values until their `var` statement runs. A
+/// function that ends without `return` returns an unspecified value. Calls
+/// between compiled functions are relative, so the result can be placed
+/// anywhere, but `extern` addresses are absolute.
+pub fn compile(source: &str) -> Result<Vec<Instruction>, String> {
+    let module = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+    }
+    .module()?;
+
+    let mut order: Vec<&FnDecl> = module.functions.iter().collect();
+    order.sort_by_key(|decl| decl.name != "main");
+
+    let mut compiler = Compiler {
+        module: &module,
+        functions: HashMap::new(),
+        items: Vec::new(),
+        labels: 0,
+        line: 0,
+    };
+    for (name, (addr, arity)) in &module.externs {
+        compiler
+            .functions
+            .insert(name.clone(), (Callee::Addr(*addr), *arity));
+    }
+    for decl in &order {
+        let label = compiler.new_label();
+        let previous = compiler
+            .functions
+            .insert(decl.name.clone(), (Callee::Label(label), decl.params.len()));
+        if previous.is_some() {
+            return Err(format!(
+                "Line {}: Function {} is already declared",
+                decl.line, decl.name
+            ));
+        }
+    }
+    for decl in order {
+        compiler.function(decl)?;
+    }
+
+    let mut addresses = vec![0; compiler.labels];
+    let mut addr = 0;
+    for item in &compiler.items {
+        match item {
+            Item::Label(label) => addresses[*label] = addr,
+            _ => addr += 2,
+        }
+    }
+    if addr > 256 {
+        return Err(format!(
+            "Program is {} bytes, more than fits in memory",
+            addr
+        ));
+    }
+
+    let mut program = Vec::new();
+    for item in compiler.items {
+        let here = (program.len() * 2) as Word;
+        let diff = |label: usize| (addresses[label] as Word).wrapping_sub(here);
+        match item {
+            Item::Ins(instruction) => program.push(instruction),
+            Item::Label(_) => {}
+            Item::Jump(flag, label) => program.push(Instruction::JmpR {
+                flag,
+                diff: diff(label),
+            }),
+            Item::Call(label) => program.push(Instruction::Stack(StackInstruction::CallR {
+                diff: diff(label),
+            })),
+        }
+    }
+    Ok(program)
+}
@@ -191,6 +191,28 @@ impl TryFrom<Word> for AluFlagRef {
     }
 }
 
+impl AluFlagRef {
+    /// The flag that is set exactly when this one isn't, if there is one.
+    /// The overflow and zero flags have no complement.
+    pub fn negate(&self) -> Option<AluFlagRef> {
+        match self {
+            Self::Equal => Some(Self::NotEqual),
+            Self::GreaterThan => Some(Self::LessOrEqual),
+            Self::GreaterThanSigned => Some(Self::LessOrEqualSigned),
+            Self::GreaterOrEqual => Some(Self::LessThan),
+            Self::GreaterOrEqualSigned => Some(Self::LessThanSigned),
+            Self::NotEqual => Some(Self::Equal),
+            Self::LessThan => Some(Self::GreaterOrEqual),
+            Self::LessThanSigned => Some(Self::GreaterOrEqualSigned),
+            Self::LessOrEqual => Some(Self::GreaterThan),
+            Self::LessOrEqualSigned => Some(Self::GreaterThanSigned),
+            Self::False => Some(Self::True),
+            Self::True => Some(Self::False),
+            Self::EqZero | Self::OverflowUnsigned | Self::OverflowSigned => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AluFlags {
     pub eq_zero: bool,
@@ -229,7 +251,7 @@ impl AluFlags {
         }
     }
 
-    fn get(&self, flag: &AluFlagRef) -> bool {
+    pub fn get(&self, flag: &AluFlagRef) -> bool {
         match *flag {
             AluFlagRef::EqZero => self.eq_zero,
             AluFlagRef::OverflowUnsigned => self.overflow_unsigned,
@@ -264,6 +286,23 @@ impl AluFlags {
                 0
             })
     }
+
+    fn set_word(&mut self, w: Word) {
+        self.eq_zero = w & 0x1 != 0;
+        self.overflow_unsigned = w & 0x2 != 0;
+        self.overflow_signed = w & 0x4 != 0;
+        self.equal = w & 0x8 != 0;
+        self.greater_than = w & 0x10 != 0;
+        self.greater_than_signed = w & 0x20 != 0;
+        self.greater_or_equal = w & 0x40 != 0;
+        self.greater_or_equal_signed = w & 0x80 != 0;
+
+        self.not_equal = !self.equal;
+        self.less_than = !self.greater_or_equal;
+        self.less_than_signed = !self.greater_or_equal_signed;
+        self.less_or_equal = !self.greater_than;
+        self.less_or_equal_signed = !self.greater_than_signed;
+    }
 }
 
 impl Display for AluFlags {
@@ -316,7 +355,7 @@ impl Registers {
         *self
             .values
             .get(reg)
-            .expect(&format!("Register not set: {:?}", reg))
+            .unwrap_or_else(|| panic!("Register not set: {:?}", reg))
     }
 
     fn get_mut(&mut self, reg: RegisterRef) -> &mut Word {
@@ -339,7 +378,7 @@ impl Display for Registers {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Instruction {
     Load {
         dest: RegisterRef,
@@ -459,7 +498,7 @@ impl TryFrom<Word> for NopOpcode {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum StackInstruction {
     Push { src: RegisterRef },
     Pop { dest: RegisterRef },
@@ -470,12 +509,12 @@ pub enum StackInstruction {
     Ret { src: RegisterRef },
 }
 
-impl Into<(Word, Word)> for &StackInstruction {
-    fn into(self) -> (Word, Word) {
+impl From<&StackInstruction> for (Word, Word) {
+    fn from(stack_ins: &StackInstruction) -> (Word, Word) {
         fn cat(op: StackOpcode, arg: Word) -> (Word, Word) {
             (((Opcode::Stack as u8) << 4) | (op as u8), arg)
         }
-        match self {
+        match stack_ins {
             StackInstruction::Push { src } => cat(StackOpcode::Push, *src as u8),
             StackInstruction::Pop { dest } => cat(StackOpcode::Pop, *dest as u8),
             StackInstruction::Call { addr_reg } => cat(StackOpcode::Call, *addr_reg as u8),
@@ -604,8 +643,8 @@ impl TryFrom<(Word, Word)> for Instruction {
     }
 }
 
-impl Into<(Word, Word)> for &Instruction {
-    fn into(self) -> (Word, Word) {
+impl From<&Instruction> for (Word, Word) {
+    fn from(ins: &Instruction) -> (Word, Word) {
         fn pack(opcode: Opcode, word1_tail: &RegisterRef, word2: Word) -> (Word, Word) {
             (((opcode as u8) << 4) | (*word1_tail as u8), word2)
         }
@@ -618,7 +657,7 @@ impl Into<(Word, Word)> for &Instruction {
             (((opcode as u8) << 4), word2)
         }
 
-        match self {
+        match ins {
             Instruction::Load { dest, addr } => pack(Opcode::Load, dest, *addr),
             Instruction::LoadP { dest, addr_src } => pack(Opcode::LoadP, dest, *addr_src as u8),
 
@@ -635,7 +674,7 @@ impl Into<(Word, Word)> for &Instruction {
 
             Instruction::Stack(stack_ins) => stack_ins.into(),
 
-            Instruction::Gpi { dest } => ((Opcode::Gpio as u8) << 4 | 0x0, *dest as u8),
+            Instruction::Gpi { dest } => ((Opcode::Gpio as u8) << 4, *dest as u8),
             Instruction::Gpo { src } => ((Opcode::Gpio as u8) << 4 | 0x1, *src as u8),
 
             Instruction::Alu {
@@ -653,6 +692,13 @@ impl Into<(Word, Word)> for &Instruction {
     }
 }
 
+/// Data memory addresses that an instruction reads and writes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryAccesses {
+    pub reads: Vec<Address>,
+    pub writes: Vec<Address>,
+}
+
 #[derive(Clone, Debug)]
 pub struct LegComputer {
     pub eip: Word,
@@ -668,21 +714,19 @@ impl Display for LegComputer {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         write!(
             f,
-            "{eip:03} {regs} {flags} [{reg_i} {reg_o}]\n",
+            "{eip:03} {regs} {flags} [{reg_i} {reg_o}]",
             eip = self.eip,
             regs = self.registers,
             flags = self.flags,
             reg_i = self.reg_i,
             reg_o = self.reg_o,
         )?;
+        writeln!(f)?;
 
-        let instruction = Instruction::try_from((
-            self.program[self.eip as usize],
-            self.program[self.eip as usize + 1],
-        ))
-        .unwrap();
-
-        write!(f, "{:?}\n", instruction)?;
+        match self.current_instruction() {
+            Ok(instruction) => writeln!(f, "{}", instruction)?,
+            Err(e) => writeln!(f, "<{}>", e)?,
+        }
 
         for (i, v) in self.memory.iter().enumerate() {
             if i % 8 == 0 {
@@ -710,16 +754,16 @@ impl Display for LegComputer {
 
 fn to_bytes(a: u8) -> [bool; 8] {
     let mut o = [false; 8];
-    for i in 0..8 {
-        o[i] = ((a >> i) & 0x01) == 0x01;
+    for (i, bit) in o.iter_mut().enumerate() {
+        *bit = ((a >> i) & 0x01) == 0x01;
     }
     o
 }
 
 fn from_bytes(a: [bool; 8]) -> u8 {
     let mut o = 0;
-    for i in 0..8 {
-        if a[i] {
+    for (i, bit) in a.iter().enumerate() {
+        if *bit {
             o |= 1 << i;
         }
     }
@@ -755,13 +799,18 @@ impl LegComputer {
         }
     }
 
+    pub fn current_instruction(&self) -> Result<Instruction, String> {
+        match (
+            self.program.get(self.eip as usize),
+            self.program.get(self.eip as usize + 1),
+        ) {
+            (Some(word1), Some(word2)) => Instruction::try_from((*word1, *word2)),
+            _ => Err(format!("Instruction pointer out of program: {}", self.eip)),
+        }
+    }
+
     pub fn is_halted(&self) -> bool {
-        let instruction = Instruction::try_from((
-            self.program[self.eip as usize],
-            self.program[self.eip as usize + 1],
-        ))
-        .unwrap();
-        instruction == Instruction::Nop(NopOpcode::Halt)
+        self.current_instruction() == Ok(Instruction::Nop(NopOpcode::Halt))
     }
 
     pub fn run(mut self) -> Self {
@@ -779,7 +828,19 @@ impl LegComputer {
         }
     }
 
-    fn stack_push(&mut self, value: Word) -> () {
+    /// Set a register from outside the program, e.g. from a debugger.
+    ///
+    /// Unlike a `MOV` into FL or IP, this does take effect: FL sets the first
+    /// 8 flags and derives the rest, and IP sets `eip`.
+    pub fn write_register(&mut self, register: RegisterRef, value: Word) {
+        match register {
+            RegisterRef::FL => self.flags.set_word(value),
+            RegisterRef::IP => self.eip = value,
+            _ => *self.registers.get_mut(register) = value,
+        }
+    }
+
+    fn stack_push(&mut self, value: Word) {
         let new_st = ((self.read_register(&RegisterRef::ST) as u16 + 255) & 0xff) as u8;
         *self.registers.get_mut(RegisterRef::ST) = new_st;
         self.memory[new_st as usize] = value;
@@ -792,7 +853,7 @@ impl LegComputer {
         result
     }
 
-    fn call(&mut self, addr: Word) -> () {
+    fn call(&mut self, addr: Word) {
         self.stack_push(self.eip);
         self.stack_push(self.read_register(&RegisterRef::BP));
         let current_st = self.read_register(&RegisterRef::ST);
@@ -800,61 +861,96 @@ impl LegComputer {
         self.eip = addr;
     }
 
-    pub fn step(&mut self) -> () {
-        let instruction = Instruction::try_from((
-            self.program[self.eip as usize],
-            self.program[self.eip as usize + 1],
-        ))
-        .unwrap();
+    /// The data memory accesses that the current instruction will make when
+    /// stepped, without executing it.
+    pub fn memory_accesses(&self) -> Result<MemoryAccesses, String> {
+        let reg = |r: &RegisterRef| self.read_register(r);
+        let st = reg(&RegisterRef::ST);
+        let bp = reg(&RegisterRef::BP);
+        let (reads, writes) = match self.current_instruction()? {
+            Instruction::Load { addr, .. } => (vec![addr], vec![]),
+            Instruction::LoadP { addr_src, .. } => (vec![reg(&addr_src)], vec![]),
+            Instruction::Store { addr, .. } => (vec![], vec![addr]),
+            Instruction::StoreP { addr_src, .. } => (vec![], vec![reg(&addr_src)]),
+            Instruction::JmpP { flag, addr_src } if self.flags.get(&flag) => {
+                (vec![reg(&addr_src)], vec![])
+            }
+            Instruction::JmpRP { flag, diff_src } if self.flags.get(&flag) => {
+                (vec![reg(&diff_src)], vec![])
+            }
+            Instruction::Stack(StackInstruction::Push { .. }) => (vec![], vec![st.wrapping_sub(1)]),
+            Instruction::Stack(StackInstruction::Pop { .. }) => (vec![st], vec![]),
+            Instruction::Stack(StackInstruction::Call { .. })
+            | Instruction::Stack(StackInstruction::CallC { .. })
+            | Instruction::Stack(StackInstruction::CallR { .. }) => {
+                (vec![], vec![st.wrapping_sub(1), st.wrapping_sub(2)])
+            }
+            Instruction::Stack(StackInstruction::Ret { .. }) => {
+                (vec![bp, bp.wrapping_add(1)], vec![bp.wrapping_add(1)])
+            }
+            Instruction::Stack(StackInstruction::Load { bp_diff, .. }) => {
+                (vec![bp.wrapping_add(bp_diff)], vec![])
+            }
+            _ => (vec![], vec![]),
+        };
+        Ok(MemoryAccesses { reads, writes })
+    }
+
+    pub fn step(&mut self) {
+        self.try_step().unwrap()
+    }
+
+    pub fn try_step(&mut self) -> Result<(), String> {
+        let instruction = self.current_instruction()?;
 
         match instruction {
             Instruction::Load { dest, addr } => {
                 *self.registers.get_mut(dest) = self.memory[addr as usize];
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
             Instruction::LoadP { dest, addr_src } => {
                 *self.registers.get_mut(dest) = self.memory[self.read_register(&addr_src) as usize];
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
 
             Instruction::Store { src, addr } => {
                 self.memory[addr as usize] = self.read_register(&src);
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
             Instruction::StoreP { src, addr_src } => {
                 let mem_index = self.read_register(&addr_src) as usize;
                 self.memory[mem_index] = self.read_register(&src);
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
 
             Instruction::Mov { src, dest } => {
                 *self.registers.get_mut(dest) = self.read_register(&src);
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
             Instruction::MovC { dest, val } => {
                 *self.registers.get_mut(dest) = val;
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
 
             Instruction::Jmp { flag, addr } => {
                 if self.flags.get(&flag) {
                     self.eip = addr;
                 } else {
-                    self.eip += 2;
+                    self.eip = self.eip.wrapping_add(2);
                 }
             }
             Instruction::JmpP { flag, addr_src } => {
                 if self.flags.get(&flag) {
                     self.eip = self.memory[self.read_register(&addr_src) as usize];
                 } else {
-                    self.eip += 2;
+                    self.eip = self.eip.wrapping_add(2);
                 }
             }
             Instruction::JmpR { flag, diff } => {
                 if self.flags.get(&flag) {
                     self.eip = (self.eip as i16 + diff as i16) as u8;
                 } else {
-                    self.eip += 2;
+                    self.eip = self.eip.wrapping_add(2);
                 }
             }
             Instruction::JmpRP { flag, diff_src } => {
@@ -863,7 +959,7 @@ impl LegComputer {
                         + self.memory[self.read_register(&diff_src) as usize] as i16)
                         as u8;
                 } else {
-                    self.eip += 2;
+                    self.eip = self.eip.wrapping_add(2);
                 }
             }
 
@@ -876,16 +972,16 @@ impl LegComputer {
                     let stored_ip = self.stack_pop();
                     *self.registers.get_mut(RegisterRef::BP) = stored_bp;
                     self.stack_push(self.read_register(&src));
-                    self.eip = stored_ip + 2;
+                    self.eip = stored_ip.wrapping_add(2);
                 }
                 StackInstruction::Push { src } => {
                     self.stack_push(self.read_register(&src));
-                    self.eip += 2;
+                    self.eip = self.eip.wrapping_add(2);
                 }
                 StackInstruction::Pop { dest } => {
                     let value = self.stack_pop();
                     *self.registers.get_mut(dest) = value;
-                    self.eip += 2;
+                    self.eip = self.eip.wrapping_add(2);
                 }
                 StackInstruction::Call { addr_reg } => {
                     self.call(self.read_register(&addr_reg));
@@ -900,17 +996,17 @@ impl LegComputer {
                     let current_bp = self.read_register(&RegisterRef::BP);
                     let load_addr = ((current_bp as i16 + bp_diff as i16 + 256) % 256) as u8;
                     *self.registers.get_mut(dest) = self.memory[load_addr as usize];
-                    self.eip += 2;
+                    self.eip = self.eip.wrapping_add(2);
                 }
             },
 
             Instruction::Gpi { dest } => {
                 *self.registers.get_mut(dest) = self.reg_i;
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
             Instruction::Gpo { src } => {
                 self.reg_o = self.read_register(&src);
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
 
             Instruction::Alu {
@@ -926,7 +1022,7 @@ impl LegComputer {
                     AluOpcode::Add => {
                         let (o, ofl_u, ofl_s) = add_8bit(arg1, arg2, false);
 
-                        *self.registers.get_mut(out) = from_bytes(o) as u8;
+                        *self.registers.get_mut(out) = from_bytes(o);
                         self.flags.overflow_unsigned = ofl_u;
                         self.flags.overflow_signed = ofl_s;
                     }
@@ -934,7 +1030,7 @@ impl LegComputer {
                     AluOpcode::AddCarry => {
                         let (o, ofl_u, ofl_s) = add_8bit(arg1, arg2, true);
 
-                        *self.registers.get_mut(out) = from_bytes(o) as u8;
+                        *self.registers.get_mut(out) = from_bytes(o);
                         self.flags.overflow_unsigned = ofl_u;
                         self.flags.overflow_signed = ofl_s;
                     }
@@ -946,7 +1042,7 @@ impl LegComputer {
                             false,
                         );
 
-                        *self.registers.get_mut(out) = from_bytes(o) as u8;
+                        *self.registers.get_mut(out) = from_bytes(o);
                         self.flags.overflow_unsigned = ofl_u;
                         self.flags.overflow_signed = ofl_s;
                     }
@@ -958,7 +1054,7 @@ impl LegComputer {
                             false,
                         );
 
-                        *self.registers.get_mut(out) = from_bytes(o) as u8;
+                        *self.registers.get_mut(out) = from_bytes(o);
                         self.flags.overflow_unsigned = ofl_u;
                         self.flags.overflow_signed = ofl_s;
                     }
@@ -976,7 +1072,7 @@ impl LegComputer {
                         for i in 0..8 {
                             o[i] = !arg2[i];
                         }
-                        *self.registers.get_mut(out) = from_bytes(o) as u8;
+                        *self.registers.get_mut(out) = from_bytes(o);
                     }
 
                     AluOpcode::Sub => {
@@ -986,7 +1082,7 @@ impl LegComputer {
                         }
                         let (o, ofl_u, ofl_s) = add_8bit(arg1, not2, true);
 
-                        *self.registers.get_mut(out) = from_bytes(o) as u8;
+                        *self.registers.get_mut(out) = from_bytes(o);
                         self.flags.overflow_unsigned = ofl_u;
                         self.flags.overflow_signed = ofl_s;
                     }
@@ -1121,13 +1217,15 @@ impl LegComputer {
                 self.flags.less_or_equal = !self.flags.greater_than;
                 self.flags.less_or_equal_signed = !self.flags.greater_than_signed;
 
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
 
             Instruction::Nop(NopOpcode::Nop) => {
-                self.eip += 2;
+                self.eip = self.eip.wrapping_add(2);
             }
             Instruction::Nop(NopOpcode::Halt) => {}
         };
+
+        Ok(())
     }
 }
@@ -6,6 +6,11 @@ use super::leg_computer::NopOpcode;
 use super::leg_computer::RegisterRef;
 use super::leg_computer::StackInstruction;
 use super::leg_computer::Word;
+use super::leg_stdlib::STDLIB;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
 use std::str::FromStr;
 
 impl FromStr for RegisterRef {
@@ -25,6 +30,12 @@ impl FromStr for RegisterRef {
     }
 }
 
+impl Display for RegisterRef {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{:?}", self)
+    }
+}
+
 impl FromStr for AluOpcode {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -48,6 +59,27 @@ impl FromStr for AluOpcode {
     }
 }
 
+impl Display for AluOpcode {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        f.write_str(match self {
+            AluOpcode::Add => "ADD",
+            AluOpcode::AddCarry => "ADDC",
+            AluOpcode::Incr => "INCR",
+            AluOpcode::Decr => "DECR",
+            AluOpcode::Xor => "XOR",
+            AluOpcode::Neg => "NEG",
+            AluOpcode::Sub => "SUB",
+            AluOpcode::Or => "OR",
+            AluOpcode::And => "AND",
+            AluOpcode::Nand => "NAND",
+            AluOpcode::Nor => "NOR",
+            AluOpcode::ShiftL => "SHIFTL",
+            AluOpcode::ShiftR => "SHIFTR",
+            AluOpcode::Echo => "ECHO",
+        })
+    }
+}
+
 impl FromStr for AluFlagRef {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -73,6 +105,29 @@ impl FromStr for AluFlagRef {
     }
 }
 
+impl Display for AluFlagRef {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        f.write_str(match self {
+            AluFlagRef::EqZero => "Z",
+            AluFlagRef::OverflowUnsigned => "Ou",
+            AluFlagRef::OverflowSigned => "Os",
+            AluFlagRef::Equal => "EQ",
+            AluFlagRef::GreaterThan => "GT",
+            AluFlagRef::GreaterThanSigned => "GTs",
+            AluFlagRef::GreaterOrEqual => "GE",
+            AluFlagRef::GreaterOrEqualSigned => "GEs",
+
+            AluFlagRef::NotEqual => "NE",
+            AluFlagRef::LessThan => "LT",
+            AluFlagRef::LessThanSigned => "LTs",
+            AluFlagRef::LessOrEqual => "LE",
+            AluFlagRef::LessOrEqualSigned => "LEs",
+            AluFlagRef::False => "F",
+            AluFlagRef::True => "T",
+        })
+    }
+}
+
 impl FromStr for Instruction {
     type Err = String;
     fn from_str(line: &str) -> Result<Instruction, Self::Err> {
@@ -167,6 +222,55 @@ impl FromStr for Instruction {
     }
 }
 
+/// Disassembles into the same syntax that `FromStr` parses. Relative
+/// offsets are written as signed numbers, everything else as unsigned.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        fn signed(w: &Word) -> i8 {
+            *w as i8
+        }
+
+        match self {
+            Self::Load { dest, addr } => write!(f, "LOAD {} => {}", addr, dest),
+            Self::LoadP { dest, addr_src } => write!(f, "LOADP {} => {}", addr_src, dest),
+
+            Self::Store { src, addr } => write!(f, "STORE {} => {}", src, addr),
+            Self::StoreP { src, addr_src } => write!(f, "STOREP {} => {}", src, addr_src),
+
+            Self::Mov { dest, src } => write!(f, "MOV {} => {}", src, dest),
+            Self::MovC { dest, val } => write!(f, "MOVC {} => {}", val, dest),
+
+            Self::Jmp { flag, addr } => write!(f, "JMP {} ? {}", flag, addr),
+            Self::JmpP { flag, addr_src } => write!(f, "JMPP {} ? {}", flag, addr_src),
+            Self::JmpR { flag, diff } => write!(f, "JMPR {} ? {}", flag, signed(diff)),
+            Self::JmpRP { flag, diff_src } => write!(f, "JMPRP {} ? {}", flag, diff_src),
+
+            Self::Stack(StackInstruction::Push { src }) => write!(f, "PUSH {}", src),
+            Self::Stack(StackInstruction::Pop { dest }) => write!(f, "POP {}", dest),
+            Self::Stack(StackInstruction::Call { addr_reg }) => write!(f, "CALL {}", addr_reg),
+            Self::Stack(StackInstruction::CallC { addr }) => write!(f, "CALLC {}", addr),
+            Self::Stack(StackInstruction::CallR { diff }) => write!(f, "CALLR {}", signed(diff)),
+            Self::Stack(StackInstruction::Ret { src }) => write!(f, "RET {}", src),
+            Self::Stack(StackInstruction::Load { dest, bp_diff }) => {
+                write!(f, "SLOAD {} => {}", signed(bp_diff), dest)
+            }
+
+            Self::Gpi { dest } => write!(f, "GPI {} <=", dest),
+            Self::Gpo { src } => write!(f, "GPO {} =>", src),
+
+            Self::Alu {
+                op,
+                arg1,
+                arg2,
+                out,
+            } => write!(f, "ALU {} {} {} => {}", op, arg1, arg2, out),
+
+            Self::Nop(NopOpcode::Nop) => write!(f, "NOP"),
+            Self::Nop(NopOpcode::Halt) => write!(f, "HALT"),
+        }
+    }
+}
+
 impl FromStr for LegComputer {
     type Err = String;
     fn from_str(source: &str) -> Result<LegComputer, Self::Err> {
@@ -176,12 +280,320 @@ impl FromStr for LegComputer {
 }
 
 pub fn assemble_program(source: &str) -> Result<Vec<Instruction>, String> {
+    Ok(assemble_lines(source)?
+        .into_iter()
+        .map(|(_, instruction)| instruction)
+        .collect())
+}
+
+/// A `.func name(arg1, arg2, ...)` block of an assembly source.
+struct FuncDecl {
+    line: usize,
+    name: String,
+    params: Vec<String>,
+    locals: Vec<String>,
+    /// Address of the first instruction
+    addr: usize,
+}
+
+impl FuncDecl {
+    /// Stack offset of an argument or local. Arguments are pushed in order,
+    /// so the last one is at offset 2, and locals are pushed below BP.
+    fn offset(&self, name: &str) -> Option<i16> {
+        match self.params.iter().position(|p| p == name) {
+            Some(i) => Some((self.params.len() + 1 - i) as i16),
+            None => self
+                .locals
+                .iter()
+                .position(|l| l == name)
+                .map(|i| -(i as i16) - 1),
+        }
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_names(list: &str) -> Result<Vec<String>, String> {
+    let names: Vec<String> = list
+        .split(',')
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect();
+    match names.iter().find(|n| !is_name(n)) {
+        Some(name) => Err(format!("Invalid name: {}", name)),
+        None => Ok(names),
+    }
+}
+
+fn parse_func_header(header: &str) -> Result<(String, Vec<String>), String> {
+    let (name, params) = match (header.find('('), header.strip_suffix(')')) {
+        (Some(open), Some(header)) => (header[..open].trim(), &header[open + 1..]),
+        _ => {
+            return Err(format!(
+                "Expected .func name(arg1, ...), found .func {}",
+                header
+            ))
+        }
+    };
+    if !is_name(name) {
+        return Err(format!("Invalid name: {}", name));
+    }
+    Ok((name.to_string(), parse_names(params)?))
+}
+
+/// Lines that are neither empty nor comments, trimmed and numbered from 1.
+fn source_lines(source: &str) -> impl Iterator<Item = (usize, &str)> {
     source
         .lines()
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty())
-        .filter(|s| !s.starts_with("#"))
-        .map(|s| s.parse())
+        .enumerate()
+        .map(|(i, s)| (i + 1, s.trim()))
+        .filter(|(_, s)| !s.is_empty())
+        .filter(|(_, s)| !s.starts_with('#'))
+}
+
+/// Like `assemble_program`, but also return the 1-based source line of each
+/// instruction.
+///
+/// Instructions between `.func name(arg1, arg2, ...)` and `.endfunc` may use
+/// the argument names, and local names declared with `.local name, ...`, as
+/// SLOAD offsets. The first local is the first value the function pushes.
+/// CALLC and CALLR may name a function instead of giving its address, and
+/// calls to a function must follow at least as many PUSHes as it has
+/// arguments on every path from the start of the calling function.
+///
+/// `.include name ...` inserts routines of the standard library, see
+/// `STDLIB`. Including a routine again has no effect.
+pub fn assemble_lines(source: &str) -> Result<Vec<(usize, Instruction)>, String> {
+    Ok(assemble_with_symbols(source)?.lines)
+}
+
+/// An assembled source.
+#[derive(Clone, Debug)]
+pub struct Assembly {
+    /// Each instruction and its source line
+    pub lines: Vec<(usize, Instruction)>,
+    /// The name and address of each `.func`
+    pub funcs: Vec<(String, Word)>,
+}
+
+/// Change in stack depth caused by an instruction, as seen by the caller. A
+/// CALL leaves the return value on top of the arguments.
+pub fn stack_delta(instruction: &Instruction) -> i16 {
+    match instruction {
+        Instruction::Stack(StackInstruction::Push { .. }) => 1,
+        Instruction::Stack(StackInstruction::Pop { .. }) => -1,
+        Instruction::Stack(StackInstruction::Call { .. })
+        | Instruction::Stack(StackInstruction::CallC { .. })
+        | Instruction::Stack(StackInstruction::CallR { .. }) => 1,
+        _ => 0,
+    }
+}
+
+/// The constant target of a CALLC or CALLR at `addr`.
+pub fn call_target(addr: Word, instruction: &Instruction) -> Option<Word> {
+    match instruction {
+        Instruction::Stack(StackInstruction::CallC { addr }) => Some(*addr),
+        Instruction::Stack(StackInstruction::CallR { diff }) => Some(addr.wrapping_add(*diff)),
+        _ => None,
+    }
+}
+
+/// The instructions that may run after instruction `i` of a function, not
+/// following calls or jumps computed at run time.
+fn successors(i: usize, instruction: &Instruction) -> Vec<usize> {
+    let addr = (i * 2) as Word;
+    let (flag, target) = match instruction {
+        Instruction::Jmp { flag, addr } => (*flag, Some(*addr)),
+        Instruction::JmpR { flag, diff } => (*flag, Some(addr.wrapping_add(*diff))),
+        Instruction::JmpP { flag, .. } | Instruction::JmpRP { flag, .. } => (*flag, None),
+        Instruction::Stack(StackInstruction::Ret { .. }) | Instruction::Nop(NopOpcode::Halt) => {
+            return Vec::new()
+        }
+        _ => (AluFlagRef::False, None),
+    };
+    let mut next = Vec::new();
+    if flag != AluFlagRef::True {
+        next.push(i + 1);
+    }
+    if flag != AluFlagRef::False {
+        // Odd targets land inside an instruction, which the linter reports
+        next.extend(target.filter(|t| t % 2 == 0).map(|t| t as usize / 2));
+    }
+    next
+}
+
+/// Check that each call to a `.func` follows at least as many values on the
+/// stack of the calling function as it has arguments, on every path there.
+/// `owners` are the `.func` of each instruction. Code outside of `.func`s
+/// counts from the start of each stretch of it.
+///
+/// The stack depth before each instruction is the least over the paths from
+/// the start of its function, with `stack_delta` of each instruction on
+/// the way.
+fn check_arguments(
+    lines: &[(usize, Instruction)],
+    owners: &[Option<usize>],
+    funcs: &[FuncDecl],
+) -> Result<(), String> {
+    let mut depths: Vec<Option<i16>> = vec![None; lines.len()];
+    let mut stack: Vec<(usize, i16)> = (0..lines.len())
+        .filter(|i| *i == 0 || owners[*i] != owners[*i - 1])
+        .map(|i| (i, 0))
+        .collect();
+    while let Some((i, depth)) = stack.pop() {
+        if depths[i].map(|d| d <= depth) == Some(true) {
+            continue;
+        }
+        depths[i] = Some(depth);
+        let instruction = &lines[i].1;
+        // Popping past the frame is the stack checker's business
+        let after = (depth + stack_delta(instruction)).max(0);
+        for next in successors(i, instruction) {
+            if next < lines.len() && owners[next] == owners[i] {
+                stack.push((next, after));
+            }
+        }
+    }
+
+    for (i, (line, instruction)) in lines.iter().enumerate() {
+        let callee = call_target((i * 2) as Word, instruction)
+            .and_then(|target| funcs.iter().find(|f| f.addr == target as usize));
+        if let (Some(callee), Some(depth)) = (callee, depths[i]) {
+            if (depth as usize) < callee.params.len() {
+                return Err(format!(
+                    "Line {}: {} takes {} arguments, but {} are pushed",
+                    line,
+                    callee.name,
+                    callee.params.len(),
+                    depth
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Like `assemble_lines`, and also returns the `.func`s.
+pub fn assemble_with_symbols(source: &str) -> Result<Assembly, String> {
+    // Expand each `.include`, numbering the routine's lines like the directive
+    let mut included: Vec<&str> = Vec::new();
+    let mut expanded: Vec<(usize, &str)> = Vec::new();
+    for (line, s) in source_lines(source) {
+        let mut words = s.split_whitespace();
+        if words.next() != Some(".include") {
+            expanded.push((line, s));
+            continue;
+        }
+        for name in words {
+            let routine = STDLIB
+                .iter()
+                .find(|(n, _)| *n == name)
+                .ok_or_else(|| format!("Line {}: Unknown library routine: {}", line, name))?
+                .1;
+            if !included.contains(&name) {
+                included.push(name);
+                expanded.extend(source_lines(routine).map(|(_, s)| (line, s)));
+            }
+        }
+    }
+
+    let mut funcs: Vec<FuncDecl> = Vec::new();
+    let mut open: Option<usize> = None;
+    let mut lines: Vec<(usize, &str, Option<usize>)> = Vec::new();
+    for (line, s) in expanded {
+        let error = |e: String| format!("Line {}: {}", line, e);
+        let directive = s.split_whitespace().next().unwrap_or("");
+        match (directive, open) {
+            (".func", None) => {
+                let (name, params) = parse_func_header(s[5..].trim()).map_err(error)?;
+                if funcs.iter().any(|f| f.name == name) {
+                    return Err(error(format!("Function {} is already declared", name)));
+                }
+                open = Some(funcs.len());
+                funcs.push(FuncDecl {
+                    line,
+                    name,
+                    params,
+                    locals: Vec::new(),
+                    addr: lines.len() * 2,
+                });
+            }
+            (".func", Some(f)) => {
+                return Err(error(format!("Missing .endfunc of {}", funcs[f].name)));
+            }
+            (".local", Some(f)) => {
+                let names = parse_names(&s[6..]).map_err(error)?;
+                funcs[f].locals.extend(names);
+            }
+            (".endfunc", Some(_)) => open = None,
+            (".local", None) | (".endfunc", None) => {
+                return Err(error(format!("{} outside of .func", directive)));
+            }
+            _ if directive.starts_with('.') => {
+                return Err(error(format!("Unknown directive: {}", directive)));
+            }
+            _ => lines.push((line, s, open)),
+        }
+    }
+    if let Some(f) = open {
+        return Err(format!(
+            "Line {}: Missing .endfunc of {}",
+            funcs[f].line, funcs[f].name
+        ));
+    }
+
+    let owners: Vec<Option<usize>> = lines.iter().map(|(_, _, func)| *func).collect();
+    let mut result = Vec::with_capacity(lines.len());
+    for (i, (line, s, func)) in lines.into_iter().enumerate() {
+        let error = |e: String| format!("Line {}: {}", line, e);
+        let addr = i * 2;
+        let func = func.map(|f| &funcs[f]);
+
+        let mut words: Vec<String> = s.split_whitespace().map(|w| w.to_string()).collect();
+        match (words[0].as_str(), words.get(1)) {
+            ("SLOAD", Some(name)) if is_name(name) => {
+                let offset = func
+                    .and_then(|f| f.offset(name))
+                    .ok_or_else(|| error(format!("Unknown argument or local: {}", name)))?;
+                words[1] = offset.to_string();
+            }
+            ("CALLC", Some(name)) | ("CALLR", Some(name)) if is_name(name) => {
+                let target = funcs
+                    .iter()
+                    .find(|f| f.name == *name)
+                    .ok_or_else(|| error(format!("Unknown function: {}", name)))?
+                    .addr as i16;
+                words[1] = if words[0] == "CALLC" {
+                    target.to_string()
+                } else {
+                    (target - addr as i16).to_string()
+                };
+            }
+            _ => {}
+        }
+
+        let instruction: Instruction = words.join(" ").parse().map_err(error)?;
+        result.push((line, instruction));
+    }
+    check_arguments(&result, &owners, &funcs)?;
+    Ok(Assembly {
+        lines: result,
+        funcs: funcs
+            .into_iter()
+            .map(|f| (f.name, f.addr as Word))
+            .collect(),
+    })
+}
+
+/// Decode a program image back into instructions. A trailing odd word is
+/// ignored.
+pub fn disassemble(program: &[Word]) -> Result<Vec<Instruction>, String> {
+    program
+        .chunks_exact(2)
+        .map(|words| Instruction::try_from((words[0], words[1])))
         .collect()
 }
 
@@ -0,0 +1,326 @@
+use super::leg_computer::Address;
+use super::leg_computer::LegComputer;
+use super::leg_computer::Word;
+use super::leg_computer_parse::assemble_with_symbols;
+use super::leg_computer_parse::generate_code;
+use std::convert::TryFrom;
+use std::path::Path;
+
+/// The first bytes of a container.
+pub const CONTAINER_MAGIC: [u8; 4] = *b"LEG\x1a";
+pub const CONTAINER_VERSION: u8 = 1;
+
+/// The instruction set that a program is written for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Dialect {
+    /// The instructions that `LegComputer` executes
+    Leg = 0,
+}
+
+impl TryFrom<Word> for Dialect {
+    type Error = String;
+    fn try_from(w: Word) -> Result<Self, Self::Error> {
+        match w {
+            0 => Ok(Self::Leg),
+            other => Err(format!("Unknown ISA dialect: {}", other)),
+        }
+    }
+}
+
+/// The section that a symbol's address is in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Section {
+    Program = 0,
+    Data = 1,
+}
+
+impl TryFrom<Word> for Section {
+    type Error = String;
+    fn try_from(w: Word) -> Result<Self, Self::Error> {
+        match w {
+            0 => Ok(Self::Program),
+            1 => Ok(Self::Data),
+            other => Err(format!("Invalid section: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub section: Section,
+    pub addr: Address,
+}
+
+/// A program with its initial memory and what is known about it.
+///
+/// The binary form is, with numbers in little endian:
+///
+/// ```text
+/// magic     4 bytes, CONTAINER_MAGIC
+/// version   1 byte
+/// dialect   1 byte
+/// entry     1 byte, the initial instruction pointer
+/// reserved  1 byte, 0
+/// program   2 bytes length, then the program image
+/// data      2 bytes length, then the initial memory
+/// symbols   2 bytes count, then per symbol: section, address, 1 byte name
+///           length and the name in UTF-8
+/// lines     2 bytes count, then per instruction: address and 4 bytes source
+///           line
+/// checksum  4 bytes, CRC-32 of everything before it
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LegContainer {
+    pub dialect: Dialect,
+    pub entry: Address,
+    pub program: Vec<Word>,
+    /// Initial memory, padded with zeros to 256 words when loaded
+    pub data: Vec<Word>,
+    pub symbols: Vec<Symbol>,
+    /// Source line of each instruction address
+    pub lines: Vec<(Address, u32)>,
+}
+
+/// CRC-32 as in zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Reads the fields of a container in order.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize, what: &str) -> Result<&'a [u8], String> {
+        if self.pos + len > self.bytes.len() {
+            return Err(format!(
+                "Truncated container: expected {} at {}",
+                what, self.pos
+            ));
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    fn byte(&mut self, what: &str) -> Result<u8, String> {
+        Ok(self.take(1, what)?[0])
+    }
+
+    fn u16(&mut self, what: &str) -> Result<usize, String> {
+        let b = self.take(2, what)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]) as usize)
+    }
+
+    fn u32(&mut self, what: &str) -> Result<u32, String> {
+        let b = self.take(4, what)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// A 2 byte length, then that many bytes.
+    fn section(&mut self, what: &str) -> Result<Vec<Word>, String> {
+        let len = self.u16(what)?;
+        if len > 256 {
+            return Err(format!("Invalid {} length: {}", what, len));
+        }
+        Ok(self.take(len, what)?.to_vec())
+    }
+}
+
+impl LegContainer {
+    /// A container with no symbols or line info, that starts at 0.
+    pub fn new(program: Vec<Word>, data: Vec<Word>) -> LegContainer {
+        LegContainer {
+            dialect: Dialect::Leg,
+            entry: 0,
+            program,
+            data,
+            symbols: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Assemble `source`, with a symbol for each `.func` and the line of
+    /// each instruction.
+    pub fn assemble(source: &str, data: Vec<Word>) -> Result<LegContainer, String> {
+        let assembly = assemble_with_symbols(source)?;
+        let program: Vec<_> = assembly.lines.iter().map(|(_, ins)| ins.clone()).collect();
+        let mut container = LegContainer::new(generate_code(&program), data);
+        container.symbols = assembly
+            .funcs
+            .into_iter()
+            .map(|(name, addr)| Symbol {
+                name,
+                section: Section::Program,
+                addr,
+            })
+            .collect();
+        container.lines = assembly
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(i, (line, _))| ((2 * i) as Address, *line as u32))
+            .collect();
+        Ok(container)
+    }
+
+    /// Fails if a section is too large for memory or a symbol name is
+    /// longer than 255 bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        for (name, section) in &[("Program", &self.program), ("Data", &self.data)] {
+            if section.len() > 256 {
+                return Err(format!("{} too large: {} bytes", name, section.len()));
+            }
+        }
+        let mut bytes = CONTAINER_MAGIC.to_vec();
+        bytes.extend(&[CONTAINER_VERSION, self.dialect as u8, self.entry, 0]);
+        for section in &[&self.program, &self.data] {
+            bytes.extend(&(section.len() as u16).to_le_bytes());
+            bytes.extend(section.iter());
+        }
+
+        bytes.extend(&(self.symbols.len() as u16).to_le_bytes());
+        for symbol in &self.symbols {
+            if symbol.name.len() > 255 {
+                return Err(format!("Symbol name too long: {}", symbol.name));
+            }
+            bytes.extend(&[symbol.section as u8, symbol.addr, symbol.name.len() as u8]);
+            bytes.extend(symbol.name.as_bytes());
+        }
+        bytes.extend(&(self.lines.len() as u16).to_le_bytes());
+        for (addr, line) in &self.lines {
+            bytes.push(*addr);
+            bytes.extend(&line.to_le_bytes());
+        }
+
+        let checksum = crc32(&bytes);
+        bytes.extend(&checksum.to_le_bytes());
+        Ok(bytes)
+    }
+
+    /// Fails on a wrong magic number or checksum, an unknown version or
+    /// dialect, or a truncated container.
+    pub fn from_bytes(bytes: &[u8]) -> Result<LegContainer, String> {
+        if bytes.len() < CONTAINER_MAGIC.len() || bytes[..4] != CONTAINER_MAGIC {
+            return Err("Not a LEG container".to_string());
+        }
+        if bytes.len() < 12 {
+            return Err("Truncated container".to_string());
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 4);
+        let expected = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        if crc32(body) != expected {
+            return Err(format!(
+                "Checksum mismatch: {:08x}, expected {:08x}",
+                crc32(body),
+                expected
+            ));
+        }
+
+        let mut reader = Reader {
+            bytes: body,
+            pos: CONTAINER_MAGIC.len(),
+        };
+        let version = reader.byte("version")?;
+        if version != CONTAINER_VERSION {
+            return Err(format!("Unsupported container version: {}", version));
+        }
+        let dialect = Dialect::try_from(reader.byte("dialect")?)?;
+        let entry = reader.byte("entry point")?;
+        reader.byte("reserved byte")?;
+        let program = reader.section("program")?;
+        let data = reader.section("data")?;
+
+        let mut symbols = Vec::new();
+        for _ in 0..reader.u16("symbol count")? {
+            let section = Section::try_from(reader.byte("symbol section")?)?;
+            let addr = reader.byte("symbol address")?;
+            let len = reader.byte("symbol name length")? as usize;
+            let name = String::from_utf8(reader.take(len, "symbol name")?.to_vec())
+                .map_err(|e| format!("Invalid symbol name: {}", e))?;
+            symbols.push(Symbol {
+                name,
+                section,
+                addr,
+            });
+        }
+        let mut lines = Vec::new();
+        for _ in 0..reader.u16("line count")? {
+            lines.push((reader.byte("line address")?, reader.u32("line number")?));
+        }
+        if reader.pos != body.len() {
+            return Err(format!(
+                "Trailing bytes in container: {}",
+                body.len() - reader.pos
+            ));
+        }
+
+        Ok(LegContainer {
+            dialect,
+            entry,
+            program,
+            data,
+            symbols,
+            lines,
+        })
+    }
+
+    /// The symbol with the highest address at or before `addr` in
+    /// `section`, and the offset from it.
+    pub fn symbol_at(&self, section: Section, addr: Address) -> Option<(&Symbol, Address)> {
+        self.symbols
+            .iter()
+            .filter(|s| s.section == section && s.addr <= addr)
+            .max_by_key(|s| s.addr)
+            .map(|s| (s, addr - s.addr))
+    }
+
+    /// The source line of the instruction at `addr`.
+    pub fn line_at(&self, addr: Address) -> Option<u32> {
+        self.lines
+            .iter()
+            .find(|(a, _)| *a == addr)
+            .map(|(_, line)| *line)
+    }
+}
+
+/// Read a container from `path`.
+pub fn read_container<P: AsRef<Path>>(path: P) -> Result<LegContainer, String> {
+    let path = path.as_ref();
+    std::fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| LegContainer::from_bytes(&bytes))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Write a container to `path`.
+pub fn write_container<P: AsRef<Path>>(path: P, container: &LegContainer) -> Result<(), String> {
+    let path = path.as_ref();
+    container
+        .to_bytes()
+        .and_then(|bytes| std::fs::write(path, bytes).map_err(|e| e.to_string()))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+impl From<&LegContainer> for LegComputer {
+    fn from(container: &LegContainer) -> LegComputer {
+        let mut memory = container.data.clone();
+        memory.resize(256.max(memory.len()), 0);
+        let mut computer = LegComputer::new(container.program.clone(), memory);
+        computer.eip = container.entry;
+        computer
+    }
+}
@@ -0,0 +1,406 @@
+use super::leg_computer::Address;
+use super::leg_computer::Instruction;
+use super::leg_computer::LegComputer;
+use super::leg_computer::MemoryAccesses;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use super::leg_computer::Word;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// Number of steps `continue`, `next` and `finish` take before giving up, so
+/// that an infinite loop doesn't hang the debugger.
+pub const DEFAULT_STEP_LIMIT: usize = 1_000_000;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    Step(usize),
+    Next,
+    Finish,
+    Continue,
+    Break(Address),
+    Watch(Address),
+    Delete(Address),
+    Registers,
+    Flags,
+    Print,
+    Disassemble { addr: Option<Address>, count: usize },
+    Examine { addr: Address, len: usize },
+    SetRegister(RegisterRef, Word),
+    SetMemory(Address, Word),
+    Help,
+    Quit,
+}
+
+pub const HELP: &str = "\
+step [N]          (s)  Execute N instructions (default 1)
+next              (n)  Step, but run a CALL until it returns
+finish            (f)  Run until the current function returns
+continue          (c)  Run until halt, breakpoint or watchpoint
+break ADDR        (b)  Break when eip reaches ADDR
+watch ADDR        (w)  Break when memory at ADDR changes
+delete ADDR       (d)  Remove breakpoint and watchpoint at ADDR
+regs              (r)  Print registers
+flags                  Print ALU flags
+print             (p)  Print the whole machine state
+disas [ADDR [N]]  (l)  Disassemble N instructions around ADDR (default eip)
+x ADDR [LEN]           Examine LEN bytes of memory (default 16)
+set REG VALUE          Set a register (A-D, FL, ST, BP, IP)
+set ADDR VALUE         Set a memory byte
+help              (h)  Print this help
+quit              (q)  Exit";
+
+/// Parse a word or address in decimal, or hexadecimal with a `0x` prefix.
+/// Negative numbers wrap around like in the assembler.
+pub fn parse_number(s: &str) -> Result<Word, String> {
+    let n: i16 = if let Some(hex) = s.strip_prefix("0x") {
+        i16::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    }
+    .map_err(|_| format!("Invalid number: {}", s))?;
+    if (-128..=255).contains(&n) {
+        Ok(((n + 256) & 0xff) as Word)
+    } else {
+        Err(format!("Number out of range: {}", s))
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+    fn from_str(line: &str) -> Result<Command, Self::Err> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        fn parse_count(s: &str) -> Result<usize, String> {
+            s.parse().map_err(|_| format!("Invalid count: {}", s))
+        }
+
+        match &words[..] {
+            ["step"] | ["s"] => Ok(Self::Step(1)),
+            ["step", n] | ["s", n] => Ok(Self::Step(parse_count(n)?)),
+            ["next"] | ["n"] => Ok(Self::Next),
+            ["finish"] | ["f"] => Ok(Self::Finish),
+            ["continue"] | ["c"] => Ok(Self::Continue),
+            ["break", addr] | ["b", addr] => Ok(Self::Break(parse_number(addr)?)),
+            ["watch", addr] | ["w", addr] => Ok(Self::Watch(parse_number(addr)?)),
+            ["delete", addr] | ["d", addr] => Ok(Self::Delete(parse_number(addr)?)),
+            ["regs"] | ["r"] => Ok(Self::Registers),
+            ["flags"] => Ok(Self::Flags),
+            ["print"] | ["p"] => Ok(Self::Print),
+            ["disas"] | ["l"] => Ok(Self::Disassemble {
+                addr: None,
+                count: 8,
+            }),
+            ["disas", addr] | ["l", addr] => Ok(Self::Disassemble {
+                addr: Some(parse_number(addr)?),
+                count: 8,
+            }),
+            ["disas", addr, n] | ["l", addr, n] => Ok(Self::Disassemble {
+                addr: Some(parse_number(addr)?),
+                count: parse_count(n)?,
+            }),
+            ["x", addr] => Ok(Self::Examine {
+                addr: parse_number(addr)?,
+                len: 16,
+            }),
+            ["x", addr, len] => Ok(Self::Examine {
+                addr: parse_number(addr)?,
+                len: parse_count(len)?,
+            }),
+            ["set", target, value] => {
+                let value = parse_number(value)?;
+                match target.parse::<RegisterRef>() {
+                    Ok(reg) => Ok(Self::SetRegister(reg, value)),
+                    Err(_) => Ok(Self::SetMemory(parse_number(target)?, value)),
+                }
+            }
+            ["help"] | ["h"] => Ok(Self::Help),
+            ["quit"] | ["q"] => Ok(Self::Quit),
+            other => Err(format!("Invalid command: {}", other.join(" "))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    Stepped,
+    Halted,
+    Breakpoint(Address),
+    Watchpoint { addr: Address, old: Word, new: Word },
+    Fault(String),
+    StepLimit,
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            StopReason::Stepped => Ok(()),
+            StopReason::Halted => write!(f, "Halted"),
+            StopReason::Breakpoint(addr) => write!(f, "Breakpoint at {}", addr),
+            StopReason::Watchpoint { addr, old, new } => {
+                write!(f, "Watchpoint at {}: {} -> {}", addr, old, new)
+            }
+            StopReason::Fault(e) => write!(f, "Fault: {}", e),
+            StopReason::StepLimit => write!(f, "Step limit reached"),
+        }
+    }
+}
+
+/// The step number of the most recent read and write of each memory address.
+#[derive(Clone, Debug)]
+pub struct AccessLog {
+    pub last_read: Vec<Option<u64>>,
+    pub last_write: Vec<Option<u64>>,
+}
+
+impl AccessLog {
+    fn new() -> AccessLog {
+        AccessLog {
+            last_read: vec![None; 256],
+            last_write: vec![None; 256],
+        }
+    }
+
+    fn record(&mut self, step: u64, accesses: &MemoryAccesses) {
+        for addr in &accesses.reads {
+            self.last_read[*addr as usize] = Some(step);
+        }
+        for addr in &accesses.writes {
+            self.last_write[*addr as usize] = Some(step);
+        }
+    }
+}
+
+pub struct Debugger {
+    pub computer: LegComputer,
+    pub breakpoints: BTreeSet<Address>,
+    pub watchpoints: BTreeSet<Address>,
+    pub step_limit: usize,
+    /// Number of instructions executed so far
+    pub steps: u64,
+    pub access_log: AccessLog,
+}
+
+impl Debugger {
+    pub fn new(computer: LegComputer) -> Debugger {
+        Debugger {
+            computer,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            step_limit: DEFAULT_STEP_LIMIT,
+            steps: 0,
+            access_log: AccessLog::new(),
+        }
+    }
+
+    /// Execute one instruction and report whether a watchpoint fired.
+    fn step_once(&mut self) -> Option<StopReason> {
+        if self.computer.is_halted() {
+            return Some(StopReason::Halted);
+        }
+
+        let watched: Vec<(Address, Word)> = self
+            .watchpoints
+            .iter()
+            .map(|addr| (*addr, self.computer.memory[*addr as usize]))
+            .collect();
+
+        let accesses = self.computer.memory_accesses().unwrap_or_default();
+        if let Err(e) = self.computer.try_step() {
+            return Some(StopReason::Fault(e));
+        }
+        self.steps += 1;
+        self.access_log.record(self.steps, &accesses);
+
+        watched
+            .into_iter()
+            .find(|(addr, old)| self.computer.memory[*addr as usize] != *old)
+            .map(|(addr, old)| StopReason::Watchpoint {
+                addr,
+                old,
+                new: self.computer.memory[addr as usize],
+            })
+    }
+
+    /// Step until `done` returns true after a step, or until the program
+    /// halts, faults or hits a breakpoint or watchpoint. `done` gets the
+    /// executed instruction, BP before it and the machine after it.
+    fn run_until<F>(&mut self, mut done: F) -> StopReason
+    where
+        F: FnMut(&Instruction, Word, &LegComputer) -> bool,
+    {
+        for _ in 0..self.step_limit {
+            let bp_before = self.computer.read_register(&RegisterRef::BP);
+            let instruction = match self.computer.current_instruction() {
+                Ok(ins) => ins,
+                Err(e) => return StopReason::Fault(e),
+            };
+
+            if let Some(reason) = self.step_once() {
+                return reason;
+            }
+            if done(&instruction, bp_before, &self.computer) {
+                return StopReason::Stepped;
+            }
+            if self.breakpoints.contains(&self.computer.eip) {
+                return StopReason::Breakpoint(self.computer.eip);
+            }
+        }
+        StopReason::StepLimit
+    }
+
+    pub fn step(&mut self, count: usize) -> StopReason {
+        if count == 0 {
+            return StopReason::Stepped;
+        }
+        let mut remaining = count;
+        self.run_until(|_, _, _| {
+            remaining -= 1;
+            remaining == 0
+        })
+    }
+
+    pub fn step_over(&mut self) -> StopReason {
+        match self.computer.current_instruction() {
+            Ok(Instruction::Stack(StackInstruction::Call { .. }))
+            | Ok(Instruction::Stack(StackInstruction::CallC { .. }))
+            | Ok(Instruction::Stack(StackInstruction::CallR { .. })) => {
+                let return_eip = self.computer.eip.wrapping_add(2);
+                let bp = self.computer.read_register(&RegisterRef::BP);
+                self.run_until(|_, _, after| {
+                    after.eip == return_eip && after.read_register(&RegisterRef::BP) == bp
+                })
+            }
+            _ => self.step(1),
+        }
+    }
+
+    pub fn finish(&mut self) -> StopReason {
+        let bp = self.computer.read_register(&RegisterRef::BP);
+        self.run_until(|ins, bp_before, _| {
+            matches!(ins, Instruction::Stack(StackInstruction::Ret { .. })) && bp_before == bp
+        })
+    }
+
+    pub fn cont(&mut self) -> StopReason {
+        self.run_until(|_, _, _| false)
+    }
+
+    pub fn disassemble(&self, addr: Address, count: usize) -> String {
+        let mut out = Vec::with_capacity(count);
+        let mut addr = addr as usize;
+        for _ in 0..count {
+            if addr + 1 >= self.computer.program.len() {
+                break;
+            }
+            let marker = match (
+                addr == self.computer.eip as usize,
+                self.breakpoints.contains(&(addr as Address)),
+            ) {
+                (true, true) => "*>",
+                (true, false) => " >",
+                (false, true) => "* ",
+                (false, false) => "  ",
+            };
+            let words = (self.computer.program[addr], self.computer.program[addr + 1]);
+            let text = match Instruction::try_from(words) {
+                Ok(ins) => ins.to_string(),
+                Err(e) => format!("<{}>", e),
+            };
+            out.push(format!("{} {:03}: {}", marker, addr, text));
+            addr += 2;
+        }
+        out.join("\n")
+    }
+
+    pub fn examine(&self, addr: Address, len: usize) -> String {
+        let mut out = String::new();
+        for i in 0..len {
+            let a = (addr as usize + i) % self.computer.memory.len();
+            if i % 8 == 0 {
+                if i > 0 {
+                    out.push('\n');
+                }
+                out.push_str(&format!("{:>3}:", a));
+            }
+            out.push_str(&format!(" {:>4}", self.computer.memory[a]));
+        }
+        out
+    }
+
+    /// One-line summary of where the machine is, for printing after a stop.
+    pub fn location(&self) -> String {
+        let text = match self.computer.current_instruction() {
+            Ok(ins) => ins.to_string(),
+            Err(e) => format!("<{}>", e),
+        };
+        format!(
+            "{:03}: {}\n{} {}",
+            self.computer.eip, text, self.computer.registers, self.computer.flags
+        )
+    }
+
+    /// Execute a command and return the text to show the user.
+    pub fn execute(&mut self, command: &Command) -> String {
+        fn stopped(reason: StopReason, dbg: &Debugger) -> String {
+            match reason {
+                StopReason::Stepped => dbg.location(),
+                other => format!("{}\n{}", other, dbg.location()),
+            }
+        }
+
+        match command {
+            Command::Step(n) => stopped(self.step(*n), self),
+            Command::Next => stopped(self.step_over(), self),
+            Command::Finish => stopped(self.finish(), self),
+            Command::Continue => stopped(self.cont(), self),
+            Command::Break(addr) => {
+                self.breakpoints.insert(*addr);
+                format!("Breakpoint at {}", addr)
+            }
+            Command::Watch(addr) => {
+                self.watchpoints.insert(*addr);
+                format!("Watchpoint at {}", addr)
+            }
+            Command::Delete(addr) => {
+                let removed_break = self.breakpoints.remove(addr);
+                let removed_watch = self.watchpoints.remove(addr);
+                if removed_break || removed_watch {
+                    format!("Deleted {}", addr)
+                } else {
+                    format!("No breakpoint or watchpoint at {}", addr)
+                }
+            }
+            Command::Registers => format!(
+                "{} [FL: {}, IP: {}]",
+                self.computer.registers,
+                self.computer.read_register(&RegisterRef::FL),
+                self.computer.eip
+            ),
+            Command::Flags => self.computer.flags.to_string(),
+            Command::Print => self.computer.to_string(),
+            Command::Disassemble { addr, count } => {
+                let start = addr.unwrap_or_else(|| self.computer.eip.saturating_sub(4) & !1);
+                self.disassemble(start, *count)
+            }
+            Command::Examine { addr, len } => self.examine(*addr, *len),
+            Command::SetRegister(reg, value) => {
+                self.computer.write_register(*reg, *value);
+                format!("{} = {}", reg, value)
+            }
+            Command::SetMemory(addr, value) => match self.computer.memory.get_mut(*addr as usize) {
+                Some(cell) => {
+                    *cell = *value;
+                    format!("[{}] = {}", addr, value)
+                }
+                None => format!("Address out of memory: {}", addr),
+            },
+            Command::Help => HELP.to_string(),
+            Command::Quit => String::new(),
+        }
+    }
+}
@@ -0,0 +1,1255 @@
+use super::leg_cfg::Cfg;
+use super::leg_cfg::EdgeKind;
+use super::leg_computer::Address;
+use super::leg_computer::AluFlagRef;
+use super::leg_computer::AluOpcode;
+use super::leg_computer::Instruction;
+use super::leg_computer::NopOpcode;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use super::leg_computer::Word;
+use super::leg_computer_parse::stack_delta;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+
+/// Node standing in for "after the function returns" in post-dominator
+/// computations.
+const EXIT: u16 = 0x100;
+
+const GENERAL_REGISTERS: [RegisterRef; 4] = [
+    RegisterRef::A,
+    RegisterRef::B,
+    RegisterRef::C,
+    RegisterRef::D,
+];
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Var {
+    Reg(RegisterRef),
+    /// Stack slot `BP - n`, where pushed values end up
+    Local(i16),
+    /// Snapshot of a flag operand, introduced by the lifter
+    Temp(usize),
+}
+
+impl Display for Var {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Var::Reg(reg) => write!(f, "{}", reg),
+            Var::Local(n) if *n > 0 => write!(f, "local{}", n),
+            Var::Local(n) => write!(f, "frame[{}]", -n),
+            Var::Temp(n) => write!(f, "t{}", n),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Value {
+    Var(Var),
+    Const(Word),
+    /// Function argument, numbered from 1 in push order
+    Param(usize),
+    /// `SLOAD 0` or `SLOAD 1`: the saved BP or the return address
+    Frame(Word),
+    /// A flag set outside the current block
+    Flag(AluFlagRef),
+    Mem(Box<Value>),
+    Input,
+    Call(String, Vec<Value>),
+    PostIncr(Var, &'static str),
+    Unary(&'static str, Box<Value>),
+    Binary(&'static str, Box<Value>, Box<Value>),
+}
+
+fn binary_precedence(op: &str) -> u8 {
+    match op {
+        "|" => 1,
+        "^" => 2,
+        "&" => 3,
+        "==" | "!=" => 4,
+        "<" | "<=" | ">" | ">=" => 5,
+        "<<" | ">>" => 6,
+        _ => 7,
+    }
+}
+
+fn negated_comparison(op: &str) -> Option<&'static str> {
+    match op {
+        "==" => Some("!="),
+        "!=" => Some("=="),
+        "<" => Some(">="),
+        ">=" => Some("<"),
+        ">" => Some("<="),
+        "<=" => Some(">"),
+        _ => None,
+    }
+}
+
+fn binary(op: &'static str, left: Value, right: Value) -> Value {
+    Value::Binary(op, Box::new(left), Box::new(right))
+}
+
+fn negate(cond: Value) -> Value {
+    match cond {
+        Value::Binary(op, left, right) => match negated_comparison(op) {
+            Some(negated) => Value::Binary(negated, left, right),
+            None => Value::Unary("!", Box::new(Value::Binary(op, left, right))),
+        },
+        Value::Unary("!", inner) => *inner,
+        other => Value::Unary("!", Box::new(other)),
+    }
+}
+
+impl Value {
+    fn children(&self) -> Vec<&Value> {
+        match self {
+            Value::Mem(a) | Value::Unary(_, a) => vec![a],
+            Value::Binary(_, a, b) => vec![a, b],
+            Value::Call(_, args) => args.iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Value::Mem(a) | Value::Unary(_, a) => vec![a],
+            Value::Binary(_, a, b) => vec![a, b],
+            Value::Call(_, args) => args.iter_mut().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn vars(&self, out: &mut Vec<Var>) {
+        if let Value::Var(v) = self {
+            out.push(*v);
+        }
+        for child in self.children() {
+            child.vars(out);
+        }
+    }
+
+    fn count(&self, var: Var) -> usize {
+        let mut vars = Vec::new();
+        self.vars(&mut vars);
+        vars.into_iter().filter(|v| *v == var).count()
+    }
+
+    fn reads_memory(&self) -> bool {
+        matches!(self, Value::Mem(_) | Value::Call(..))
+            || self.children().iter().any(|c| c.reads_memory())
+    }
+
+    /// Whether evaluating the value does more than compute it, so it must
+    /// stay put relative to other statements.
+    fn has_effect(&self) -> bool {
+        matches!(self, Value::Input | Value::Call(..) | Value::PostIncr(..))
+            || self.children().iter().any(|c| c.has_effect())
+    }
+
+    fn substitute(&mut self, var: Var, value: &Value) {
+        if *self == Value::Var(var) {
+            *self = value.clone();
+        } else {
+            for child in self.children_mut() {
+                child.substitute(var, value);
+            }
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Value::Binary(op, _, _) => binary_precedence(op),
+            Value::Unary(..) => 8,
+            _ => 9,
+        }
+    }
+
+    fn render_at(&self, min_precedence: u8) -> String {
+        if self.precedence() < min_precedence {
+            format!("({})", self)
+        } else {
+            self.to_string()
+        }
+    }
+
+    /// Like `render_at`, but also parenthesizes bitwise operations mixed
+    /// with other operators, which C precedence makes easy to misread.
+    fn render_operand(&self, parent: &str, min_precedence: u8) -> String {
+        let bitwise = |op: &str| matches!(op, "|" | "^" | "&" | "<<" | ">>");
+        match self {
+            Value::Binary(op, _, _) if *op != parent && (bitwise(op) || bitwise(parent)) => {
+                format!("({})", self)
+            }
+            _ => self.render_at(min_precedence),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Value::Var(v) => write!(f, "{}", v),
+            Value::Const(c) => write!(f, "{}", c),
+            Value::Param(i) => write!(f, "arg{}", i),
+            Value::Frame(0) => write!(f, "saved_bp"),
+            Value::Frame(_) => write!(f, "return_address"),
+            Value::Flag(flag) => write!(f, "FL.{}", flag),
+            Value::Mem(addr) => write!(f, "mem[{}]", addr),
+            Value::Input => write!(f, "gpi()"),
+            Value::Call(name, args) => write!(
+                f,
+                "{}({})",
+                name,
+                args.iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Value::PostIncr(v, op) => write!(f, "{}{}", v, op),
+            Value::Unary(op, inner) => write!(f, "{}{}", op, inner.render_at(8)),
+            Value::Binary(op, left, right) => {
+                let precedence = binary_precedence(op);
+                write!(
+                    f,
+                    "{} {} {}",
+                    left.render_operand(op, precedence),
+                    op,
+                    right.render_operand(op, precedence + 1)
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Stmt {
+    Assign(Var, Value),
+    Store(Value, Value),
+    Eval(Value),
+    Output(Value),
+    Return(Value),
+    Halt,
+    Comment(String),
+}
+
+impl Stmt {
+    fn values(&self) -> Vec<&Value> {
+        match self {
+            Stmt::Assign(_, v) | Stmt::Eval(v) | Stmt::Output(v) | Stmt::Return(v) => vec![v],
+            Stmt::Store(a, v) => vec![a, v],
+            Stmt::Halt | Stmt::Comment(_) => Vec::new(),
+        }
+    }
+
+    fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Stmt::Assign(_, v) | Stmt::Eval(v) | Stmt::Output(v) | Stmt::Return(v) => vec![v],
+            Stmt::Store(a, v) => vec![a, v],
+            Stmt::Halt | Stmt::Comment(_) => Vec::new(),
+        }
+    }
+
+    fn defines(&self) -> Option<Var> {
+        match self {
+            Stmt::Assign(v, _) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Calls are free to use all of the general purpose registers.
+    fn clobbers_registers(&self) -> bool {
+        self.values()
+            .iter()
+            .any(|v| v.reads_memory() && v.has_effect())
+    }
+
+    /// Whether the statement overwrites `var`
+    fn kills(&self, var: Var) -> bool {
+        self.defines() == Some(var) || (matches!(var, Var::Reg(_)) && self.clobbers_registers())
+    }
+
+    fn count(&self, var: Var) -> usize {
+        self.values().iter().map(|v| v.count(var)).sum()
+    }
+
+    fn writes_memory(&self) -> bool {
+        matches!(self, Stmt::Store(..)) || self.clobbers_registers()
+    }
+
+    fn has_effect(&self) -> bool {
+        matches!(self, Stmt::Output(_)) || self.values().iter().any(|v| v.has_effect())
+    }
+}
+
+impl Display for Stmt {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Stmt::Assign(var, value) => write!(f, "{} = {};", var, value),
+            Stmt::Store(addr, value) => write!(f, "mem[{}] = {};", addr, value),
+            Stmt::Eval(value) => write!(f, "{};", value),
+            Stmt::Output(value) => write!(f, "gpo({});", value),
+            Stmt::Return(value) => write!(f, "return {};", value),
+            Stmt::Halt => write!(f, "halt();"),
+            Stmt::Comment(text) => write!(f, "/* {} */", text),
+        }
+    }
+}
+
+/// How control leaves a lifted block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Exit {
+    /// RET, HALT or an undecodable instruction
+    Stop,
+    Goto(Address),
+    Branch {
+        cond: Value,
+        taken: Address,
+        fall: Address,
+    },
+    /// A jump to a computed address, with no condition if unconditional
+    Indirect {
+        cond: Option<Value>,
+        target: Value,
+        fall: Option<Address>,
+    },
+}
+
+impl Exit {
+    fn successors(&self) -> Vec<Address> {
+        match self {
+            Exit::Stop => Vec::new(),
+            Exit::Goto(target) => vec![*target],
+            Exit::Branch { taken, fall, .. } => vec![*taken, *fall],
+            Exit::Indirect { fall, .. } => fall.iter().copied().collect(),
+        }
+    }
+
+    fn values(&self) -> Vec<&Value> {
+        match self {
+            Exit::Branch { cond, .. } => vec![cond],
+            Exit::Indirect { cond, target, .. } => cond.iter().chain(Some(target)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Exit::Branch { cond, .. } => vec![cond],
+            Exit::Indirect { cond, target, .. } => cond.iter_mut().chain(Some(target)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn count(&self, var: Var) -> usize {
+        self.values().iter().map(|v| v.count(var)).sum()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Lifted {
+    stmts: Vec<Stmt>,
+    exit: Exit,
+}
+
+fn function_name(entry: Address) -> String {
+    if entry == 0 {
+        "main".to_string()
+    } else {
+        format!("fn_{:03}", entry)
+    }
+}
+
+/// Number of arguments a function reads with `SLOAD`, counting the deepest
+/// one as the first.
+fn parameter_count(cfg: &Cfg, entry: Address) -> usize {
+    cfg.functions[&entry]
+        .blocks
+        .iter()
+        .flat_map(|start| cfg.blocks[start].instructions.iter())
+        .filter_map(|(_, instruction)| match instruction {
+            Instruction::Stack(StackInstruction::Load { bp_diff, .. }) if *bp_diff as i8 >= 2 => {
+                Some(*bp_diff as usize - 1)
+            }
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn comparison(flag: AluFlagRef, a: Value, b: Value) -> Option<Value> {
+    let signed = |v: Value| Value::Unary("(i8)", Box::new(v));
+    Some(match flag {
+        AluFlagRef::Equal => binary("==", a, b),
+        AluFlagRef::NotEqual => binary("!=", a, b),
+        AluFlagRef::GreaterThan => binary(">", a, b),
+        AluFlagRef::GreaterOrEqual => binary(">=", a, b),
+        AluFlagRef::LessThan => binary("<", a, b),
+        AluFlagRef::LessOrEqual => binary("<=", a, b),
+        AluFlagRef::GreaterThanSigned => binary(">", signed(a), signed(b)),
+        AluFlagRef::GreaterOrEqualSigned => binary(">=", signed(a), signed(b)),
+        AluFlagRef::LessThanSigned => binary("<", signed(a), signed(b)),
+        AluFlagRef::LessOrEqualSigned => binary("<=", signed(a), signed(b)),
+        _ => return None,
+    })
+}
+
+fn alu_value(op: AluOpcode, a: Value, b: Value) -> Value {
+    match op {
+        AluOpcode::Add => binary("+", a, b),
+        AluOpcode::AddCarry => binary(
+            "+",
+            binary("+", a, b),
+            Value::Flag(AluFlagRef::OverflowUnsigned),
+        ),
+        AluOpcode::Incr => binary("+", a, Value::Const(1)),
+        AluOpcode::Decr => binary("-", a, Value::Const(1)),
+        AluOpcode::Xor => binary("^", a, b),
+        AluOpcode::Neg => Value::Unary("~", Box::new(b)),
+        AluOpcode::Sub => binary("-", a, b),
+        AluOpcode::Or => binary("|", a, b),
+        AluOpcode::And => binary("&", a, b),
+        AluOpcode::Nand => Value::Unary("~", Box::new(binary("&", a, b))),
+        AluOpcode::Nor => Value::Unary("~", Box::new(binary("|", a, b))),
+        AluOpcode::ShiftL => binary("<<", a, b),
+        AluOpcode::ShiftR => binary(">>", a, b),
+        AluOpcode::Echo => a,
+    }
+}
+
+struct Lifter<'a> {
+    cfg: &'a Cfg,
+    parameters: &'a HashMap<Address, usize>,
+    own_parameters: usize,
+    temps: usize,
+}
+
+impl<'a> Lifter<'a> {
+    fn temp(&mut self) -> Var {
+        self.temps += 1;
+        Var::Temp(self.temps)
+    }
+
+    fn lift(&mut self, start: Address, mut depth: i16) -> Lifted {
+        let block = &self.cfg.blocks[&start];
+        let read = |addr: Address, reg: RegisterRef| match reg {
+            RegisterRef::IP => Value::Const(addr),
+            other => Value::Var(Var::Reg(other)),
+        };
+        let writable = |reg: RegisterRef| reg != RegisterRef::FL && reg != RegisterRef::IP;
+
+        let exit_flag = match block.instructions.last() {
+            Some((_, Instruction::Jmp { flag, .. }))
+            | Some((_, Instruction::JmpP { flag, .. }))
+            | Some((_, Instruction::JmpR { flag, .. }))
+            | Some((_, Instruction::JmpRP { flag, .. }))
+                if *flag != AluFlagRef::True && *flag != AluFlagRef::False =>
+            {
+                Some(*flag)
+            }
+            _ => None,
+        };
+        let last_alu = block
+            .instructions
+            .iter()
+            .rposition(|(_, i)| matches!(i, Instruction::Alu { .. }));
+
+        let mut stmts = Vec::new();
+        let mut cond = exit_flag.map(Value::Flag);
+
+        for (index, (addr, instruction)) in block.instructions.iter().enumerate() {
+            let addr = *addr;
+            match instruction {
+                Instruction::Load { dest, addr } => {
+                    stmts.push(Stmt::Assign(
+                        Var::Reg(*dest),
+                        Value::Mem(Box::new(Value::Const(*addr))),
+                    ));
+                }
+                Instruction::LoadP { dest, addr_src } => {
+                    stmts.push(Stmt::Assign(
+                        Var::Reg(*dest),
+                        Value::Mem(Box::new(read(addr, *addr_src))),
+                    ));
+                }
+                Instruction::Store { src, addr: target } => {
+                    stmts.push(Stmt::Store(Value::Const(*target), read(addr, *src)));
+                }
+                Instruction::StoreP { src, addr_src } => {
+                    stmts.push(Stmt::Store(read(addr, *addr_src), read(addr, *src)));
+                }
+                Instruction::Mov { dest, src } if writable(*dest) => {
+                    stmts.push(Stmt::Assign(Var::Reg(*dest), read(addr, *src)));
+                }
+                Instruction::MovC { dest, val } if writable(*dest) => {
+                    stmts.push(Stmt::Assign(Var::Reg(*dest), Value::Const(*val)));
+                }
+                Instruction::Mov { .. } | Instruction::MovC { .. } => {}
+
+                Instruction::Stack(StackInstruction::Push { src }) => {
+                    stmts.push(Stmt::Assign(Var::Local(depth + 1), read(addr, *src)));
+                }
+                Instruction::Stack(StackInstruction::Pop { dest }) => {
+                    if writable(*dest) {
+                        stmts.push(Stmt::Assign(Var::Reg(*dest), Value::Var(Var::Local(depth))));
+                    }
+                }
+                Instruction::Stack(StackInstruction::Load { dest, bp_diff }) => {
+                    let n = *bp_diff as i8;
+                    let value = if n >= 2 {
+                        Value::Param((self.own_parameters + 2).saturating_sub(n as usize))
+                    } else if n >= 0 {
+                        Value::Frame(n as Word)
+                    } else {
+                        Value::Var(Var::Local(-(n as i16)))
+                    };
+                    stmts.push(Stmt::Assign(Var::Reg(*dest), value));
+                }
+                Instruction::Stack(call @ StackInstruction::Call { .. })
+                | Instruction::Stack(call @ StackInstruction::CallC { .. })
+                | Instruction::Stack(call @ StackInstruction::CallR { .. }) => {
+                    let (name, arity) = match call {
+                        StackInstruction::CallC { addr: target } => (
+                            function_name(*target),
+                            self.parameters.get(target).copied().unwrap_or(0),
+                        ),
+                        StackInstruction::CallR { diff } => {
+                            let target = addr.wrapping_add(*diff);
+                            (
+                                function_name(target),
+                                self.parameters.get(&target).copied().unwrap_or(0),
+                            )
+                        }
+                        StackInstruction::Call { addr_reg } => {
+                            (format!("(*{})", read(addr, *addr_reg)), 0)
+                        }
+                        _ => unreachable!(),
+                    };
+                    let args = (0..arity as i16)
+                        .rev()
+                        .map(|i| Value::Var(Var::Local(depth - i)))
+                        .collect();
+                    stmts.push(Stmt::Assign(Var::Local(depth + 1), Value::Call(name, args)));
+                }
+                Instruction::Stack(StackInstruction::Ret { src }) => {
+                    stmts.push(Stmt::Return(read(addr, *src)));
+                }
+
+                Instruction::Gpi { dest } if writable(*dest) => {
+                    stmts.push(Stmt::Assign(Var::Reg(*dest), Value::Input));
+                }
+                Instruction::Gpi { .. } => stmts.push(Stmt::Eval(Value::Input)),
+                Instruction::Gpo { src } => stmts.push(Stmt::Output(read(addr, *src))),
+
+                Instruction::Alu {
+                    op,
+                    arg1,
+                    arg2,
+                    out,
+                } => {
+                    let value = alu_value(*op, read(addr, *arg1), read(addr, *arg2));
+                    match exit_flag {
+                        Some(AluFlagRef::EqZero) if Some(index) == last_alu => {
+                            stmts.push(Stmt::Assign(Var::Reg(*out), value));
+                            let result = self.temp();
+                            stmts.push(Stmt::Assign(result, Value::Var(Var::Reg(*out))));
+                            cond = Some(binary("==", Value::Var(result), Value::Const(0)));
+                        }
+                        Some(flag) if Some(index) == last_alu => {
+                            let a = self.temp();
+                            let b = self.temp();
+                            stmts.push(Stmt::Assign(a, read(addr, *arg1)));
+                            stmts.push(Stmt::Assign(b, read(addr, *arg2)));
+                            stmts.push(Stmt::Assign(Var::Reg(*out), value));
+                            if let Some(c) = comparison(flag, Value::Var(a), Value::Var(b)) {
+                                cond = Some(c);
+                            }
+                        }
+                        _ => stmts.push(Stmt::Assign(Var::Reg(*out), value)),
+                    }
+                }
+
+                Instruction::Nop(NopOpcode::Halt) => stmts.push(Stmt::Halt),
+                Instruction::Nop(NopOpcode::Nop) | Instruction::Jmp { .. } => {}
+                Instruction::JmpP { .. } | Instruction::JmpR { .. } | Instruction::JmpRP { .. } => {
+                }
+            }
+            depth += stack_delta(instruction);
+        }
+
+        let mut taken = None;
+        let mut fall = None;
+        for edge in &block.edges {
+            match edge.kind {
+                EdgeKind::Jump(_) => taken = Some(edge.target),
+                EdgeKind::NotTaken(_) | EdgeKind::Next | EdgeKind::AfterCall => fall = edge.target,
+                EdgeKind::Call => {}
+            }
+        }
+        let exit = match (taken, fall) {
+            (Some(Some(t)), Some(f)) if t != f => Exit::Branch {
+                cond: cond.unwrap_or(Value::Flag(AluFlagRef::True)),
+                taken: t,
+                fall: f,
+            },
+            (Some(Some(t)), _) => Exit::Goto(t),
+            (Some(None), fall) => {
+                let target = match block.instructions.last() {
+                    Some((addr, Instruction::JmpP { addr_src, .. })) => read(*addr, *addr_src),
+                    Some((addr, Instruction::JmpRP { diff_src, .. })) => {
+                        binary("+", Value::Const(*addr), read(*addr, *diff_src))
+                    }
+                    _ => Value::Flag(AluFlagRef::True),
+                };
+                Exit::Indirect {
+                    cond: if fall.is_some() { cond } else { None },
+                    target,
+                    fall,
+                }
+            }
+            (None, Some(f)) => Exit::Goto(f),
+            (None, None) => {
+                let end = block
+                    .instructions
+                    .last()
+                    .map(|(a, _)| a.wrapping_add(2))
+                    .unwrap_or(start);
+                if let Some((_, e)) = self.cfg.errors.iter().find(|(a, _)| *a == end) {
+                    stmts.push(Stmt::Comment(e.clone()));
+                }
+                Exit::Stop
+            }
+        };
+
+        Lifted { stmts, exit }
+    }
+}
+
+/// Fold single-use assignments into their use and drop dead ones, as long as
+/// that doesn't move reads past writes.
+fn simplify(lifted: &mut Lifted, live_out: &HashSet<Var>) {
+    let is_live = |var: Var| !matches!(var, Var::Temp(_)) && live_out.contains(&var);
+    'outer: loop {
+        let stmts = &mut lifted.stmts;
+        for i in 0..stmts.len() {
+            let (var, value) = match &stmts[i] {
+                Stmt::Assign(var, value) => (*var, value.clone()),
+                _ => continue,
+            };
+            if value == Value::Var(var) {
+                stmts.remove(i);
+                continue 'outer;
+            }
+
+            let mut uses = Vec::new();
+            let mut redefined = false;
+            for (j, stmt) in stmts.iter().enumerate().skip(i + 1) {
+                let n = stmt.count(var);
+                if n > 0 {
+                    uses.push((Some(j), n));
+                }
+                if stmt.kills(var) {
+                    redefined = true;
+                    break;
+                }
+            }
+            if !redefined {
+                let n = lifted.exit.count(var);
+                if n > 0 {
+                    uses.push((None, n));
+                }
+                if is_live(var) {
+                    continue;
+                }
+            }
+
+            let total: usize = uses.iter().map(|(_, n)| n).sum();
+            if total == 0 {
+                if value.has_effect() {
+                    if !matches!(value, Value::Var(_)) {
+                        stmts[i] = Stmt::Eval(value);
+                    }
+                    continue;
+                }
+                stmts.remove(i);
+                continue 'outer;
+            }
+            if total > 1 {
+                continue;
+            }
+
+            let use_index = uses[0].0;
+            let end = use_index.unwrap_or(stmts.len());
+            let mut value_vars = Vec::new();
+            value.vars(&mut value_vars);
+            let blocked = stmts[i + 1..end].iter().any(|stmt| {
+                value_vars.iter().any(|v| stmt.kills(*v))
+                    || (value.reads_memory() && stmt.writes_memory())
+                    || (value.has_effect() && stmt.has_effect())
+            }) || (value.has_effect() && end != i + 1);
+            if blocked {
+                continue;
+            }
+
+            match use_index {
+                Some(j) => {
+                    for v in stmts[j].values_mut() {
+                        v.substitute(var, &value);
+                    }
+                }
+                None => {
+                    for v in lifted.exit.values_mut() {
+                        v.substitute(var, &value);
+                    }
+                }
+            }
+            stmts.remove(i);
+            continue 'outer;
+        }
+        break;
+    }
+
+    // `t = x; x = x + 1; if (t < y)` reads better as `if (x++ < y)`
+    let n = lifted.stmts.len();
+    if n >= 2 {
+        if let (Stmt::Assign(temp @ Var::Temp(_), Value::Var(var)), Stmt::Assign(target, value)) =
+            (&lifted.stmts[n - 2], &lifted.stmts[n - 1])
+        {
+            let op = match value {
+                Value::Binary("+", a, b) if **a == Value::Var(*var) && **b == Value::Const(1) => {
+                    Some("++")
+                }
+                Value::Binary("-", a, b) if **a == Value::Var(*var) && **b == Value::Const(1) => {
+                    Some("--")
+                }
+                _ => None,
+            };
+            if let Some(op) = op {
+                if target == var && lifted.exit.count(*temp) == 1 && lifted.exit.count(*var) == 0 {
+                    let (temp, var) = (*temp, *var);
+                    for v in lifted.exit.values_mut() {
+                        v.substitute(temp, &Value::PostIncr(var, op));
+                    }
+                    lifted.stmts.truncate(n - 2);
+                }
+            }
+        }
+    }
+}
+
+/// Dominator sets of the nodes reachable from `entry`.
+fn dominators(
+    nodes: &BTreeSet<u16>,
+    entry: u16,
+    preds: &HashMap<u16, Vec<u16>>,
+    succs: &HashMap<u16, Vec<u16>>,
+) -> HashMap<u16, BTreeSet<u16>> {
+    let mut reachable = BTreeSet::new();
+    let mut stack = vec![entry];
+    while let Some(n) = stack.pop() {
+        if reachable.insert(n) {
+            stack.extend(
+                succs
+                    .get(&n)
+                    .into_iter()
+                    .flatten()
+                    .filter(|s| nodes.contains(s)),
+            );
+        }
+    }
+
+    let mut dom: HashMap<u16, BTreeSet<u16>> = reachable
+        .iter()
+        .map(|n| {
+            if *n == entry {
+                (*n, Some(*n).into_iter().collect())
+            } else {
+                (*n, reachable.clone())
+            }
+        })
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for n in reachable.iter().filter(|n| **n != entry) {
+            let mut new: Option<BTreeSet<u16>> = None;
+            for p in preds
+                .get(n)
+                .into_iter()
+                .flatten()
+                .filter(|p| reachable.contains(p))
+            {
+                new = Some(match new {
+                    None => dom[p].clone(),
+                    Some(acc) => acc.intersection(&dom[p]).copied().collect(),
+                });
+            }
+            let mut new = new.unwrap_or_default();
+            new.insert(*n);
+            if new != dom[n] {
+                dom.insert(*n, new);
+                changed = true;
+            }
+        }
+    }
+    dom
+}
+
+fn immediate(dom: &HashMap<u16, BTreeSet<u16>>, node: u16) -> Option<u16> {
+    let set = dom.get(&node)?;
+    set.iter()
+        .filter(|d| **d != node)
+        .max_by_key(|d| dom[d].len())
+        .copied()
+}
+
+struct Loop {
+    header: Address,
+    exit: Option<Address>,
+    /// The latch whose condition becomes a `do ... while`
+    latch: Option<Address>,
+}
+
+struct FunctionWriter<'a> {
+    blocks: &'a BTreeMap<Address, Lifted>,
+    dom: HashMap<u16, BTreeSet<u16>>,
+    postdom: HashMap<u16, BTreeSet<u16>>,
+    back_edges: Vec<(Address, Address)>,
+    loops: Vec<Loop>,
+    emitted: HashSet<Address>,
+    labels: BTreeSet<Address>,
+    block_lines: Vec<(usize, Address)>,
+    lines: Vec<String>,
+    indent: usize,
+}
+
+impl<'a> FunctionWriter<'a> {
+    fn line(&mut self, text: String) {
+        self.lines
+            .push(format!("{}{}", "    ".repeat(self.indent), text));
+    }
+
+    fn ipdom(&self, node: Address) -> Option<Address> {
+        immediate(&self.postdom, node as u16)
+            .filter(|n| *n != EXIT)
+            .map(|n| n as Address)
+    }
+
+    fn is_header(&self, node: Address) -> bool {
+        self.back_edges.iter().any(|(_, h)| *h == node)
+    }
+
+    /// A `break`, `continue` or `goto` if reaching `node` leaves the
+    /// structured region.
+    fn jump_out(&mut self, node: Address) -> Option<String> {
+        if let Some(l) = self.loops.last() {
+            if node == l.header {
+                return Some("continue;".to_string());
+            }
+            if Some(node) == l.exit {
+                return Some("break;".to_string());
+            }
+        }
+        if self.emitted.contains(&node) || self.loops.iter().any(|l| l.header == node) {
+            self.labels.insert(node);
+            return Some(format!("goto L_{:03};", node));
+        }
+        None
+    }
+
+    fn emit_sequence(&mut self, mut current: Option<Address>, stop: Option<Address>) {
+        while let Some(node) = current {
+            if Some(node) == stop {
+                return;
+            }
+            if let Some(jump) = self.jump_out(node) {
+                self.line(jump);
+                return;
+            }
+            current = if self.is_header(node) {
+                self.emit_loop(node)
+            } else {
+                self.emit_block(node)
+            };
+        }
+    }
+
+    fn emit_statements(&mut self, node: Address) {
+        self.emitted.insert(node);
+        self.block_lines.push((self.lines.len(), node));
+        let blocks = self.blocks;
+        for stmt in &blocks[&node].stmts {
+            self.line(stmt.to_string());
+        }
+    }
+
+    /// Emit a block and any if/else hanging off it, and return where the code
+    /// continues.
+    fn emit_block(&mut self, node: Address) -> Option<Address> {
+        self.emit_statements(node);
+        if self.loops.last().and_then(|l| l.latch) == Some(node) {
+            return None;
+        }
+        let blocks = self.blocks;
+        match &blocks[&node].exit {
+            Exit::Stop => None,
+            Exit::Goto(target) => Some(*target),
+            Exit::Indirect { cond, target, fall } => {
+                match cond {
+                    Some(cond) => self.line(format!("if ({}) goto *({});", cond, target)),
+                    None => self.line(format!("goto *({});", target)),
+                }
+                *fall
+            }
+            Exit::Branch { cond, taken, fall } => {
+                let (cond, taken, fall) = (cond.clone(), *taken, *fall);
+                let exit = self.loops.last().and_then(|l| l.exit);
+                if Some(taken) == exit {
+                    self.line(format!("if ({}) break;", cond));
+                    return Some(fall);
+                } else if Some(fall) == exit {
+                    self.line(format!("if ({}) break;", negate(cond)));
+                    return Some(taken);
+                }
+                let merge = self.ipdom(node);
+                let stops = |n: Address| blocks[&n].exit == Exit::Stop;
+                if merge.is_none() && stops(fall) && !self.emitted.contains(&fall) {
+                    self.emit_if(negate(cond), fall, None, None);
+                    Some(taken)
+                } else if merge.is_none() && stops(taken) && !self.emitted.contains(&taken) {
+                    self.emit_if(cond, taken, None, None);
+                    Some(fall)
+                } else if Some(fall) == merge {
+                    self.emit_if(cond, taken, None, merge);
+                    merge
+                } else if Some(taken) == merge {
+                    self.emit_if(negate(cond), fall, None, merge);
+                    merge
+                } else {
+                    self.emit_if(cond, taken, Some(fall), merge);
+                    merge
+                }
+            }
+        }
+    }
+
+    fn emit_if(
+        &mut self,
+        cond: Value,
+        then: Address,
+        otherwise: Option<Address>,
+        merge: Option<Address>,
+    ) {
+        self.line(format!("if ({}) {{", cond));
+        self.indent += 1;
+        self.emit_sequence(Some(then), merge);
+        self.indent -= 1;
+        if let Some(otherwise) = otherwise {
+            self.line("} else {".to_string());
+            self.indent += 1;
+            self.emit_sequence(Some(otherwise), merge);
+            self.indent -= 1;
+        }
+        self.line("}".to_string());
+    }
+
+    fn emit_loop(&mut self, header: Address) -> Option<Address> {
+        let mut body = BTreeSet::new();
+        body.insert(header);
+        let latches: Vec<Address> = self
+            .back_edges
+            .iter()
+            .filter(|(_, h)| *h == header)
+            .map(|(p, _)| *p)
+            .collect();
+        let mut stack = latches.clone();
+        while let Some(n) = stack.pop() {
+            if body.insert(n) {
+                stack.extend(self.preds(n));
+            }
+        }
+
+        let exits: BTreeSet<Address> = body
+            .iter()
+            .flat_map(|n| self.blocks[n].exit.successors())
+            .filter(|s| !body.contains(s))
+            .collect();
+        let exit = match self.ipdom(header) {
+            Some(p) if exits.contains(&p) => Some(p),
+            _ => exits.iter().next().copied(),
+        };
+
+        let in_loop = |target: &Address| body.contains(target);
+        let header_block = &self.blocks[&header];
+        let while_cond = match &header_block.exit {
+            Exit::Branch { cond, taken, fall }
+                if header_block.stmts.is_empty()
+                    && (in_loop(taken) != in_loop(fall))
+                    && (Some(*taken) == exit || Some(*fall) == exit) =>
+            {
+                if in_loop(taken) {
+                    Some((cond.clone(), *taken))
+                } else {
+                    Some((negate(cond.clone()), *fall))
+                }
+            }
+            _ => None,
+        };
+        let do_while = match (&latches[..], while_cond.is_none()) {
+            ([latch], true) => match &self.blocks[latch].exit {
+                Exit::Branch { cond, taken, fall }
+                    if Some(*taken) == exit || Some(*fall) == exit =>
+                {
+                    if *taken == header {
+                        Some((*latch, cond.clone()))
+                    } else if *fall == header {
+                        Some((*latch, negate(cond.clone())))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+
+        self.loops.push(Loop {
+            header,
+            exit,
+            latch: do_while.as_ref().map(|(latch, _)| *latch),
+        });
+        match (while_cond, do_while) {
+            (Some((cond, first)), _) => {
+                self.emit_statements(header);
+                self.line(format!("while ({}) {{", cond));
+                self.indent += 1;
+                self.emit_sequence(Some(first), Some(header));
+            }
+            (None, Some((latch, cond))) => {
+                self.line("do {".to_string());
+                self.indent += 1;
+                let next = self.emit_block(header);
+                if header != latch {
+                    self.emit_sequence(next, Some(header));
+                }
+                self.indent -= 1;
+                self.line(format!("}} while ({});", cond));
+                self.loops.pop();
+                return exit;
+            }
+            (None, None) => {
+                self.line("while (true) {".to_string());
+                self.indent += 1;
+                let next = self.emit_block(header);
+                self.emit_sequence(next, Some(header));
+            }
+        }
+        self.indent -= 1;
+        self.line("}".to_string());
+        self.loops.pop();
+        exit
+    }
+
+    fn preds(&self, node: Address) -> Vec<Address> {
+        self.blocks
+            .iter()
+            .filter(|(_, b)| b.exit.successors().contains(&node))
+            .map(|(start, _)| *start)
+            .collect()
+    }
+}
+
+/// Registers and locals live at the end of each block.
+fn liveness(blocks: &BTreeMap<Address, Lifted>) -> HashMap<Address, HashSet<Var>> {
+    let mut uses: HashMap<Address, HashSet<Var>> = HashMap::new();
+    let mut defs: HashMap<Address, HashSet<Var>> = HashMap::new();
+    for (start, lifted) in blocks {
+        let mut used = HashSet::new();
+        let mut defined: HashSet<Var> = HashSet::new();
+        let all_values = lifted
+            .stmts
+            .iter()
+            .map(|stmt| (stmt.values(), Some(stmt)))
+            .chain(Some((lifted.exit.values(), None)));
+        for (values, stmt) in all_values {
+            for value in values {
+                let mut vars = Vec::new();
+                value.vars(&mut vars);
+                used.extend(vars.into_iter().filter(|v| !defined.contains(v)));
+            }
+            if let Some(stmt) = stmt {
+                defined.extend(stmt.defines());
+                if stmt.clobbers_registers() {
+                    defined.extend(GENERAL_REGISTERS.iter().map(|r| Var::Reg(*r)));
+                }
+            }
+        }
+        uses.insert(*start, used);
+        defs.insert(*start, defined);
+    }
+
+    let mut live_in: HashMap<Address, HashSet<Var>> =
+        blocks.keys().map(|b| (*b, HashSet::new())).collect();
+    let mut live_out = live_in.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (start, lifted) in blocks.iter().rev() {
+            let out: HashSet<Var> = lifted
+                .exit
+                .successors()
+                .iter()
+                .filter_map(|s| live_in.get(s))
+                .flatten()
+                .copied()
+                .collect();
+            let mut inn: HashSet<Var> = out.difference(&defs[start]).copied().collect();
+            inn.extend(uses[start].iter().copied());
+            if inn != live_in[start] || out != live_out[start] {
+                live_in.insert(*start, inn);
+                live_out.insert(*start, out);
+                changed = true;
+            }
+        }
+    }
+    live_out
+}
+
+fn decompile_function(
+    cfg: &Cfg,
+    entry: Address,
+    parameters: &HashMap<Address, usize>,
+) -> Vec<String> {
+    let function = &cfg.functions[&entry];
+    let mut lifter = Lifter {
+        cfg,
+        parameters,
+        own_parameters: parameters[&entry],
+        temps: 0,
+    };
+
+    let mut depths = HashMap::new();
+    let mut blocks = BTreeMap::new();
+    let mut stack = vec![(entry, 0)];
+    while let Some((start, depth)) = stack.pop() {
+        if !function.blocks.contains(&start) || depths.contains_key(&start) {
+            continue;
+        }
+        depths.insert(start, depth);
+        let lifted = lifter.lift(start, depth);
+        let out_depth = depth
+            + cfg.blocks[&start]
+                .instructions
+                .iter()
+                .map(|(_, i)| stack_delta(i))
+                .sum::<i16>();
+        for succ in lifted.exit.successors() {
+            stack.push((succ, out_depth));
+        }
+        blocks.insert(start, lifted);
+    }
+
+    // Each round of folding can make more assignments dead
+    loop {
+        let live_out = liveness(&blocks);
+        let before = blocks.clone();
+        for (start, lifted) in blocks.iter_mut() {
+            simplify(lifted, &live_out[start]);
+        }
+        if blocks == before {
+            break;
+        }
+    }
+
+    let nodes: BTreeSet<u16> = blocks.keys().map(|b| *b as u16).collect();
+    let mut succs: HashMap<u16, Vec<u16>> = HashMap::new();
+    let mut preds: HashMap<u16, Vec<u16>> = HashMap::new();
+    for (start, lifted) in &blocks {
+        let mut out: Vec<u16> = lifted.exit.successors().iter().map(|s| *s as u16).collect();
+        if out.is_empty() {
+            out.push(EXIT);
+        }
+        for s in &out {
+            preds.entry(*s).or_default().push(*start as u16);
+        }
+        succs.insert(*start as u16, out);
+    }
+    let dom = dominators(&nodes, entry as u16, &preds, &succs);
+    let mut all = nodes.clone();
+    all.insert(EXIT);
+    let postdom = dominators(&all, EXIT, &succs, &preds);
+
+    let back_edges = succs
+        .iter()
+        .flat_map(|(p, out)| out.iter().map(move |s| (*p, *s)))
+        .filter(|(p, s)| dom.get(p).map(|d| d.contains(s)).unwrap_or(false))
+        .map(|(p, s)| (p as Address, s as Address))
+        .collect();
+
+    let mut writer = FunctionWriter {
+        blocks: &blocks,
+        dom,
+        postdom,
+        back_edges,
+        loops: Vec::new(),
+        emitted: HashSet::new(),
+        labels: BTreeSet::new(),
+        block_lines: Vec::new(),
+        lines: Vec::new(),
+        indent: 1,
+    };
+    writer.emit_sequence(Some(entry), None);
+
+    // Blocks only reachable through a goto into the middle of a region
+    let mut leftover: Vec<Address> = blocks
+        .keys()
+        .filter(|b| !writer.emitted.contains(b) && writer.dom.contains_key(&(**b as u16)))
+        .copied()
+        .collect();
+    while let Some(start) = leftover.pop() {
+        if !writer.emitted.contains(&start) {
+            writer.labels.insert(start);
+            writer.emit_sequence(Some(start), None);
+        }
+    }
+
+    let mut lines = writer.lines;
+    for (index, start) in writer.block_lines.iter().rev() {
+        if writer.labels.contains(start) {
+            lines.insert(*index, format!("L_{:03}:", start));
+        }
+    }
+
+    let arguments: Vec<String> = (1..=parameters[&entry])
+        .map(|i| format!("u8 arg{}", i))
+        .collect();
+    let mut out = vec![if entry == 0 {
+        "void main() {".to_string()
+    } else {
+        format!("u8 {}({}) {{", function_name(entry), arguments.join(", "))
+    }];
+    out.extend(lines);
+    out.push("}".to_string());
+    out
+}
+
+/// Print the functions of a recovered control flow graph as C-like
+/// pseudocode. Arguments are numbered in the order they are pushed, and
+/// values pushed within a function are named after their stack slot.
+pub fn decompile(cfg: &Cfg) -> String {
+    let parameters: HashMap<Address, usize> = cfg
+        .functions
+        .keys()
+        .map(|entry| (*entry, parameter_count(cfg, *entry)))
+        .collect();
+
+    cfg.functions
+        .keys()
+        .map(|entry| decompile_function(cfg, *entry, &parameters).join("\n") + "\n")
+        .collect::<Vec<String>>()
+        .join("\n")
+}
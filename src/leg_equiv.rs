@@ -0,0 +1,247 @@
+use super::leg_computer::Address;
+use super::leg_computer::LegComputer;
+use super::leg_computer::Word;
+use super::leg_symbolic::SymbolicInput;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+
+/// Where two runs on the same input first differ.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Divergence {
+    /// Both halted, with different values at `addr` in the output region
+    Memory {
+        addr: Address,
+        left: Word,
+        right: Word,
+    },
+    /// At least one run did not halt, or failed differently than the other
+    Outcome { left: String, right: String },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Counterexample {
+    /// Input byte `i` is placed at the address of input `i`
+    pub input: Vec<Word>,
+    pub divergence: Divergence,
+}
+
+impl Display for Counterexample {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "input {:?}: ", self.input)?;
+        match &self.divergence {
+            Divergence::Memory { addr, left, right } => write!(
+                f,
+                "memory {:03} differs: {} (left) vs {} (right)",
+                addr, left, right
+            ),
+            Divergence::Outcome { left, right } => {
+                write!(f, "{} (left) vs {} (right)", left, right)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Equivalence {
+    /// No difference found in `runs` inputs, which was all of them if
+    /// `exhaustive`
+    Equivalent {
+        runs: usize,
+        exhaustive: bool,
+    },
+    Counterexample(Counterexample),
+}
+
+/// Compares the final memory of two programs run on the same inputs.
+pub struct EquivalenceChecker {
+    pub left: Vec<Word>,
+    pub right: Vec<Word>,
+    /// Memory image that the inputs are written into
+    pub memory: Vec<Word>,
+    pub inputs: Vec<SymbolicInput>,
+    /// Start and length of the memory region that must match after halting
+    pub output: (Address, usize),
+    pub max_steps: usize,
+    /// Enumerate all inputs if there are at most this many, otherwise sample
+    pub max_exhaustive: usize,
+    pub samples: usize,
+    pub seed: u64,
+}
+
+/// Why a run didn't halt.
+struct Failure {
+    /// Where it faulted, or None if it ran out of steps
+    eip: Option<Address>,
+    /// What went wrong. Runs are compared by this alone, since equivalent
+    /// programs may fault at different addresses.
+    reason: String,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self.eip {
+            Some(eip) => write!(f, "fault at {:03}: {}", eip, self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+/// Run until halted, or describe why the run didn't halt.
+fn run(program: &[Word], memory: Vec<Word>, max_steps: usize) -> Result<LegComputer, Failure> {
+    let mut computer = LegComputer::new(program.to_vec(), memory);
+    for _ in 0..max_steps {
+        if computer.is_halted() {
+            return Ok(computer);
+        }
+        if let Err(e) = computer.try_step() {
+            // The error names the address, which depends on the program length
+            let reason = if computer.eip as usize + 1 >= program.len() {
+                "ran past the end of the program".to_string()
+            } else {
+                e
+            };
+            return Err(Failure {
+                eip: Some(computer.eip),
+                reason,
+            });
+        }
+    }
+    if computer.is_halted() {
+        Ok(computer)
+    } else {
+        Err(Failure {
+            eip: None,
+            reason: format!("not halted after {} steps", max_steps),
+        })
+    }
+}
+
+impl EquivalenceChecker {
+    pub fn new(
+        left: Vec<Word>,
+        right: Vec<Word>,
+        memory: Vec<Word>,
+        inputs: Vec<SymbolicInput>,
+    ) -> EquivalenceChecker {
+        EquivalenceChecker {
+            left,
+            right,
+            memory,
+            inputs,
+            output: (0, 256),
+            max_steps: 100_000,
+            max_exhaustive: 1 << 16,
+            samples: 10_000,
+            seed: 0,
+        }
+    }
+
+    /// Number of distinct inputs, or None if more than `usize::MAX`.
+    pub fn input_space(&self) -> Option<usize> {
+        self.inputs
+            .iter()
+            .try_fold(1usize, |n, input| n.checked_mul(input.domain.len()))
+    }
+
+    /// Run both programs on one input and compare the outcomes.
+    pub fn compare(&self, input: &[Word]) -> Option<Divergence> {
+        let mut memory = self.memory.clone();
+        memory.resize(256, 0);
+        for (spec, value) in self.inputs.iter().zip(input) {
+            memory[spec.addr as usize] = *value;
+        }
+
+        let left = run(&self.left, memory.clone(), self.max_steps);
+        let right = run(&self.right, memory, self.max_steps);
+        match (left, right) {
+            (Ok(left), Ok(right)) => {
+                let (start, len) = self.output;
+                (start as usize..usize::min(start as usize + len, 256))
+                    .find(|i| left.memory[*i] != right.memory[*i])
+                    .map(|i| Divergence::Memory {
+                        addr: i as Address,
+                        left: left.memory[i],
+                        right: right.memory[i],
+                    })
+            }
+            (Err(left), Err(right)) if left.reason == right.reason => None,
+            (left, right) => {
+                let describe = |r: Result<LegComputer, Failure>| match r {
+                    Ok(_) => "halted".to_string(),
+                    Err(e) => e.to_string(),
+                };
+                Some(Divergence::Outcome {
+                    left: describe(left),
+                    right: describe(right),
+                })
+            }
+        }
+    }
+
+    fn counterexample(&self, input: &[Word]) -> Option<Counterexample> {
+        self.compare(input).map(|divergence| Counterexample {
+            input: input.to_vec(),
+            divergence,
+        })
+    }
+
+    /// Check every input if the input space is small enough, otherwise
+    /// `samples` random ones.
+    pub fn check(&self) -> Equivalence {
+        if self.inputs.iter().any(|i| i.domain.is_empty()) {
+            return Equivalence::Equivalent {
+                runs: 0,
+                exhaustive: true,
+            };
+        }
+
+        match self.input_space() {
+            Some(space) if space <= self.max_exhaustive => {
+                let mut choice = vec![0; self.inputs.len()];
+                for _ in 0..space {
+                    let input: Vec<Word> = self
+                        .inputs
+                        .iter()
+                        .zip(&choice)
+                        .map(|(spec, i)| spec.domain[*i])
+                        .collect();
+                    if let Some(counterexample) = self.counterexample(&input) {
+                        return Equivalence::Counterexample(counterexample);
+                    }
+                    for (c, spec) in choice.iter_mut().zip(&self.inputs) {
+                        *c += 1;
+                        if *c < spec.domain.len() {
+                            break;
+                        }
+                        *c = 0;
+                    }
+                }
+                Equivalence::Equivalent {
+                    runs: space,
+                    exhaustive: true,
+                }
+            }
+            _ => {
+                let mut rng = StdRng::seed_from_u64(self.seed);
+                for _ in 0..self.samples {
+                    let input: Vec<Word> = self
+                        .inputs
+                        .iter()
+                        .map(|spec| spec.domain[rng.gen_range(0, spec.domain.len())])
+                        .collect();
+                    if let Some(counterexample) = self.counterexample(&input) {
+                        return Equivalence::Counterexample(counterexample);
+                    }
+                }
+                Equivalence::Equivalent {
+                    runs: self.samples,
+                    exhaustive: false,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use super::leg_challenge::CHALLENGE_PROGRAM;
+use super::leg_challenge::PLAIN_PROGRAM;
+use super::leg_computer::Address;
+use super::leg_computer::Word;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// How a program checks its input against the solution in memory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Checker {
+    /// The challenge: copy the input after itself, sort the copy, and compare
+    /// the xor of the two with the solution
+    SortXor,
+    /// Compare the input with the flag byte by byte, as challenge/plain.leg
+    /// does
+    Plain,
+}
+
+impl FromStr for Checker {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sort-xor" => Ok(Checker::SortXor),
+            "plain" => Ok(Checker::Plain),
+            other => Err(format!("Invalid checker: {}", other)),
+        }
+    }
+}
+
+impl Checker {
+    /// The program image of the checker.
+    pub fn program(&self) -> &'static [u8] {
+        match self {
+            Checker::SortXor => CHALLENGE_PROGRAM,
+            Checker::Plain => PLAIN_PROGRAM,
+        }
+    }
+
+    /// The bytes that the checker compares the input with, for `flag`.
+    pub fn solution(&self, flag: &[Word]) -> Vec<Word> {
+        match self {
+            Checker::SortXor => {
+                let mut sorted = flag.to_vec();
+                sorted.sort_unstable();
+                flag.iter().zip(&sorted).map(|(a, b)| a ^ b).collect()
+            }
+            Checker::Plain => flag.to_vec(),
+        }
+    }
+
+    /// Bytes after an input of `len` bytes that the checker writes to.
+    pub fn scratch(&self, len: usize) -> usize {
+        match self {
+            Checker::SortXor => len,
+            Checker::Plain => 0,
+        }
+    }
+
+    /// Bytes at the end of memory that the stack grows into while checking
+    /// an input of `len` bytes.
+    pub fn stack(&self, len: usize) -> usize {
+        match self {
+            // Measured on the challenge: the quicksort recurses once per
+            // byte of sorted input, 5 bytes at a time
+            Checker::SortXor => (5 * len).saturating_sub(1).max(8),
+            // challenge/plain.leg keeps everything in registers
+            Checker::Plain => 0,
+        }
+    }
+}
+
+/// Where `FlagMemory::build` put everything. Ranges are a start address and
+/// a length.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FlagLayout {
+    pub checker: Checker,
+    pub ok: (Address, usize),
+    pub err: (Address, usize),
+    pub solution: (Address, usize),
+    /// Start of the input, which the checker's scratch space follows
+    pub input: Address,
+    /// Lowest address of the stack when checking an input as long as the
+    /// flag
+    pub stack: usize,
+}
+
+impl Display for FlagLayout {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let range =
+            |(start, len): (Address, usize)| format!("{:03}-{:03}", start, start as usize + len);
+        writeln!(f, "checker:  {:?}", self.checker)?;
+        writeln!(f, "ok:       {}", range(self.ok))?;
+        writeln!(f, "err:      {}", range(self.err))?;
+        writeln!(f, "solution: {}", range(self.solution))?;
+        writeln!(f, "input:    {:03}", self.input)?;
+        write!(f, "stack:    {:03}-256", self.stack)
+    }
+}
+
+/// Ok if an input of `len` bytes at `start`, the checker's scratch space and
+/// the stack fit in memory together, otherwise the end of the free memory
+/// after `start`. The end of the input must fit in a word, so address 255 is
+/// never free.
+fn fits(checker: Checker, start: usize, len: usize) -> Result<(), usize> {
+    let free = 256usize.saturating_sub(checker.stack(len)).min(255);
+    if start + len + checker.scratch(len) <= free {
+        Ok(())
+    } else {
+        Err(free.max(start))
+    }
+}
+
+/// An initial memory image for a flag, and its layout.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FlagImage {
+    pub memory: Vec<Word>,
+    pub layout: FlagLayout,
+}
+
+impl FlagImage {
+    /// The memory with `input` placed at the start of the input, and the end
+    /// of the input written to address 1. Fails if the input, the checker's
+    /// scratch space and the stack don't fit in memory together.
+    pub fn with_input(&self, input: &[Word]) -> Result<Vec<Word>, String> {
+        let start = self.layout.input as usize;
+        let end = start + input.len();
+        if let Err(free) = fits(self.layout.checker, start, input.len()) {
+            return Err(format!(
+                "Input of {} bytes does not fit in memory: {:03}-{:03} are free",
+                input.len(),
+                start,
+                free
+            ));
+        }
+        let mut memory = self.memory.clone();
+        memory[1] = end as Word;
+        memory[start..end].copy_from_slice(input);
+        Ok(memory)
+    }
+}
+
+/// Lays out the initial memory of a checker like the challenge's: pointers to
+/// the input, solution and output strings at addresses 0 to 7, then the
+/// output strings and the solution, then the input.
+pub struct FlagMemory {
+    pub checker: Checker,
+    /// Written to address 0 if the input is correct
+    pub ok: Vec<Word>,
+    /// Written to address 0 if the input is incorrect
+    pub err: Vec<Word>,
+    pub ok_addr: Address,
+    pub err_addr: Address,
+    pub solution_addr: Address,
+}
+
+/// Addresses 0 to 7 hold the pointers that the checker reads.
+pub const POINTERS: usize = 8;
+
+impl FlagMemory {
+    /// The layout of the challenge.
+    pub fn new(checker: Checker) -> FlagMemory {
+        FlagMemory {
+            checker,
+            ok: b"OK!".to_vec(),
+            err: b"ERR".to_vec(),
+            ok_addr: 8,
+            err_addr: 12,
+            solution_addr: 16,
+        }
+    }
+
+    /// The memory image for `flag`, with an empty input after everything
+    /// else. Fails if regions overlap, or if the flag wouldn't fit as input.
+    pub fn build(&self, flag: &[Word]) -> Result<FlagImage, String> {
+        let solution = self.checker.solution(flag);
+        // The checker copies the output strings up to their end inclusive,
+        // so each takes a byte more than its length
+        let regions = [
+            ("pointers", 0, POINTERS),
+            ("OK string", self.ok_addr as usize, self.ok.len() + 1),
+            ("ERR string", self.err_addr as usize, self.err.len() + 1),
+            ("solution", self.solution_addr as usize, solution.len()),
+        ];
+        for (i, &(name, start, len)) in regions.iter().enumerate() {
+            if start + len > 256 {
+                return Err(format!(
+                    "Does not fit in memory: {} at {:03}-{:03}",
+                    name,
+                    start,
+                    start + len
+                ));
+            }
+            for &(other, other_start, other_len) in &regions[..i] {
+                if start < other_start + other_len && other_start < start + len {
+                    return Err(format!(
+                        "Regions overlap: {} at {:03}-{:03} and {} at {:03}-{:03}",
+                        other,
+                        other_start,
+                        other_start + other_len,
+                        name,
+                        start,
+                        start + len,
+                    ));
+                }
+            }
+        }
+
+        // Past the end of memory if the regions reach it, which `fits` rejects
+        let input = regions
+            .iter()
+            .map(|(_, start, len)| start + len)
+            .max()
+            .unwrap_or(POINTERS);
+        let end = |(start, len): (Address, usize)| (start as usize + len) as Word;
+        let layout = FlagLayout {
+            checker: self.checker,
+            ok: (self.ok_addr, self.ok.len()),
+            err: (self.err_addr, self.err.len()),
+            solution: (self.solution_addr, solution.len()),
+            input: input as Address,
+            stack: 256usize.saturating_sub(self.checker.stack(flag.len())),
+        };
+        // Only challenge/plain.leg reads the end of the solution
+        let solution_end = match self.checker {
+            Checker::SortXor => 0,
+            Checker::Plain => end(layout.solution),
+        };
+
+        let mut memory = vec![0; 256];
+        memory[0..POINTERS].copy_from_slice(&[
+            layout.input,
+            layout.input,
+            self.solution_addr,
+            solution_end,
+            self.ok_addr,
+            end(layout.ok),
+            self.err_addr,
+            end(layout.err),
+        ]);
+        for (addr, bytes) in &[
+            (self.ok_addr, &self.ok),
+            (self.err_addr, &self.err),
+            (self.solution_addr, &solution),
+        ] {
+            let addr = *addr as usize;
+            memory[addr..addr + bytes.len()].copy_from_slice(bytes);
+        }
+
+        if let Err(free) = fits(self.checker, input, flag.len()) {
+            return Err(format!(
+                "Flag of {} bytes does not fit in memory: {:03}-{:03} are free",
+                flag.len(),
+                input,
+                free
+            ));
+        }
+        Ok(FlagImage { memory, layout })
+    }
+}
+
+/// The challenge's memory image for `flag`, checked by `checker`.
+pub fn flag_image(flag: &[Word], checker: Checker) -> Result<FlagImage, String> {
+    FlagMemory::new(checker).build(flag)
+}
@@ -0,0 +1,333 @@
+use super::leg_computer::Address;
+use super::leg_computer::AluFlagRef;
+use super::leg_computer::Instruction;
+use super::leg_computer::LegComputer;
+use super::leg_computer::NopOpcode;
+use super::leg_computer::Word;
+use super::leg_symbolic::Target;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+use std::path::PathBuf;
+
+/// How a single run of the program ended.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Halted,
+    /// The program reached the fuzzer's target
+    Target,
+    /// Stepping failed, e.g. because eip left the program
+    Fault(String),
+    /// The step budget ran out
+    Timeout,
+}
+
+impl Outcome {
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Halted => "halted",
+            Outcome::Target => "target",
+            Outcome::Fault(_) => "fault",
+            Outcome::Timeout => "timeout",
+        }
+    }
+}
+
+/// A conditional jump at an address, whether it was taken, and the hit count
+/// bucket. Bucketing the counts makes a loop running more iterations than
+/// before count as new coverage, without every extra iteration doing so.
+pub type CoverageEdge = (Address, bool, u8);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Execution {
+    pub outcome: Outcome,
+    /// eip when the run ended
+    pub eip: Address,
+    pub steps: usize,
+    pub coverage: BTreeSet<CoverageEdge>,
+}
+
+/// An input that crashed, timed out or reached the target.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+    pub input: Vec<Word>,
+    pub outcome: Outcome,
+    pub eip: Address,
+}
+
+impl Finding {
+    /// File name for the finding, unique per outcome and eip.
+    pub fn file_name(&self) -> String {
+        format!("{}-{:03}", self.outcome.name(), self.eip)
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FuzzStats {
+    pub executions: usize,
+    pub corpus: usize,
+    pub edges: usize,
+    pub faults: usize,
+    pub timeouts: usize,
+    pub targets: usize,
+}
+
+impl Display for FuzzStats {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(
+            f,
+            "execs: {}, corpus: {}, edges: {}, faults: {}, timeouts: {}, targets: {}",
+            self.executions, self.corpus, self.edges, self.faults, self.timeouts, self.targets
+        )
+    }
+}
+
+fn bucket(count: usize) -> u8 {
+    match count {
+        0..=3 => count as u8,
+        4..=7 => 4,
+        8..=15 => 5,
+        16..=31 => 6,
+        32..=127 => 7,
+        _ => 8,
+    }
+}
+
+const INTERESTING: [Word; 8] = [0, 1, 0x20, 0x41, 0x61, 0x7f, 0x80, 0xff];
+
+/// Mutates the input region of a memory image, keeping inputs that reach new
+/// conditional jump edges.
+pub struct Fuzzer {
+    pub program: Vec<Word>,
+    /// Memory image that the input is written into before each run
+    pub memory: Vec<Word>,
+    pub input_start: Address,
+    /// At most `255 - input_start`, so that the end of the input fits in a
+    /// word
+    pub max_input_len: usize,
+    /// Where to store the end address of the input, like address 1 of the
+    /// challenge
+    pub input_end_pointer: Option<Address>,
+    pub max_steps: usize,
+    pub target: Option<Target>,
+    /// Directory to save findings to, as raw input bytes
+    pub output_dir: Option<PathBuf>,
+    pub corpus: Vec<Vec<Word>>,
+    pub findings: Vec<Finding>,
+    pub stats: FuzzStats,
+    coverage: BTreeSet<CoverageEdge>,
+    rng: StdRng,
+}
+
+impl Fuzzer {
+    pub fn new(
+        program: Vec<Word>,
+        memory: Vec<Word>,
+        input_start: Address,
+        max_input_len: usize,
+        seed: u64,
+    ) -> Fuzzer {
+        Fuzzer {
+            program,
+            memory,
+            input_start,
+            max_input_len: usize::min(max_input_len, 255 - input_start as usize),
+            input_end_pointer: None,
+            max_steps: 100_000,
+            target: None,
+            output_dir: None,
+            corpus: Vec::new(),
+            findings: Vec::new(),
+            stats: FuzzStats::default(),
+            coverage: BTreeSet::new(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Run the program once with `input` in memory.
+    pub fn execute(&self, input: &[Word]) -> Execution {
+        let mut memory = self.memory.clone();
+        memory.resize(256, 0);
+        let start = self.input_start as usize;
+        let max_len = usize::min(self.max_input_len, 255 - start);
+        let input = &input[..usize::min(input.len(), max_len)];
+        memory[start..start + input.len()].copy_from_slice(input);
+        if let Some(pointer) = self.input_end_pointer {
+            memory[pointer as usize] = (start + input.len()) as Word;
+        }
+
+        let mut computer = LegComputer::new(self.program.clone(), memory);
+        let mut hits: BTreeMap<(Address, bool), usize> = BTreeMap::new();
+        let mut steps = 0;
+        let outcome = loop {
+            if let Some(Target::Reach(addr)) = &self.target {
+                if computer.eip == *addr {
+                    break Outcome::Target;
+                }
+            }
+            let instruction = match computer.current_instruction() {
+                Ok(instruction) => instruction,
+                Err(e) => break Outcome::Fault(e),
+            };
+            match instruction {
+                Instruction::Nop(NopOpcode::Halt) => match &self.target {
+                    Some(Target::HaltWithMemory { addr, bytes })
+                        if computer.memory[*addr as usize..].starts_with(bytes) =>
+                    {
+                        break Outcome::Target
+                    }
+                    _ => break Outcome::Halted,
+                },
+                Instruction::Jmp { flag, .. }
+                | Instruction::JmpP { flag, .. }
+                | Instruction::JmpR { flag, .. }
+                | Instruction::JmpRP { flag, .. }
+                    if flag != AluFlagRef::True && flag != AluFlagRef::False =>
+                {
+                    *hits
+                        .entry((computer.eip, computer.flags.get(&flag)))
+                        .or_insert(0) += 1;
+                }
+                _ => {}
+            }
+            if steps >= self.max_steps {
+                break Outcome::Timeout;
+            }
+            if let Err(e) = computer.try_step() {
+                break Outcome::Fault(e);
+            }
+            steps += 1;
+        };
+
+        Execution {
+            outcome,
+            eip: computer.eip,
+            steps,
+            coverage: hits
+                .into_iter()
+                .map(|((eip, taken), count)| (eip, taken, bucket(count)))
+                .collect(),
+        }
+    }
+
+    /// Run `input` and keep it in the corpus whether or not it is interesting.
+    pub fn add_seed(&mut self, input: Vec<Word>) -> Result<Execution, String> {
+        let execution = self.execute(&input);
+        self.record(&input, &execution)?;
+        self.corpus.push(input);
+        self.stats.corpus = self.corpus.len();
+        Ok(execution)
+    }
+
+    /// Record the coverage and any finding of an execution. Returns true if
+    /// it reached new edges.
+    fn record(&mut self, input: &[Word], execution: &Execution) -> Result<bool, String> {
+        self.stats.executions += 1;
+        let before = self.coverage.len();
+        self.coverage.extend(execution.coverage.iter().cloned());
+        self.stats.edges = self.coverage.len();
+
+        match execution.outcome {
+            Outcome::Halted => {}
+            Outcome::Target => self.stats.targets += 1,
+            Outcome::Fault(_) => self.stats.faults += 1,
+            Outcome::Timeout => self.stats.timeouts += 1,
+        }
+        if execution.outcome != Outcome::Halted {
+            let finding = Finding {
+                input: input.to_vec(),
+                outcome: execution.outcome.clone(),
+                eip: execution.eip,
+            };
+            if !self
+                .findings
+                .iter()
+                .any(|f| f.file_name() == finding.file_name())
+            {
+                if let Some(dir) = &self.output_dir {
+                    let path = dir.join(finding.file_name());
+                    std::fs::write(&path, &finding.input)
+                        .map_err(|e| format!("{}: {}", path.display(), e))?;
+                }
+                self.findings.push(finding);
+            }
+        }
+
+        Ok(self.coverage.len() > before)
+    }
+
+    fn mutate(&mut self, input: &[Word]) -> Vec<Word> {
+        let mut input = input.to_vec();
+        let rounds = 1 << self.rng.gen_range(0, 3);
+        for _ in 0..rounds {
+            let len = input.len();
+            match self.rng.gen_range(0, 8) {
+                0 if len > 0 => {
+                    let i = self.rng.gen_range(0, len);
+                    input[i] ^= 1 << self.rng.gen_range(0, 8);
+                }
+                1 if len > 0 => {
+                    let i = self.rng.gen_range(0, len);
+                    input[i] = self.rng.gen();
+                }
+                2 if len > 0 => {
+                    let i = self.rng.gen_range(0, len);
+                    input[i] = INTERESTING[self.rng.gen_range(0, INTERESTING.len())];
+                }
+                3 if len > 0 => {
+                    let i = self.rng.gen_range(0, len);
+                    let delta: i8 = self.rng.gen_range(-16, 17);
+                    input[i] = input[i].wrapping_add(delta as Word);
+                }
+                4 if len < self.max_input_len => {
+                    let i = self.rng.gen_range(0, len + 1);
+                    input.insert(i, self.rng.gen());
+                }
+                5 if len > 0 => {
+                    let i = self.rng.gen_range(0, len);
+                    input.remove(i);
+                }
+                6 if len > 0 && len < self.max_input_len => {
+                    let i = self.rng.gen_range(0, len);
+                    input.insert(i, input[i]);
+                }
+                7 if !self.corpus.is_empty() => {
+                    let other = &self.corpus[self.rng.gen_range(0, self.corpus.len())];
+                    let split = self.rng.gen_range(0, usize::min(len, other.len()) + 1);
+                    input.truncate(split);
+                    input.extend_from_slice(&other[split..]);
+                }
+                _ => {
+                    if len < self.max_input_len {
+                        input.push(self.rng.gen());
+                    }
+                }
+            }
+        }
+        input.truncate(self.max_input_len);
+        input
+    }
+
+    /// Mutate corpus entries `iterations` times, seeding the corpus with the
+    /// empty input if it is empty.
+    pub fn run(&mut self, iterations: usize) -> Result<&FuzzStats, String> {
+        if self.corpus.is_empty() {
+            self.add_seed(Vec::new())?;
+        }
+        for _ in 0..iterations {
+            let parent = self.corpus[self.rng.gen_range(0, self.corpus.len())].clone();
+            let input = self.mutate(&parent);
+            let execution = self.execute(&input);
+            if self.record(&input, &execution)? && execution.outcome != Outcome::Timeout {
+                self.corpus.push(input);
+                self.stats.corpus = self.corpus.len();
+            }
+        }
+        Ok(&self.stats)
+    }
+}
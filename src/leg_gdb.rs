@@ -0,0 +1,374 @@
+use super::leg_computer::LegComputer;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::Word;
+use super::leg_debugger::Debugger;
+use super::leg_debugger::StopReason;
+use std::io::Read;
+use std::io::Write;
+
+/// GDB register numbers, in the order of the `g` packet and the target
+/// description.
+pub const REGISTERS: [RegisterRef; 8] = [
+    RegisterRef::A,
+    RegisterRef::B,
+    RegisterRef::C,
+    RegisterRef::D,
+    RegisterRef::FL,
+    RegisterRef::ST,
+    RegisterRef::BP,
+    RegisterRef::IP,
+];
+
+/// The program and data memories are separate, so they are mapped into one
+/// GDB address space the same way as on AVR: program memory at 0 and data
+/// memory at this offset.
+pub const DATA_OFFSET: usize = 0x80_0000;
+
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.emlun.leg.core">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="b" bitsize="8" type="uint8" regnum="1"/>
+    <reg name="c" bitsize="8" type="uint8" regnum="2"/>
+    <reg name="d" bitsize="8" type="uint8" regnum="3"/>
+    <reg name="fl" bitsize="8" type="uint8" regnum="4"/>
+    <reg name="st" bitsize="8" type="data_ptr" regnum="5"/>
+    <reg name="bp" bitsize="8" type="data_ptr" regnum="6"/>
+    <reg name="ip" bitsize="8" type="code_ptr" regnum="7"/>
+  </feature>
+</target>
+"#;
+
+pub const MEMORY_MAP_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE memory-map PUBLIC "+//IDN gnu.org//DTD GDB Memory Map V1.0//EN" "http://sourceware.org/gdb/gdb-memory-map.dtd">
+<memory-map>
+  <memory type="rom" start="0x0" length="0x100"/>
+  <memory type="ram" start="0x800000" length="0x100"/>
+</memory-map>
+"#;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Reply {
+    Send(String),
+    SendAndClose(String),
+    Close,
+}
+
+pub struct GdbStub {
+    pub debugger: Debugger,
+    /// Why execution last stopped, for the `?` packet
+    stop: StopReason,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) {
+        return Err(format!("Odd length hex string: {}", s));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("Invalid hex: {}", s))
+        })
+        .collect()
+}
+
+fn parse_hex_usize(s: &str) -> Result<usize, String> {
+    usize::from_str_radix(s, 16).map_err(|_| format!("Invalid hex number: {}", s))
+}
+
+/// Parse `addr,length` as used by the `m`, `M` and `Z` packets.
+fn parse_addr_len(s: &str) -> Result<(usize, usize), String> {
+    let mut parts = s.splitn(2, ',');
+    match (parts.next(), parts.next()) {
+        (Some(addr), Some(len)) => Ok((parse_hex_usize(addr)?, parse_hex_usize(len)?)),
+        _ => Err(format!("Invalid address and length: {}", s)),
+    }
+}
+
+/// Answer a `qXfer:...:read` request for `offset,length` of `document`.
+fn xfer_chunk(document: &str, range: &str) -> Result<String, String> {
+    let (offset, len) = parse_addr_len(range)?;
+    let bytes = document.as_bytes();
+    if offset >= bytes.len() {
+        return Ok("l".to_string());
+    }
+    let end = usize::min(offset.saturating_add(len), bytes.len());
+    let prefix = if end == bytes.len() { "l" } else { "m" };
+    Ok(format!(
+        "{}{}",
+        prefix,
+        String::from_utf8_lossy(&bytes[offset..end])
+    ))
+}
+
+impl GdbStub {
+    pub fn new(computer: LegComputer) -> GdbStub {
+        let stop = if computer.is_halted() {
+            StopReason::Halted
+        } else {
+            StopReason::Stepped
+        };
+        GdbStub {
+            debugger: Debugger::new(computer),
+            stop,
+        }
+    }
+
+    fn computer(&mut self) -> &mut LegComputer {
+        &mut self.debugger.computer
+    }
+
+    /// The stop reply packet for why execution stopped.
+    pub fn stop_reply(reason: &StopReason) -> String {
+        match reason {
+            StopReason::Stepped => "S05".to_string(),
+            StopReason::Breakpoint(_) => "T05swbreak:;".to_string(),
+            StopReason::Watchpoint { addr, .. } => {
+                format!("T05watch:{:x};", DATA_OFFSET + *addr as usize)
+            }
+            StopReason::Halted => "W00".to_string(),
+            StopReason::Fault(_) => "S04".to_string(),
+            StopReason::StepLimit => "S02".to_string(),
+        }
+    }
+
+    fn memory_mut(&mut self, addr: usize) -> Option<&mut Word> {
+        if addr >= DATA_OFFSET {
+            self.computer().memory.get_mut(addr - DATA_OFFSET)
+        } else {
+            self.computer().program.get_mut(addr)
+        }
+    }
+
+    fn read_memory(&mut self, addr: usize, len: usize) -> Result<Vec<Word>, String> {
+        let end = addr
+            .checked_add(len)
+            .ok_or_else(|| format!("Invalid length: {:x}", len))?;
+        (addr..end)
+            .map(|a| {
+                self.memory_mut(a)
+                    .map(|w| *w)
+                    .ok_or_else(|| format!("Address out of range: {:x}", a))
+            })
+            .collect()
+    }
+
+    fn write_memory(&mut self, addr: usize, data: &[Word]) -> Result<(), String> {
+        for (i, value) in data.iter().enumerate() {
+            let a = addr
+                .checked_add(i)
+                .ok_or_else(|| format!("Address out of range: {:x}", addr))?;
+            *self
+                .memory_mut(a)
+                .ok_or_else(|| format!("Address out of range: {:x}", a))? = *value;
+        }
+        Ok(())
+    }
+
+    fn register(n: &str) -> Result<RegisterRef, String> {
+        REGISTERS
+            .get(parse_hex_usize(n)?)
+            .copied()
+            .ok_or_else(|| format!("Invalid register number: {}", n))
+    }
+
+    fn breakpoint(&mut self, packet: &str, insert: bool) -> Result<String, String> {
+        let kind = packet.get(1..2).unwrap_or("");
+        let (addr, _) = parse_addr_len(packet.get(3..).unwrap_or(""))?;
+        let (set, addr) = match kind {
+            "0" if addr < DATA_OFFSET => (&mut self.debugger.breakpoints, addr),
+            "2" if addr >= DATA_OFFSET => (&mut self.debugger.watchpoints, addr - DATA_OFFSET),
+            _ => return Ok(String::new()),
+        };
+        if addr > 0xff {
+            return Err(format!("Address out of range: {:x}", addr));
+        }
+        if insert {
+            set.insert(addr as Word);
+        } else {
+            set.remove(&(addr as Word));
+        }
+        Ok("OK".to_string())
+    }
+
+    fn handle(&mut self, packet: &str) -> Result<Reply, String> {
+        let send = |s: &str| Ok(Reply::Send(s.to_string()));
+
+        match packet.get(0..1).unwrap_or("") {
+            "?" => send(&Self::stop_reply(&self.stop)),
+
+            "g" => {
+                let values: Vec<Word> = REGISTERS
+                    .iter()
+                    .map(|r| self.debugger.computer.read_register(r))
+                    .collect();
+                send(&to_hex(&values))
+            }
+            "G" => {
+                let values = from_hex(&packet[1..])?;
+                for (reg, value) in REGISTERS.iter().zip(values) {
+                    self.computer().write_register(*reg, value);
+                }
+                send("OK")
+            }
+            "p" => {
+                let reg = Self::register(&packet[1..])?;
+                send(&to_hex(&[self.debugger.computer.read_register(&reg)]))
+            }
+            "P" => {
+                let mut parts = packet[1..].splitn(2, '=');
+                let reg = Self::register(parts.next().unwrap_or(""))?;
+                match from_hex(parts.next().unwrap_or(""))?.as_slice() {
+                    [value] => {
+                        self.computer().write_register(reg, *value);
+                        send("OK")
+                    }
+                    _ => Err(format!("Invalid register value: {}", packet)),
+                }
+            }
+
+            "m" => {
+                let (addr, len) = parse_addr_len(&packet[1..])?;
+                let data = self.read_memory(addr, len)?;
+                send(&to_hex(&data))
+            }
+            "M" => {
+                let mut parts = packet[1..].splitn(2, ':');
+                let (addr, len) = parse_addr_len(parts.next().unwrap_or(""))?;
+                let data = from_hex(parts.next().unwrap_or(""))?;
+                if data.len() != len {
+                    return Err(format!("Length mismatch: {}", packet));
+                }
+                self.write_memory(addr, &data)?;
+                send("OK")
+            }
+
+            "s" => {
+                self.stop = self.debugger.step(1);
+                send(&Self::stop_reply(&self.stop))
+            }
+            "c" => {
+                self.stop = self.debugger.cont();
+                send(&Self::stop_reply(&self.stop))
+            }
+
+            "Z" => Ok(Reply::Send(self.breakpoint(packet, true)?)),
+            "z" => Ok(Reply::Send(self.breakpoint(packet, false)?)),
+
+            "H" => send("OK"),
+            "k" => Ok(Reply::Close),
+            "D" => Ok(Reply::SendAndClose("OK".to_string())),
+
+            "q" => {
+                if packet.starts_with("qSupported") {
+                    send("PacketSize=1000;qXfer:features:read+;qXfer:memory-map:read+;swbreak+")
+                } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+                    Ok(Reply::Send(xfer_chunk(TARGET_XML, range)?))
+                } else if let Some(range) = packet.strip_prefix("qXfer:memory-map:read::") {
+                    Ok(Reply::Send(xfer_chunk(MEMORY_MAP_XML, range)?))
+                } else if packet == "qAttached" {
+                    send("1")
+                } else if packet == "qC" {
+                    send("QC1")
+                } else if packet == "qfThreadInfo" {
+                    send("m1")
+                } else if packet == "qsThreadInfo" {
+                    send("l")
+                } else {
+                    send("")
+                }
+            }
+
+            _ => send(""),
+        }
+    }
+
+    /// Handle one packet body (without `$` and checksum). Malformed
+    /// requests get an `E01` error reply.
+    pub fn handle_packet(&mut self, packet: &str) -> Reply {
+        self.handle(packet)
+            .unwrap_or_else(|_| Reply::Send("E01".to_string()))
+    }
+
+    /// Frame and send a packet, escaping the characters that the protocol
+    /// reserves.
+    pub fn send_packet<W: Write>(out: &mut W, data: &str) -> std::io::Result<()> {
+        let mut escaped = Vec::with_capacity(data.len());
+        for b in data.bytes() {
+            if b == b'#' || b == b'$' || b == b'}' || b == b'*' {
+                escaped.push(b'}');
+                escaped.push(b ^ 0x20);
+            } else {
+                escaped.push(b);
+            }
+        }
+        out.write_all(b"$")?;
+        out.write_all(&escaped)?;
+        write!(out, "#{:02x}", checksum(&escaped))?;
+        out.flush()
+    }
+
+    /// Serve one debugger session over `stream` until the client detaches,
+    /// kills the target or closes the connection.
+    pub fn serve<S: Read + Write>(&mut self, mut stream: S) -> std::io::Result<()> {
+        let mut byte = [0];
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(());
+            }
+            match byte[0] {
+                b'$' => {}
+                // Interrupt request: we only run while handling a packet, so
+                // there is never anything to interrupt.
+                0x03 => {
+                    Self::send_packet(&mut stream, "S02")?;
+                    continue;
+                }
+                // Acks, and anything else outside a packet
+                _ => continue,
+            }
+
+            let mut body = Vec::new();
+            loop {
+                if stream.read(&mut byte)? == 0 {
+                    return Ok(());
+                }
+                if byte[0] == b'#' {
+                    break;
+                }
+                body.push(byte[0]);
+            }
+            let mut sum = [0; 2];
+            stream.read_exact(&mut sum)?;
+
+            let expected = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            if expected != Some(checksum(&body)) {
+                stream.write_all(b"-")?;
+                continue;
+            }
+            stream.write_all(b"+")?;
+
+            match self.handle_packet(&String::from_utf8_lossy(&body)) {
+                Reply::Send(reply) => Self::send_packet(&mut stream, &reply)?,
+                Reply::SendAndClose(reply) => {
+                    Self::send_packet(&mut stream, &reply)?;
+                    return Ok(());
+                }
+                Reply::Close => return Ok(()),
+            }
+        }
+    }
+}
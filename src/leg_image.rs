@@ -0,0 +1,366 @@
+use super::leg_computer::Word;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How a program or memory image is stored in a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImageFormat {
+    /// One byte per word, as `generate_code` returns them
+    Raw,
+    /// Two hex digits per word, separated by whitespace, 16 words per line.
+    /// Lines starting with `#` are comments. Intel HEX and `$readmemh` files
+    /// are often named `.hex` too, so if the first other line starts with `:`
+    /// the text is parsed as Intel HEX, and with `@`, `//` or `/*` as
+    /// `$readmemh`.
+    Hex,
+    /// A JSON array of numbers
+    Json,
+    /// A Logisim-evolution ROM or RAM file: `v2.0 raw`, then hex words
+    /// separated by whitespace, where `N*W` repeats `W` `N` times
+    Logisim,
+    /// Hex words for Verilog's `$readmemh`, which may skip to an address
+    /// with `@ADDR` and have `//` and `/* */` comments
+    Readmemh,
+    /// Intel HEX records of up to 16 bytes
+    IntelHex,
+}
+
+pub const IMAGE_FORMATS: [ImageFormat; 6] = [
+    ImageFormat::Raw,
+    ImageFormat::Hex,
+    ImageFormat::Json,
+    ImageFormat::Logisim,
+    ImageFormat::Readmemh,
+    ImageFormat::IntelHex,
+];
+
+/// Images may not be larger than the 16-bit address space of Intel HEX
+/// without extended addresses.
+const MAX_IMAGE: usize = 0x10000;
+
+/// Parse a hex word, with `line` for the error.
+fn hex_word(word: &str, line: usize) -> Result<Word, String> {
+    Word::from_str_radix(word, 16).map_err(|_| format!("line {}: Invalid hex byte: {}", line, word))
+}
+
+/// Write `word` at `addr`, growing the image if needed.
+fn put(image: &mut Vec<Word>, addr: usize, word: Word, line: usize) -> Result<(), String> {
+    if addr >= MAX_IMAGE {
+        return Err(format!("line {}: Address out of range: {:#x}", line, addr));
+    }
+    if image.len() <= addr {
+        image.resize(addr + 1, 0);
+    }
+    image[addr] = word;
+    Ok(())
+}
+
+fn parse_logisim(text: &str) -> Result<Vec<Word>, String> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == "v2.0 raw" => {}
+        _ => return Err("Expected a v2.0 raw header".to_string()),
+    }
+    let mut image = Vec::new();
+    for (i, line) in lines {
+        let line = line.split('#').next().unwrap_or("");
+        for word in line.split_whitespace() {
+            let (count, value) = match word.find('*') {
+                Some(star) => (
+                    word[..star]
+                        .parse::<usize>()
+                        .map_err(|_| format!("line {}: Invalid count: {}", i + 1, word))?,
+                    &word[star + 1..],
+                ),
+                None => (1, word),
+            };
+            let value = hex_word(value, i + 1)?;
+            if count > MAX_IMAGE - image.len() {
+                return Err(format!("line {}: Image too large", i + 1));
+            }
+            image.resize(image.len() + count, value);
+        }
+    }
+    Ok(image)
+}
+
+fn write_logisim(image: &[Word]) -> String {
+    let mut words = Vec::new();
+    let mut i = 0;
+    while i < image.len() {
+        let run = image[i..].iter().take_while(|w| **w == image[i]).count();
+        // Like Logisim, only runs of four or more are shortened
+        if run >= 4 {
+            words.push(format!("{}*{:x}", run, image[i]));
+            i += run;
+        } else {
+            words.push(format!("{:x}", image[i]));
+            i += 1;
+        }
+    }
+    let lines: Vec<String> = words.chunks(8).map(|line| line.join(" ") + "\n").collect();
+    format!("v2.0 raw\n{}", lines.concat())
+}
+
+fn parse_readmemh(text: &str) -> Result<Vec<Word>, String> {
+    let mut image = Vec::new();
+    let mut addr = 0;
+    let mut in_comment = false;
+    for (i, line) in text.lines().enumerate() {
+        // Drop comments, which /* */ may span lines
+        let mut code = String::new();
+        let mut rest = line;
+        loop {
+            if in_comment {
+                match rest.find("*/") {
+                    Some(end) => {
+                        rest = &rest[end + 2..];
+                        in_comment = false;
+                    }
+                    None => break,
+                }
+            } else {
+                let block = rest.find("/*");
+                let line_comment = rest.find("//");
+                match (block, line_comment) {
+                    (Some(b), Some(l)) if l < b => {
+                        code.push_str(&rest[..l]);
+                        break;
+                    }
+                    (Some(b), _) => {
+                        code.push_str(&rest[..b]);
+                        code.push(' ');
+                        rest = &rest[b + 2..];
+                        in_comment = true;
+                    }
+                    (None, Some(l)) => {
+                        code.push_str(&rest[..l]);
+                        break;
+                    }
+                    (None, None) => {
+                        code.push_str(rest);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for word in code.split_whitespace() {
+            match word.strip_prefix('@') {
+                Some(a) => {
+                    addr = usize::from_str_radix(a, 16)
+                        .map_err(|_| format!("line {}: Invalid address: {}", i + 1, word))?
+                }
+                None => {
+                    put(
+                        &mut image,
+                        addr,
+                        hex_word(&word.replace('_', ""), i + 1)?,
+                        i + 1,
+                    )?;
+                    addr += 1;
+                }
+            }
+        }
+    }
+    if in_comment {
+        return Err("Unterminated comment".to_string());
+    }
+    Ok(image)
+}
+
+fn parse_intel_hex(text: &str) -> Result<Vec<Word>, String> {
+    let mut image = Vec::new();
+    let mut base = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |e: &str| format!("line {}: {}", i + 1, e);
+        let record = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("Expected a record starting with ':'"))?;
+        if record.len() % 2 != 0 || record.len() < 10 || !record.is_ascii() {
+            return Err(error("Invalid record"));
+        }
+        let bytes = (0..record.len())
+            .step_by(2)
+            .map(|j| hex_word(&record[j..j + 2], i + 1))
+            .collect::<Result<Vec<Word>, String>>()?;
+        let len = bytes[0] as usize;
+        if bytes.len() != len + 5 {
+            return Err(error("Record length does not match its data"));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(error("Checksum mismatch"));
+        }
+
+        let offset = (bytes[1] as usize) << 8 | bytes[2] as usize;
+        let data = &bytes[4..4 + len];
+        match bytes[3] {
+            0 => {
+                for (j, word) in data.iter().enumerate() {
+                    put(&mut image, base + offset + j, *word, i + 1)?;
+                }
+            }
+            1 => return Ok(image),
+            2 | 4 if len == 2 => {
+                let value = (data[0] as usize) << 8 | data[1] as usize;
+                base = if bytes[3] == 2 {
+                    value << 4
+                } else {
+                    value << 16
+                };
+            }
+            // Start addresses don't apply to LEG
+            3 | 5 => {}
+            other => return Err(error(&format!("Invalid record type: {}", other))),
+        }
+    }
+    Err("Missing end of file record".to_string())
+}
+
+fn write_intel_hex(image: &[Word]) -> String {
+    let record = |kind: Word, addr: usize, data: &[Word]| {
+        let mut bytes = vec![data.len() as Word, (addr >> 8) as Word, addr as Word, kind];
+        bytes.extend(data);
+        let checksum = bytes
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b))
+            .wrapping_neg();
+        bytes.push(checksum);
+        let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!(":{}\n", hex)
+    };
+    let mut out: String = image
+        .chunks(16)
+        .enumerate()
+        .map(|(i, data)| record(0, 16 * i, data))
+        .collect();
+    out.push_str(&record(1, 0, &[]));
+    out
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(ImageFormat::Raw),
+            "hex" => Ok(ImageFormat::Hex),
+            "json" => Ok(ImageFormat::Json),
+            "logisim" => Ok(ImageFormat::Logisim),
+            "readmemh" => Ok(ImageFormat::Readmemh),
+            "ihex" => Ok(ImageFormat::IntelHex),
+            other => Err(format!("Invalid image format: {}", other)),
+        }
+    }
+}
+
+impl ImageFormat {
+    /// The format of a file named `path`: hex for `.hex`, JSON for `.json`,
+    /// Logisim for `.logisim`, `$readmemh` for `.mem`, Intel HEX for `.ihex`
+    /// and `.ihx`, and raw otherwise.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> ImageFormat {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("hex") => ImageFormat::Hex,
+            Some("json") => ImageFormat::Json,
+            Some("logisim") => ImageFormat::Logisim,
+            Some("mem") => ImageFormat::Readmemh,
+            Some("ihex") | Some("ihx") => ImageFormat::IntelHex,
+            _ => ImageFormat::Raw,
+        }
+    }
+
+    pub fn parse(&self, bytes: &[u8]) -> Result<Vec<Word>, String> {
+        let text = || std::str::from_utf8(bytes).map_err(|e| format!("Invalid text: {}", e));
+        match self {
+            ImageFormat::Raw => Ok(bytes.to_vec()),
+            ImageFormat::Hex => {
+                let text = text()?;
+                let first = text
+                    .lines()
+                    .map(str::trim_start)
+                    .find(|line| !line.is_empty() && !line.starts_with('#'));
+                match first {
+                    Some(line) if line.starts_with(':') => return parse_intel_hex(text),
+                    Some(line)
+                        if line.starts_with('@')
+                            || line.starts_with("//")
+                            || line.starts_with("/*") =>
+                    {
+                        return parse_readmemh(text)
+                    }
+                    _ => {}
+                }
+                let mut words = Vec::new();
+                for (i, line) in text.lines().enumerate() {
+                    if line.trim_start().starts_with('#') {
+                        continue;
+                    }
+                    for word in line.split_whitespace() {
+                        words.push(hex_word(word, i + 1)?);
+                    }
+                }
+                Ok(words)
+            }
+            ImageFormat::Json => {
+                let text = text()?.trim();
+                let inner = text
+                    .strip_prefix('[')
+                    .and_then(|t| t.strip_suffix(']'))
+                    .ok_or("Expected a JSON array")?;
+                if inner.trim().is_empty() {
+                    return Ok(Vec::new());
+                }
+                inner
+                    .split(',')
+                    .map(|n| {
+                        n.trim()
+                            .parse()
+                            .map_err(|_| format!("Invalid byte: {}", n.trim()))
+                    })
+                    .collect()
+            }
+            ImageFormat::Logisim => parse_logisim(text()?),
+            ImageFormat::Readmemh => parse_readmemh(text()?),
+            ImageFormat::IntelHex => parse_intel_hex(text()?),
+        }
+    }
+
+    pub fn write(&self, image: &[Word]) -> Vec<u8> {
+        match self {
+            ImageFormat::Raw => image.to_vec(),
+            ImageFormat::Hex | ImageFormat::Readmemh => image
+                .chunks(16)
+                .map(|line| {
+                    let words: Vec<String> = line.iter().map(|w| format!("{:02x}", w)).collect();
+                    words.join(" ") + "\n"
+                })
+                .collect::<String>()
+                .into_bytes(),
+            ImageFormat::Json => {
+                let words: Vec<String> = image.iter().map(|w| w.to_string()).collect();
+                format!("[{}]\n", words.join(", ")).into_bytes()
+            }
+            ImageFormat::Logisim => write_logisim(image).into_bytes(),
+            ImageFormat::IntelHex => write_intel_hex(image).into_bytes(),
+        }
+    }
+}
+
+/// Read an image from `path` in the format its name suggests.
+pub fn read_image<P: AsRef<Path>>(path: P) -> Result<Vec<Word>, String> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    ImageFormat::from_path(path)
+        .parse(&bytes)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Write an image to `path` in the format its name suggests.
+pub fn write_image<P: AsRef<Path>>(path: P, image: &[Word]) -> Result<(), String> {
+    let path = path.as_ref();
+    std::fs::write(path, ImageFormat::from_path(path).write(image))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
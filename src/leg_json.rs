@@ -0,0 +1,321 @@
+//! A JSON form of the machine state, for scripts.
+
+use super::leg_computer::AluFlags;
+use super::leg_computer::LegComputer;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::Word;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The registers in the JSON state, in order. FL and IP are derived from
+/// `flags` and `eip`.
+const REGISTERS: [RegisterRef; 6] = [
+    RegisterRef::A,
+    RegisterRef::B,
+    RegisterRef::C,
+    RegisterRef::D,
+    RegisterRef::ST,
+    RegisterRef::BP,
+];
+
+const FLAGS: [&str; 13] = [
+    "eq_zero",
+    "overflow_unsigned",
+    "overflow_signed",
+    "equal",
+    "greater_than",
+    "greater_than_signed",
+    "greater_or_equal",
+    "greater_or_equal_signed",
+    "not_equal",
+    "less_than",
+    "less_than_signed",
+    "less_or_equal",
+    "less_or_equal_signed",
+];
+
+fn flag_mut<'a>(flags: &'a mut AluFlags, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "eq_zero" => Some(&mut flags.eq_zero),
+        "overflow_unsigned" => Some(&mut flags.overflow_unsigned),
+        "overflow_signed" => Some(&mut flags.overflow_signed),
+        "equal" => Some(&mut flags.equal),
+        "greater_than" => Some(&mut flags.greater_than),
+        "greater_than_signed" => Some(&mut flags.greater_than_signed),
+        "greater_or_equal" => Some(&mut flags.greater_or_equal),
+        "greater_or_equal_signed" => Some(&mut flags.greater_or_equal_signed),
+        "not_equal" => Some(&mut flags.not_equal),
+        "less_than" => Some(&mut flags.less_than),
+        "less_than_signed" => Some(&mut flags.less_than_signed),
+        "less_or_equal" => Some(&mut flags.less_or_equal),
+        "less_or_equal_signed" => Some(&mut flags.less_or_equal_signed),
+        _ => None,
+    }
+}
+
+/// The JSON values that the state is made of.
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> String {
+        format!("JSON at {}: {}", self.pos, msg)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.pos..].chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", c)))
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if self.text[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("Invalid value"))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(s);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => {
+                        let hex: String = (0..4)
+                            .filter_map(|_| chars.next())
+                            .map(|(_, c)| c)
+                            .collect();
+                        let code = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or_else(|| self.error(&format!("Invalid escape: \\u{}", hex)))?;
+                        s.push(code);
+                    }
+                    _ => return Err(self.error("Invalid escape")),
+                },
+                c => s.push(c),
+            }
+        }
+        Err(self.error("Unterminated string"))
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                let mut object = BTreeMap::new();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(object));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    object.insert(key, self.value()?);
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        _ => break,
+                    }
+                }
+                self.expect('}')?;
+                Ok(Json::Object(object))
+            }
+            Some('[') => {
+                self.pos += 1;
+                let mut array = Vec::new();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(array));
+                }
+                loop {
+                    array.push(self.value()?);
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(']')?;
+                Ok(Json::Array(array))
+            }
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let rest = &self.text[self.pos..];
+                let len = rest
+                    .find(|c: char| !(c == '-' || c.is_ascii_digit()))
+                    .unwrap_or(rest.len());
+                let number = rest[..len]
+                    .parse()
+                    .map_err(|_| self.error(&format!("Invalid number: {}", &rest[..len])))?;
+                self.pos += len;
+                Ok(Json::Number(number))
+            }
+            _ => Err(self.error("Expected a value")),
+        }
+    }
+}
+
+fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(_) => Err(parser.error("Trailing characters")),
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn hex(words: &[Word]) -> String {
+    words.iter().map(|w| format!("{:02x}", w)).collect()
+}
+
+impl Json {
+    fn field<'a>(&'a self, name: &str) -> Result<&'a Json, String> {
+        match self {
+            Json::Object(object) => object.get(name).ok_or(format!("Missing field: {}", name)),
+            _ => Err(format!("Expected an object with a field {}", name)),
+        }
+    }
+
+    fn word(&self, name: &str) -> Result<Word, String> {
+        match self.field(name)? {
+            Json::Number(n) if 0 <= *n && *n <= Word::MAX as i64 => Ok(*n as Word),
+            other => Err(format!("{}: Expected a byte, got {:?}", name, other)),
+        }
+    }
+
+    fn bool(&self, name: &str) -> Result<bool, String> {
+        match self.field(name)? {
+            Json::Bool(b) => Ok(*b),
+            other => Err(format!("{}: Expected a boolean, got {:?}", name, other)),
+        }
+    }
+
+    /// An array of bytes, or a string of two hex digits per byte.
+    fn words(&self, name: &str) -> Result<Vec<Word>, String> {
+        let invalid = || format!("{}: Expected bytes", name);
+        match self.field(name)? {
+            Json::Array(array) => array
+                .iter()
+                .map(|v| match v {
+                    Json::Number(n) if 0 <= *n && *n <= Word::MAX as i64 => Ok(*n as Word),
+                    _ => Err(invalid()),
+                })
+                .collect(),
+            Json::String(s) if s.len() % 2 == 0 && s.is_ascii() => (0..s.len())
+                .step_by(2)
+                .map(|i| Word::from_str_radix(&s[i..i + 2], 16).map_err(|_| invalid()))
+                .collect(),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl LegComputer {
+    /// The state as a JSON object, with the program and memory as hex
+    /// strings. The decoded current instruction is null if it is invalid.
+    pub fn to_json(&self) -> String {
+        let instruction = match self.current_instruction() {
+            Ok(instruction) => quote(&instruction.to_string()),
+            Err(_) => "null".to_string(),
+        };
+        let registers: Vec<String> = REGISTERS
+            .iter()
+            .map(|r| format!("{}: {}", quote(&r.to_string()), self.read_register(r)))
+            .collect();
+        let mut flags = self.flags.clone();
+        let flags: Vec<String> = FLAGS
+            .iter()
+            .map(|name| format!("{}: {}", quote(name), flag_mut(&mut flags, name).unwrap()))
+            .collect();
+
+        let mut out = String::from("{\n");
+        writeln!(out, "  \"eip\": {},", self.eip).unwrap();
+        writeln!(out, "  \"instruction\": {},", instruction).unwrap();
+        writeln!(out, "  \"registers\": {{{}}},", registers.join(", ")).unwrap();
+        writeln!(out, "  \"flags\": {{\n    {}\n  }},", flags.join(",\n    ")).unwrap();
+        writeln!(out, "  \"reg_i\": {},", self.reg_i).unwrap();
+        writeln!(out, "  \"reg_o\": {},", self.reg_o).unwrap();
+        writeln!(out, "  \"program\": {},", quote(&hex(&self.program))).unwrap();
+        writeln!(out, "  \"memory\": {}", quote(&hex(&self.memory))).unwrap();
+        out.push('}');
+        out
+    }
+
+    /// Parse a state written by `to_json`. The program and memory may also
+    /// be arrays of numbers, and the instruction is ignored.
+    pub fn from_json(text: &str) -> Result<LegComputer, String> {
+        let json = parse(text)?;
+        let mut computer = LegComputer::new(json.words("program")?, json.words("memory")?);
+        computer.eip = json.word("eip")?;
+        computer.reg_i = json.word("reg_i")?;
+        computer.reg_o = json.word("reg_o")?;
+
+        let registers = json.field("registers")?;
+        for register in &REGISTERS {
+            computer.write_register(*register, registers.word(&register.to_string())?);
+        }
+        let flags = json.field("flags")?;
+        for name in &FLAGS {
+            *flag_mut(&mut computer.flags, name).unwrap() = flags.bool(name)?;
+        }
+        Ok(computer)
+    }
+}
@@ -0,0 +1,489 @@
+use super::leg_cfg::Cfg;
+use super::leg_cfg::EdgeKind;
+use super::leg_computer::Address;
+use super::leg_computer::AluFlagRef;
+use super::leg_computer::AluOpcode;
+use super::leg_computer::Instruction;
+use super::leg_computer::NopOpcode;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use super::leg_computer::Word;
+use super::leg_computer_parse::assemble_lines;
+use super::leg_computer_parse::generate_code;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LintKind {
+    /// A constant jump or call target in the middle of an instruction
+    OddJump,
+    /// A constant jump or call target past the end of the program
+    JumpOutOfProgram,
+    Unreachable,
+    UnreachableHalt,
+    /// A register read before it's written on some path through the function
+    Uninitialized,
+    /// A write to FL or IP, which `read_register` never sees
+    DroppedWrite,
+    /// An ALU op whose flags no path consumes, and whose result is either
+    /// never read or the unchanged first argument, like `ALU ECHO X Y => X`
+    UnusedFlags,
+}
+
+pub const LINT_KINDS: [LintKind; 7] = [
+    LintKind::OddJump,
+    LintKind::JumpOutOfProgram,
+    LintKind::Unreachable,
+    LintKind::UnreachableHalt,
+    LintKind::Uninitialized,
+    LintKind::DroppedWrite,
+    LintKind::UnusedFlags,
+];
+
+impl Display for LintKind {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        f.write_str(match self {
+            LintKind::OddJump => "odd-jump",
+            LintKind::JumpOutOfProgram => "jump-out-of-program",
+            LintKind::Unreachable => "unreachable",
+            LintKind::UnreachableHalt => "unreachable-halt",
+            LintKind::Uninitialized => "uninitialized",
+            LintKind::DroppedWrite => "dropped-write",
+            LintKind::UnusedFlags => "unused-flags",
+        })
+    }
+}
+
+impl FromStr for LintKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LINT_KINDS
+            .iter()
+            .find(|kind| kind.to_string() == s)
+            .copied()
+            .ok_or_else(|| format!("Unknown lint: {}", s))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub addr: Address,
+    /// 1-based source line of the instruction at `addr`
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(
+            f,
+            "line {} ({:03}): {} [{}]",
+            self.line, self.addr, self.message, self.kind
+        )
+    }
+}
+
+/// Lints suppressed by `# allow: name, ...` for the next instruction, or by
+/// `# allow-file: name, ...` for the whole source.
+struct Suppressions {
+    file: HashSet<LintKind>,
+    lines: HashMap<usize, HashSet<LintKind>>,
+}
+
+impl Suppressions {
+    fn parse(source: &str) -> Result<Suppressions, String> {
+        let mut file = HashSet::new();
+        let mut lines = HashMap::new();
+        let mut pending = HashSet::new();
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('.') {
+                continue;
+            }
+            if !line.starts_with('#') {
+                lines.insert(i + 1, std::mem::take(&mut pending));
+                continue;
+            }
+            let comment = line.trim_start_matches('#').trim();
+            let (names, target) = if let Some(names) = comment.strip_prefix("allow-file:") {
+                (names, &mut file)
+            } else if let Some(names) = comment.strip_prefix("allow:") {
+                (names, &mut pending)
+            } else {
+                continue;
+            };
+            for name in names.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+                target.insert(name.parse().map_err(|e| format!("Line {}: {}", i + 1, e))?);
+            }
+        }
+        Ok(Suppressions { file, lines })
+    }
+
+    fn allows(&self, kind: LintKind, line: usize) -> bool {
+        self.file.contains(&kind)
+            || self
+                .lines
+                .get(&line)
+                .map(|kinds| kinds.contains(&kind))
+                .unwrap_or(false)
+    }
+}
+
+fn decode(program: &[Word], addr: Address) -> Option<Instruction> {
+    program
+        .get(addr as usize..addr as usize + 2)
+        .and_then(|words| Instruction::try_from((words[0], words[1])).ok())
+}
+
+fn jump_target(addr: Address, instruction: &Instruction) -> Option<Address> {
+    match instruction {
+        Instruction::Jmp { flag, .. } | Instruction::JmpR { flag, .. }
+            if *flag == AluFlagRef::False =>
+        {
+            None
+        }
+        Instruction::Jmp { addr, .. } => Some(*addr),
+        Instruction::JmpR { diff, .. } => Some(addr.wrapping_add(*diff)),
+        Instruction::Stack(StackInstruction::CallC { addr }) => Some(*addr),
+        Instruction::Stack(StackInstruction::CallR { diff }) => Some(addr.wrapping_add(*diff)),
+        _ => None,
+    }
+}
+
+/// Registers an instruction reads and writes.
+fn register_use(instruction: &Instruction) -> (Vec<RegisterRef>, Vec<RegisterRef>) {
+    match instruction {
+        Instruction::Load { dest, .. } => (vec![], vec![*dest]),
+        Instruction::LoadP { dest, addr_src } => (vec![*addr_src], vec![*dest]),
+        Instruction::Store { src, .. } => (vec![*src], vec![]),
+        Instruction::StoreP { src, addr_src } => (vec![*src, *addr_src], vec![]),
+        Instruction::Mov { dest, src } => (vec![*src], vec![*dest]),
+        Instruction::MovC { dest, .. } => (vec![], vec![*dest]),
+        Instruction::Jmp { .. } | Instruction::JmpR { .. } => (vec![], vec![]),
+        Instruction::JmpP { addr_src, .. } => (vec![*addr_src], vec![]),
+        Instruction::JmpRP { diff_src, .. } => (vec![*diff_src], vec![]),
+        Instruction::Stack(StackInstruction::Push { src }) => (vec![*src], vec![]),
+        Instruction::Stack(StackInstruction::Pop { dest }) => (vec![], vec![*dest]),
+        Instruction::Stack(StackInstruction::Load { dest, .. }) => (vec![], vec![*dest]),
+        Instruction::Stack(StackInstruction::Call { addr_reg }) => (vec![*addr_reg], vec![]),
+        Instruction::Stack(StackInstruction::CallC { .. })
+        | Instruction::Stack(StackInstruction::CallR { .. }) => (vec![], vec![]),
+        Instruction::Stack(StackInstruction::Ret { src }) => (vec![*src], vec![]),
+        Instruction::Gpi { dest } => (vec![], vec![*dest]),
+        Instruction::Gpo { src } => (vec![*src], vec![]),
+        Instruction::Alu {
+            arg1, arg2, out, ..
+        } => (vec![*arg1, *arg2], vec![*out]),
+        Instruction::Nop(_) => (vec![], vec![]),
+    }
+}
+
+fn is_call(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Stack(StackInstruction::Call { .. })
+            | Instruction::Stack(StackInstruction::CallC { .. })
+            | Instruction::Stack(StackInstruction::CallR { .. })
+    )
+}
+
+fn consumes_flags(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Jmp { flag, .. }
+        | Instruction::JmpP { flag, .. }
+        | Instruction::JmpR { flag, .. }
+        | Instruction::JmpRP { flag, .. } => {
+            *flag != AluFlagRef::True && *flag != AluFlagRef::False
+        }
+        other => register_use(other).0.contains(&RegisterRef::FL),
+    }
+}
+
+fn general_purpose(reg: RegisterRef) -> Option<u8> {
+    match reg {
+        RegisterRef::A => Some(0x1),
+        RegisterRef::B => Some(0x2),
+        RegisterRef::C => Some(0x4),
+        RegisterRef::D => Some(0x8),
+        _ => None,
+    }
+}
+
+/// Instruction-level control flow within functions: calls continue after
+/// the CALL instead of entering the callee.
+struct Flow<'a> {
+    cfg: &'a Cfg,
+    instructions: HashMap<Address, Instruction>,
+}
+
+impl<'a> Flow<'a> {
+    fn new(cfg: &'a Cfg) -> Flow<'a> {
+        let instructions = cfg
+            .blocks
+            .values()
+            .flat_map(|b| b.instructions.iter().cloned())
+            .collect();
+        Flow { cfg, instructions }
+    }
+
+    fn successors(&self, addr: Address) -> Vec<Address> {
+        if let Some(block) = self
+            .cfg
+            .blocks
+            .values()
+            .find(|b| b.instructions.last().map(|(a, _)| *a) == Some(addr))
+        {
+            block
+                .edges
+                .iter()
+                .filter(|e| e.kind != EdgeKind::Call)
+                .filter_map(|e| e.target)
+                .collect()
+        } else if self.instructions.contains_key(&addr.wrapping_add(2)) {
+            vec![addr.wrapping_add(2)]
+        } else {
+            vec![]
+        }
+    }
+}
+
+fn uninitialized_reads(flow: &Flow) -> Vec<(Address, String)> {
+    let mut lints = Vec::new();
+    for entry in flow.cfg.functions.keys() {
+        // Bit set of the registers written on every path so far
+        let mut written: HashMap<Address, u8> = HashMap::new();
+        let mut worklist = vec![(*entry, 0)];
+        while let Some((addr, state)) = worklist.pop() {
+            let state = match written.get(&addr) {
+                Some(old) if old & state == *old => continue,
+                Some(old) => old & state,
+                None => state,
+            };
+            written.insert(addr, state);
+            let instruction = match flow.instructions.get(&addr) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            let mut next = state;
+            let (_, writes) = register_use(instruction);
+            for reg in writes {
+                next |= general_purpose(reg).unwrap_or(0);
+            }
+            if is_call(instruction) {
+                next = 0xf;
+            }
+            for succ in flow.successors(addr) {
+                worklist.push((succ, next));
+            }
+        }
+
+        for (addr, state) in &written {
+            if let Some(instruction) = flow.instructions.get(addr) {
+                let (reads, _) = register_use(instruction);
+                let mut reported = BTreeSet::new();
+                for reg in reads {
+                    if let Some(bit) = general_purpose(reg) {
+                        if state & bit == 0 && reported.insert(bit) {
+                            lints.push((
+                                *addr,
+                                format!(
+                                    "{:?} may be read before it is written in fn {:03}",
+                                    reg, entry
+                                ),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    lints
+}
+
+/// Whether some path from the compare at `addr` reaches an instruction that
+/// uses the flags before another ALU op or call replaces them.
+fn flags_consumed(flow: &Flow, addr: Address) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = flow.successors(addr);
+    while let Some(addr) = stack.pop() {
+        if !visited.insert(addr) {
+            continue;
+        }
+        let instruction = match flow.instructions.get(&addr) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        if consumes_flags(instruction) {
+            return true;
+        }
+        if matches!(instruction, Instruction::Alu { .. }) || is_call(instruction) {
+            continue;
+        }
+        stack.extend(flow.successors(addr));
+    }
+    false
+}
+
+/// Whether some path from `addr` reads `reg` before writing it. Calls,
+/// returns and computed jumps may read any register.
+fn register_read(flow: &Flow, addr: Address, reg: RegisterRef) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = flow.successors(addr);
+    while let Some(addr) = stack.pop() {
+        if !visited.insert(addr) {
+            continue;
+        }
+        let instruction = match flow.instructions.get(&addr) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        let (reads, writes) = register_use(instruction);
+        if reads.contains(&reg)
+            || is_call(instruction)
+            || matches!(
+                instruction,
+                Instruction::Stack(StackInstruction::Ret { .. })
+                    | Instruction::JmpP { .. }
+                    | Instruction::JmpRP { .. }
+            )
+        {
+            return true;
+        }
+        if writes.contains(&reg) {
+            continue;
+        }
+        stack.extend(flow.successors(addr));
+    }
+    false
+}
+
+/// Whether the ALU op only sets the flags: its output is its unchanged
+/// first argument, or nothing reads it.
+fn only_sets_flags(flow: &Flow, addr: Address, instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Alu {
+            op,
+            arg1,
+            arg2,
+            out,
+        } => {
+            let unchanged = arg1 == out
+                && match op {
+                    AluOpcode::Echo => true,
+                    AluOpcode::And | AluOpcode::Or => arg1 == arg2,
+                    _ => false,
+                };
+            general_purpose(*out).is_some() && (unchanged || !register_read(flow, addr, *out))
+        }
+        _ => false,
+    }
+}
+
+/// Lint an assembly source. Lints are ordered by address.
+pub fn lint(source: &str) -> Result<Vec<Lint>, String> {
+    let (lines, instructions): (Vec<usize>, Vec<Instruction>) =
+        assemble_lines(source)?.into_iter().unzip();
+    let suppressions = Suppressions::parse(source)?;
+    let program = generate_code(&instructions);
+    let cfg = Cfg::recover(&program);
+    let flow = Flow::new(&cfg);
+
+    let mut found: Vec<(LintKind, Address, String)> = Vec::new();
+
+    let source_addresses = (0..program.len()).step_by(2).map(|a| a as Address);
+    let mut unreachable_run = false;
+    for addr in source_addresses {
+        let instruction = match decode(&program, addr) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+
+        if let Some(target) = jump_target(addr, &instruction) {
+            if target as usize + 1 >= program.len() {
+                found.push((
+                    LintKind::JumpOutOfProgram,
+                    addr,
+                    format!("jumps to {:03}, past the end of the program", target),
+                ));
+            } else if target % 2 == 1 {
+                found.push((
+                    LintKind::OddJump,
+                    addr,
+                    format!("jumps to {:03}, in the middle of an instruction", target),
+                ));
+            }
+        }
+
+        let (_, writes) = register_use(&instruction);
+        for reg in writes {
+            if reg == RegisterRef::FL || reg == RegisterRef::IP {
+                found.push((
+                    LintKind::DroppedWrite,
+                    addr,
+                    format!("write to {:?} has no effect", reg),
+                ));
+            }
+        }
+
+        if flow.instructions.contains_key(&addr) {
+            unreachable_run = false;
+            if only_sets_flags(&flow, addr, &instruction) && !flags_consumed(&flow, addr) {
+                found.push((
+                    LintKind::UnusedFlags,
+                    addr,
+                    "compare whose flags are never used".to_string(),
+                ));
+            }
+        } else if instruction == Instruction::Nop(NopOpcode::Halt) {
+            found.push((
+                LintKind::UnreachableHalt,
+                addr,
+                "HALT is never reached".to_string(),
+            ));
+        } else if !unreachable_run {
+            unreachable_run = true;
+            let after = addr
+                .checked_sub(2)
+                .filter(|a| flow.instructions.contains_key(a))
+                .and_then(|a| decode(&program, a));
+            found.push((
+                LintKind::Unreachable,
+                addr,
+                match after {
+                    Some(previous) => format!("unreachable code after {}", previous),
+                    None => "unreachable code".to_string(),
+                },
+            ));
+        }
+    }
+
+    for (addr, message) in uninitialized_reads(&flow) {
+        found.push((LintKind::Uninitialized, addr, message));
+    }
+
+    let mut lints: Vec<Lint> = found
+        .into_iter()
+        .filter_map(|(kind, addr, message)| {
+            let line = lines.get(addr as usize / 2).copied().unwrap_or(0);
+            if suppressions.allows(kind, line) {
+                None
+            } else {
+                Some(Lint {
+                    kind,
+                    addr,
+                    line,
+                    message,
+                })
+            }
+        })
+        .collect();
+    lints.sort_by(|a, b| (a.addr, a.kind, &a.message).cmp(&(b.addr, b.kind, &b.message)));
+    Ok(lints)
+}
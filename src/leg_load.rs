@@ -0,0 +1,121 @@
+use super::leg_computer::Address;
+use super::leg_computer::LegComputer;
+use super::leg_computer::Word;
+use std::str::FromStr;
+
+/// Where a program expects its input in memory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputStart {
+    /// At the address stored at this address, like the challenge's address 0
+    Pointer(Address),
+    /// At this address
+    At(Address),
+}
+
+impl FromStr for InputStart {
+    type Err = String;
+    /// `*ADDR` for a pointer, `ADDR` for a fixed address.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid input start: {}", s);
+        match s.strip_prefix('*') {
+            Some(addr) => Ok(InputStart::Pointer(addr.parse().map_err(|_| invalid())?)),
+            None => Ok(InputStart::At(s.parse().map_err(|_| invalid())?)),
+        }
+    }
+}
+
+/// Parse `START-END` into a start address and length.
+pub fn parse_range(s: &str) -> Result<(Address, usize), String> {
+    let invalid = || format!("Invalid range: {}", s);
+    let parse = |n: &str| n.parse::<usize>().map_err(|_| invalid());
+    match s.find('-') {
+        Some(i) => {
+            let (start, end) = (parse(&s[..i])?, parse(&s[i + 1..])?);
+            if start > end || end > 256 {
+                return Err(invalid());
+            }
+            Ok((start as Address, end - start))
+        }
+        None => Err(invalid()),
+    }
+}
+
+/// Loads a program and its memory with an input, the way the challenge does
+/// by default: the input goes where address 0 points, its end is written to
+/// address 1, and the output is at addresses 0 to 2.
+#[derive(Clone, Debug)]
+pub struct ImageLoader {
+    pub program: Vec<Word>,
+    /// Padded with zeros to 256 words
+    pub memory: Vec<Word>,
+    pub input_start: InputStart,
+    /// Where to write the address after the last byte of the input, if
+    /// anywhere
+    pub end_pointer: Option<Address>,
+    /// Start and length of the output
+    pub output: (Address, usize),
+}
+
+impl ImageLoader {
+    /// Fails if the program or the memory doesn't fit in 256 words.
+    pub fn new(program: Vec<Word>, mut memory: Vec<Word>) -> Result<ImageLoader, String> {
+        if program.len() > 256 {
+            return Err(format!("Program too large: {} bytes", program.len()));
+        }
+        if memory.len() > 256 {
+            return Err(format!("Memory image too large: {} bytes", memory.len()));
+        }
+        memory.resize(256, 0);
+        Ok(ImageLoader {
+            program,
+            memory,
+            input_start: InputStart::Pointer(0),
+            end_pointer: Some(1),
+            output: (0, 3),
+        })
+    }
+
+    /// The initial memory with `input` in place. Fails if the input runs past
+    /// the end of memory, or if its end doesn't fit in the end pointer.
+    pub fn memory_with_input(&self, input: &[Word]) -> Result<Vec<Word>, String> {
+        let start = match self.input_start {
+            InputStart::Pointer(addr) => self.memory[addr as usize],
+            InputStart::At(addr) => addr,
+        } as usize;
+        let end = start + input.len();
+        if end > self.memory.len() {
+            return Err(format!(
+                "Input of {} bytes does not fit in memory: {:03}-256 are free",
+                input.len(),
+                start
+            ));
+        }
+
+        let mut memory = self.memory.clone();
+        if let Some(pointer) = self.end_pointer {
+            if end > Word::MAX as usize {
+                return Err(format!(
+                    "Input of {} bytes does not fit in memory: its end {} does not fit in a word",
+                    input.len(),
+                    end
+                ));
+            }
+            memory[pointer as usize] = end as Word;
+        }
+        memory[start..end].copy_from_slice(input);
+        Ok(memory)
+    }
+
+    pub fn load(&self, input: &[Word]) -> Result<LegComputer, String> {
+        Ok(LegComputer::new(
+            self.program.clone(),
+            self.memory_with_input(input)?,
+        ))
+    }
+
+    /// The output of a computer that this loaded.
+    pub fn output<'a>(&self, computer: &'a LegComputer) -> &'a [Word] {
+        let (start, len) = self.output;
+        &computer.memory[start as usize..start as usize + len]
+    }
+}
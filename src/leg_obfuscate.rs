@@ -0,0 +1,777 @@
+use super::leg_computer::Address;
+use super::leg_computer::AluFlagRef;
+use super::leg_computer::AluOpcode;
+use super::leg_computer::Instruction;
+use super::leg_computer::NopOpcode;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use super::leg_computer::Word;
+use super::leg_computer_parse::generate_code;
+use super::leg_equiv::Equivalence;
+use super::leg_equiv::EquivalenceChecker;
+use super::leg_optimize::reg_index;
+use super::leg_optimize::sets_overflow;
+use super::leg_optimize::static_targets;
+use super::leg_optimize::uses_defs;
+use super::leg_optimize::FLAGS_COMPARE;
+use super::leg_optimize::FLAGS_OVERFLOW;
+use super::leg_symbolic::SymbolicInput;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand::SeedableRng;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Technique {
+    /// Replace ALU ops and moves with equivalent sequences, e.g. SUB with
+    /// NEG and ADDC
+    Substitute,
+    /// Jumps on flags that always or never hold after `ALU ECHO X X => X`
+    OpaquePredicates,
+    /// Instructions that only write registers that are never read
+    Junk,
+    /// Route jumps through `JMPP T ? X` dispatchers and a jump table in
+    /// memory, with X pointing to the table entry
+    Flatten,
+    /// Permute registers A to D throughout the program
+    RenameRegisters,
+}
+
+pub const TECHNIQUES: [Technique; 5] = [
+    Technique::Substitute,
+    Technique::OpaquePredicates,
+    Technique::Junk,
+    Technique::Flatten,
+    Technique::RenameRegisters,
+];
+
+impl FromStr for Technique {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "substitute" => Ok(Technique::Substitute),
+            "opaque" => Ok(Technique::OpaquePredicates),
+            "junk" => Ok(Technique::Junk),
+            "flatten" => Ok(Technique::Flatten),
+            "rename" => Ok(Technique::RenameRegisters),
+            other => Err(format!("Invalid technique: {}", other)),
+        }
+    }
+}
+
+/// What `Obfuscator::obfuscate` did, counted in places changed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ObfuscateReport {
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+    pub substituted: usize,
+    pub opaque_predicates: usize,
+    pub junk: usize,
+    /// Jumps routed through a dispatcher
+    pub flattened: usize,
+    /// Jump table to place at `Obfuscator::table` in memory
+    pub table: Vec<Word>,
+    pub renamed: bool,
+    /// Inputs the result was checked to be equivalent on
+    pub checked: usize,
+}
+
+impl Display for ObfuscateReport {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{} -> {} bytes", self.bytes_before, self.bytes_after)?;
+        for (name, count) in &[
+            ("substituted", self.substituted),
+            ("opaque predicates", self.opaque_predicates),
+            ("junk", self.junk),
+            ("flattened", self.flattened),
+        ] {
+            if *count > 0 {
+                write!(f, ", {}: {}", name, count)?;
+            }
+        }
+        if !self.table.is_empty() {
+            write!(f, ", jump table: {:?}", self.table)?;
+        }
+        if self.renamed {
+            write!(f, ", registers renamed")?;
+        }
+        write!(f, ", equivalent on {} inputs", self.checked)
+    }
+}
+
+const REGISTERS: [RegisterRef; 4] = [
+    RegisterRef::A,
+    RegisterRef::B,
+    RegisterRef::C,
+    RegisterRef::D,
+];
+
+fn reg_bit(reg: RegisterRef) -> u16 {
+    1 << reg_index(reg).unwrap()
+}
+
+fn is_general(reg: RegisterRef) -> bool {
+    reg_index(reg).is_some()
+}
+
+fn alu(op: AluOpcode, arg1: RegisterRef, arg2: RegisterRef, out: RegisterRef) -> Instruction {
+    Instruction::Alu {
+        op,
+        arg1,
+        arg2,
+        out,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Target {
+    /// The first instruction of a group
+    Group(usize),
+    /// This many instructions after this one
+    Forward(usize),
+}
+
+/// An instruction whose jump or call target is still to be laid out.
+#[derive(Clone, Debug)]
+struct Op {
+    instruction: Instruction,
+    target: Option<Target>,
+}
+
+impl Op {
+    fn plain(instruction: Instruction) -> Op {
+        Op {
+            instruction,
+            target: None,
+        }
+    }
+
+    fn to(instruction: Instruction, target: Target) -> Op {
+        Op {
+            instruction,
+            target: Some(target),
+        }
+    }
+}
+
+/// The code replacing one original instruction. Jumps to the instruction go
+/// to the start of the prefix.
+#[derive(Clone, Debug, Default)]
+struct Group {
+    prefix: Vec<Op>,
+    body: Vec<Op>,
+}
+
+impl Group {
+    fn len(&self) -> usize {
+        self.prefix.len() + self.body.len()
+    }
+}
+
+/// Rewrites a program into an equivalent one that is harder to read, and
+/// checks the equivalence by running both.
+///
+/// The passes rely on the liveness of registers and flags, so they assume
+/// that nothing reads A to D or the flags except the program itself.
+pub struct Obfuscator {
+    pub seed: u64,
+    pub techniques: Vec<Technique>,
+    /// Chance of applying each technique at each instruction
+    pub rate: f64,
+    /// Stop adding code at this size
+    pub max_bytes: usize,
+    /// Memory image for the equivalence check
+    pub memory: Vec<Word>,
+    pub inputs: Vec<SymbolicInput>,
+    /// Start and length of the memory region that must match after halting
+    pub output: (Address, usize),
+    /// Random inputs to check if there are too many to check all
+    pub samples: usize,
+    /// Where the jump table for flattening goes in memory, up to the end of
+    /// memory. The program must not use the bytes that the table ends up in.
+    /// Jumps are not flattened without a table.
+    pub table: Option<Address>,
+}
+
+struct Pass<'a> {
+    options: &'a Obfuscator,
+    rng: StdRng,
+    groups: Vec<Group>,
+    targets: Vec<Option<usize>>,
+    live_in: Vec<u16>,
+    live_out: Vec<u16>,
+    /// Dispatcher group for each register, for flattening
+    dispatchers: [Option<usize>; 4],
+    /// Group of each jump table entry
+    table: Vec<usize>,
+    size: usize,
+    report: ObfuscateReport,
+}
+
+/// Registers and flags live before and after each instruction.
+fn liveness(program: &[Instruction], targets: &[Option<usize>]) -> (Vec<u16>, Vec<u16>) {
+    let len = program.len();
+    let successors = |i: usize| -> Vec<usize> {
+        let next = i + 1;
+        let successors = match &program[i] {
+            Instruction::Jmp { flag, .. } | Instruction::JmpR { flag, .. } => match flag {
+                AluFlagRef::True => vec![targets[i].unwrap()],
+                AluFlagRef::False => vec![next],
+                _ => vec![targets[i].unwrap(), next],
+            },
+            Instruction::Stack(StackInstruction::CallC { .. })
+            | Instruction::Stack(StackInstruction::CallR { .. }) => {
+                vec![targets[i].unwrap(), next]
+            }
+            Instruction::Stack(StackInstruction::Ret { .. })
+            | Instruction::Nop(NopOpcode::Halt) => vec![],
+            _ => vec![next],
+        };
+        successors.into_iter().filter(|s| *s < len).collect()
+    };
+
+    let mut live_in = vec![0u16; len];
+    let mut live_out = vec![0u16; len];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..len).rev() {
+            let out = successors(i).into_iter().fold(0, |acc, s| acc | live_in[s]);
+            let (uses, defs) = uses_defs(&program[i]);
+            let new_in = uses | (out & !defs);
+            if out != live_out[i] || new_in != live_in[i] {
+                live_out[i] = out;
+                live_in[i] = new_in;
+                changed = true;
+            }
+        }
+    }
+    (live_in, live_out)
+}
+
+fn rename(instruction: &Instruction, map: &[RegisterRef; 4]) -> Instruction {
+    let r = |reg: &RegisterRef| match reg_index(*reg) {
+        Some(i) => map[i],
+        None => *reg,
+    };
+    match instruction {
+        Instruction::Load { dest, addr } => Instruction::Load {
+            dest: r(dest),
+            addr: *addr,
+        },
+        Instruction::LoadP { dest, addr_src } => Instruction::LoadP {
+            dest: r(dest),
+            addr_src: r(addr_src),
+        },
+        Instruction::Store { src, addr } => Instruction::Store {
+            src: r(src),
+            addr: *addr,
+        },
+        Instruction::StoreP { src, addr_src } => Instruction::StoreP {
+            src: r(src),
+            addr_src: r(addr_src),
+        },
+        Instruction::Mov { dest, src } => Instruction::Mov {
+            dest: r(dest),
+            src: r(src),
+        },
+        Instruction::MovC { dest, val } => Instruction::MovC {
+            dest: r(dest),
+            val: *val,
+        },
+        Instruction::JmpP { flag, addr_src } => Instruction::JmpP {
+            flag: *flag,
+            addr_src: r(addr_src),
+        },
+        Instruction::JmpRP { flag, diff_src } => Instruction::JmpRP {
+            flag: *flag,
+            diff_src: r(diff_src),
+        },
+        Instruction::Stack(StackInstruction::Push { src }) => {
+            Instruction::Stack(StackInstruction::Push { src: r(src) })
+        }
+        Instruction::Stack(StackInstruction::Pop { dest }) => {
+            Instruction::Stack(StackInstruction::Pop { dest: r(dest) })
+        }
+        Instruction::Stack(StackInstruction::Load { dest, bp_diff }) => {
+            Instruction::Stack(StackInstruction::Load {
+                dest: r(dest),
+                bp_diff: *bp_diff,
+            })
+        }
+        Instruction::Stack(StackInstruction::Call { addr_reg }) => {
+            Instruction::Stack(StackInstruction::Call {
+                addr_reg: r(addr_reg),
+            })
+        }
+        Instruction::Stack(StackInstruction::Ret { src }) => {
+            Instruction::Stack(StackInstruction::Ret { src: r(src) })
+        }
+        Instruction::Gpi { dest } => Instruction::Gpi { dest: r(dest) },
+        Instruction::Gpo { src } => Instruction::Gpo { src: r(src) },
+        Instruction::Alu {
+            op,
+            arg1,
+            arg2,
+            out,
+        } => alu(*op, r(arg1), r(arg2), r(out)),
+        other => other.clone(),
+    }
+}
+
+impl<'a> Pass<'a> {
+    fn dead(&self, live: u16) -> Vec<RegisterRef> {
+        REGISTERS
+            .iter()
+            .cloned()
+            .filter(|reg| live & reg_bit(*reg) == 0)
+            .collect()
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> Option<T> {
+        items.choose(&mut self.rng).cloned()
+    }
+
+    fn random_group(&mut self) -> usize {
+        self.rng.gen_range(0, self.targets.len())
+    }
+
+    /// The replacement of instruction `i`, or None if it can't be replaced.
+    fn substitute(&mut self, i: usize) -> Option<Vec<Op>> {
+        let live = self.live_out[i];
+        let compare_dead = live & FLAGS_COMPARE == 0;
+        let flags_dead = live & (FLAGS_COMPARE | FLAGS_OVERFLOW) == 0;
+        let dead = self.dead(live);
+        let temp = |not: &[RegisterRef], out: RegisterRef| {
+            REGISTERS
+                .iter()
+                .cloned()
+                .filter(|t| !not.contains(t) && (*t == out || dead.contains(t)))
+                .collect::<Vec<RegisterRef>>()
+        };
+
+        if self.groups[i].body.len() > 1 || self.groups[i].body[0].target.is_some() {
+            return None;
+        }
+        let ops = match self.groups[i].body[0].instruction.clone() {
+            Instruction::Alu {
+                op: AluOpcode::Sub,
+                arg1,
+                arg2,
+                out,
+            } if compare_dead && [arg1, arg2, out].iter().all(|r| is_general(*r)) => {
+                // X - Y = X + !Y + 1, with the same overflow flags
+                let t = self.pick(&temp(&[arg1], out))?;
+                vec![
+                    alu(AluOpcode::Neg, arg2, arg2, t),
+                    alu(AluOpcode::AddCarry, arg1, t, out),
+                ]
+            }
+            Instruction::Alu {
+                op: AluOpcode::Add,
+                arg1,
+                arg2,
+                out,
+            } if flags_dead && [arg1, arg2, out].iter().all(|r| is_general(*r)) => {
+                // X + Y = X - (!Y + 1) + 1 - 1
+                let t = self.pick(&temp(&[arg1], out))?;
+                vec![
+                    alu(AluOpcode::Neg, arg2, arg2, t),
+                    alu(AluOpcode::Incr, t, t, t),
+                    alu(AluOpcode::Sub, arg1, t, out),
+                ]
+            }
+            Instruction::Alu {
+                op: AluOpcode::Xor,
+                arg1,
+                arg2,
+                out,
+            } if compare_dead && [arg1, arg2, out].iter().all(|r| is_general(*r)) => {
+                // X ^ Y = (X | Y) & !(X & Y)
+                let t = self.pick(&temp(&[arg1, arg2, out], RegisterRef::FL))?;
+                vec![
+                    alu(AluOpcode::Nand, arg1, arg2, t),
+                    alu(AluOpcode::Or, arg1, arg2, out),
+                    alu(AluOpcode::And, out, t, out),
+                ]
+            }
+            Instruction::Mov { dest, src }
+                if compare_dead && is_general(dest) && is_general(src) =>
+            {
+                let op = self.pick(&[AluOpcode::Or, AluOpcode::And, AluOpcode::Echo])?;
+                vec![alu(op, src, src, dest)]
+            }
+            Instruction::MovC { dest, val } if compare_dead && is_general(dest) => {
+                let t = self.pick(&temp(&[dest], RegisterRef::FL))?;
+                let key: Word = self.rng.gen();
+                vec![
+                    Instruction::MovC {
+                        dest,
+                        val: val ^ key,
+                    },
+                    Instruction::MovC { dest: t, val: key },
+                    alu(AluOpcode::Xor, dest, t, dest),
+                ]
+            }
+            _ => return None,
+        };
+        Some(ops.into_iter().map(Op::plain).collect())
+    }
+
+    /// A jump on `ALU ECHO X X => X`, which is always or never taken, to
+    /// insert before instruction `i`.
+    fn opaque_predicate(&mut self, i: usize) -> Option<Vec<Op>> {
+        if self.live_in[i] & FLAGS_COMPARE != 0 {
+            return None;
+        }
+        let reg = self.pick(&REGISTERS)?;
+        let echo = Op::plain(alu(AluOpcode::Echo, reg, reg, reg));
+        if self.rng.gen() {
+            let flag = self.pick(&[
+                AluFlagRef::Equal,
+                AluFlagRef::GreaterOrEqual,
+                AluFlagRef::LessOrEqual,
+                AluFlagRef::GreaterOrEqualSigned,
+                AluFlagRef::LessOrEqualSigned,
+            ])?;
+            let bogus = self.bogus();
+            Some(vec![
+                echo,
+                Op::to(Instruction::JmpR { flag, diff: 0 }, Target::Forward(2)),
+                bogus,
+            ])
+        } else {
+            let flag = self.pick(&[
+                AluFlagRef::NotEqual,
+                AluFlagRef::GreaterThan,
+                AluFlagRef::LessThan,
+                AluFlagRef::GreaterThanSigned,
+                AluFlagRef::LessThanSigned,
+            ])?;
+            let target = Target::Group(self.random_group());
+            Some(vec![
+                echo,
+                Op::to(Instruction::JmpR { flag, diff: 0 }, target),
+            ])
+        }
+    }
+
+    /// An instruction that is never executed.
+    fn bogus(&mut self) -> Op {
+        let reg = self.pick(&REGISTERS).unwrap();
+        let target = Target::Group(self.random_group());
+        match self.rng.gen_range(0, 5) {
+            0 => Op::plain(Instruction::Nop(NopOpcode::Halt)),
+            1 => Op::to(
+                Instruction::JmpR {
+                    flag: AluFlagRef::True,
+                    diff: 0,
+                },
+                target,
+            ),
+            2 => Op::plain(Instruction::MovC {
+                dest: reg,
+                val: self.rng.gen(),
+            }),
+            3 => Op::plain(Instruction::Store {
+                src: reg,
+                addr: self.rng.gen(),
+            }),
+            _ => Op::to(
+                Instruction::Stack(StackInstruction::CallR { diff: 0 }),
+                target,
+            ),
+        }
+    }
+
+    /// An instruction to insert before instruction `i` that only writes
+    /// registers and flags that are dead there.
+    fn junk(&mut self, i: usize) -> Option<Vec<Op>> {
+        let live = self.live_in[i];
+        let dead = self.dead(live);
+        let dest = match self.pick(&dead) {
+            Some(dest) => dest,
+            None => return Some(vec![Op::plain(Instruction::Nop(NopOpcode::Nop))]),
+        };
+        let src = self.pick(&REGISTERS)?;
+        let ops: Vec<AluOpcode> = [
+            AluOpcode::Add,
+            AluOpcode::Incr,
+            AluOpcode::Xor,
+            AluOpcode::Neg,
+            AluOpcode::Sub,
+            AluOpcode::Or,
+            AluOpcode::And,
+            AluOpcode::Nand,
+            AluOpcode::ShiftL,
+            AluOpcode::ShiftR,
+        ]
+        .iter()
+        .cloned()
+        .filter(|op| {
+            live & FLAGS_COMPARE == 0 && (!sets_overflow(*op) || live & FLAGS_OVERFLOW == 0)
+        })
+        .collect();
+        let instruction = match (self.rng.gen_range(0, 3), self.pick(&ops)) {
+            (0, Some(op)) => alu(op, src, dest, dest),
+            (1, _) => Instruction::Mov { dest, src },
+            _ => Instruction::MovC {
+                dest,
+                val: self.rng.gen(),
+            },
+        };
+        Some(vec![Op::plain(instruction)])
+    }
+
+    /// The flag of jump `i` and a register that is dead after it, if the jump
+    /// can be flattened.
+    fn flatten(&mut self, i: usize) -> Option<(AluFlagRef, RegisterRef)> {
+        let flag = match self.groups[i].body[0].instruction {
+            Instruction::Jmp { flag, .. } | Instruction::JmpR { flag, .. }
+                if flag != AluFlagRef::False && self.groups[i].body.len() == 1 =>
+            {
+                flag
+            }
+            _ => return None,
+        };
+        let dead = self.dead(self.live_out[i]);
+        let reg = self.pick(&dead)?;
+        Some((flag, reg))
+    }
+
+    fn apply(&mut self, i: usize, technique: Technique) {
+        match technique {
+            Technique::Substitute => {
+                if let Some(body) = self.substitute(i) {
+                    let added = body.len() - self.groups[i].body.len();
+                    if self.size + added <= self.options.max_bytes / 2 {
+                        self.size += added;
+                        self.groups[i].body = body;
+                        self.report.substituted += 1;
+                    }
+                }
+            }
+            Technique::OpaquePredicates | Technique::Junk => {
+                let snippet = if technique == Technique::Junk {
+                    self.junk(i)
+                } else {
+                    self.opaque_predicate(i)
+                };
+                if let Some(snippet) = snippet {
+                    if self.size + snippet.len() <= self.options.max_bytes / 2 {
+                        self.size += snippet.len();
+                        self.groups[i].prefix.extend(snippet);
+                        if technique == Technique::Junk {
+                            self.report.junk += 1;
+                        } else {
+                            self.report.opaque_predicates += 1;
+                        }
+                    }
+                }
+            }
+            Technique::Flatten => {
+                let table = match self.options.table {
+                    Some(table) => table,
+                    None => return,
+                };
+                if let Some((flag, reg)) = self.flatten(i) {
+                    // Load the address of the table entry and jump to the
+                    // dispatcher for the register, which jumps to the entry
+                    let target = self.targets[i].unwrap();
+                    let entry = match self.table.iter().position(|t| *t == target) {
+                        Some(entry) => entry,
+                        None if table as usize + self.table.len() < 256 => self.table.len(),
+                        None => return,
+                    };
+                    let slot = reg_index(reg).unwrap();
+                    let added = 1 + self.dispatchers[slot].is_none() as usize;
+                    if self.size + added <= self.options.max_bytes / 2 {
+                        self.size += added;
+                        if entry == self.table.len() {
+                            self.table.push(target);
+                        }
+                        let dispatcher = *self.dispatchers[slot].get_or_insert(self.groups.len());
+                        if dispatcher == self.groups.len() {
+                            self.groups.push(Group {
+                                prefix: Vec::new(),
+                                body: vec![Op::plain(Instruction::JmpP {
+                                    flag: AluFlagRef::True,
+                                    addr_src: reg,
+                                })],
+                            });
+                        }
+                        self.groups[i].body = vec![
+                            Op::plain(Instruction::MovC {
+                                dest: reg,
+                                val: table.wrapping_add(entry as Word),
+                            }),
+                            Op::to(
+                                Instruction::JmpR { flag, diff: 0 },
+                                Target::Group(dispatcher),
+                            ),
+                        ];
+                        self.report.flattened += 1;
+                    }
+                }
+            }
+            Technique::RenameRegisters => {}
+        }
+    }
+
+    /// The program with all targets resolved, and the start address of each
+    /// group.
+    fn layout(&self) -> (Vec<Instruction>, Vec<Word>) {
+        let mut starts = Vec::with_capacity(self.groups.len());
+        let mut addr = 0;
+        for group in &self.groups {
+            starts.push(addr as Word);
+            addr += group.len() * 2;
+        }
+
+        let mut program = Vec::with_capacity(self.size);
+        for group in &self.groups {
+            for op in group.prefix.iter().chain(&group.body) {
+                let here = (program.len() * 2) as Word;
+                let target = match op.target {
+                    Some(Target::Group(g)) => starts[g],
+                    Some(Target::Forward(n)) => here.wrapping_add(2 * n as Word),
+                    None => {
+                        program.push(op.instruction.clone());
+                        continue;
+                    }
+                };
+                program.push(match &op.instruction {
+                    Instruction::Jmp { flag, .. } => Instruction::Jmp {
+                        flag: *flag,
+                        addr: target,
+                    },
+                    Instruction::JmpR { flag, .. } => Instruction::JmpR {
+                        flag: *flag,
+                        diff: target.wrapping_sub(here),
+                    },
+                    Instruction::Stack(StackInstruction::CallC { .. }) => {
+                        Instruction::Stack(StackInstruction::CallC { addr: target })
+                    }
+                    Instruction::Stack(StackInstruction::CallR { .. }) => {
+                        Instruction::Stack(StackInstruction::CallR {
+                            diff: target.wrapping_sub(here),
+                        })
+                    }
+                    other => other.clone(),
+                });
+            }
+        }
+        (program, starts)
+    }
+}
+
+impl Obfuscator {
+    pub fn new(seed: u64) -> Obfuscator {
+        Obfuscator {
+            seed,
+            techniques: TECHNIQUES.to_vec(),
+            rate: 0.3,
+            max_bytes: 256,
+            memory: vec![0; 256],
+            inputs: Vec::new(),
+            output: (0, 256),
+            samples: 1000,
+            table: None,
+        }
+    }
+
+    /// Apply the techniques at random places while the program fits in
+    /// `max_bytes`, then check that the result, with the jump table in
+    /// memory, is equivalent to `program`.
+    pub fn obfuscate(
+        &self,
+        program: &[Instruction],
+    ) -> Result<(Vec<Instruction>, ObfuscateReport), String> {
+        let targets = static_targets(program)?;
+        // Dispatchers are added after the last instruction
+        if let Some(i) = targets.iter().position(|t| *t == Some(program.len())) {
+            return Err(format!("{:03}: jumps past the end of the program", i * 2));
+        }
+        let (live_in, live_out) = liveness(program, &targets);
+        let mut pass = Pass {
+            options: self,
+            rng: StdRng::seed_from_u64(self.seed),
+            groups: program
+                .iter()
+                .zip(&targets)
+                .map(|(instruction, target)| Group {
+                    prefix: Vec::new(),
+                    body: vec![Op {
+                        instruction: instruction.clone(),
+                        target: target.map(Target::Group),
+                    }],
+                })
+                .collect(),
+            targets,
+            live_in,
+            live_out,
+            dispatchers: [None; 4],
+            table: Vec::new(),
+            size: program.len(),
+            report: ObfuscateReport {
+                bytes_before: program.len() * 2,
+                ..ObfuscateReport::default()
+            },
+        };
+
+        let mut edits = Vec::new();
+        for i in 0..program.len() {
+            for technique in &self.techniques {
+                if *technique != Technique::RenameRegisters && pass.rng.gen_bool(self.rate) {
+                    edits.push((i, *technique));
+                }
+            }
+        }
+        edits.shuffle(&mut pass.rng);
+        for (i, technique) in edits {
+            pass.apply(i, technique);
+        }
+
+        let (mut result, starts) = pass.layout();
+        pass.report.table = pass.table.iter().map(|g| starts[*g]).collect();
+        if self.techniques.contains(&Technique::RenameRegisters) {
+            let mut map = REGISTERS;
+            while map == REGISTERS {
+                map.shuffle(&mut pass.rng);
+            }
+            result = result.iter().map(|i| rename(i, &map)).collect();
+            pass.report.renamed = true;
+        }
+        pass.report.bytes_after = result.len() * 2;
+
+        let mut memory = self.memory.clone();
+        if let Some(table) = self.table {
+            let table = table as usize;
+            memory.resize(memory.len().max(table + pass.report.table.len()), 0);
+            memory[table..table + pass.report.table.len()].copy_from_slice(&pass.report.table);
+        }
+        let mut checker = EquivalenceChecker::new(
+            generate_code(program),
+            generate_code(&result),
+            memory,
+            self.inputs.clone(),
+        );
+        checker.output = self.output;
+        checker.samples = self.samples;
+        checker.seed = self.seed;
+        match checker.check() {
+            Equivalence::Equivalent { runs, .. } => pass.report.checked = runs,
+            Equivalence::Counterexample(counterexample) => {
+                return Err(format!(
+                    "Obfuscated program is not equivalent, {}",
+                    counterexample
+                ))
+            }
+        }
+        Ok((result, pass.report))
+    }
+}
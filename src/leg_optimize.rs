@@ -0,0 +1,910 @@
+use super::leg_computer::AluFlagRef;
+use super::leg_computer::AluOpcode;
+use super::leg_computer::Instruction;
+use super::leg_computer::LegComputer;
+use super::leg_computer::NopOpcode;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use super::leg_computer::Word;
+use super::leg_computer_parse::generate_code;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+
+/// What `optimize` did, counted in instructions removed or rewritten.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OptimizeReport {
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+    /// Jumps removed, resolved or retargeted past other jumps
+    pub jumps: usize,
+    pub unreachable: usize,
+    /// `PUSH X; POP Y` pairs removed or turned into a MOV
+    pub stack: usize,
+    /// Instructions simplified or removed using known register and flag
+    /// values
+    pub constants: usize,
+    /// `ALU ECHO` compares that recompute the flags they already set
+    pub flags: usize,
+    /// NOPs and register writes that are never read
+    pub dead_code: usize,
+    /// STOREs overwritten before being read
+    pub dead_stores: usize,
+}
+
+impl OptimizeReport {
+    pub fn saved(&self) -> usize {
+        self.bytes_before - self.bytes_after
+    }
+}
+
+impl Display for OptimizeReport {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(
+            f,
+            "{} -> {} bytes, saved {}",
+            self.bytes_before,
+            self.bytes_after,
+            self.saved()
+        )?;
+        for (name, count) in &[
+            ("jumps", self.jumps),
+            ("unreachable", self.unreachable),
+            ("stack", self.stack),
+            ("constants", self.constants),
+            ("flags", self.flags),
+            ("dead code", self.dead_code),
+            ("dead stores", self.dead_stores),
+        ] {
+            if *count > 0 {
+                write!(f, ", {}: {}", name, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Registers and flags for liveness, as bit masks. A to D are bits 0 to 3.
+const ST: u16 = 0x10;
+const BP: u16 = 0x20;
+/// Z and the flags comparing the ALU arguments, set by every ALU op
+pub const FLAGS_COMPARE: u16 = 0x40;
+/// Ou and Os, set only by the arithmetic ops
+pub const FLAGS_OVERFLOW: u16 = 0x80;
+const ALL: u16 = 0xff;
+
+/// Bits of the FL word holding Ou and Os
+const OVERFLOW_BITS: Word = 0x06;
+
+pub fn reg_index(reg: RegisterRef) -> Option<usize> {
+    match reg {
+        RegisterRef::A => Some(0),
+        RegisterRef::B => Some(1),
+        RegisterRef::C => Some(2),
+        RegisterRef::D => Some(3),
+        _ => None,
+    }
+}
+
+fn read_bits(reg: RegisterRef) -> u16 {
+    match reg {
+        RegisterRef::FL => FLAGS_COMPARE | FLAGS_OVERFLOW,
+        RegisterRef::ST => ST,
+        RegisterRef::BP => BP,
+        RegisterRef::IP => 0,
+        _ => 1 << reg_index(reg).unwrap(),
+    }
+}
+
+/// Writes to FL and IP are dropped.
+fn write_bits(reg: RegisterRef) -> u16 {
+    match reg {
+        RegisterRef::FL | RegisterRef::IP => 0,
+        _ => read_bits(reg),
+    }
+}
+
+fn flag_bits(flag: AluFlagRef) -> u16 {
+    match flag {
+        AluFlagRef::True | AluFlagRef::False => 0,
+        AluFlagRef::OverflowUnsigned | AluFlagRef::OverflowSigned => FLAGS_OVERFLOW,
+        _ => FLAGS_COMPARE,
+    }
+}
+
+pub fn sets_overflow(op: AluOpcode) -> bool {
+    matches!(
+        op,
+        AluOpcode::Add | AluOpcode::AddCarry | AluOpcode::Incr | AluOpcode::Decr | AluOpcode::Sub
+    )
+}
+
+/// Registers and flags read and written by an instruction.
+pub fn uses_defs(instruction: &Instruction) -> (u16, u16) {
+    match instruction {
+        Instruction::Load { dest, .. } => (0, write_bits(*dest)),
+        Instruction::LoadP { dest, addr_src } => (read_bits(*addr_src), write_bits(*dest)),
+        Instruction::Store { src, .. } => (read_bits(*src), 0),
+        Instruction::StoreP { src, addr_src } => (read_bits(*src) | read_bits(*addr_src), 0),
+        Instruction::Mov { dest, src } => (read_bits(*src), write_bits(*dest)),
+        Instruction::MovC { dest, .. } => (0, write_bits(*dest)),
+        Instruction::Jmp { flag, .. } | Instruction::JmpR { flag, .. } => (flag_bits(*flag), 0),
+        Instruction::JmpP {
+            flag,
+            addr_src: src,
+        }
+        | Instruction::JmpRP {
+            flag,
+            diff_src: src,
+        } => (flag_bits(*flag) | read_bits(*src), 0),
+        Instruction::Stack(StackInstruction::Push { src }) => (read_bits(*src) | ST, ST),
+        Instruction::Stack(StackInstruction::Pop { dest }) => (ST, write_bits(*dest) | ST),
+        Instruction::Stack(StackInstruction::Load { dest, .. }) => (BP, write_bits(*dest)),
+        Instruction::Stack(_) => (ALL, 0),
+        Instruction::Gpi { dest } => (0, write_bits(*dest)),
+        Instruction::Gpo { src } => (read_bits(*src), 0),
+        Instruction::Alu {
+            op,
+            arg1,
+            arg2,
+            out,
+        } => (
+            read_bits(*arg1) | read_bits(*arg2),
+            write_bits(*out)
+                | FLAGS_COMPARE
+                | if sets_overflow(*op) {
+                    FLAGS_OVERFLOW
+                } else {
+                    0
+                },
+        ),
+        Instruction::Nop(_) => (0, 0),
+    }
+}
+
+fn is_call(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Stack(StackInstruction::Call { .. })
+            | Instruction::Stack(StackInstruction::CallC { .. })
+            | Instruction::Stack(StackInstruction::CallR { .. })
+    )
+}
+
+/// True if the instruction uses the value of IP, which changes when code is
+/// moved.
+fn reads_ip(instruction: &Instruction) -> bool {
+    let ip = |r: &RegisterRef| *r == RegisterRef::IP;
+    match instruction {
+        Instruction::LoadP { addr_src, .. } => ip(addr_src),
+        Instruction::Store { src, .. }
+        | Instruction::Mov { src, .. }
+        | Instruction::Gpo { src } => ip(src),
+        Instruction::StoreP { src, addr_src } => ip(src) || ip(addr_src),
+        Instruction::Stack(StackInstruction::Push { src })
+        | Instruction::Stack(StackInstruction::Ret { src }) => ip(src),
+        Instruction::Alu { arg1, arg2, .. } => ip(arg1) || ip(arg2),
+        _ => false,
+    }
+}
+
+/// Result and FL word of an ALU op, computed by the emulator itself.
+fn eval_alu(op: AluOpcode, a: Word, b: Word, flags: Word) -> (Word, Word) {
+    let instruction = Instruction::Alu {
+        op,
+        arg1: RegisterRef::A,
+        arg2: RegisterRef::B,
+        out: RegisterRef::C,
+    };
+    let mut computer = LegComputer::new(generate_code(&[instruction]), vec![0; 256]);
+    computer.write_register(RegisterRef::A, a);
+    computer.write_register(RegisterRef::B, b);
+    computer.write_register(RegisterRef::FL, flags);
+    computer.step();
+    (
+        computer.read_register(&RegisterRef::C),
+        computer.read_register(&RegisterRef::FL),
+    )
+}
+
+fn flag_value(flags: Word, flag: AluFlagRef) -> bool {
+    let mut computer = LegComputer::new(Vec::new(), Vec::new());
+    computer.write_register(RegisterRef::FL, flags);
+    computer.flags.get(&flag)
+}
+
+/// Register and flag values known before an instruction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Known {
+    registers: [Option<Word>; 4],
+    /// The FL word without the overflow bits
+    compare: Option<Word>,
+    overflow: Option<Word>,
+    /// Arguments of the last flag setting op, if it was `ALU ECHO X Y => X`
+    /// and neither register has changed since
+    last_echo: Option<(RegisterRef, RegisterRef)>,
+}
+
+impl Known {
+    fn unknown() -> Known {
+        Known {
+            registers: [None; 4],
+            compare: None,
+            overflow: None,
+            last_echo: None,
+        }
+    }
+
+    fn value(&self, reg: RegisterRef) -> Option<Word> {
+        match reg {
+            RegisterRef::FL => Some(self.compare? | self.overflow?),
+            _ => reg_index(reg).and_then(|i| self.registers[i]),
+        }
+    }
+
+    fn set(&mut self, reg: RegisterRef, value: Option<Word>) {
+        if let Some(i) = reg_index(reg) {
+            self.registers[i] = value;
+        }
+        if let Some((x, y)) = self.last_echo {
+            if x == reg || y == reg {
+                self.last_echo = None;
+            }
+        }
+    }
+
+    fn flag(&self, flag: AluFlagRef) -> Option<bool> {
+        match flag_bits(flag) {
+            FLAGS_OVERFLOW => self.overflow.map(|w| flag_value(w, flag)),
+            FLAGS_COMPARE => self.compare.map(|w| flag_value(w, flag)),
+            _ => Some(flag == AluFlagRef::True),
+        }
+    }
+
+    fn meet(&self, other: &Known) -> Known {
+        let pick = |a: Option<Word>, b: Option<Word>| if a == b { a } else { None };
+        let mut registers = [None; 4];
+        for (i, r) in registers.iter_mut().enumerate() {
+            *r = pick(self.registers[i], other.registers[i]);
+        }
+        Known {
+            registers,
+            compare: pick(self.compare, other.compare),
+            overflow: pick(self.overflow, other.overflow),
+            last_echo: if self.last_echo == other.last_echo {
+                self.last_echo
+            } else {
+                None
+            },
+        }
+    }
+
+    fn transfer(&self, instruction: &Instruction) -> Known {
+        let mut next = self.clone();
+        match instruction {
+            Instruction::Load { dest, .. }
+            | Instruction::LoadP { dest, .. }
+            | Instruction::Stack(StackInstruction::Load { dest, .. })
+            | Instruction::Stack(StackInstruction::Pop { dest })
+            | Instruction::Gpi { dest } => next.set(*dest, None),
+            Instruction::Mov { dest, src } => next.set(*dest, self.value(*src)),
+            Instruction::MovC { dest, val } => next.set(*dest, Some(*val)),
+            Instruction::Stack(StackInstruction::Call { .. })
+            | Instruction::Stack(StackInstruction::CallC { .. })
+            | Instruction::Stack(StackInstruction::CallR { .. }) => next = Known::unknown(),
+            Instruction::Alu {
+                op,
+                arg1,
+                arg2,
+                out,
+            } => {
+                let opaque = |r: &RegisterRef| reg_index(*r).is_none();
+                let args = (self.value(*arg1), self.value(*arg2));
+                match args {
+                    (Some(a), Some(b)) if !opaque(arg1) && !opaque(arg2) && !opaque(out) => {
+                        let (result, flags) = eval_alu(*op, a, b, self.overflow.unwrap_or(0));
+                        next.set(*out, Some(result));
+                        next.compare = Some(flags & !OVERFLOW_BITS);
+                        if sets_overflow(*op) {
+                            next.overflow = Some(flags & OVERFLOW_BITS);
+                        }
+                    }
+                    _ => {
+                        let result = match op {
+                            AluOpcode::Echo if !opaque(arg1) => args.0,
+                            _ => None,
+                        };
+                        next.set(*out, result);
+                        next.compare = None;
+                        if sets_overflow(*op) || opaque(out) {
+                            next.overflow = None;
+                        }
+                    }
+                }
+                next.last_echo = match op {
+                    AluOpcode::Echo if out == arg1 && !opaque(arg1) && !opaque(arg2) => {
+                        Some((*arg1, *arg2))
+                    }
+                    _ => None,
+                };
+            }
+            _ => {}
+        }
+        next
+    }
+}
+
+/// The index of the instruction that each jump or call jumps to. Fails if a
+/// target is computed, is not an instruction, or if code reads IP, since
+/// then the code cannot be moved.
+pub fn static_targets(program: &[Instruction]) -> Result<Vec<Option<usize>>, String> {
+    let len = program.len();
+    let mut targets = Vec::with_capacity(len);
+    for (i, instruction) in program.iter().enumerate() {
+        let addr = (i * 2) as Word;
+        if reads_ip(instruction) {
+            return Err(format!("{:03}: {} reads IP", addr, instruction));
+        }
+        let target_addr = match instruction {
+            Instruction::Jmp { addr, .. }
+            | Instruction::Stack(StackInstruction::CallC { addr }) => Some(*addr),
+            Instruction::JmpR { diff, .. }
+            | Instruction::Stack(StackInstruction::CallR { diff }) => {
+                Some(addr.wrapping_add(*diff))
+            }
+            Instruction::JmpP { flag, .. } | Instruction::JmpRP { flag, .. }
+                if *flag != AluFlagRef::False =>
+            {
+                return Err(format!(
+                    "{:03}: {} has a computed target",
+                    addr, instruction
+                ))
+            }
+            Instruction::Stack(StackInstruction::Call { .. }) => {
+                return Err(format!(
+                    "{:03}: {} has a computed target",
+                    addr, instruction
+                ))
+            }
+            _ => None,
+        };
+        targets.push(match target_addr {
+            Some(t) if t % 2 == 1 || t as usize / 2 > len => {
+                return Err(format!(
+                    "{:03}: {} jumps to {:03}, which is not an instruction",
+                    addr, instruction, t
+                ))
+            }
+            Some(t) => Some(t as usize / 2),
+            None => None,
+        });
+    }
+    Ok(targets)
+}
+
+struct Node {
+    instruction: Instruction,
+    /// Index of the jump or call target
+    target: Option<usize>,
+    removed: bool,
+}
+
+struct Optimizer {
+    nodes: Vec<Node>,
+    report: OptimizeReport,
+}
+
+impl Optimizer {
+    fn new(program: &[Instruction]) -> Result<Optimizer, String> {
+        let nodes = program
+            .iter()
+            .zip(static_targets(program)?)
+            .map(|(instruction, target)| Node {
+                instruction: instruction.clone(),
+                target,
+                removed: false,
+            })
+            .collect();
+
+        Ok(Optimizer {
+            nodes,
+            report: OptimizeReport {
+                bytes_before: program.len() * 2,
+                ..OptimizeReport::default()
+            },
+        })
+    }
+
+    fn next_live(&self, mut i: usize) -> usize {
+        while i < self.nodes.len() && self.nodes[i].removed {
+            i += 1;
+        }
+        i
+    }
+
+    fn live(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.nodes.len()).filter(move |i| !self.nodes[*i].removed)
+    }
+
+    /// Indices that execution may continue at after node `i`. Removed nodes
+    /// fall through.
+    fn successors(&self, i: usize) -> Vec<usize> {
+        let node = &self.nodes[i];
+        let next = i + 1;
+        let successors = if node.removed {
+            vec![next]
+        } else {
+            match &node.instruction {
+                Instruction::Jmp { flag, .. } | Instruction::JmpR { flag, .. } => match flag {
+                    AluFlagRef::True => vec![node.target.unwrap()],
+                    AluFlagRef::False => vec![next],
+                    _ => vec![node.target.unwrap(), next],
+                },
+                Instruction::Stack(StackInstruction::CallC { .. })
+                | Instruction::Stack(StackInstruction::CallR { .. }) => {
+                    vec![node.target.unwrap(), next]
+                }
+                Instruction::Stack(StackInstruction::Ret { .. })
+                | Instruction::Nop(NopOpcode::Halt) => vec![],
+                _ => vec![next],
+            }
+        };
+        successors
+            .into_iter()
+            .filter(|s| *s < self.nodes.len())
+            .collect()
+    }
+
+    /// Live nodes that execution can reach other than by falling through.
+    fn labels(&self) -> BTreeSet<usize> {
+        let mut labels = BTreeSet::new();
+        for i in self.live() {
+            let node = &self.nodes[i];
+            if let Some(target) = node.target {
+                labels.insert(self.next_live(target));
+            }
+            if is_call(&node.instruction) {
+                labels.insert(self.next_live(i + 1));
+            }
+        }
+        labels
+    }
+
+    fn remove(&mut self, i: usize) {
+        self.nodes[i].removed = true;
+    }
+
+    fn is_jump(&self, i: usize) -> bool {
+        matches!(
+            self.nodes[i].instruction,
+            Instruction::Jmp { .. } | Instruction::JmpR { .. }
+        )
+    }
+
+    fn jump_flag(&self, i: usize) -> Option<AluFlagRef> {
+        match self.nodes[i].instruction {
+            Instruction::Jmp { flag, .. }
+            | Instruction::JmpR { flag, .. }
+            | Instruction::JmpP { flag, .. }
+            | Instruction::JmpRP { flag, .. } => Some(flag),
+            _ => None,
+        }
+    }
+
+    fn set_jump_flag(&mut self, i: usize, new_flag: AluFlagRef) {
+        match &mut self.nodes[i].instruction {
+            Instruction::Jmp { flag, .. } | Instruction::JmpR { flag, .. } => *flag = new_flag,
+            _ => {}
+        }
+    }
+
+    fn remove_jumps(&mut self) -> bool {
+        let mut changed = false;
+        for i in self.live().collect::<Vec<usize>>() {
+            if self.jump_flag(i) == Some(AluFlagRef::False) {
+                self.remove(i);
+                self.report.jumps += 1;
+                changed = true;
+                continue;
+            }
+            if !self.is_jump(i) {
+                continue;
+            }
+
+            let original = self.next_live(self.nodes[i].target.unwrap());
+            let mut target = original;
+            for _ in 0..self.nodes.len() {
+                let j = self.next_live(target);
+                if j < self.nodes.len()
+                    && j != i
+                    && self.is_jump(j)
+                    && self.jump_flag(j) == Some(AluFlagRef::True)
+                {
+                    target = self.next_live(self.nodes[j].target.unwrap());
+                } else {
+                    break;
+                }
+            }
+            if target != original {
+                self.nodes[i].target = Some(target);
+                self.report.jumps += 1;
+                changed = true;
+            }
+
+            if target == self.next_live(i + 1) {
+                self.remove(i);
+                self.report.jumps += 1;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn remove_unreachable(&mut self) -> bool {
+        let mut reachable = vec![false; self.nodes.len()];
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            if i < self.nodes.len() && !reachable[i] {
+                reachable[i] = true;
+                stack.extend(self.successors(i));
+            }
+        }
+
+        let mut changed = false;
+        for i in self.live().collect::<Vec<usize>>() {
+            if !reachable[i] {
+                self.remove(i);
+                self.report.unreachable += 1;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn merge_push_pop(&mut self) -> bool {
+        let labels = self.labels();
+        let mut changed = false;
+        for i in self.live().collect::<Vec<usize>>() {
+            if self.nodes[i].removed {
+                continue;
+            }
+            let j = self.next_live(i + 1);
+            if j >= self.nodes.len() || labels.contains(&j) {
+                continue;
+            }
+            let pair = match (&self.nodes[i].instruction, &self.nodes[j].instruction) {
+                (
+                    Instruction::Stack(StackInstruction::Push { src }),
+                    Instruction::Stack(StackInstruction::Pop { dest }),
+                ) => (*src, *dest),
+                _ => continue,
+            };
+            match pair {
+                (RegisterRef::ST, _) | (_, RegisterRef::ST) => continue,
+                (RegisterRef::BP, _) | (_, RegisterRef::BP) => continue,
+                (src, dest) if src == dest => {
+                    self.remove(i);
+                    self.report.stack += 1;
+                }
+                (src, dest) => {
+                    self.nodes[i].instruction = Instruction::Mov { dest, src };
+                }
+            }
+            self.remove(j);
+            self.report.stack += 1;
+            changed = true;
+        }
+        changed
+    }
+
+    /// Known values before each node, or None if unreachable.
+    fn known(&self) -> Vec<Option<Known>> {
+        let len = self.nodes.len();
+        let mut known: Vec<Option<Known>> = vec![None; len];
+        let mut worklist = Vec::new();
+        if len > 0 {
+            known[0] = Some(Known {
+                registers: [Some(0); 4],
+                ..Known::unknown()
+            });
+            worklist.push(0);
+        }
+        for i in self.live() {
+            if is_call(&self.nodes[i].instruction) {
+                if let Some(target) = self.nodes[i].target {
+                    if target < len {
+                        known[target] = Some(Known::unknown());
+                        worklist.push(target);
+                    }
+                }
+            }
+        }
+
+        while let Some(i) = worklist.pop() {
+            let before = known[i].clone().unwrap();
+            let after = if self.nodes[i].removed {
+                before
+            } else {
+                before.transfer(&self.nodes[i].instruction)
+            };
+            for s in self.successors(i) {
+                let node = &self.nodes[i];
+                if !node.removed && is_call(&node.instruction) && Some(s) == node.target {
+                    continue;
+                }
+                let merged = match &known[s] {
+                    Some(k) => k.meet(&after),
+                    None => after.clone(),
+                };
+                if known[s].as_ref() != Some(&merged) {
+                    known[s] = Some(merged);
+                    worklist.push(s);
+                }
+            }
+        }
+        known
+    }
+
+    fn propagate_constants(&mut self) -> bool {
+        let known = self.known();
+        let mut changed = false;
+        for i in self.live().collect::<Vec<usize>>() {
+            let k = match &known[i] {
+                Some(k) => k,
+                None => continue,
+            };
+            let replacement = match &self.nodes[i].instruction {
+                Instruction::MovC { dest, val } if k.value(*dest) == Some(*val) => None,
+                Instruction::Mov { dest, src } if reg_index(*dest).is_some() => {
+                    match k.value(*src) {
+                        Some(v) if k.value(*dest) == Some(v) => None,
+                        Some(v) => Some(Instruction::MovC {
+                            dest: *dest,
+                            val: v,
+                        }),
+                        None => continue,
+                    }
+                }
+                Instruction::LoadP { dest, addr_src } => match k.value(*addr_src) {
+                    Some(addr) => Some(Instruction::Load { dest: *dest, addr }),
+                    None => continue,
+                },
+                Instruction::StoreP { src, addr_src } => match k.value(*addr_src) {
+                    Some(addr) => Some(Instruction::Store { src: *src, addr }),
+                    None => continue,
+                },
+                Instruction::Jmp { flag, .. } | Instruction::JmpR { flag, .. }
+                    if *flag != AluFlagRef::True && *flag != AluFlagRef::False =>
+                {
+                    match k.flag(*flag) {
+                        Some(true) => {
+                            self.set_jump_flag(i, AluFlagRef::True);
+                            self.report.constants += 1;
+                            changed = true;
+                            continue;
+                        }
+                        Some(false) => None,
+                        None => continue,
+                    }
+                }
+                Instruction::Alu {
+                    op: AluOpcode::Echo,
+                    arg1,
+                    arg2,
+                    out,
+                } if out == arg1 && k.last_echo == Some((*arg1, *arg2)) => {
+                    self.remove(i);
+                    self.report.flags += 1;
+                    changed = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            match replacement {
+                Some(instruction) => self.nodes[i].instruction = instruction,
+                None => self.remove(i),
+            }
+            self.report.constants += 1;
+            changed = true;
+        }
+        changed
+    }
+
+    /// Registers and flags live after each node.
+    fn live_out(&self) -> Vec<u16> {
+        let len = self.nodes.len();
+        let mut live_in = vec![0u16; len];
+        let mut live_out = vec![0u16; len];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in (0..len).rev() {
+                let out = self
+                    .successors(i)
+                    .into_iter()
+                    .fold(0, |acc, s| acc | live_in[s]);
+                let (uses, defs) = if self.nodes[i].removed {
+                    (0, 0)
+                } else {
+                    uses_defs(&self.nodes[i].instruction)
+                };
+                let new_in = uses | (out & !defs);
+                if out != live_out[i] || new_in != live_in[i] {
+                    live_out[i] = out;
+                    live_in[i] = new_in;
+                    changed = true;
+                }
+            }
+        }
+        live_out
+    }
+
+    fn remove_dead_code(&mut self) -> bool {
+        let live_out = self.live_out();
+        let known = self.known();
+        let mut changed = false;
+        for i in self.live().collect::<Vec<usize>>() {
+            let live = live_out[i];
+            let dead =
+                |reg: &RegisterRef| reg_index(*reg).is_some() && live & write_bits(*reg) == 0;
+            match &self.nodes[i].instruction {
+                Instruction::Nop(NopOpcode::Nop) => {
+                    self.remove(i);
+                    self.report.dead_code += 1;
+                    changed = true;
+                }
+                Instruction::Load { dest, .. }
+                | Instruction::LoadP { dest, .. }
+                | Instruction::Mov { dest, .. }
+                | Instruction::MovC { dest, .. }
+                | Instruction::Stack(StackInstruction::Load { dest, .. })
+                    if dead(dest) =>
+                {
+                    self.remove(i);
+                    self.report.dead_code += 1;
+                    changed = true;
+                }
+                Instruction::Alu {
+                    op,
+                    arg1,
+                    arg2,
+                    out,
+                } if reg_index(*out).is_some() => {
+                    let (_, defs) = uses_defs(&self.nodes[i].instruction);
+                    if live & defs & (FLAGS_COMPARE | FLAGS_OVERFLOW) != 0 {
+                        continue;
+                    }
+                    let unchanged = *op == AluOpcode::Echo && out == arg1;
+                    if dead(out) || unchanged {
+                        self.remove(i);
+                        self.report.dead_code += 1;
+                        changed = true;
+                        continue;
+                    }
+                    let k = match &known[i] {
+                        Some(k) => k,
+                        None => continue,
+                    };
+                    if let (Some(a), Some(b)) = (k.value(*arg1), k.value(*arg2)) {
+                        if reg_index(*arg1).is_some() && reg_index(*arg2).is_some() {
+                            let (result, _) = eval_alu(*op, a, b, k.overflow.unwrap_or(0));
+                            self.nodes[i].instruction = Instruction::MovC {
+                                dest: *out,
+                                val: result,
+                            };
+                            self.report.constants += 1;
+                            changed = true;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        changed
+    }
+
+    fn remove_dead_stores(&mut self) -> bool {
+        let labels = self.labels();
+        let mut changed = false;
+        for i in self.live().collect::<Vec<usize>>() {
+            let addr = match self.nodes[i].instruction {
+                Instruction::Store { addr, .. } => addr,
+                _ => continue,
+            };
+            let mut j = self.next_live(i + 1);
+            while j < self.nodes.len() && !labels.contains(&j) {
+                match &self.nodes[j].instruction {
+                    Instruction::Store { addr: a, .. } if *a == addr => {
+                        self.remove(i);
+                        self.report.dead_stores += 1;
+                        changed = true;
+                        break;
+                    }
+                    Instruction::Store { .. }
+                    | Instruction::StoreP { .. }
+                    | Instruction::Mov { .. }
+                    | Instruction::MovC { .. }
+                    | Instruction::Gpi { .. }
+                    | Instruction::Gpo { .. }
+                    | Instruction::Alu { .. }
+                    | Instruction::Stack(StackInstruction::Push { .. })
+                    | Instruction::Nop(NopOpcode::Nop) => {}
+                    Instruction::Load { addr: a, .. } if *a != addr => {}
+                    _ => break,
+                }
+                j = self.next_live(j + 1);
+            }
+        }
+        changed
+    }
+
+    fn layout(&self) -> Vec<Instruction> {
+        let mut new_index = Vec::with_capacity(self.nodes.len() + 1);
+        let mut count = 0;
+        for node in &self.nodes {
+            new_index.push(count);
+            if !node.removed {
+                count += 1;
+            }
+        }
+        new_index.push(count);
+
+        self.live()
+            .map(|i| {
+                let node = &self.nodes[i];
+                let addr = (new_index[i] * 2) as Word;
+                let target = node
+                    .target
+                    .map(|t| (new_index[self.next_live(t)] * 2) as Word);
+                match (&node.instruction, target) {
+                    (Instruction::Jmp { flag, .. }, Some(t)) => Instruction::Jmp {
+                        flag: *flag,
+                        addr: t,
+                    },
+                    (Instruction::JmpR { flag, .. }, Some(t)) => Instruction::JmpR {
+                        flag: *flag,
+                        diff: t.wrapping_sub(addr),
+                    },
+                    (Instruction::Stack(StackInstruction::CallC { .. }), Some(t)) => {
+                        Instruction::Stack(StackInstruction::CallC { addr: t })
+                    }
+                    (Instruction::Stack(StackInstruction::CallR { .. }), Some(t)) => {
+                        Instruction::Stack(StackInstruction::CallR {
+                            diff: t.wrapping_sub(addr),
+                        })
+                    }
+                    (instruction, _) => instruction.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Shrink a program with peephole rewrites, constant propagation and
+/// dead code elimination, then lay it out again with jump and call offsets
+/// fixed up.
+///
+/// Registers are considered dead at HALT and values below the stack top are
+/// considered dead, so those may differ from the original after a run. Fails
+/// if the program has a computed jump or call target, since code can't be
+/// moved then.
+pub fn optimize(program: &[Instruction]) -> Result<(Vec<Instruction>, OptimizeReport), String> {
+    let mut optimizer = Optimizer::new(program)?;
+    loop {
+        let mut changed = optimizer.remove_jumps();
+        changed |= optimizer.remove_unreachable();
+        changed |= optimizer.merge_push_pop();
+        changed |= optimizer.propagate_constants();
+        changed |= optimizer.remove_dead_code();
+        changed |= optimizer.remove_dead_stores();
+        if !changed {
+            break;
+        }
+    }
+
+    let optimized = optimizer.layout();
+    let mut report = optimizer.report;
+    report.bytes_after = optimized.len() * 2;
+    Ok((optimized, report))
+}
@@ -0,0 +1,292 @@
+use super::leg_computer::LegComputer;
+use super::leg_computer::Word;
+use super::leg_flag::Checker;
+use super::leg_flag::FlagImage;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+
+/// Accepted inputs of one length.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LengthCount {
+    pub len: usize,
+    /// Accepted inputs, or a lower bound if the search ran out of budget
+    pub count: u64,
+    pub complete: bool,
+    /// True if the count follows from the structure of the checker, rather
+    /// than from running the program on every input
+    pub structural: bool,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UniquenessReport {
+    /// Accepted inputs, up to `FlagProver::max_solutions` of them
+    pub solutions: Vec<Vec<Word>>,
+    pub lengths: Vec<LengthCount>,
+}
+
+impl UniquenessReport {
+    pub fn total(&self) -> u64 {
+        self.lengths.iter().map(|l| l.count).sum()
+    }
+
+    pub fn complete(&self) -> bool {
+        self.lengths.iter().all(|l| l.complete)
+    }
+
+    /// True if exactly one input is accepted. Check `solutions` to see that it
+    /// is the flag.
+    pub fn unique(&self) -> bool {
+        self.complete() && self.total() == 1
+    }
+}
+
+impl Display for UniquenessReport {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        for length in &self.lengths {
+            writeln!(
+                f,
+                "length {:3}: {}{} accepted{}",
+                length.len,
+                if length.complete { "" } else { "at least " },
+                length.count,
+                if length.structural { "" } else { " (searched)" },
+            )?;
+        }
+        for solution in &self.solutions {
+            let text: String = solution
+                .iter()
+                .flat_map(|b| std::ascii::escape_default(*b))
+                .map(char::from)
+                .collect();
+            writeln!(f, "accepted: \"{}\"", text)?;
+        }
+        match (self.complete(), self.total()) {
+            (true, 1) => write!(f, "Exactly one input is accepted"),
+            (true, n) => write!(f, "{} inputs are accepted", n),
+            (false, n) => write!(f, "At least {} inputs are accepted", n),
+        }
+    }
+}
+
+/// Finds every input, over an alphabet and a range of lengths, that a
+/// checker program accepts.
+///
+/// Inputs no longer than the solution of a `Checker::SortXor` image are
+/// enumerated through the structure of the check: an input `x` is accepted
+/// if `x[i] ^ y[i] == s[i]` for the sorted input `y` and the solution `s`,
+/// so each sorted `y` gives one candidate `x`, which must be a permutation of
+/// `y`. Other lengths and checkers are searched by running the program on
+/// every input, if there are few enough.
+pub struct FlagProver {
+    pub program: Vec<Word>,
+    pub image: FlagImage,
+    pub alphabet: Vec<Word>,
+    /// Shortest and longest input, inclusive
+    pub lengths: (usize, usize),
+    pub max_solutions: usize,
+    /// Search nodes for the structural enumeration of each length
+    pub max_nodes: usize,
+    /// Program runs for the search of each length
+    pub max_runs: usize,
+    pub max_steps: usize,
+}
+
+/// Enumerates sorted sequences `y` such that `y ^ s` is a permutation of `y`.
+struct SortXorSearch<'a> {
+    solution: &'a [Word],
+    allowed: [bool; 256],
+    /// How many more times each value occurs in `y` than in `x` so far
+    surplus: [i32; 256],
+    /// Sum of `surplus` below the last value of `y`, which only later bytes
+    /// of `x` can pay off since `y` is sorted
+    owed: usize,
+    y: Vec<Word>,
+    nodes: usize,
+    max_nodes: usize,
+    count: u64,
+    found: Vec<Vec<Word>>,
+    max_found: usize,
+}
+
+impl<'a> SortXorSearch<'a> {
+    fn search(&mut self) {
+        let i = self.y.len();
+        if i == self.solution.len() {
+            if self.owed == 0 && self.surplus.iter().all(|s| *s == 0) {
+                self.count += 1;
+                if self.found.len() < self.max_found {
+                    let x = self.y.iter().zip(self.solution).map(|(y, s)| y ^ s);
+                    self.found.push(x.collect());
+                }
+            }
+            return;
+        }
+
+        let remaining = self.solution.len() - i;
+        let low = self.y.last().cloned().unwrap_or(0) as usize;
+        // Sum of the surplus of the values in low..value, which y passes
+        let mut closed = 0;
+        for value in low..256 {
+            if value > low {
+                let passed = self.surplus[value - 1];
+                if passed < 0 {
+                    // x has a value that no later y can match
+                    break;
+                }
+                closed += passed as usize;
+            }
+            // Each later byte of x pays off at most one
+            if self.owed + closed > remaining || self.nodes >= self.max_nodes {
+                break;
+            }
+            self.nodes += 1;
+            let x = (value as Word ^ self.solution[i]) as usize;
+            if !self.allowed[value] || !self.allowed[x] {
+                continue;
+            }
+
+            self.surplus[value] += 1;
+            self.surplus[x] -= 1;
+            let owed = match x < value {
+                true if self.surplus[x] < 0 => None,
+                true => Some(self.owed + closed - 1),
+                false => Some(self.owed + closed),
+            };
+            if let Some(owed) = owed.filter(|o| *o < remaining) {
+                let previous = self.owed;
+                self.owed = owed;
+                self.y.push(value as Word);
+                self.search();
+                self.y.pop();
+                self.owed = previous;
+            }
+            self.surplus[value] -= 1;
+            self.surplus[x] += 1;
+        }
+    }
+}
+
+impl FlagProver {
+    pub fn new(program: Vec<Word>, image: FlagImage) -> FlagProver {
+        FlagProver {
+            program,
+            image,
+            alphabet: (0x20..0x7f).collect(),
+            lengths: (0, 0),
+            max_solutions: 100,
+            max_nodes: 100_000_000,
+            max_runs: 1_000_000,
+            max_steps: 100_000,
+        }
+    }
+
+    /// True if the program writes the OK string for `input`.
+    fn accepts(&self, input: &[Word]) -> Result<bool, String> {
+        let (ok, ok_len) = self.image.layout.ok;
+        let expected = &self.image.memory[ok as usize..ok as usize + ok_len];
+        let mut computer = LegComputer::new(self.program.clone(), self.image.with_input(input)?);
+        for _ in 0..self.max_steps {
+            if computer.is_halted() {
+                return Ok(computer.memory[0..ok_len] == *expected);
+            }
+            if computer.try_step().is_err() {
+                return Ok(false);
+            }
+        }
+        Ok(computer.is_halted() && computer.memory[0..ok_len] == *expected)
+    }
+
+    fn structural(&self, len: usize, report: &mut UniquenessReport) -> Result<LengthCount, String> {
+        let (start, _) = self.image.layout.solution;
+        let mut allowed = [false; 256];
+        for a in &self.alphabet {
+            allowed[*a as usize] = true;
+        }
+        let mut search = SortXorSearch {
+            solution: &self.image.memory[start as usize..start as usize + len],
+            allowed,
+            surplus: [0; 256],
+            owed: 0,
+            y: Vec::with_capacity(len),
+            nodes: 0,
+            max_nodes: self.max_nodes,
+            count: 0,
+            found: Vec::new(),
+            max_found: self.max_solutions.saturating_sub(report.solutions.len()),
+        };
+        search.search();
+
+        // Check the model against the program itself
+        for x in &search.found {
+            if !self.accepts(x)? {
+                return Err(format!("The program rejects {:?}, which should pass", x));
+            }
+        }
+        report.solutions.extend(search.found);
+        Ok(LengthCount {
+            len,
+            count: search.count,
+            complete: search.nodes < self.max_nodes,
+            structural: true,
+        })
+    }
+
+    fn exhaustive(&self, len: usize, report: &mut UniquenessReport) -> Result<LengthCount, String> {
+        // There are no inputs of this length to run
+        if self.alphabet.is_empty() && len > 0 {
+            return Ok(LengthCount {
+                len,
+                count: 0,
+                complete: true,
+                structural: false,
+            });
+        }
+        let mut count = 0;
+        let mut runs = 0;
+        let mut choice = vec![0; len];
+        let complete = loop {
+            if runs >= self.max_runs {
+                break false;
+            }
+            runs += 1;
+            let input: Vec<Word> = choice.iter().map(|c| self.alphabet[*c]).collect();
+            if self.accepts(&input)? {
+                count += 1;
+                if report.solutions.len() < self.max_solutions {
+                    report.solutions.push(input);
+                }
+            }
+            match choice.iter().rposition(|c| c + 1 < self.alphabet.len()) {
+                Some(i) => {
+                    choice[i] += 1;
+                    for c in &mut choice[i + 1..] {
+                        *c = 0;
+                    }
+                }
+                None => break true,
+            }
+        };
+        Ok(LengthCount {
+            len,
+            count,
+            complete,
+            structural: false,
+        })
+    }
+
+    /// Count the accepted inputs of each length, and list some of them.
+    pub fn prove(&self) -> Result<UniquenessReport, String> {
+        let mut report = UniquenessReport::default();
+        let (_, solution_len) = self.image.layout.solution;
+        for len in self.lengths.0..=self.lengths.1 {
+            let count = if self.image.layout.checker == Checker::SortXor && len <= solution_len {
+                self.structural(len, &mut report)?
+            } else {
+                self.exhaustive(len, &mut report)?
+            };
+            report.lengths.push(count);
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,254 @@
+use super::leg_cfg::Cfg;
+use super::leg_cfg::EdgeKind;
+use super::leg_computer::Address;
+use super::leg_computer::Instruction;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use super::leg_computer_parse::call_target;
+use super::leg_computer_parse::stack_delta;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum StackIssue {
+    /// A block is reached with different stack depths, e.g. by a loop that
+    /// pushes more than it pops
+    DepthMismatch {
+        addr: Address,
+        expected: i16,
+        found: i16,
+        from: Address,
+    },
+    /// POP of the saved BP or return address, or past the initial ST in the
+    /// entry function
+    PopBelowFrame { addr: Address, depth: i16 },
+    /// `SLOAD n` with n >= 2 where a call site pushed fewer than n - 1 values
+    SloadPastArguments {
+        addr: Address,
+        offset: i8,
+        call_site: Address,
+        pushed: i16,
+    },
+    /// `SLOAD n` with n >= 0 in the entry function, which has no caller
+    SloadWithoutCaller { addr: Address, offset: i8 },
+    /// `SLOAD 0` or `SLOAD 1`, reading the saved BP or return address
+    SloadFrameHeader { addr: Address, offset: i8 },
+    /// `SLOAD -n` for a slot that has not been pushed yet
+    SloadAboveTop {
+        addr: Address,
+        offset: i8,
+        depth: i16,
+    },
+    /// MOV or POP into ST or BP, which the analysis can't follow
+    StackPointerWrite { addr: Address },
+}
+
+impl StackIssue {
+    pub fn addr(&self) -> Address {
+        match self {
+            StackIssue::DepthMismatch { addr, .. }
+            | StackIssue::PopBelowFrame { addr, .. }
+            | StackIssue::SloadPastArguments { addr, .. }
+            | StackIssue::SloadWithoutCaller { addr, .. }
+            | StackIssue::SloadFrameHeader { addr, .. }
+            | StackIssue::SloadAboveTop { addr, .. }
+            | StackIssue::StackPointerWrite { addr } => *addr,
+        }
+    }
+}
+
+impl Display for StackIssue {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            StackIssue::DepthMismatch {
+                addr,
+                expected,
+                found,
+                from,
+            } => write!(
+                f,
+                "{:03}: reached with stack depth {} from {:03}, but {} on another path",
+                addr, found, from, expected
+            ),
+            StackIssue::PopBelowFrame { addr, depth } => write!(
+                f,
+                "{:03}: POP at stack depth {} reads below the frame",
+                addr, depth
+            ),
+            StackIssue::SloadPastArguments {
+                addr,
+                offset,
+                call_site,
+                pushed,
+            } => write!(
+                f,
+                "{:03}: SLOAD {} reads past the arguments of the call at {:03}, which pushed {}",
+                addr, offset, call_site, pushed
+            ),
+            StackIssue::SloadWithoutCaller { addr, offset } => write!(
+                f,
+                "{:03}: SLOAD {} reads arguments, but the entry function has no caller",
+                addr, offset
+            ),
+            StackIssue::SloadFrameHeader { addr, offset } => write!(
+                f,
+                "{:03}: SLOAD {} reads the {}",
+                addr,
+                offset,
+                if *offset == 0 {
+                    "saved BP"
+                } else {
+                    "return address"
+                }
+            ),
+            StackIssue::SloadAboveTop {
+                addr,
+                offset,
+                depth,
+            } => write!(
+                f,
+                "{:03}: SLOAD {} reads above the stack top at depth {}",
+                addr, offset, depth
+            ),
+            StackIssue::StackPointerWrite { addr } => write!(
+                f,
+                "{:03}: writes a stack pointer, stack depths after it are unreliable",
+                addr
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StackReport {
+    /// Stack depth relative to BP before each reachable instruction
+    pub depths: BTreeMap<Address, i16>,
+    /// Deepest stack depth reached within each function, by entry
+    pub max_depths: BTreeMap<Address, i16>,
+    pub issues: Vec<StackIssue>,
+}
+
+/// Compute the stack depth at every instruction reachable in `cfg` and check
+/// the calling convention.
+pub fn check_stack(cfg: &Cfg) -> StackReport {
+    let mut report = StackReport::default();
+    let mut call_sites: BTreeMap<Address, Vec<(Address, i16)>> = BTreeMap::new();
+    let mut sloads = Vec::new();
+
+    for (entry, function) in &cfg.functions {
+        let mut block_depths: BTreeMap<Address, (i16, Address)> = BTreeMap::new();
+        let mut max_depth = 0;
+        let mut stack = vec![(*entry, 0, *entry)];
+        while let Some((start, depth, from)) = stack.pop() {
+            if !function.blocks.contains(&start) {
+                continue;
+            }
+            if let Some((expected, _)) = block_depths.get(&start) {
+                if *expected != depth {
+                    report.issues.push(StackIssue::DepthMismatch {
+                        addr: start,
+                        expected: *expected,
+                        found: depth,
+                        from,
+                    });
+                }
+                continue;
+            }
+            block_depths.insert(start, (depth, from));
+
+            let block = &cfg.blocks[&start];
+            let mut depth = depth;
+            for (addr, instruction) in &block.instructions {
+                report.depths.entry(*addr).or_insert(depth);
+                match instruction {
+                    Instruction::Stack(StackInstruction::Pop { dest }) => {
+                        if depth <= 0 {
+                            report
+                                .issues
+                                .push(StackIssue::PopBelowFrame { addr: *addr, depth });
+                        }
+                        if *dest == RegisterRef::ST || *dest == RegisterRef::BP {
+                            report
+                                .issues
+                                .push(StackIssue::StackPointerWrite { addr: *addr });
+                        }
+                    }
+                    Instruction::Mov { dest, .. } | Instruction::MovC { dest, .. }
+                        if *dest == RegisterRef::ST || *dest == RegisterRef::BP =>
+                    {
+                        report
+                            .issues
+                            .push(StackIssue::StackPointerWrite { addr: *addr });
+                    }
+                    Instruction::Stack(StackInstruction::Load { bp_diff, .. }) => {
+                        sloads.push((*entry, *addr, *bp_diff as i8, depth));
+                    }
+                    _ => {}
+                }
+                if let Some(target) = call_target(*addr, instruction) {
+                    call_sites.entry(target).or_default().push((*addr, depth));
+                }
+                depth += stack_delta(instruction);
+                max_depth = i16::max(max_depth, depth);
+            }
+
+            let last = block.instructions.last().map(|(a, _)| *a).unwrap_or(start);
+            for edge in block.edges.iter().filter(|e| e.kind != EdgeKind::Call) {
+                if let Some(target) = edge.target {
+                    stack.push((target, depth, last));
+                }
+            }
+        }
+        report.max_depths.insert(*entry, max_depth);
+    }
+
+    for (entry, addr, offset, depth) in sloads {
+        match offset {
+            0 | 1 if entry == 0 => {
+                report
+                    .issues
+                    .push(StackIssue::SloadWithoutCaller { addr, offset });
+            }
+            0 | 1 => report
+                .issues
+                .push(StackIssue::SloadFrameHeader { addr, offset }),
+            n if n >= 2 => match call_sites.get(&entry) {
+                Some(sites) => {
+                    for (call_site, pushed) in sites {
+                        if (n as i16) - 1 > *pushed {
+                            report.issues.push(StackIssue::SloadPastArguments {
+                                addr,
+                                offset,
+                                call_site: *call_site,
+                                pushed: *pushed,
+                            });
+                        }
+                    }
+                }
+                // Other functions without a constant call site are called
+                // through a register, by callers we can't check
+                None if entry != 0 => {}
+                None => report
+                    .issues
+                    .push(StackIssue::SloadWithoutCaller { addr, offset }),
+            },
+            n => {
+                if -(n as i16) > depth {
+                    report.issues.push(StackIssue::SloadAboveTop {
+                        addr,
+                        offset,
+                        depth,
+                    });
+                }
+            }
+        }
+    }
+
+    report
+        .issues
+        .sort_by(|a, b| (a.addr(), a).cmp(&(b.addr(), b)));
+    report.issues.dedup();
+    report
+}
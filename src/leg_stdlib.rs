@@ -0,0 +1,275 @@
+/// memcpy(dest, src, len) -> dest
+///
+/// Copy `len` bytes from `src` to `dest`, front to back, so `dest` may
+/// overlap `src` only if `dest <= src`.
+pub const MEMCPY: &str = "
+.func memcpy(dest, src, len)
+SLOAD dest => C
+SLOAD src => B
+SLOAD len => D
+ALU ECHO D D => D
+JMPR Z ? 14
+LOADP B => A
+STOREP A => C
+ALU INCR B B => B
+ALU INCR C C => C
+ALU DECR D D => D
+JMPR T ? -14
+SLOAD dest => A
+RET A
+.endfunc
+";
+
+/// memset(dest, value, len) -> dest
+///
+/// Fill `len` bytes from `dest` with `value`.
+pub const MEMSET: &str = "
+.func memset(dest, value, len)
+SLOAD dest => C
+SLOAD value => A
+SLOAD len => D
+ALU ECHO D D => D
+JMPR Z ? 10
+STOREP A => C
+ALU INCR C C => C
+ALU DECR D D => D
+JMPR T ? -10
+SLOAD dest => A
+RET A
+.endfunc
+";
+
+/// memcmp(a, b, len) -> difference
+///
+/// Compare `len` bytes from `a` and `b`. Returns 0 if they are equal,
+/// otherwise `a[i] - b[i]` for the first `i` where they differ.
+pub const MEMCMP: &str = "
+.func memcmp(a, b, len)
+.local end
+SLOAD a => C
+SLOAD len => D
+ALU ADD C D => D
+PUSH D
+SLOAD b => B
+
+# Loop until a reaches end
+SLOAD end => D
+ALU ECHO C D => C
+JMPR EQ ? 18
+LOADP C => A
+LOADP B => D
+ALU SUB A D => A
+JMPR NE ? 8
+ALU INCR B B => B
+ALU INCR C C => C
+JMPR T ? -18
+
+RET A
+MOVC 0 => A
+RET A
+.endfunc
+";
+
+/// sort(start, len) -> start
+///
+/// Sort `len` bytes from `start` in place, as unsigned, in ascending order.
+/// Takes quadratic time.
+pub const SORT: &str = "
+.func sort(start, len)
+.local end
+SLOAD start => C
+SLOAD len => D
+ALU ADD C D => D
+PUSH D
+
+# Outer loop: move the least of C..end to C
+SLOAD end => D
+ALU ECHO C D => C
+JMPR EQ ? 30
+MOV C => D
+
+# Inner loop: D runs from C + 1 to end
+ALU INCR D D => D
+SLOAD end => A
+ALU ECHO D A => D
+JMPR EQ ? 16
+LOADP C => A
+LOADP D => B
+ALU ECHO B A => B
+JMPR GE ? -14
+STOREP B => C
+STOREP A => D
+JMPR T ? -20
+
+ALU INCR C C => C
+JMPR T ? -32
+
+SLOAD start => A
+RET A
+.endfunc
+";
+
+/// xor_fold(start, len) -> xor
+///
+/// XOR together `len` bytes from `start`. Returns 0 if `len` is 0.
+pub const XOR_FOLD: &str = "
+.func xor_fold(start, len)
+SLOAD start => C
+SLOAD len => D
+MOVC 0 => A
+ALU ECHO D D => D
+JMPR Z ? 12
+LOADP C => B
+ALU XOR A B => A
+ALU INCR C C => C
+ALU DECR D D => D
+JMPR T ? -12
+RET A
+.endfunc
+";
+
+/// checksum(start, len) -> sum
+///
+/// Add together `len` bytes from `start`, modulo 256.
+pub const CHECKSUM: &str = "
+.func checksum(start, len)
+SLOAD start => C
+SLOAD len => D
+MOVC 0 => A
+ALU ECHO D D => D
+JMPR Z ? 12
+LOADP C => B
+ALU ADD A B => A
+ALU INCR C C => C
+ALU DECR D D => D
+JMPR T ? -12
+RET A
+.endfunc
+";
+
+/// mul(a, b) -> a * b
+///
+/// Multiply by shifting and adding, modulo 256.
+pub const MUL: &str = "
+.func mul(a, b)
+SLOAD a => B
+SLOAD b => C
+MOVC 0 => A
+
+# Add B for each set bit of C, doubling B for each bit
+ALU ECHO C C => C
+JMPR Z ? 22
+MOVC 1 => D
+ALU AND C D => D
+JMPR Z ? 4
+ALU ADD A B => A
+MOVC 1 => D
+ALU SHIFTL B D => B
+
+# SHIFTR copies the top bit, so clear it
+ALU SHIFTR C D => C
+MOVC 127 => D
+ALU AND C D => C
+JMPR T ? -22
+
+RET A
+.endfunc
+";
+
+/// div(a, b) -> a / b
+///
+/// Divide by repeated subtraction, rounding down. Returns 255 if `b` is 0.
+pub const DIV: &str = "
+.func div(a, b)
+SLOAD a => B
+SLOAD b => C
+MOVC 255 => A
+ALU ECHO C C => C
+JMPR Z ? 14
+MOVC 0 => A
+
+# Subtract C from B until B < C
+ALU ECHO B C => B
+JMPR LT ? 8
+ALU SUB B C => B
+ALU INCR A A => A
+JMPR T ? -8
+
+RET A
+.endfunc
+";
+
+/// mod(a, b) -> a % b
+///
+/// Remainder of `div(a, b)`. Returns `a` if `b` is 0.
+pub const MOD: &str = "
+.func mod(a, b)
+SLOAD a => B
+SLOAD b => C
+ALU ECHO C C => C
+JMPR Z ? 10
+
+# Subtract C from B until B < C
+ALU ECHO B C => B
+JMPR LT ? 6
+ALU SUB B C => B
+JMPR T ? -6
+
+RET B
+.endfunc
+";
+
+/// hex_out(value) -> value
+///
+/// Write `value` as two lowercase hex digits to GPO, high digit first.
+pub const HEX_OUT: &str = "
+.func hex_out(value)
+SLOAD value => A
+
+# High digit: add '0' below 10, otherwise 'a' - 10
+MOVC 4 => B
+ALU SHIFTR A B => C
+MOVC 15 => B
+ALU AND C B => C
+MOVC 10 => D
+ALU ECHO C D => C
+MOVC 48 => D
+JMPR LT ? 4
+MOVC 87 => D
+ALU ADD C D => C
+GPO C =>
+
+# Low digit
+ALU AND A B => C
+MOVC 10 => D
+ALU ECHO C D => C
+MOVC 48 => D
+JMPR LT ? 4
+MOVC 87 => D
+ALU ADD C D => C
+GPO C =>
+
+RET A
+.endfunc
+";
+
+/// The library routines by name, for `.include name` in assembly sources.
+///
+/// All routines follow the convention of the challenge: the caller pushes
+/// the arguments in order, so the last one is at stack offset 2, calls the
+/// routine, and then pops the return value and the arguments. Routines may
+/// overwrite A to D and the flags, and leave BP and ST as they found them.
+/// Memory ranges are given as a start address and a length, and may wrap
+/// around the end of memory.
+pub const STDLIB: [(&str, &str); 10] = [
+    ("memcpy", MEMCPY),
+    ("memset", MEMSET),
+    ("memcmp", MEMCMP),
+    ("sort", SORT),
+    ("xor_fold", XOR_FOLD),
+    ("checksum", CHECKSUM),
+    ("mul", MUL),
+    ("div", DIV),
+    ("mod", MOD),
+    ("hex_out", HEX_OUT),
+];
@@ -0,0 +1,941 @@
+use super::leg_computer::Address;
+use super::leg_computer::AluFlagRef;
+use super::leg_computer::AluOpcode;
+use super::leg_computer::Instruction;
+use super::leg_computer::NopOpcode;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use super::leg_computer::Word;
+use std::cell::Cell;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinOp {
+    Add,
+    Xor,
+    Or,
+    And,
+    Nand,
+    Nor,
+    /// Shift left by the low 3 bits of the right operand
+    ShiftL,
+    /// Arithmetic shift right by the low 3 bits of the right operand
+    ShiftR,
+}
+
+/// An 8-bit value computed from the symbolic input bytes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Const(Word),
+    /// The input byte with this index
+    Input(usize),
+    Not(Rc<Expr>),
+    Bin(BinOp, Rc<Expr>, Rc<Expr>),
+    Ite(Rc<Cond>, Rc<Expr>, Rc<Expr>),
+}
+
+/// A boolean condition over input bytes, as produced by the ALU flags.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Cond {
+    Const(bool),
+    Eq(Rc<Expr>, Rc<Expr>),
+    /// Unsigned less than
+    Ult(Rc<Expr>, Rc<Expr>),
+    /// Signed less than
+    Slt(Rc<Expr>, Rc<Expr>),
+    /// Unsigned overflow of `a + b + carry_in`
+    Carry(Rc<Expr>, Rc<Expr>, bool),
+    /// Signed overflow of `a + b + carry_in`
+    SignedOverflow(Rc<Expr>, Rc<Expr>, bool),
+    Not(Rc<Cond>),
+    And(Rc<Cond>, Rc<Cond>),
+}
+
+fn eval_bin(op: BinOp, a: Word, b: Word) -> Word {
+    match op {
+        BinOp::Add => a.wrapping_add(b),
+        BinOp::Xor => a ^ b,
+        BinOp::Or => a | b,
+        BinOp::And => a & b,
+        BinOp::Nand => !(a & b),
+        BinOp::Nor => !(a | b),
+        BinOp::ShiftL => a << (b & 0x7),
+        BinOp::ShiftR => ((a as i8) >> (b & 0x7)) as Word,
+    }
+}
+
+impl Expr {
+    pub fn eval(&self, inputs: &[Word]) -> Word {
+        match self {
+            Expr::Const(w) => *w,
+            Expr::Input(i) => inputs[*i],
+            Expr::Not(a) => !a.eval(inputs),
+            Expr::Bin(op, a, b) => eval_bin(*op, a.eval(inputs), b.eval(inputs)),
+            Expr::Ite(c, a, b) => {
+                if c.eval(inputs) {
+                    a.eval(inputs)
+                } else {
+                    b.eval(inputs)
+                }
+            }
+        }
+    }
+
+    pub fn as_const(&self) -> Option<Word> {
+        match self {
+            Expr::Const(w) => Some(*w),
+            _ => None,
+        }
+    }
+
+    fn collect_inputs(&self, out: &mut BTreeSet<usize>) {
+        match self {
+            Expr::Const(_) => {}
+            Expr::Input(i) => {
+                out.insert(*i);
+            }
+            Expr::Not(a) => a.collect_inputs(out),
+            Expr::Bin(_, a, b) => {
+                a.collect_inputs(out);
+                b.collect_inputs(out);
+            }
+            Expr::Ite(c, a, b) => {
+                c.collect_inputs(out);
+                a.collect_inputs(out);
+                b.collect_inputs(out);
+            }
+        }
+    }
+}
+
+impl Cond {
+    pub fn eval(&self, inputs: &[Word]) -> bool {
+        fn add(a: Word, b: Word, carry_in: bool) -> i16 {
+            a as i16 + b as i16 + carry_in as i16
+        }
+        fn add_signed(a: Word, b: Word, carry_in: bool) -> i16 {
+            a as i8 as i16 + b as i8 as i16 + carry_in as i16
+        }
+
+        match self {
+            Cond::Const(b) => *b,
+            Cond::Eq(a, b) => a.eval(inputs) == b.eval(inputs),
+            Cond::Ult(a, b) => a.eval(inputs) < b.eval(inputs),
+            Cond::Slt(a, b) => (a.eval(inputs) as i8) < (b.eval(inputs) as i8),
+            Cond::Carry(a, b, c) => add(a.eval(inputs), b.eval(inputs), *c) > 0xff,
+            Cond::SignedOverflow(a, b, c) => {
+                !(-128..=127).contains(&add_signed(a.eval(inputs), b.eval(inputs), *c))
+            }
+            Cond::Not(c) => !c.eval(inputs),
+            Cond::And(a, b) => a.eval(inputs) && b.eval(inputs),
+        }
+    }
+
+    pub fn as_const(&self) -> Option<bool> {
+        match self {
+            Cond::Const(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    fn collect_inputs(&self, out: &mut BTreeSet<usize>) {
+        match self {
+            Cond::Const(_) => {}
+            Cond::Eq(a, b)
+            | Cond::Ult(a, b)
+            | Cond::Slt(a, b)
+            | Cond::Carry(a, b, _)
+            | Cond::SignedOverflow(a, b, _) => {
+                a.collect_inputs(out);
+                b.collect_inputs(out);
+            }
+            Cond::Not(c) => c.collect_inputs(out),
+            Cond::And(a, b) => {
+                a.collect_inputs(out);
+                b.collect_inputs(out);
+            }
+        }
+    }
+
+    /// The indices of the input bytes this condition depends on.
+    pub fn inputs(&self) -> BTreeSet<usize> {
+        let mut out = BTreeSet::new();
+        self.collect_inputs(&mut out);
+        out
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Expr::Const(w) => write!(f, "{}", w),
+            Expr::Input(i) => write!(f, "in[{}]", i),
+            Expr::Not(a) => write!(f, "~{}", a),
+            Expr::Bin(op, a, b) => {
+                let op = match op {
+                    BinOp::Add => "+",
+                    BinOp::Xor => "^",
+                    BinOp::Or => "|",
+                    BinOp::And => "&",
+                    BinOp::Nand => "~&",
+                    BinOp::Nor => "~|",
+                    BinOp::ShiftL => "<<",
+                    BinOp::ShiftR => ">>",
+                };
+                write!(f, "({} {} {})", a, op, b)
+            }
+            Expr::Ite(c, a, b) => write!(f, "({} ? {} : {})", c, a, b),
+        }
+    }
+}
+
+impl Display for Cond {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Cond::Const(b) => write!(f, "{}", b),
+            Cond::Eq(a, b) => write!(f, "{} == {}", a, b),
+            Cond::Ult(a, b) => write!(f, "{} < {}", a, b),
+            Cond::Slt(a, b) => write!(f, "{} <s {}", a, b),
+            Cond::Carry(a, b, c) => write!(f, "carry({}, {}, {})", a, b, *c as u8),
+            Cond::SignedOverflow(a, b, c) => write!(f, "overflow({}, {}, {})", a, b, *c as u8),
+            Cond::Not(c) => write!(f, "!({})", c),
+            Cond::And(a, b) => write!(f, "({}) && ({})", a, b),
+        }
+    }
+}
+
+fn konst(w: Word) -> Rc<Expr> {
+    Rc::new(Expr::Const(w))
+}
+
+fn not(a: Rc<Expr>) -> Rc<Expr> {
+    match a.as_const() {
+        Some(w) => konst(!w),
+        None => Rc::new(Expr::Not(a)),
+    }
+}
+
+fn bin(op: BinOp, a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+    match (a.as_const(), b.as_const()) {
+        (Some(x), Some(y)) => konst(eval_bin(op, x, y)),
+        (_, Some(0)) if op == BinOp::Add || op == BinOp::Xor || op == BinOp::Or => a,
+        _ => Rc::new(Expr::Bin(op, a, b)),
+    }
+}
+
+fn ite(c: Rc<Cond>, a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+    match c.as_const() {
+        Some(true) => a,
+        Some(false) => b,
+        None => Rc::new(Expr::Ite(c, a, b)),
+    }
+}
+
+/// Build a condition, folding it to a constant if it doesn't depend on
+/// any input.
+fn cond(c: Cond) -> Rc<Cond> {
+    if c.inputs().is_empty() {
+        Rc::new(Cond::Const(c.eval(&[])))
+    } else {
+        Rc::new(c)
+    }
+}
+
+fn cond_not(c: Rc<Cond>) -> Rc<Cond> {
+    match &*c {
+        Cond::Const(b) => Rc::new(Cond::Const(!b)),
+        Cond::Not(inner) => inner.clone(),
+        _ => Rc::new(Cond::Not(c)),
+    }
+}
+
+/// Split a condition into simpler conditions that must all hold, so that
+/// each depends on as few input bytes as possible. This is what lets the
+/// solver prune early on checks like `(x ^ y) | (z ^ w) == 0`.
+fn conjuncts(c: &Rc<Cond>) -> Vec<Rc<Cond>> {
+    fn zero(e: &Rc<Expr>) -> Vec<Rc<Cond>> {
+        match &**e {
+            Expr::Bin(BinOp::Or, a, b) => {
+                let mut out = zero(a);
+                out.extend(zero(b));
+                out
+            }
+            Expr::Bin(BinOp::Xor, a, b) => equal(a, b),
+            _ => vec![cond(Cond::Eq(e.clone(), konst(0)))],
+        }
+    }
+
+    fn equal(a: &Rc<Expr>, b: &Rc<Expr>) -> Vec<Rc<Cond>> {
+        match (&**a, b.as_const()) {
+            (_, Some(0)) => zero(a),
+            (Expr::Bin(BinOp::Xor, x, k), Some(m)) if k.as_const().is_some() => {
+                equal(x, &konst(k.as_const().unwrap() ^ m))
+            }
+            (Expr::Bin(BinOp::Xor, k, x), Some(m)) if k.as_const().is_some() => {
+                equal(x, &konst(k.as_const().unwrap() ^ m))
+            }
+            _ if a.as_const().is_some() && b.as_const().is_none() => equal(b, a),
+            _ => vec![cond(Cond::Eq(a.clone(), b.clone()))],
+        }
+    }
+
+    match &**c {
+        Cond::And(a, b) => {
+            let mut out = conjuncts(a);
+            out.extend(conjuncts(b));
+            out
+        }
+        Cond::Eq(a, b) => equal(a, b),
+        _ => vec![c.clone()],
+    }
+    .into_iter()
+    .filter(|c| c.as_const() != Some(true))
+    .collect()
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum SolveResult {
+    Sat(Vec<Word>),
+    Unsat,
+    /// The search gave up after `Solver::node_limit` assignments
+    Unknown,
+}
+
+/// Backtracking bit-vector solver over the input bytes. Each input byte
+/// ranges over its own domain, and each constraint is checked as soon as all
+/// the input bytes it depends on are assigned.
+#[derive(Clone, Debug)]
+pub struct Solver {
+    pub domains: Vec<Vec<Word>>,
+    pub node_limit: usize,
+}
+
+impl Solver {
+    pub fn new(domains: Vec<Vec<Word>>) -> Solver {
+        Solver {
+            domains,
+            node_limit: 10_000_000,
+        }
+    }
+
+    /// Try `model` first, and only search if it doesn't satisfy every
+    /// constraint.
+    pub fn solve_from(&self, constraints: &[Rc<Cond>], model: &[Word]) -> SolveResult {
+        if constraints.iter().all(|c| c.eval(model)) {
+            SolveResult::Sat(model.to_vec())
+        } else {
+            self.solve(constraints)
+        }
+    }
+
+    pub fn solve(&self, constraints: &[Rc<Cond>]) -> SolveResult {
+        let n = self.domains.len();
+        if self.domains.iter().any(|d| d.is_empty()) {
+            return SolveResult::Unsat;
+        }
+
+        let deps: Vec<BTreeSet<usize>> = constraints.iter().map(|c| c.inputs()).collect();
+        if deps
+            .iter()
+            .zip(constraints)
+            .any(|(d, c)| d.is_empty() && !c.eval(&[]))
+        {
+            return SolveResult::Unsat;
+        }
+
+        // Assign the most constrained variables first, preferring ones that
+        // complete many constraints together with already ordered variables.
+        let mut order: Vec<usize> = Vec::with_capacity(n);
+        let mut ordered = vec![false; n];
+        while order.len() < n {
+            let next = (0..n)
+                .filter(|v| !ordered[*v])
+                .max_by_key(|v| {
+                    let touching = deps.iter().filter(|d| d.contains(v));
+                    let completing = touching
+                        .clone()
+                        .filter(|d| d.iter().all(|u| u == v || ordered[*u]))
+                        .count();
+                    (completing, touching.count(), std::cmp::Reverse(*v))
+                })
+                .unwrap();
+            ordered[next] = true;
+            order.push(next);
+        }
+
+        let mut position = vec![0; n];
+        for (i, v) in order.iter().enumerate() {
+            position[*v] = i;
+        }
+        let mut checks: Vec<Vec<usize>> = vec![Vec::new(); n];
+        for (ci, d) in deps.iter().enumerate() {
+            if let Some(last) = d.iter().map(|v| position[*v]).max() {
+                checks[last].push(ci);
+            }
+        }
+
+        let mut model: Vec<Word> = self.domains.iter().map(|d| d[0]).collect();
+        let mut choice = vec![0; n];
+        let mut depth = 0;
+        let mut nodes = 0;
+        loop {
+            if depth == n {
+                return SolveResult::Sat(model);
+            }
+            let var = order[depth];
+            if choice[depth] >= self.domains[var].len() {
+                if depth == 0 {
+                    return SolveResult::Unsat;
+                }
+                choice[depth] = 0;
+                depth -= 1;
+                choice[depth] += 1;
+                continue;
+            }
+
+            nodes += 1;
+            if nodes > self.node_limit {
+                return SolveResult::Unknown;
+            }
+            model[var] = self.domains[var][choice[depth]];
+            if checks[depth].iter().all(|ci| constraints[*ci].eval(&model)) {
+                depth += 1;
+            } else {
+                choice[depth] += 1;
+            }
+        }
+    }
+}
+
+/// A symbolic input byte placed in memory before the run.
+#[derive(Clone, Debug)]
+pub struct SymbolicInput {
+    pub addr: Address,
+    pub domain: Vec<Word>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    /// eip reaches this address
+    Reach(Address),
+    /// The program halts with these bytes in memory starting at `addr`
+    HaltWithMemory { addr: Address, bytes: Vec<Word> },
+}
+
+#[derive(Clone, Debug)]
+pub struct SymState {
+    pub eip: Word,
+    pub registers: HashMap<RegisterRef, Rc<Expr>>,
+    pub memory: Vec<Rc<Expr>>,
+    pub flags: HashMap<AluFlagRef, Rc<Cond>>,
+    pub constraints: Vec<Rc<Cond>>,
+    /// An input satisfying `constraints`
+    pub model: Vec<Word>,
+    pub steps: usize,
+}
+
+/// A path that reaches the target, and an input that takes it.
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub input: Vec<Word>,
+    pub constraints: Vec<Rc<Cond>>,
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolicReport {
+    pub solutions: Vec<Solution>,
+    /// Number of paths followed to the end, a fault or the step limit
+    pub paths: usize,
+    /// True if some path was cut short by a limit, or the solver gave up, so
+    /// there may be more solutions than reported
+    pub incomplete: bool,
+    pub errors: Vec<String>,
+}
+
+enum Step {
+    Continue,
+    /// Replace this state with these states
+    Fork(Vec<SymState>),
+    Halted,
+}
+
+const FLAGS: [AluFlagRef; 15] = [
+    AluFlagRef::EqZero,
+    AluFlagRef::OverflowUnsigned,
+    AluFlagRef::OverflowSigned,
+    AluFlagRef::Equal,
+    AluFlagRef::GreaterThan,
+    AluFlagRef::GreaterThanSigned,
+    AluFlagRef::GreaterOrEqual,
+    AluFlagRef::GreaterOrEqualSigned,
+    AluFlagRef::NotEqual,
+    AluFlagRef::LessThan,
+    AluFlagRef::LessThanSigned,
+    AluFlagRef::LessOrEqual,
+    AluFlagRef::LessOrEqualSigned,
+    AluFlagRef::False,
+    AluFlagRef::True,
+];
+
+pub struct SymbolicExecutor {
+    pub program: Vec<Word>,
+    pub solver: Solver,
+    pub target: Target,
+    pub max_steps: usize,
+    pub max_paths: usize,
+    /// Stop after this many solutions
+    pub max_solutions: usize,
+    initial: SymState,
+    gave_up: Cell<bool>,
+}
+
+impl SymbolicExecutor {
+    /// Set up a run of `program` from `memory`, with the bytes given by
+    /// `inputs` made symbolic. Input byte `i` is `inputs[i]`. Fails if an
+    /// input has an empty domain, as no run could take it.
+    pub fn new(
+        program: Vec<Word>,
+        memory: &[Word],
+        inputs: &[SymbolicInput],
+        target: Target,
+    ) -> Result<SymbolicExecutor, String> {
+        if let Some(i) = inputs.iter().position(|i| i.domain.is_empty()) {
+            return Err(format!(
+                "Input {} at {:03} has an empty domain",
+                i, inputs[i].addr
+            ));
+        }
+        let mut memory: Vec<Rc<Expr>> = memory.iter().map(|w| konst(*w)).collect();
+        memory.resize(256, konst(0));
+        for (i, input) in inputs.iter().enumerate() {
+            memory[input.addr as usize] = Rc::new(Expr::Input(i));
+        }
+
+        let mut registers = HashMap::new();
+        for r in &[
+            RegisterRef::A,
+            RegisterRef::B,
+            RegisterRef::C,
+            RegisterRef::D,
+            RegisterRef::ST,
+            RegisterRef::BP,
+        ] {
+            registers.insert(*r, konst(0));
+        }
+
+        let mut flags = HashMap::new();
+        for flag in &FLAGS {
+            flags.insert(*flag, Rc::new(Cond::Const(*flag == AluFlagRef::True)));
+        }
+
+        let solver = Solver::new(inputs.iter().map(|i| i.domain.clone()).collect());
+        let model = solver.domains.iter().map(|d| d[0]).collect();
+
+        Ok(SymbolicExecutor {
+            program,
+            solver,
+            target,
+            max_steps: 100_000,
+            max_paths: 100_000,
+            max_solutions: 1,
+            initial: SymState {
+                eip: 0,
+                registers,
+                memory,
+                flags,
+                constraints: Vec::new(),
+                model,
+                steps: 0,
+            },
+            gave_up: Cell::new(false),
+        })
+    }
+
+    /// Explore paths depth first until the limits are reached.
+    pub fn run(&self) -> SymbolicReport {
+        let mut report = SymbolicReport::default();
+        self.gave_up.set(false);
+        let mut worklist = vec![self.initial.clone()];
+
+        while let Some(mut state) = worklist.pop() {
+            if report.paths >= self.max_paths || report.solutions.len() >= self.max_solutions {
+                report.incomplete = true;
+                break;
+            }
+
+            loop {
+                if let Target::Reach(addr) = self.target {
+                    if state.eip == addr {
+                        report.solutions.push(Solution {
+                            input: state.model.clone(),
+                            constraints: state.constraints.clone(),
+                        });
+                        report.paths += 1;
+                        break;
+                    }
+                }
+                if state.steps >= self.max_steps {
+                    report.incomplete = true;
+                    report.paths += 1;
+                    break;
+                }
+
+                match self.step(&mut state) {
+                    Ok(Step::Continue) => {}
+                    Ok(Step::Fork(states)) => {
+                        worklist.extend(states);
+                        break;
+                    }
+                    Ok(Step::Halted) => {
+                        report.paths += 1;
+                        self.check_halted(&state, &mut report);
+                        break;
+                    }
+                    Err(e) => {
+                        report.paths += 1;
+                        report.errors.push(format!("eip {}: {}", state.eip, e));
+                        break;
+                    }
+                }
+            }
+        }
+
+        report.incomplete |= self.gave_up.get();
+        report
+    }
+
+    fn check_halted(&self, state: &SymState, report: &mut SymbolicReport) {
+        if let Target::HaltWithMemory { addr, bytes } = &self.target {
+            let mut constraints = state.constraints.clone();
+            for (i, b) in bytes.iter().enumerate() {
+                let cell = state.memory[(*addr as usize + i) % 256].clone();
+                constraints.extend(conjuncts(&cond(Cond::Eq(cell, konst(*b)))));
+            }
+            match self.solver.solve_from(&constraints, &state.model) {
+                SolveResult::Sat(input) => report.solutions.push(Solution { input, constraints }),
+                SolveResult::Unsat => {}
+                SolveResult::Unknown => report.incomplete = true,
+            }
+        }
+    }
+
+    /// Split the state on `c`, keeping the current model on the side it
+    /// satisfies and solving for a model for the other side. A side is
+    /// `None` if it is infeasible.
+    fn fork(&self, state: &SymState, c: Rc<Cond>) -> (Option<SymState>, Option<SymState>) {
+        let mut taken = state.clone();
+        let mut not_taken = state.clone();
+        taken.constraints.extend(conjuncts(&c));
+        not_taken.constraints.push(cond_not(c.clone()));
+
+        let model_takes = c.eval(&state.model);
+        let other = if model_takes {
+            &mut not_taken
+        } else {
+            &mut taken
+        };
+        let other_feasible = match self.solver.solve(&other.constraints) {
+            SolveResult::Sat(model) => {
+                other.model = model;
+                true
+            }
+            SolveResult::Unsat => false,
+            SolveResult::Unknown => {
+                self.gave_up.set(true);
+                false
+            }
+        };
+
+        if model_takes {
+            (Some(taken), Some(not_taken).filter(|_| other_feasible))
+        } else {
+            (Some(taken).filter(|_| other_feasible), Some(not_taken))
+        }
+    }
+
+    /// Get a concrete value for `e`. If it depends on the input, fork into
+    /// one state where it equals the value in the current model, and one
+    /// where it doesn't, which will try again when it is stepped.
+    fn concretize(&self, state: &SymState, e: &Rc<Expr>) -> Result<Word, Step> {
+        if let Some(w) = e.as_const() {
+            return Ok(w);
+        }
+        let value = e.eval(&state.model);
+        let (equal, different) = self.fork(state, cond(Cond::Eq(e.clone(), konst(value))));
+        Err(Step::Fork(equal.into_iter().chain(different).collect()))
+    }
+
+    fn read(&self, state: &SymState, reg: &RegisterRef) -> Rc<Expr> {
+        match reg {
+            RegisterRef::FL => {
+                let mut fl = konst(0);
+                for (i, flag) in FLAGS[0..8].iter().enumerate() {
+                    let bit = ite(state.flags[flag].clone(), konst(1 << i), konst(0));
+                    fl = bin(BinOp::Or, fl, bit);
+                }
+                fl
+            }
+            RegisterRef::IP => konst(state.eip),
+            other => state.registers[other].clone(),
+        }
+    }
+
+    /// Writes to FL and IP are dropped, like in `LegComputer`.
+    fn write(state: &mut SymState, reg: RegisterRef, value: Rc<Expr>) {
+        if reg != RegisterRef::FL && reg != RegisterRef::IP {
+            state.registers.insert(reg, value);
+        }
+    }
+
+    fn push(state: &mut SymState, st: Word, value: Rc<Expr>) {
+        let new_st = st.wrapping_sub(1);
+        state.registers.insert(RegisterRef::ST, konst(new_st));
+        state.memory[new_st as usize] = value;
+    }
+
+    fn jump(&self, state: &mut SymState, flag: &AluFlagRef, target: Word) -> Result<Step, String> {
+        let c = state.flags[flag].clone();
+        match c.as_const() {
+            Some(true) => state.eip = target,
+            Some(false) => state.eip = state.eip.wrapping_add(2),
+            None => {
+                let (taken, not_taken) = self.fork(state, c);
+                let mut forks = Vec::new();
+                if let Some(mut s) = not_taken {
+                    s.eip = s.eip.wrapping_add(2);
+                    s.steps += 1;
+                    forks.push(s);
+                }
+                if let Some(mut s) = taken {
+                    s.eip = target;
+                    s.steps += 1;
+                    forks.push(s);
+                }
+                return Ok(Step::Fork(forks));
+            }
+        }
+        Ok(Step::Continue)
+    }
+
+    fn call(state: &mut SymState, st: Word, addr: Word) {
+        let bp = state.registers[&RegisterRef::BP].clone();
+        Self::push(state, st, konst(state.eip));
+        Self::push(state, st.wrapping_sub(1), bp);
+        state
+            .registers
+            .insert(RegisterRef::BP, konst(st.wrapping_sub(2)));
+        state.eip = addr;
+    }
+
+    fn alu(
+        state: &mut SymState,
+        op: AluOpcode,
+        a: Rc<Expr>,
+        b: Rc<Expr>,
+        out: RegisterRef,
+    ) -> Rc<Expr> {
+        let mut carry = |x: Rc<Expr>, y: Rc<Expr>, c: bool| {
+            state.flags.insert(
+                AluFlagRef::OverflowUnsigned,
+                cond(Cond::Carry(x.clone(), y.clone(), c)),
+            );
+            state.flags.insert(
+                AluFlagRef::OverflowSigned,
+                cond(Cond::SignedOverflow(x, y, c)),
+            );
+        };
+
+        let result = match op {
+            AluOpcode::Add => {
+                carry(a.clone(), b.clone(), false);
+                bin(BinOp::Add, a.clone(), b.clone())
+            }
+            AluOpcode::AddCarry => {
+                carry(a.clone(), b.clone(), true);
+                bin(BinOp::Add, bin(BinOp::Add, a.clone(), b.clone()), konst(1))
+            }
+            AluOpcode::Incr => {
+                carry(a.clone(), konst(1), false);
+                bin(BinOp::Add, a.clone(), konst(1))
+            }
+            AluOpcode::Decr => {
+                carry(a.clone(), konst(0xff), false);
+                bin(BinOp::Add, a.clone(), konst(0xff))
+            }
+            AluOpcode::Sub => {
+                carry(a.clone(), not(b.clone()), true);
+                bin(
+                    BinOp::Add,
+                    bin(BinOp::Add, a.clone(), not(b.clone())),
+                    konst(1),
+                )
+            }
+            AluOpcode::Xor => bin(BinOp::Xor, a.clone(), b.clone()),
+            AluOpcode::Neg => not(b.clone()),
+            AluOpcode::Or => bin(BinOp::Or, a.clone(), b.clone()),
+            AluOpcode::And => bin(BinOp::And, a.clone(), b.clone()),
+            AluOpcode::Nand => bin(BinOp::Nand, a.clone(), b.clone()),
+            AluOpcode::Nor => bin(BinOp::Nor, a.clone(), b.clone()),
+            AluOpcode::ShiftL => bin(BinOp::ShiftL, a.clone(), b.clone()),
+            AluOpcode::ShiftR => bin(BinOp::ShiftR, a.clone(), b.clone()),
+            AluOpcode::Echo => a.clone(),
+        };
+        Self::write(state, out, result.clone());
+
+        let gt = cond(Cond::Ult(b.clone(), a.clone()));
+        let gts = cond(Cond::Slt(b.clone(), a.clone()));
+        let lt = cond(Cond::Ult(a.clone(), b.clone()));
+        let lts = cond(Cond::Slt(a.clone(), b.clone()));
+        let eq = cond(Cond::Eq(a, b));
+        let flags = &mut state.flags;
+        flags.insert(AluFlagRef::EqZero, cond(Cond::Eq(result.clone(), konst(0))));
+        flags.insert(AluFlagRef::Equal, eq.clone());
+        flags.insert(AluFlagRef::NotEqual, cond_not(eq));
+        flags.insert(AluFlagRef::GreaterThan, gt.clone());
+        flags.insert(AluFlagRef::GreaterThanSigned, gts.clone());
+        flags.insert(AluFlagRef::GreaterOrEqual, cond_not(lt.clone()));
+        flags.insert(AluFlagRef::GreaterOrEqualSigned, cond_not(lts.clone()));
+        flags.insert(AluFlagRef::LessThan, lt);
+        flags.insert(AluFlagRef::LessThanSigned, lts);
+        flags.insert(AluFlagRef::LessOrEqual, cond_not(gt));
+        flags.insert(AluFlagRef::LessOrEqualSigned, cond_not(gts));
+        result
+    }
+
+    fn step(&self, state: &mut SymState) -> Result<Step, String> {
+        let eip = state.eip as usize;
+        let instruction = match (self.program.get(eip), self.program.get(eip + 1)) {
+            (Some(w1), Some(w2)) => Instruction::try_from((*w1, *w2))?,
+            _ => return Err(format!("Instruction pointer out of program: {}", eip)),
+        };
+
+        macro_rules! concrete {
+            ($e:expr) => {
+                match self.concretize(state, &$e) {
+                    Ok(w) => w,
+                    Err(step) => return Ok(step),
+                }
+            };
+        }
+
+        let st = concrete!(state.registers[&RegisterRef::ST].clone());
+        let next = state.eip.wrapping_add(2);
+
+        match instruction {
+            Instruction::Load { dest, addr } => {
+                let value = state.memory[addr as usize].clone();
+                Self::write(state, dest, value);
+            }
+            Instruction::LoadP { dest, addr_src } => {
+                let addr = concrete!(self.read(state, &addr_src));
+                let value = state.memory[addr as usize].clone();
+                Self::write(state, dest, value);
+            }
+            Instruction::Store { src, addr } => {
+                state.memory[addr as usize] = self.read(state, &src);
+            }
+            Instruction::StoreP { src, addr_src } => {
+                let addr = concrete!(self.read(state, &addr_src));
+                state.memory[addr as usize] = self.read(state, &src);
+            }
+            Instruction::Mov { dest, src } => {
+                let value = self.read(state, &src);
+                Self::write(state, dest, value);
+            }
+            Instruction::MovC { dest, val } => Self::write(state, dest, konst(val)),
+
+            Instruction::Jmp { flag, addr } => {
+                state.steps += 1;
+                return self.jump(state, &flag, addr);
+            }
+            Instruction::JmpP { flag, addr_src } => {
+                let target = state.memory[concrete!(self.read(state, &addr_src)) as usize].clone();
+                let target = concrete!(target);
+                state.steps += 1;
+                return self.jump(state, &flag, target);
+            }
+            Instruction::JmpR { flag, diff } => {
+                state.steps += 1;
+                let target = state.eip.wrapping_add(diff);
+                return self.jump(state, &flag, target);
+            }
+            Instruction::JmpRP { flag, diff_src } => {
+                let diff = state.memory[concrete!(self.read(state, &diff_src)) as usize].clone();
+                let target = state.eip.wrapping_add(concrete!(diff));
+                state.steps += 1;
+                return self.jump(state, &flag, target);
+            }
+
+            Instruction::Stack(StackInstruction::Push { src }) => {
+                let value = self.read(state, &src);
+                Self::push(state, st, value);
+            }
+            Instruction::Stack(StackInstruction::Pop { dest }) => {
+                let value = state.memory[st as usize].clone();
+                state
+                    .registers
+                    .insert(RegisterRef::ST, konst(st.wrapping_add(1)));
+                Self::write(state, dest, value);
+            }
+            Instruction::Stack(StackInstruction::Call { addr_reg }) => {
+                let addr = concrete!(self.read(state, &addr_reg));
+                Self::call(state, st, addr);
+                state.steps += 1;
+                return Ok(Step::Continue);
+            }
+            Instruction::Stack(StackInstruction::CallC { addr }) => {
+                Self::call(state, st, addr);
+                state.steps += 1;
+                return Ok(Step::Continue);
+            }
+            Instruction::Stack(StackInstruction::CallR { diff }) => {
+                Self::call(state, st, state.eip.wrapping_add(diff));
+                state.steps += 1;
+                return Ok(Step::Continue);
+            }
+            Instruction::Stack(StackInstruction::Ret { src }) => {
+                let bp = concrete!(state.registers[&RegisterRef::BP].clone());
+                let stored_bp = state.memory[bp as usize].clone();
+                let stored_ip = concrete!(state.memory[bp.wrapping_add(1) as usize].clone());
+                state.registers.insert(RegisterRef::BP, stored_bp);
+                let value = self.read(state, &src);
+                Self::push(state, bp.wrapping_add(2), value);
+                state.eip = stored_ip.wrapping_add(2);
+                state.steps += 1;
+                return Ok(Step::Continue);
+            }
+            Instruction::Stack(StackInstruction::Load { dest, bp_diff }) => {
+                let bp = concrete!(state.registers[&RegisterRef::BP].clone());
+                let value = state.memory[bp.wrapping_add(bp_diff) as usize].clone();
+                Self::write(state, dest, value);
+            }
+
+            Instruction::Gpi { dest } => Self::write(state, dest, konst(0)),
+            Instruction::Gpo { .. } => {}
+
+            Instruction::Alu {
+                op,
+                arg1,
+                arg2,
+                out,
+            } => {
+                let a = state.registers[&arg1].clone();
+                let b = state.registers[&arg2].clone();
+                Self::alu(state, op, a, b, out);
+            }
+
+            Instruction::Nop(NopOpcode::Nop) => {}
+            Instruction::Nop(NopOpcode::Halt) => return Ok(Step::Halted),
+        }
+
+        state.eip = next;
+        state.steps += 1;
+        Ok(Step::Continue)
+    }
+}
@@ -0,0 +1,332 @@
+use super::leg_computer::Address;
+use super::leg_computer::AluFlagRef;
+use super::leg_computer::AluOpcode;
+use super::leg_computer::Instruction;
+use super::leg_computer::LegComputer;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fmt::Error;
+use std::fmt::Formatter;
+
+/// Indices of the input bytes that a value depends on.
+pub type Taint = BTreeSet<usize>;
+
+/// What the input bytes did to one control flow decision, over all the times
+/// it was executed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BranchTaint {
+    pub executions: usize,
+    /// Number of executions where the jump was taken
+    pub taken: usize,
+    /// Input bytes that the flag or computed target depended on in any
+    /// execution
+    pub taint: Taint,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TaintReport {
+    /// Number of input bytes, numbered in the order of the source ranges
+    pub inputs: usize,
+    /// Conditional jumps and jumps or calls to computed targets, by address
+    pub branches: BTreeMap<Address, BranchTaint>,
+    /// Input bytes that reached GPO
+    pub outputs: Taint,
+}
+
+impl TaintReport {
+    /// The input bytes that influenced the branch at `eip`, empty if it was
+    /// never executed.
+    pub fn influencing(&self, eip: Address) -> Taint {
+        self.branches
+            .get(&eip)
+            .map(|b| b.taint.clone())
+            .unwrap_or_default()
+    }
+
+    /// The input bytes that affected neither a branch nor an output.
+    pub fn unused(&self) -> Vec<usize> {
+        let used: Taint = self
+            .branches
+            .values()
+            .flat_map(|b| b.taint.iter().cloned())
+            .chain(self.outputs.iter().cloned())
+            .collect();
+        (0..self.inputs).filter(|i| !used.contains(i)).collect()
+    }
+}
+
+fn format_taint(taint: &Taint) -> String {
+    if taint.is_empty() {
+        return "-".to_string();
+    }
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for i in taint {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *i => *end = *i,
+            _ => ranges.push((*i, *i)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+impl Display for TaintReport {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        for (eip, branch) in &self.branches {
+            writeln!(
+                f,
+                "{:03}: taken {}/{}, input {}",
+                eip,
+                branch.taken,
+                branch.executions,
+                format_taint(&branch.taint)
+            )?;
+        }
+        if !self.outputs.is_empty() {
+            writeln!(f, "output: input {}", format_taint(&self.outputs))?;
+        }
+        let unused: Taint = self.unused().into_iter().collect();
+        writeln!(f, "unused: input {}", format_taint(&unused))
+    }
+}
+
+/// Runs a machine alongside shadow registers, memory and flags that record
+/// which input bytes each value depends on.
+///
+/// Taint follows data only: a value loaded through a tainted pointer, or
+/// written under a tainted branch, is not itself tainted.
+pub struct TaintTracker {
+    pub computer: LegComputer,
+    pub report: TaintReport,
+    registers: HashMap<RegisterRef, Taint>,
+    memory: Vec<Taint>,
+    /// Taint of the flags comparing the ALU arguments, and of Z
+    compare_flags: Taint,
+    /// Taint of Ou and Os, which only the arithmetic ops update
+    overflow_flags: Taint,
+}
+
+impl TaintTracker {
+    /// Track `computer` with the memory ranges `(start, length)` in `sources`
+    /// as inputs. Input byte numbers count through the ranges in order.
+    pub fn new(computer: LegComputer, sources: &[(Address, usize)]) -> TaintTracker {
+        let mut memory = vec![Taint::new(); computer.memory.len()];
+        let mut inputs = 0;
+        for (start, len) in sources {
+            for addr in *start as usize..*start as usize + len {
+                if let Some(taint) = memory.get_mut(addr) {
+                    taint.insert(inputs);
+                }
+                inputs += 1;
+            }
+        }
+        TaintTracker {
+            computer,
+            report: TaintReport {
+                inputs,
+                ..TaintReport::default()
+            },
+            registers: HashMap::new(),
+            memory,
+            compare_flags: Taint::new(),
+            overflow_flags: Taint::new(),
+        }
+    }
+
+    /// The input bytes that `register` currently depends on.
+    pub fn register_taint(&self, register: &RegisterRef) -> Taint {
+        match register {
+            RegisterRef::FL => self
+                .compare_flags
+                .union(&self.overflow_flags)
+                .cloned()
+                .collect(),
+            RegisterRef::IP => Taint::new(),
+            _ => self.registers.get(register).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// The input bytes that the value at `addr` currently depends on.
+    pub fn memory_taint(&self, addr: Address) -> Taint {
+        self.memory.get(addr as usize).cloned().unwrap_or_default()
+    }
+
+    fn flag_taint(&self, flag: &AluFlagRef) -> Taint {
+        match flag {
+            AluFlagRef::True | AluFlagRef::False => Taint::new(),
+            AluFlagRef::OverflowUnsigned | AluFlagRef::OverflowSigned => {
+                self.overflow_flags.clone()
+            }
+            _ => self.compare_flags.clone(),
+        }
+    }
+
+    fn set_register(&mut self, register: RegisterRef, taint: Taint) {
+        match register {
+            RegisterRef::FL | RegisterRef::IP => {}
+            _ => {
+                self.registers.insert(register, taint);
+            }
+        }
+    }
+
+    fn set_memory(&mut self, addr: Address, taint: Taint) {
+        if let Some(slot) = self.memory.get_mut(addr as usize) {
+            *slot = taint;
+        }
+    }
+
+    fn record_branch(&mut self, taint: Taint, taken: bool) {
+        let branch = self.report.branches.entry(self.computer.eip).or_default();
+        branch.executions += 1;
+        if taken {
+            branch.taken += 1;
+        }
+        branch.taint.extend(taint);
+    }
+
+    /// Propagate taint through the current instruction, then execute it.
+    pub fn step(&mut self) -> Result<(), String> {
+        let instruction = self.computer.current_instruction()?;
+        let accesses = self.computer.memory_accesses()?;
+        let reg = |r: &RegisterRef| self.register_taint(r);
+        let mem = |i: usize| self.memory_taint(accesses.reads[i]);
+        let union = |mut a: Taint, b: Taint| {
+            a.extend(b);
+            a
+        };
+
+        match &instruction {
+            Instruction::Load { dest, .. }
+            | Instruction::LoadP { dest, .. }
+            | Instruction::Stack(StackInstruction::Load { dest, .. }) => {
+                let taint = mem(0);
+                self.set_register(*dest, taint);
+            }
+            Instruction::Store { src, .. } | Instruction::StoreP { src, .. } => {
+                let taint = reg(src);
+                self.set_memory(accesses.writes[0], taint);
+            }
+            Instruction::Mov { dest, src } => {
+                let taint = reg(src);
+                self.set_register(*dest, taint);
+            }
+            Instruction::MovC { dest, .. } | Instruction::Gpi { dest } => {
+                self.set_register(*dest, Taint::new());
+            }
+            Instruction::Gpo { src } => {
+                let taint = reg(src);
+                self.report.outputs.extend(taint);
+            }
+
+            Instruction::Jmp { flag, .. } | Instruction::JmpR { flag, .. } => {
+                if *flag != AluFlagRef::True && *flag != AluFlagRef::False {
+                    let taint = self.flag_taint(flag);
+                    let taken = self.computer.flags.get(flag);
+                    self.record_branch(taint, taken);
+                }
+            }
+            Instruction::JmpP {
+                flag,
+                addr_src: src,
+            }
+            | Instruction::JmpRP {
+                flag,
+                diff_src: src,
+            } => {
+                if *flag != AluFlagRef::False {
+                    let taken = self.computer.flags.get(flag);
+                    let mut taint = self.flag_taint(flag);
+                    if taken {
+                        taint.extend(union(reg(src), mem(0)));
+                    }
+                    self.record_branch(taint, taken);
+                }
+            }
+
+            Instruction::Stack(StackInstruction::Push { src }) => {
+                let taint = reg(src);
+                self.set_memory(accesses.writes[0], taint);
+            }
+            Instruction::Stack(StackInstruction::Pop { dest }) => {
+                let taint = mem(0);
+                self.set_register(*dest, taint);
+            }
+            Instruction::Stack(StackInstruction::Call { .. })
+            | Instruction::Stack(StackInstruction::CallC { .. })
+            | Instruction::Stack(StackInstruction::CallR { .. }) => {
+                let bp = reg(&RegisterRef::BP);
+                let st = reg(&RegisterRef::ST);
+                if let Instruction::Stack(StackInstruction::Call { addr_reg }) = &instruction {
+                    let taint = reg(addr_reg);
+                    self.record_branch(taint, true);
+                }
+                self.set_memory(accesses.writes[0], Taint::new());
+                self.set_memory(accesses.writes[1], bp);
+                self.set_register(RegisterRef::BP, st);
+            }
+            Instruction::Stack(StackInstruction::Ret { src }) => {
+                let bp = mem(0);
+                let taint = reg(src);
+                self.set_register(RegisterRef::BP, bp);
+                self.set_memory(accesses.writes[0], taint);
+            }
+
+            Instruction::Alu {
+                op,
+                arg1,
+                arg2,
+                out,
+            } => {
+                let args = union(reg(arg1), reg(arg2));
+                let result = match op {
+                    AluOpcode::Echo | AluOpcode::Incr | AluOpcode::Decr => reg(arg1),
+                    AluOpcode::Neg => reg(arg2),
+                    _ => args.clone(),
+                };
+                match op {
+                    AluOpcode::Add
+                    | AluOpcode::AddCarry
+                    | AluOpcode::Incr
+                    | AluOpcode::Decr
+                    | AluOpcode::Sub => self.overflow_flags = result.clone(),
+                    _ => {}
+                }
+                self.compare_flags = union(args, result.clone());
+                self.set_register(*out, result);
+            }
+
+            Instruction::Nop(_) => {}
+        }
+
+        self.computer.try_step()
+    }
+
+    /// Step until the machine halts, or fail after `max_steps` steps.
+    pub fn run(&mut self, max_steps: usize) -> Result<(), String> {
+        for _ in 0..max_steps {
+            if self.computer.is_halted() {
+                return Ok(());
+            }
+            self.step()?;
+        }
+        if self.computer.is_halted() {
+            Ok(())
+        } else {
+            Err(format!("Not halted after {} steps", max_steps))
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use super::leg_computer::Address;
+use super::leg_computer::Instruction;
+use super::leg_computer::LegComputer;
+use super::leg_computer::RegisterRef;
+use super::leg_computer::StackInstruction;
+use super::leg_debugger::Debugger;
+use super::leg_debugger::StopReason;
+use std::convert::TryFrom;
+
+const CLEAR: &str = "\x1b[H\x1b[2J";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+const HOT_WRITE: &str = "\x1b[97;41m";
+const WARM_WRITE: &str = "\x1b[31m";
+const HOT_READ: &str = "\x1b[30;42m";
+const WARM_READ: &str = "\x1b[32m";
+
+/// How many steps an access stays highlighted in the memory heat map. The
+/// first quarter of that is highlighted more strongly.
+pub const DEFAULT_HEAT_STEPS: u64 = 32;
+
+pub const KEYS: &str = "s step  n next  f finish  c continue  r run  : command  q quit";
+
+/// One entry in the call stack, found by walking the saved BP chain.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+    pub bp: Address,
+    /// Address of the CALL instruction that created the frame
+    pub call_site: Address,
+    /// Entry point of the called function, if the CALL had a constant target
+    pub entry: Option<Address>,
+}
+
+/// Walk the frames from the innermost outwards, until BP is back at its
+/// initial value 0.
+pub fn call_stack(computer: &LegComputer) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut bp = computer.read_register(&RegisterRef::BP);
+    while bp != 0 && frames.len() < 32 {
+        let saved_bp = computer.memory[bp as usize];
+        let call_site = computer.memory[bp.wrapping_add(1) as usize];
+        let call = computer
+            .program
+            .get(call_site as usize..call_site as usize + 2)
+            .and_then(|words| Instruction::try_from((words[0], words[1])).ok());
+        let entry = match call {
+            Some(Instruction::Stack(StackInstruction::CallC { addr })) => Some(addr),
+            Some(Instruction::Stack(StackInstruction::CallR { diff })) => {
+                Some(call_site.wrapping_add(diff))
+            }
+            _ => None,
+        };
+        frames.push(Frame {
+            bp,
+            call_site,
+            entry,
+        });
+        if saved_bp <= bp {
+            break;
+        }
+        bp = saved_bp;
+    }
+    frames
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    Redraw,
+    /// Keep stepping and redrawing until a key is pressed or the machine
+    /// stops
+    Animate,
+    /// Read a debugger command line and pass it to `Tui::command`
+    Prompt,
+    Quit,
+}
+
+pub struct Tui {
+    pub debugger: Debugger,
+    pub heat_steps: u64,
+    pub status: String,
+}
+
+impl Tui {
+    pub fn new(computer: LegComputer) -> Tui {
+        Tui {
+            debugger: Debugger::new(computer),
+            heat_steps: DEFAULT_HEAT_STEPS,
+            status: String::new(),
+        }
+    }
+
+    fn report(&mut self, reason: StopReason) {
+        self.status = reason.to_string();
+    }
+
+    pub fn handle_key(&mut self, key: char) -> Action {
+        match key {
+            's' => {
+                let reason = self.debugger.step(1);
+                self.report(reason);
+            }
+            'n' => {
+                let reason = self.debugger.step_over();
+                self.report(reason);
+            }
+            'f' => {
+                let reason = self.debugger.finish();
+                self.report(reason);
+            }
+            'c' => {
+                let reason = self.debugger.cont();
+                self.report(reason);
+            }
+            'r' => {
+                self.status = "Running".to_string();
+                return Action::Animate;
+            }
+            ':' => return Action::Prompt,
+            'q' => return Action::Quit,
+            _ => self.status = KEYS.to_string(),
+        }
+        Action::Redraw
+    }
+
+    /// Take one animation step. Returns false when the animation should stop.
+    pub fn animate_step(&mut self) -> bool {
+        let reason = self.debugger.step(1);
+        if reason != StopReason::Stepped {
+            self.report(reason);
+            false
+        } else if self
+            .debugger
+            .breakpoints
+            .contains(&self.debugger.computer.eip)
+        {
+            self.report(StopReason::Breakpoint(self.debugger.computer.eip));
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn command(&mut self, line: &str) {
+        self.status = match line.parse() {
+            Ok(command) => self.debugger.execute(&command).replace('\n', "  "),
+            Err(e) => e,
+        };
+    }
+
+    fn heat_color(&self, addr: usize) -> Option<&'static str> {
+        let log = &self.debugger.access_log;
+        let now = self.debugger.steps;
+        let age = |step: Option<u64>| step.map(|s| now - s).filter(|a| *a < self.heat_steps);
+        let hot = self.heat_steps / 4;
+        match (age(log.last_write[addr]), age(log.last_read[addr])) {
+            (Some(w), Some(r)) if r < w => Some(if r < hot { HOT_READ } else { WARM_READ }),
+            (Some(w), _) => Some(if w < hot { HOT_WRITE } else { WARM_WRITE }),
+            (None, Some(r)) => Some(if r < hot { HOT_READ } else { WARM_READ }),
+            (None, None) => None,
+        }
+    }
+
+    fn render_memory(&self, out: &mut Vec<String>) {
+        let computer = &self.debugger.computer;
+        let st = computer.read_register(&RegisterRef::ST) as usize;
+        let bp = computer.read_register(&RegisterRef::BP) as usize;
+
+        out.push(format!(
+            "{}Memory{}  {}write{} {}read{}  [ = ST  ] = BP",
+            BOLD, RESET, HOT_WRITE, RESET, HOT_READ, RESET
+        ));
+        for row in 0..computer.memory.len().div_ceil(16) {
+            let mut line = format!("{:>3}:", row * 16);
+            for addr in (row * 16)..usize::min(row * 16 + 16, computer.memory.len()) {
+                let cell = format!(
+                    "{}{:>3}{}",
+                    if addr == st { "[" } else { " " },
+                    computer.memory[addr],
+                    if addr == bp { "]" } else { " " },
+                );
+                match self.heat_color(addr) {
+                    Some(color) => line.push_str(&format!("{}{}{}", color, cell, RESET)),
+                    None => line.push_str(&cell),
+                }
+            }
+            out.push(line);
+        }
+    }
+
+    /// Render a full screen, starting with the escape codes to clear it.
+    pub fn render(&self) -> String {
+        let computer = &self.debugger.computer;
+        let mut out = Vec::new();
+
+        out.push(format!(
+            "{}LEG{}  eip {:03}  steps {}  {}",
+            BOLD, RESET, computer.eip, self.debugger.steps, self.status
+        ));
+        out.push(format!(
+            "{} [FL: {}]",
+            computer.registers,
+            computer.read_register(&RegisterRef::FL)
+        ));
+        out.push(format!("Flags {}", computer.flags));
+        out.push(String::new());
+
+        let disassembly: Vec<String> = self
+            .debugger
+            .disassemble(computer.eip.saturating_sub(10) & !1, 11)
+            .lines()
+            .map(|s| s.to_string())
+            .collect();
+        let mut stack = vec![format!("{}Call stack{}", BOLD, RESET)];
+        stack.push(format!("#0 eip {:03}", computer.eip));
+        for (i, frame) in call_stack(computer).iter().enumerate() {
+            stack.push(format!(
+                "#{} {} called from {:03}, bp {}",
+                i + 1,
+                frame
+                    .entry
+                    .map(|e| format!("fn {:03}", e))
+                    .unwrap_or_else(|| "fn ???".to_string()),
+                frame.call_site,
+                frame.bp,
+            ));
+        }
+
+        out.push(format!(
+            "{}{:<34}{}{}",
+            BOLD, "Disassembly", RESET, stack[0]
+        ));
+        for i in 0..usize::max(disassembly.len(), stack.len() - 1) {
+            out.push(format!(
+                "{:<34}{}",
+                disassembly.get(i).map(|s| s.as_str()).unwrap_or(""),
+                stack.get(i + 1).map(|s| s.as_str()).unwrap_or(""),
+            ));
+        }
+        out.push(String::new());
+
+        self.render_memory(&mut out);
+        out.push(String::new());
+        out.push(KEYS.to_string());
+
+        format!("{}{}", CLEAR, out.join("\r\n"))
+    }
+}
@@ -0,0 +1,148 @@
+use super::leg_computer::Address;
+use super::leg_computer::Instruction;
+use super::leg_computer::LegComputer;
+use super::leg_computer::Word;
+use super::leg_computer_parse::generate_code;
+use super::leg_flag::Checker;
+use super::leg_flag::FlagLayout;
+use super::leg_flag::FlagMemory;
+use super::leg_flag::POINTERS;
+use super::leg_obfuscate::ObfuscateReport;
+use super::leg_obfuscate::Obfuscator;
+use super::leg_obfuscate::Technique;
+use super::leg_symbolic::SymbolicInput;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand::SeedableRng;
+
+/// One build of the challenge, distinct from others but checking the same
+/// flag.
+#[derive(Clone, Debug)]
+pub struct Variant {
+    pub seed: u64,
+    pub flag: Vec<Word>,
+    pub program: Vec<Word>,
+    /// Initial memory, with an empty input
+    pub memory: Vec<Word>,
+    pub layout: FlagLayout,
+    pub report: ObfuscateReport,
+}
+
+impl Variant {
+    /// What to keep on record about the variant, in the same format as
+    /// `FlagLayout`'s `Display`.
+    pub fn manifest(&self) -> String {
+        let flag: String = self
+            .flag
+            .iter()
+            .flat_map(|b| std::ascii::escape_default(*b))
+            .map(char::from)
+            .collect();
+        format!(
+            "seed:     {}\nflag:     \"{}\"\n{}\nprogram:  {}\n",
+            self.seed, flag, self.layout, self.report
+        )
+    }
+}
+
+/// Generates variants of a challenge program that differ in memory layout,
+/// constants, instruction selection and register allocation.
+pub struct VariantGenerator {
+    /// A program that finds its input, solution and output strings through
+    /// the pointers at addresses 0 to 7, like the challenge
+    pub program: Vec<Instruction>,
+    pub flag: Vec<Word>,
+    pub checker: Checker,
+    /// Chance of replacing each instruction that can be replaced
+    pub rate: f64,
+    /// Random inputs to check each variant against the program on
+    pub samples: usize,
+}
+
+impl VariantGenerator {
+    pub fn new(program: Vec<Instruction>, flag: &[Word]) -> VariantGenerator {
+        VariantGenerator {
+            program,
+            flag: flag.to_vec(),
+            checker: Checker::SortXor,
+            rate: 0.5,
+            samples: 200,
+        }
+    }
+
+    /// The layout of the challenge, with the output strings and the solution
+    /// in random order at random offsets.
+    fn shuffle_layout(&self, rng: &mut StdRng) -> FlagMemory {
+        let mut memory = FlagMemory::new(self.checker);
+        let len = self.flag.len();
+        // The output strings are copied up to their end inclusive
+        let sizes = [memory.ok.len() + 1, memory.err.len() + 1, len];
+
+        // The input and what the checker needs after it go after the regions,
+        // and must end before address 255 like in `FlagMemory::build`
+        let end = 256usize
+            .saturating_sub(self.checker.stack(len))
+            .min(255)
+            .saturating_sub(len + self.checker.scratch(len));
+        let slack = end.saturating_sub(POINTERS + sizes.iter().sum::<usize>());
+        let mut cuts: Vec<usize> = (0..sizes.len())
+            .map(|_| rng.gen_range(0, slack + 1))
+            .collect();
+        cuts.sort_unstable();
+
+        let mut order = [0, 1, 2];
+        order.shuffle(rng);
+        let mut addr = POINTERS;
+        let mut previous_cut = 0;
+        for (region, cut) in order.iter().zip(cuts) {
+            addr += cut - previous_cut;
+            previous_cut = cut;
+            match region {
+                0 => memory.ok_addr = addr as Address,
+                1 => memory.err_addr = addr as Address,
+                _ => memory.solution_addr = addr as Address,
+            }
+            addr += sizes[*region];
+        }
+        memory
+    }
+
+    /// The variant for `seed`. Fails if the flag doesn't fit in memory, or if
+    /// the variant doesn't accept the flag or differs from the program.
+    pub fn generate(&self, seed: u64) -> Result<Variant, String> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let image = self.shuffle_layout(&mut rng).build(&self.flag)?;
+        let with_flag = image.with_input(&self.flag)?;
+
+        let mut obfuscator = Obfuscator::new(rng.gen());
+        obfuscator.techniques = vec![Technique::Substitute, Technique::RenameRegisters];
+        obfuscator.rate = self.rate;
+        obfuscator.memory = with_flag.clone();
+        obfuscator.inputs = (0..self.flag.len())
+            .map(|i| SymbolicInput {
+                addr: image.layout.input.wrapping_add(i as Word),
+                domain: (0x20..0x7f).collect(),
+            })
+            .collect();
+        obfuscator.output = (0, 3);
+        obfuscator.samples = self.samples;
+        let (program, report) = obfuscator.obfuscate(&self.program)?;
+        let program = generate_code(&program);
+
+        let computer = LegComputer::new(program.clone(), with_flag).run();
+        let ok = &image.memory[image.layout.ok.0 as usize..][..image.layout.ok.1];
+        if computer.memory[0..ok.len()] != *ok {
+            return Err(format!("Variant {} does not accept the flag", seed));
+        }
+
+        Ok(Variant {
+            seed,
+            flag: self.flag.clone(),
+            program,
+            memory: image.memory,
+            layout: image.layout,
+            report,
+        })
+    }
+}
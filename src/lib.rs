@@ -1,8 +1,156 @@
+mod leg_cfg;
+mod leg_challenge;
+mod leg_compile;
 mod leg_computer;
 mod leg_computer_parse;
+mod leg_container;
+mod leg_debugger;
+mod leg_decompile;
+#[cfg(feature = "rand")]
+mod leg_equiv;
+mod leg_flag;
+#[cfg(feature = "rand")]
+mod leg_fuzz;
+mod leg_gdb;
+mod leg_image;
+mod leg_json;
+mod leg_lint;
+mod leg_load;
+#[cfg(feature = "rand")]
+mod leg_obfuscate;
+mod leg_optimize;
+mod leg_prove;
+mod leg_stack_check;
+mod leg_stdlib;
+mod leg_symbolic;
+mod leg_taint;
+mod leg_tui;
+#[cfg(feature = "rand")]
+mod leg_variant;
 
+pub use leg_cfg::BasicBlock;
+pub use leg_cfg::Cfg;
+pub use leg_cfg::Edge;
+pub use leg_cfg::EdgeKind;
+pub use leg_cfg::Function;
+pub use leg_challenge::CHALLENGE_MEMORY;
+pub use leg_challenge::CHALLENGE_PROGRAM;
+pub use leg_challenge::PLAIN_PROGRAM;
+pub use leg_compile::compile;
+pub use leg_computer::AluFlagRef;
+pub use leg_computer::AluFlags;
+pub use leg_computer::AluOpcode;
+pub use leg_computer::Instruction;
 pub use leg_computer::LegComputer;
+pub use leg_computer::MemoryAccesses;
+pub use leg_computer::NopOpcode;
 pub use leg_computer::RegisterRef;
+pub use leg_computer::Registers;
+pub use leg_computer::StackInstruction;
 pub use leg_computer::Word;
+pub use leg_computer_parse::assemble_lines;
 pub use leg_computer_parse::assemble_program;
+pub use leg_computer_parse::assemble_with_symbols;
+pub use leg_computer_parse::disassemble;
 pub use leg_computer_parse::generate_code;
+pub use leg_computer_parse::Assembly;
+pub use leg_container::read_container;
+pub use leg_container::write_container;
+pub use leg_container::Dialect;
+pub use leg_container::LegContainer;
+pub use leg_container::Section;
+pub use leg_container::Symbol;
+pub use leg_container::CONTAINER_MAGIC;
+pub use leg_container::CONTAINER_VERSION;
+pub use leg_debugger::Command;
+pub use leg_debugger::Debugger;
+pub use leg_debugger::StopReason;
+pub use leg_decompile::decompile;
+#[cfg(feature = "rand")]
+pub use leg_equiv::Counterexample;
+#[cfg(feature = "rand")]
+pub use leg_equiv::Divergence;
+#[cfg(feature = "rand")]
+pub use leg_equiv::Equivalence;
+#[cfg(feature = "rand")]
+pub use leg_equiv::EquivalenceChecker;
+pub use leg_flag::flag_image;
+pub use leg_flag::Checker;
+pub use leg_flag::FlagImage;
+pub use leg_flag::FlagLayout;
+pub use leg_flag::FlagMemory;
+#[cfg(feature = "rand")]
+pub use leg_fuzz::CoverageEdge;
+#[cfg(feature = "rand")]
+pub use leg_fuzz::Execution;
+#[cfg(feature = "rand")]
+pub use leg_fuzz::Finding;
+#[cfg(feature = "rand")]
+pub use leg_fuzz::FuzzStats;
+#[cfg(feature = "rand")]
+pub use leg_fuzz::Fuzzer;
+#[cfg(feature = "rand")]
+pub use leg_fuzz::Outcome;
+pub use leg_gdb::GdbStub;
+pub use leg_gdb::Reply;
+pub use leg_image::read_image;
+pub use leg_image::write_image;
+pub use leg_image::ImageFormat;
+pub use leg_image::IMAGE_FORMATS;
+pub use leg_lint::lint;
+pub use leg_lint::Lint;
+pub use leg_lint::LintKind;
+pub use leg_lint::LINT_KINDS;
+pub use leg_load::parse_range;
+pub use leg_load::ImageLoader;
+pub use leg_load::InputStart;
+#[cfg(feature = "rand")]
+pub use leg_obfuscate::ObfuscateReport;
+#[cfg(feature = "rand")]
+pub use leg_obfuscate::Obfuscator;
+#[cfg(feature = "rand")]
+pub use leg_obfuscate::Technique;
+#[cfg(feature = "rand")]
+pub use leg_obfuscate::TECHNIQUES;
+pub use leg_optimize::optimize;
+pub use leg_optimize::OptimizeReport;
+pub use leg_prove::FlagProver;
+pub use leg_prove::LengthCount;
+pub use leg_prove::UniquenessReport;
+pub use leg_stack_check::check_stack;
+pub use leg_stack_check::StackIssue;
+pub use leg_stack_check::StackReport;
+pub use leg_stdlib::CHECKSUM;
+pub use leg_stdlib::DIV;
+pub use leg_stdlib::HEX_OUT;
+pub use leg_stdlib::MEMCMP;
+pub use leg_stdlib::MEMCPY;
+pub use leg_stdlib::MEMSET;
+pub use leg_stdlib::MOD;
+pub use leg_stdlib::MUL;
+pub use leg_stdlib::SORT;
+pub use leg_stdlib::STDLIB;
+pub use leg_stdlib::XOR_FOLD;
+pub use leg_symbolic::BinOp;
+pub use leg_symbolic::Cond;
+pub use leg_symbolic::Expr;
+pub use leg_symbolic::Solution;
+pub use leg_symbolic::SolveResult;
+pub use leg_symbolic::Solver;
+pub use leg_symbolic::SymState;
+pub use leg_symbolic::SymbolicExecutor;
+pub use leg_symbolic::SymbolicInput;
+pub use leg_symbolic::SymbolicReport;
+pub use leg_symbolic::Target;
+pub use leg_taint::BranchTaint;
+pub use leg_taint::Taint;
+pub use leg_taint::TaintReport;
+pub use leg_taint::TaintTracker;
+pub use leg_tui::call_stack;
+pub use leg_tui::Action;
+pub use leg_tui::Frame;
+pub use leg_tui::Tui;
+#[cfg(feature = "rand")]
+pub use leg_variant::Variant;
+#[cfg(feature = "rand")]
+pub use leg_variant::VariantGenerator;
@@ -1,49 +1,89 @@
-mod leg_computer;
-
-use leg_computer::LegComputer;
+use evil_electronic_enigma::parse_range;
+use evil_electronic_enigma::read_image;
+use evil_electronic_enigma::ImageLoader;
+use evil_electronic_enigma::CHALLENGE_MEMORY;
+use evil_electronic_enigma::CHALLENGE_PROGRAM;
 use std::io::Read;
 
-const PROGRAM: &[u8] = &[
-    18, 0, 19, 1, 177, 2, 177, 3, 177, 3, 182, 72, 178, 2, 178, 3, 178, 3, 178, 3, 214, 179, 208,
-    179, 211, 243, 177, 2, 177, 3, 182, 128, 178, 0, 178, 3, 210, 243, 177, 3, 17, 0, 177, 1, 17,
-    2, 177, 1, 182, 58, 178, 0, 223, 0, 144, 8, 18, 6, 19, 7, 159, 8, 18, 4, 19, 5, 0, 255, 97, 0,
-    32, 2, 64, 1, 210, 81, 210, 178, 153, 248, 0, 0, 184, 4, 185, 3, 186, 2, 223, 16, 153, 6, 186,
-    2, 176, 2, 35, 0, 67, 2, 210, 0, 210, 162, 159, 240, 186, 5, 185, 3, 187, 2, 177, 3, 99, 0,
-    177, 3, 187, 4, 223, 178, 153, 6, 178, 0, 176, 0, 32, 1, 35, 2, 212, 48, 187, 255, 35, 3, 212,
-    48, 178, 3, 216, 48, 210, 81, 210, 162, 178, 3, 210, 243, 177, 3, 177, 0, 159, 218, 186, 3,
-    187, 2, 223, 178, 153, 4, 176, 2, 83, 2, 184, 2, 223, 195, 152, 38, 177, 2, 184, 3, 177, 0,
-    211, 160, 177, 0, 182, 228, 178, 0, 178, 1, 178, 1, 210, 0, 177, 0, 184, 2, 177, 0, 182, 212,
-    178, 0, 178, 0, 178, 0, 176, 2, 210, 243, 32, 2, 33, 3, 223, 16, 155, 12, 65, 2, 210, 162, 33,
-    2, 65, 3, 64, 2, 159, 194,
-];
-const MEMORY: [u8; 256] = [
-    45, 74, 16, 0, 8, 11, 12, 15, 79, 75, 33, 0, 69, 82, 82, 0, 68, 70, 85, 93, 93, 82, 95, 48, 54,
-    57, 110, 27, 75, 8, 85, 9, 55, 30, 70, 54, 33, 89, 49, 12, 29, 64, 78, 3, 3, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-];
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut input: Vec<u8> = Vec::new();
-    let input_len = std::io::stdin()
-        .read_to_end(&mut input)
-        .expect("Failed to read input");
-    let mut memory = MEMORY.to_vec();
+const USAGE: &str = "\
+Usage: evil-electronic-enigma [--program IMAGE] [--memory IMAGE]
+                              [--input-at ADDR|*ADDR] [--end-pointer ADDR|none]
+                              [--output START-END] [--max-steps N]
+
+Run a LEG program with standard input in memory, and print its output. The
+program and memory default to the challenge's. The input goes at ADDR, or
+where the address stored at ADDR points (default *0). The address after it is
+written to the end pointer (default 1). The output is memory START to END
+(default 0-3). The program fails if it runs for more than N steps (default
+1000000).";
 
-    let input_start_index = memory[0] as usize;
-    memory[1] = (input_start_index + input_len) as u8;
-    for i in 0..input_len {
-        memory[input_start_index + i] = input[i];
+const MAX_STEPS: usize = 1_000_000;
+
+fn run() -> Result<(), String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut program = CHALLENGE_PROGRAM.to_vec();
+    let mut memory = CHALLENGE_MEMORY.to_vec();
+    let mut input_start = None;
+    let mut end_pointer = None;
+    let mut output = None;
+    let mut max_steps = MAX_STEPS;
+    while !args.is_empty() {
+        let flag = args.remove(0);
+        if args.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        let value = args.remove(0);
+        match flag.as_str() {
+            "--program" => program = read_image(&value)?,
+            "--memory" => memory = read_image(&value)?,
+            "--input-at" => input_start = Some(value.parse()?),
+            "--end-pointer" if value == "none" => end_pointer = Some(None),
+            "--end-pointer" => {
+                let addr = value
+                    .parse()
+                    .map_err(|_| format!("Invalid end pointer: {}", value))?;
+                end_pointer = Some(Some(addr));
+            }
+            "--output" => output = Some(parse_range(&value)?),
+            "--max-steps" => {
+                max_steps = value
+                    .parse()
+                    .map_err(|_| format!("Invalid step count: {}", value))?
+            }
+            _ => return Err(USAGE.to_string()),
+        }
     }
 
-    let computer = LegComputer::new(PROGRAM.to_vec(), memory);
-    let computer = computer.run();
-    println!("{}", String::from_utf8(computer.memory[0..3].to_vec())?);
+    let mut loader = ImageLoader::new(program, memory)?;
+    loader.input_start = input_start.unwrap_or(loader.input_start);
+    loader.end_pointer = end_pointer.unwrap_or(loader.end_pointer);
+    loader.output = output.unwrap_or(loader.output);
+
+    let mut input: Vec<u8> = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut input)
+        .map_err(|e| format!("Failed to read input: {}", e))?;
+    let mut computer = loader.load(&input)?;
+    for _ in 0..max_steps {
+        if computer.is_halted() {
+            break;
+        }
+        let eip = computer.eip;
+        computer
+            .try_step()
+            .map_err(|e| format!("Fault at {:03}: {}", eip, e))?;
+    }
+    if !computer.is_halted() {
+        return Err(format!("Step limit reached after {} steps", max_steps));
+    }
+    println!("{}", String::from_utf8_lossy(loader.output(&computer)));
 
     Ok(())
 }
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+}
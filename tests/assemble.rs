@@ -0,0 +1,194 @@
+mod common;
+
+use common::challenge_source;
+use evil_electronic_enigma::assemble_program;
+
+/// The challenge, with named functions, arguments and locals.
+const CHALLENGE_FUNCS: &str = "
+LOAD 0 => C
+LOAD 1 => D
+PUSH C
+PUSH D
+PUSH D
+CALLR copy_list
+POP C
+POP D
+POP D
+POP D
+
+ALU SUB C D => D
+ALU ADD C D => D
+ALU DECR D D => D
+PUSH C
+PUSH D
+CALLR quicksort
+POP A
+POP D
+ALU INCR D D => D
+PUSH D
+
+LOAD 0 => B
+PUSH B
+LOAD 2 => B
+PUSH B
+CALLR xor_check
+POP A
+
+ALU ECHO A A => A
+JMPR Z ? 8
+LOAD 6 => C
+LOAD 7 => D
+JMPR T ? 8
+LOAD 4 => C
+LOAD 5 => D
+NOP
+
+MOVC 0 => B
+LOADP C => A
+STOREP A => B
+ALU INCR B B => B
+ALU INCR C D => C
+JMPR LT ? -8
+HALT
+
+.func copy_list(start, end, dest)
+SLOAD start => A
+SLOAD end => B
+SLOAD dest => C
+
+ALU ECHO A B => A
+JMPR LT ? 6
+SLOAD dest => C
+RET C
+
+LOADP A => D
+STOREP D => C
+ALU INCR A A => A
+ALU INCR C C => C
+JMPR T ? -16
+.endfunc
+
+.func xor_check(start, end, other, template)
+.local template_next
+SLOAD start => C
+SLOAD other => B
+SLOAD template => D
+PUSH D
+MOVC 0 => D
+PUSH D
+
+SLOAD end => D
+
+ALU ECHO C D => C
+JMPR LT ? 6
+POP A
+RET A
+
+LOADP B => A
+LOADP C => D
+ALU XOR A D => A
+
+SLOAD template_next => D
+LOADP D => D
+ALU XOR A D => A
+
+POP D
+ALU OR A D => A
+
+ALU INCR B B => B
+ALU INCR C C => C
+POP D
+ALU INCR D D => D
+PUSH D
+PUSH A
+
+JMPR T ? -38
+.endfunc
+
+.func quicksort(start, end)
+SLOAD start => C
+SLOAD end => D
+ALU ECHO C D => C
+JMPR LT ? 4
+RET C
+
+MOV C => D
+
+SLOAD end => A
+ALU ECHO D A => D
+JMPR NE ? 38
+
+PUSH C
+
+SLOAD start => A
+PUSH A
+ALU DECR C C => A
+PUSH A
+CALLR quicksort
+POP A
+POP B
+POP B
+
+ALU INCR A A => A
+PUSH A
+SLOAD end => A
+PUSH A
+CALLR quicksort
+POP A
+POP A
+POP A
+
+RET C
+
+ALU INCR D D => D
+LOADP C => A
+LOADP D => B
+ALU ECHO A B => A
+JMPR LE ? 12
+STOREP B => C
+ALU INCR C C => C
+LOADP C => B
+STOREP B => D
+STOREP A => C
+JMPR T ? -62
+.endfunc
+";
+
+#[test]
+fn named_functions_assemble_like_the_challenge() -> Result<(), String> {
+    assert_eq!(
+        assemble_program(&challenge_source())?,
+        assemble_program(CHALLENGE_FUNCS)?
+    );
+    Ok(())
+}
+
+#[test]
+fn function_errors() {
+    let errors = [
+        (
+            "PUSH A\nCALLR f\nHALT\n.func f(a, b)\nRET A\n.endfunc",
+            "Line 2: f takes 2 arguments, but 1 are pushed",
+        ),
+        (
+            "PUSH A\nPUSH B\nCALLR f\nPOP C\nPOP C\nPOP C\nCALLR f\nHALT\n.func f(a, b)\nRET A\n.endfunc",
+            "Line 7: f takes 2 arguments, but 0 are pushed",
+        ),
+        (
+            // Counted on the path that jumps past the second PUSH
+            "PUSH A\nALU ECHO A A => A\nJMPR Z ? 4\nPUSH B\nCALLR f\nHALT\n.func f(a, b)\nRET A\n.endfunc",
+            "Line 5: f takes 2 arguments, but 1 are pushed",
+        ),
+        (
+            ".func f(a)\nSLOAD b => A\nRET A\n.endfunc",
+            "Line 2: Unknown argument or local: b",
+        ),
+        ("CALLC g", "Line 1: Unknown function: g"),
+        (".func f(a)\nRET A", "Line 1: Missing .endfunc of f"),
+        (".local x", "Line 1: .local outside of .func"),
+        (".func f(1)\n.endfunc", "Line 1: Invalid name: 1"),
+    ];
+    for (source, error) in errors.iter() {
+        assert_eq!(assemble_program(source), Err(error.to_string()));
+    }
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::challenge_program;
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::AluFlagRef;
+use evil_electronic_enigma::Cfg;
+use evil_electronic_enigma::Edge;
+use evil_electronic_enigma::EdgeKind;
+
+#[test]
+fn challenge_functions_are_recovered() -> Result<(), String> {
+    let cfg = Cfg::recover(&challenge_program()?);
+
+    assert_eq!(
+        cfg.functions.keys().copied().collect::<Vec<_>>(),
+        vec![0, 82, 106, 158]
+    );
+    assert_eq!(
+        cfg.functions[&0]
+            .callees
+            .iter()
+            .copied()
+            .collect::<Vec<_>>(),
+        vec![82, 106, 158]
+    );
+    assert!(
+        cfg.functions[&158].callees.contains(&158),
+        "quicksort is recursive"
+    );
+    assert!(cfg.unresolved().is_empty());
+    assert!(cfg.errors.is_empty());
+
+    // JMPR LT ? 6 at 90 splits the copy loop
+    assert_eq!(
+        cfg.blocks[&88].edges,
+        vec![
+            Edge {
+                target: Some(96),
+                kind: EdgeKind::Jump(AluFlagRef::LessThan),
+            },
+            Edge {
+                target: Some(92),
+                kind: EdgeKind::NotTaken(AluFlagRef::LessThan),
+            },
+        ]
+    );
+
+    let dot = cfg.to_dot();
+    assert!(dot.starts_with("digraph cfg {"));
+    assert!(dot.contains("subgraph cluster_158 {"));
+    assert!(dot.contains("b088 -> b092 [label=\"GE\"];"));
+    assert!(dot.contains("b050 -> b056 [label=\"!Z\"];"));
+    assert!(dot.contains("b000 -> b082 [label=\"call\", style=dashed];"));
+
+    assert!(cfg
+        .summary()
+        .starts_with("4 functions, 27 blocks, 117 instructions\nfn 000: 9 blocks, 41 instructions, calls 082 106 158\n"));
+
+    Ok(())
+}
+
+#[test]
+fn indirect_jumps_are_unresolved() -> Result<(), String> {
+    let program = generate_code(&assemble_program(
+        "
+MOVC 8 => A
+JMPP LT ? A
+CALL B
+HALT
+RET A
+",
+    )?);
+    let cfg = Cfg::recover(&program);
+
+    assert_eq!(cfg.unresolved(), vec![2, 4]);
+    assert_eq!(cfg.functions.len(), 1);
+    assert_eq!(cfg.blocks.len(), 3);
+    assert_eq!(
+        cfg.blocks[&4].edges,
+        vec![
+            Edge {
+                target: None,
+                kind: EdgeKind::Call,
+            },
+            Edge {
+                target: Some(6),
+                kind: EdgeKind::AfterCall,
+            },
+        ]
+    );
+
+    let dot = cfg.to_dot();
+    assert!(dot.contains("unresolved_000 [label=\"?\", shape=diamond, color=red];"));
+    assert!(dot.contains("b000 -> unresolved_000 [label=\"LT\", color=red];"));
+    assert!(cfg
+        .summary()
+        .contains("unresolved 002: JMPP LT ? A\nunresolved 004: CALL B\n"));
+
+    Ok(())
+}
+
+#[test]
+fn undecodable_targets_are_reported() -> Result<(), String> {
+    let program = generate_code(&assemble_program(
+        "
+JMPR EQ ? 4
+HALT
+",
+    )?);
+    let cfg = Cfg::recover(&program);
+
+    assert_eq!(
+        cfg.errors,
+        vec![(4, "Instruction pointer out of program: 4".to_string())]
+    );
+    assert_eq!(cfg.blocks.len(), 2);
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! LEG sources of the challenge in challenge/, shared between the
+//! integration tests.
+#![allow(dead_code)]
+
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::flag_image;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::Checker;
+use evil_electronic_enigma::Word;
+
+pub const CHALLENGE_PROG: &str = include_str!("../../challenge/main.leg");
+pub const COPY_LIST_FN: &str = include_str!("../../challenge/copy_list.leg");
+pub const XOR_LIST_CHECK_FN: &str = include_str!("../../challenge/xor_list_check.leg");
+pub const QUICKSORT_FN: &str = include_str!("../../challenge/quicksort.leg");
+
+pub fn challenge_source() -> String {
+    format!(
+        "{}\n{}\n{}\n{}",
+        CHALLENGE_PROG, COPY_LIST_FN, XOR_LIST_CHECK_FN, QUICKSORT_FN
+    )
+}
+
+pub fn challenge_program() -> Result<Vec<Word>, String> {
+    Ok(generate_code(&assemble_program(&challenge_source())?))
+}
+
+/// The challenge's memory for `correct_input`, with `input` placed in it.
+pub fn challenge_memory(correct_input: &[u8], input: &[u8]) -> Vec<Word> {
+    flag_image(correct_input, Checker::SortXor)
+        .and_then(|image| image.with_input(input))
+        .unwrap()
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use common::challenge_memory;
+use common::CHALLENGE_PROG;
+use common::COPY_LIST_FN;
+use common::XOR_LIST_CHECK_FN;
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::compile;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::Instruction;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::NopOpcode;
+
+const SORT: &str = "
+// Sort start..=end in place, like the quicksort of the challenge. The
+// challenge reads the arguments again after the call, so leave them be.
+fn sort(start, end) {
+    var j = end;
+    while (start < j) {
+        var i = start;
+        while (i < j) {
+            var t = mem[i];
+            if (mem[j] < t) {
+                mem[i] = mem[j];
+                mem[j] = t;
+            }
+            i = i + 1;
+        }
+        j = j - 1;
+    }
+}
+";
+
+#[test]
+fn compiled_sort_replaces_quicksort() -> Result<(), String> {
+    let mut program = assemble_program(&format!(
+        "{}\n{}\n{}",
+        CHALLENGE_PROG, COPY_LIST_FN, XOR_LIST_CHECK_FN
+    ))?;
+    // The challenge calls the sort right after the other functions
+    assert_eq!(program.len() * 2, 158);
+    program.extend(compile(SORT)?);
+    let program = generate_code(&program);
+    assert!(program.len() <= 256);
+
+    let flag = b"midnight{s0rt3d}";
+    let computer = LegComputer::new(program.clone(), challenge_memory(flag, flag)).run();
+    assert_eq!(b"OK!"[..], computer.memory[0..3]);
+
+    let computer = LegComputer::new(program, challenge_memory(flag, b"midnight{d3tr0s}")).run();
+    assert_eq!(b"ERR"[..], computer.memory[0..3]);
+    Ok(())
+}
+
+#[test]
+fn compiled_code_calls_handwritten_code() -> Result<(), String> {
+    let mut program = compile(
+        "
+extern fn copy_list(start, end, dest) @ 200;
+global start @ 0;
+global end @ 1;
+const DEST = 100;
+
+fn sum(list, len) {
+    var total = 0;
+    var i = 0;
+    while (i < len) {
+        total = total + list[i];
+        i = i + 1;
+    }
+    return total;
+}
+
+fn fib(n) {
+    if (n < 2) {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+
+fn main() {
+    var dest = copy_list(start, end, DEST);
+    mem[2] = sum(dest, end - start);
+    mem[3] = fib(10);
+    if (mem[DEST] == 'L' && !(mem[DEST + 1] != 'E') || 0) {
+        out(mem[DEST + 2]);
+    }
+}
+",
+    )?;
+    assert!(program.len() <= 100);
+    program.resize(100, Instruction::Nop(NopOpcode::Halt));
+    program.extend(assemble_program(COPY_LIST_FN)?);
+
+    let mut memory = vec![20, 24];
+    memory.resize(20, 0);
+    memory.extend(b"LEG!");
+    memory.resize(256, 0);
+
+    let computer = LegComputer::new(generate_code(&program), memory).run();
+    assert_eq!(b"LEG!"[..], computer.memory[100..104]);
+    assert_eq!(
+        b"LEG!".iter().fold(0u8, |a, b| a.wrapping_add(*b)),
+        computer.memory[2]
+    );
+    assert_eq!(55, computer.memory[3]);
+    assert_eq!(b'G', computer.reg_o);
+    Ok(())
+}
+
+#[test]
+fn errors_have_line_numbers() {
+    assert_eq!(
+        compile("fn f(a) {\n  return a;\n}\nfn main() {\n  f(1, 2);\n}"),
+        Err("Line 5: f takes 1 arguments, but 2 were given".to_string())
+    );
+    assert_eq!(
+        compile("fn main() {\n  x = 1;\n}"),
+        Err("Line 2: Unknown variable: x".to_string())
+    );
+    assert_eq!(
+        compile("fn main() {\n  var x = 1 +;\n}"),
+        Err("Line 2: Expected an expression, found ';'".to_string())
+    );
+}
@@ -0,0 +1,117 @@
+use evil_electronic_enigma::read_container;
+use evil_electronic_enigma::write_container;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::LegContainer;
+use evil_electronic_enigma::Section;
+use evil_electronic_enigma::Symbol;
+use evil_electronic_enigma::CHALLENGE_MEMORY;
+use evil_electronic_enigma::CHALLENGE_PROGRAM;
+use evil_electronic_enigma::CONTAINER_MAGIC;
+
+const SOURCE: &str = "\
+.include memset
+MOVC 100 => A
+PUSH A
+MOVC 7 => A
+PUSH A
+MOVC 3 => A
+PUSH A
+CALLC memset
+HALT
+";
+
+#[test]
+fn container_round_trips_and_runs() -> Result<(), String> {
+    let mut container = LegContainer::assemble(SOURCE, vec![1, 2, 3])?;
+    assert_eq!(
+        container.symbols,
+        vec![Symbol {
+            name: "memset".to_string(),
+            section: Section::Program,
+            addr: 0,
+        }]
+    );
+    // Library routines are numbered like the .include
+    assert_eq!(container.line_at(0), Some(1));
+    let start = container
+        .lines
+        .iter()
+        .find(|(_, line)| *line == 2)
+        .map(|(addr, _)| *addr)
+        .ok_or("No line 2")?;
+    assert_eq!(start, container.program.len() as u8 - 16);
+    assert_eq!(
+        container
+            .symbol_at(Section::Program, 6)
+            .map(|(s, o)| (&s.name[..], o)),
+        Some(("memset", 6))
+    );
+    container.entry = start;
+    container.symbols.push(Symbol {
+        name: "buffer".to_string(),
+        section: Section::Data,
+        addr: 100,
+    });
+
+    let bytes = container.to_bytes()?;
+    assert_eq!(bytes[..4], CONTAINER_MAGIC);
+    assert_eq!(LegContainer::from_bytes(&bytes)?, container);
+
+    let computer = LegComputer::from(&container);
+    assert_eq!(computer.eip, start);
+    assert_eq!(computer.memory.len(), 256);
+    let computer = computer.run();
+    assert_eq!(computer.memory[0..3], [1, 2, 3]);
+    assert_eq!(computer.memory[100..104], [7, 7, 7, 0]);
+
+    let path = std::env::temp_dir().join(format!("leg-container-test-{}.legc", std::process::id()));
+    let challenge = LegContainer::new(CHALLENGE_PROGRAM.to_vec(), CHALLENGE_MEMORY.to_vec());
+    write_container(&path, &challenge)?;
+    assert_eq!(read_container(&path)?, challenge);
+    std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[test]
+fn damaged_containers_are_rejected() -> Result<(), String> {
+    let bytes = LegContainer::assemble(SOURCE, vec![])?.to_bytes()?;
+    let error = |bytes: &[u8]| LegContainer::from_bytes(bytes).err().unwrap_or_default();
+
+    assert_eq!(error(&bytes[1..]), "Not a LEG container");
+    assert_eq!(error(&bytes[..6]), "Truncated container");
+
+    let mut flipped = bytes.clone();
+    flipped[20] ^= 1;
+    assert!(error(&flipped).starts_with("Checksum mismatch"));
+
+    // Checked after the checksum, so rewrite it
+    let with_checksum = |mut body: Vec<u8>| {
+        body.truncate(body.len() - 4);
+        let mut crc = !0u32;
+        for byte in &body {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        body.extend(&(!crc).to_le_bytes());
+        body
+    };
+    let mut version = bytes.clone();
+    version[4] = 2;
+    assert_eq!(
+        error(&with_checksum(version)),
+        "Unsupported container version: 2"
+    );
+    let mut dialect = bytes.clone();
+    dialect[5] = 9;
+    assert_eq!(error(&with_checksum(dialect)), "Unknown ISA dialect: 9");
+    let mut short = bytes.clone();
+    short.remove(bytes.len() - 5);
+    assert!(error(&with_checksum(short)).starts_with("Truncated container: expected line number"));
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::disassemble;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::Command;
+use evil_electronic_enigma::Debugger;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::RegisterRef;
+use evil_electronic_enigma::StopReason;
+
+// Calls a function that stores 7 at address 40, then stores 9 at address 41.
+const PROGRAM: &str = "
+MOVC 3 => A
+PUSH A
+CALLR 10
+POP B
+MOVC 9 => C
+STORE C => 41
+HALT
+
+SLOAD 2 => A
+MOVC 7 => D
+STORE D => 40
+ALU INCR A A => A
+RET A
+";
+
+fn debugger() -> Result<Debugger, String> {
+    let program = generate_code(&assemble_program(PROGRAM)?);
+    Ok(Debugger::new(LegComputer::new(program, vec![0; 256])))
+}
+
+#[test]
+fn disassembly_round_trips() -> Result<(), String> {
+    let source = "
+LOAD 0 => C
+JMPR LT ? -8
+SLOAD -1 => D
+CALLR 72
+ALU XOR A D => A
+GPI B <=
+GPO C =>
+MOVC 255 => A
+HALT
+";
+    let program = assemble_program(source)?;
+    let disassembled: Vec<String> = disassemble(&generate_code(&program))?
+        .iter()
+        .map(|ins| ins.to_string())
+        .collect();
+    let expected: Vec<&str> = source.trim().lines().collect();
+    assert_eq!(expected, disassembled);
+    Ok(())
+}
+
+#[test]
+fn next_steps_over_call() -> Result<(), String> {
+    let mut dbg = debugger()?;
+    assert_eq!(StopReason::Stepped, dbg.step(2));
+    assert_eq!(4, dbg.computer.eip);
+
+    assert_eq!(StopReason::Stepped, dbg.step_over());
+    assert_eq!(6, dbg.computer.eip);
+    assert_eq!(7, dbg.computer.memory[40]);
+
+    assert_eq!(StopReason::Stepped, dbg.step_over());
+    assert_eq!(4, dbg.computer.read_register(&RegisterRef::B));
+    Ok(())
+}
+
+#[test]
+fn finish_runs_until_return() -> Result<(), String> {
+    let mut dbg = debugger()?;
+    dbg.step(4);
+    assert_eq!(16, dbg.computer.eip);
+
+    assert_eq!(StopReason::Stepped, dbg.finish());
+    assert_eq!(6, dbg.computer.eip);
+    Ok(())
+}
+
+#[test]
+fn continue_stops_at_breakpoints_and_watchpoints() -> Result<(), String> {
+    let mut dbg = debugger()?;
+    dbg.execute(&"break 8".parse::<Command>()?);
+    dbg.execute(&"watch 41".parse::<Command>()?);
+
+    assert_eq!(StopReason::Breakpoint(8), dbg.cont());
+    assert_eq!(
+        StopReason::Watchpoint {
+            addr: 41,
+            old: 0,
+            new: 9
+        },
+        dbg.cont()
+    );
+    assert_eq!(StopReason::Halted, dbg.cont());
+    Ok(())
+}
+
+#[test]
+fn set_changes_registers_and_memory() -> Result<(), String> {
+    let mut dbg = debugger()?;
+    dbg.execute(&"set B 0x2a".parse::<Command>()?);
+    dbg.execute(&"set 100 -1".parse::<Command>()?);
+    dbg.execute(&"set IP 4".parse::<Command>()?);
+
+    assert_eq!(42, dbg.computer.read_register(&RegisterRef::B));
+    assert_eq!(255, dbg.computer.memory[100]);
+    assert_eq!(4, dbg.computer.eip);
+    Ok(())
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use common::challenge_program;
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::decompile;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::Cfg;
+
+fn assert_lines(output: &str, expected: &[&str]) {
+    let lines: Vec<&str> = output.lines().map(|l| l.trim()).collect();
+    for line in expected {
+        assert!(lines.contains(line), "missing {:?} in:\n{}", line, output);
+    }
+}
+
+#[test]
+fn challenge_reads_as_copy_sort_xor_compare() -> Result<(), String> {
+    let output = decompile(&Cfg::recover(&challenge_program()?));
+
+    // Copy
+    assert_lines(
+        &output,
+        &[
+            "u8 fn_082(u8 arg1, u8 arg2, u8 arg3) {",
+            "while (A < B) {",
+            "mem[C] = mem[A];",
+        ],
+    );
+    // Sort
+    assert_lines(
+        &output,
+        &[
+            "u8 fn_158(u8 arg1, u8 arg2) {",
+            "if (A > B) {",
+            "local4 = fn_158(arg1, C - 1);",
+            "fn_158(local4 + 1, arg2);",
+        ],
+    );
+    // Xor-compare
+    assert_lines(
+        &output,
+        &[
+            "u8 fn_106(u8 arg1, u8 arg2, u8 arg3, u8 arg4) {",
+            "while (C < arg2) {",
+            "A = (mem[B] ^ mem[C] ^ mem[local1]) | local2;",
+            "return local2;",
+        ],
+    );
+    // Main prints OK or ERR depending on the result
+    assert_lines(
+        &output,
+        &[
+            "void main() {",
+            "local5 = fn_106(local1, local2 + 1, mem[0], mem[2]);",
+            "if (local5 == 0) {",
+            "} else {",
+            "} while (C++ < D);",
+            "halt();",
+        ],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn loops_io_and_indirect_jumps() -> Result<(), String> {
+    let program = generate_code(&assemble_program(
+        "
+MOVC 0 => B
+GPI A <=
+ALU ECHO A A => A
+JMPR Z ? 8
+ALU ADD A B => B
+GPO A =>
+JMPR T ? -10
+PUSH B
+CALLR 4
+HALT
+SLOAD 2 => C
+ALU SHIFTL C C => D
+MOVC 40 => A
+JMPP LT ? A
+RET D
+",
+    )?);
+
+    assert_eq!(
+        decompile(&Cfg::recover(&program)),
+        "\
+void main() {
+    B = 0;
+    while (true) {
+        A = gpi();
+        if (A == 0) break;
+        B = A + B;
+        gpo(A);
+    }
+    fn_020(B);
+    halt();
+}
+
+u8 fn_020(u8 arg1) {
+    C = arg1;
+    D = C << C;
+    if (C < C) goto *(40);
+    return D;
+}
+"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use common::challenge_memory;
+use common::challenge_source;
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::Counterexample;
+use evil_electronic_enigma::Divergence;
+use evil_electronic_enigma::Equivalence;
+use evil_electronic_enigma::EquivalenceChecker;
+use evil_electronic_enigma::SymbolicInput;
+use evil_electronic_enigma::Word;
+
+fn assemble(source: &str) -> Result<Vec<Word>, String> {
+    Ok(generate_code(&assemble_program(source)?))
+}
+
+fn inputs(addrs: std::ops::Range<u8>, domain: std::ops::RangeInclusive<u8>) -> Vec<SymbolicInput> {
+    addrs
+        .map(|addr| SymbolicInput {
+            addr,
+            domain: domain.clone().collect(),
+        })
+        .collect()
+}
+
+#[test]
+fn small_domains_are_checked_exhaustively() -> Result<(), String> {
+    // max(a, b) at address 0, computed by branching either way round
+    let left = assemble(
+        "
+LOAD 16 => A
+LOAD 17 => B
+ALU ECHO A B => A
+JMPR GE ? 4
+MOV B => A
+STORE A => 0
+HALT
+",
+    )?;
+    let right = assemble(
+        "
+LOAD 17 => A
+LOAD 16 => B
+ALU ECHO A B => A
+JMPR GT ? 4
+MOV B => A
+STORE A => 0
+HALT
+",
+    )?;
+    let mut checker = EquivalenceChecker::new(left, right, vec![0; 256], inputs(16..18, 0..=255));
+    checker.output = (0, 1);
+
+    assert_eq!(
+        checker.check(),
+        Equivalence::Equivalent {
+            runs: 65536,
+            exhaustive: true
+        }
+    );
+
+    // Signed comparison differs once either byte has the top bit set
+    checker.right = assemble(
+        "
+LOAD 17 => A
+LOAD 16 => B
+ALU ECHO A B => A
+JMPR GTs ? 4
+MOV B => A
+STORE A => 0
+HALT
+",
+    )?;
+    assert_eq!(
+        checker.check(),
+        Equivalence::Counterexample(Counterexample {
+            input: vec![128, 0],
+            divergence: Divergence::Memory {
+                addr: 0,
+                left: 128,
+                right: 0
+            },
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn faults_are_compared_without_their_address() -> Result<(), String> {
+    let left = assemble("LOAD 16 => A\nSTORE A => 0")?;
+    let right = assemble("LOAD 16 => A\nNOP\nSTORE A => 0")?;
+    let mut checker = EquivalenceChecker::new(left, right, vec![0; 256], inputs(16..17, 0..=3));
+    assert_eq!(
+        checker.check(),
+        Equivalence::Equivalent {
+            runs: 4,
+            exhaustive: true
+        }
+    );
+
+    checker.right = assemble("LOAD 16 => A\nSTORE A => 0\nHALT")?;
+    assert_eq!(
+        checker.compare(&[0]),
+        Some(Divergence::Outcome {
+            left: "fault at 004: ran past the end of the program".to_string(),
+            right: "halted".to_string(),
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn challenge_variants_are_sampled() -> Result<(), String> {
+    let flag = b"midnight{s0rt3d}";
+    let memory = challenge_memory(flag, flag);
+    let start = memory[0];
+    let source = challenge_source();
+    let original = assemble(&source)?;
+
+    // Swapping equal elements doesn't change the sorted list
+    let unstable = assemble(&source.replace("JMPR LE ? 12", "JMPR LT ? 12"))?;
+    let mut checker = EquivalenceChecker::new(
+        original.clone(),
+        unstable,
+        memory.clone(),
+        inputs(start..start + flag.len() as u8, 0x61..=0x66),
+    );
+    checker.output = (0, 3);
+    checker.samples = 200;
+    assert_eq!(
+        checker.check(),
+        Equivalence::Equivalent {
+            runs: 200,
+            exhaustive: false
+        }
+    );
+
+    // Sorting in descending order only gives the same result on inputs that
+    // are already wrong
+    checker.right = assemble(&source.replace("JMPR LE ? 12", "JMPR GE ? 12"))?;
+    checker.inputs = flag
+        .iter()
+        .enumerate()
+        .map(|(i, b)| SymbolicInput {
+            addr: start + i as u8,
+            domain: vec![*b],
+        })
+        .collect();
+    match checker.check() {
+        Equivalence::Counterexample(Counterexample {
+            input,
+            divergence: Divergence::Memory { addr: 0, .. },
+        }) => assert_eq!(input, flag.to_vec()),
+        result => panic!("{:?}", result),
+    }
+
+    Ok(())
+}
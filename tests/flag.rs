@@ -0,0 +1,114 @@
+use evil_electronic_enigma::flag_image;
+use evil_electronic_enigma::Checker;
+use evil_electronic_enigma::FlagMemory;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::CHALLENGE_MEMORY;
+use evil_electronic_enigma::CHALLENGE_PROGRAM;
+use evil_electronic_enigma::PLAIN_PROGRAM;
+
+fn check(memory: Vec<u8>) -> Vec<u8> {
+    LegComputer::new(CHALLENGE_PROGRAM.to_vec(), memory)
+        .run()
+        .memory[0..3]
+        .to_vec()
+}
+
+#[test]
+fn challenge_memory_is_generated() -> Result<(), String> {
+    let flag = std::fs::read("flag.txt").map_err(|e| e.to_string())?;
+    let image = flag_image(&flag, Checker::SortXor)?;
+    assert_eq!(image.memory, CHALLENGE_MEMORY);
+    assert_eq!(image.layout.ok, (8, 3));
+    assert_eq!(image.layout.err, (12, 3));
+    assert_eq!(image.layout.solution, (16, 29));
+    assert_eq!(image.layout.input, 45);
+
+    assert_eq!(check(image.with_input(&flag)?), b"OK!");
+    assert_eq!(
+        check(image.with_input(b"midnight{fiddlin_wi_ma_bits}")?),
+        b"ERR"
+    );
+    Ok(())
+}
+
+#[test]
+fn longest_flag_still_fits() -> Result<(), String> {
+    // The input, its sorted copy and the quicksort's stack fill memory
+    let flag = b"midnight{this_is_the_longest!}";
+    let image = flag_image(flag, Checker::SortXor)?;
+    assert_eq!(image.layout.input as usize + 2 * flag.len(), 106);
+    assert_eq!(image.layout.stack, 107);
+    assert_eq!(check(image.with_input(flag)?), b"OK!");
+
+    let mut reversed = flag.to_vec();
+    reversed.reverse();
+    assert_eq!(check(image.with_input(&reversed)?), b"ERR");
+    assert_eq!(
+        image.with_input(b"midnight{this_is_too_long_an_input}"),
+        Err("Input of 35 bytes does not fit in memory: 046-082 are free".to_string())
+    );
+
+    assert_eq!(
+        flag_image(b"midnight{this_is_a_bit_too_long}", Checker::SortXor),
+        Err("Flag of 32 bytes does not fit in memory: 048-097 are free".to_string())
+    );
+    Ok(())
+}
+
+#[test]
+fn plain_checker_compares_the_flag() -> Result<(), String> {
+    let run = |memory| {
+        LegComputer::new(PLAIN_PROGRAM.to_vec(), memory)
+            .run()
+            .memory[0..3]
+            .to_vec()
+    };
+    let flag = b"midnight{plain_and_simple}";
+    let image = flag_image(flag, Checker::Plain)?;
+    assert_eq!(image.layout.stack, 256);
+    assert_eq!(&image.memory[16..16 + flag.len()], flag);
+    assert_eq!(run(image.with_input(flag)?), b"OK!");
+    assert_eq!(
+        run(image.with_input(b"midnight{plain_and_simplE}")?),
+        b"ERR"
+    );
+    assert_eq!(run(image.with_input(b"midnight{plain}")?), b"ERR");
+    assert_eq!(run(image.with_input(b"")?), b"ERR");
+
+    // Without the sort or the stack, the input can take the rest of memory
+    // but address 255, which its end couldn't be written as
+    let flag = [b'x'; 119];
+    let image = flag_image(&flag, Checker::Plain)?;
+    assert_eq!(image.layout.input as usize + flag.len(), 254);
+    assert_eq!(run(image.with_input(&flag)?), b"OK!");
+    assert_eq!(run(image.with_input(&flag[1..])?), b"ERR");
+    assert_eq!(
+        flag_image(&[b'x'; 120], Checker::Plain),
+        Err("Flag of 120 bytes does not fit in memory: 136-255 are free".to_string())
+    );
+    Ok(())
+}
+
+#[test]
+fn regions_must_not_overlap() {
+    let mut memory = FlagMemory::new(Checker::SortXor);
+    memory.ok = b"Correct!".to_vec();
+    assert_eq!(
+        memory.build(b"midnight{}"),
+        Err("Regions overlap: OK string at 008-017 and ERR string at 012-016".to_string())
+    );
+
+    // The byte after each string is copied with it
+    memory.err_addr = 16;
+    assert_eq!(
+        memory.build(b"midnight{}"),
+        Err("Regions overlap: OK string at 008-017 and ERR string at 016-020".to_string())
+    );
+
+    memory.err_addr = 17;
+    memory.solution_addr = 250;
+    assert_eq!(
+        memory.build(b"midnight{}"),
+        Err("Does not fit in memory: solution at 250-260".to_string())
+    );
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::challenge_memory;
+use common::challenge_program;
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::Fuzzer;
+use evil_electronic_enigma::Outcome;
+use evil_electronic_enigma::Target;
+
+/// Writes 1 to address 0 if the input starts with "LEG", loops forever on
+/// "!" and jumps out of the program on "?".
+const MAGIC: &str = "
+LOAD 16 => A
+MOVC 33 => D
+ALU ECHO A D => A
+JMPR NE ? 4
+JMPR T ? 0
+MOVC 63 => D
+ALU ECHO A D => A
+JMPR NE ? 4
+JMP T ? 250
+MOVC 76 => D
+ALU ECHO A D => A
+JMPR NE ? 22
+LOAD 17 => A
+MOVC 69 => D
+ALU ECHO A D => A
+JMPR NE ? 14
+LOAD 18 => A
+MOVC 71 => D
+ALU ECHO A D => A
+JMPR NE ? 6
+MOVC 1 => A
+STORE A => 0
+HALT
+";
+
+#[test]
+fn coverage_leads_to_the_target() -> Result<(), String> {
+    let program = generate_code(&assemble_program(MAGIC)?);
+    let output_dir = std::env::temp_dir().join(format!("leg-fuzz-test-{}", std::process::id()));
+    std::fs::create_dir_all(&output_dir).map_err(|e| e.to_string())?;
+
+    let mut fuzzer = Fuzzer::new(program, vec![0; 256], 16, 8, 1);
+    fuzzer.max_steps = 1000;
+    fuzzer.target = Some(Target::HaltWithMemory {
+        addr: 0,
+        bytes: vec![1],
+    });
+    fuzzer.output_dir = Some(output_dir.clone());
+    let stats = fuzzer.run(50_000)?.clone();
+
+    assert!(stats.targets > 0, "{}", stats);
+    assert!(stats.faults > 0, "{}", stats);
+    assert!(stats.timeouts > 0, "{}", stats);
+
+    let target = fuzzer
+        .findings
+        .iter()
+        .find(|f| f.outcome == Outcome::Target)
+        .unwrap();
+    assert!(target.input.starts_with(b"LEG"));
+    let fault = fuzzer
+        .findings
+        .iter()
+        .find(|f| f.outcome != Outcome::Target && f.outcome != Outcome::Timeout)
+        .unwrap();
+    assert_eq!(fault.input[0], b'?');
+    assert_eq!(fault.eip, 250);
+
+    let saved = std::fs::read(output_dir.join(target.file_name())).map_err(|e| e.to_string())?;
+    assert_eq!(saved, target.input);
+    assert!(output_dir.join("timeout-008").exists());
+    std::fs::remove_dir_all(&output_dir).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[test]
+fn challenge_runs_under_the_fuzzer() -> Result<(), String> {
+    let flag = b"midnight{s0rt3d}";
+    let memory = challenge_memory(flag, b"");
+    let mut fuzzer = Fuzzer::new(challenge_program()?, memory.clone(), memory[0], 16, 7);
+    fuzzer.input_end_pointer = Some(1);
+    fuzzer.target = Some(Target::HaltWithMemory {
+        addr: 0,
+        bytes: b"OK!".to_vec(),
+    });
+
+    let execution = fuzzer.add_seed(flag.to_vec())?;
+    assert_eq!(execution.outcome, Outcome::Target);
+    let execution = fuzzer.add_seed(b"midnight{s0rt3e}".to_vec())?;
+    assert_eq!(execution.outcome, Outcome::Halted);
+
+    fuzzer.run(200)?;
+    assert_eq!(fuzzer.stats.faults, 0);
+    assert_eq!(fuzzer.stats.timeouts, 0);
+    assert!(fuzzer.corpus.len() > 2);
+
+    Ok(())
+}
+
+#[test]
+fn input_end_fits_in_a_word() -> Result<(), String> {
+    let program = generate_code(&assemble_program("LOAD 1 => A\nSTORE A => 0\nHALT")?);
+    let mut fuzzer = Fuzzer::new(program, vec![0; 256], 200, 100, 0);
+    assert_eq!(fuzzer.max_input_len, 55);
+    fuzzer.input_end_pointer = Some(1);
+    fuzzer.target = Some(Target::HaltWithMemory {
+        addr: 0,
+        bytes: vec![255],
+    });
+    assert_eq!(fuzzer.execute(&[b'x'; 100]).outcome, Outcome::Target);
+    Ok(())
+}
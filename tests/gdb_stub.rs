@@ -0,0 +1,119 @@
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::GdbStub;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::Reply;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
+
+const PROGRAM: &str = "
+MOVC 5 => A
+STORE A => 16
+MOVC 6 => B
+HALT
+";
+
+fn stub() -> Result<GdbStub, String> {
+    let program = generate_code(&assemble_program(PROGRAM)?);
+    Ok(GdbStub::new(LegComputer::new(program, vec![0; 256])))
+}
+
+fn send(stub: &mut GdbStub, packet: &str) -> String {
+    match stub.handle_packet(packet) {
+        Reply::Send(reply) => reply,
+        other => panic!("Unexpected reply: {:?}", other),
+    }
+}
+
+#[test]
+fn registers_read_and_write() -> Result<(), String> {
+    let mut stub = stub()?;
+    assert_eq!("S05", send(&mut stub, "s"));
+    assert_eq!("0500000000000002", send(&mut stub, "g"));
+
+    assert_eq!("OK", send(&mut stub, "P1=2a"));
+    assert_eq!("2a", send(&mut stub, "p1"));
+    assert_eq!("OK", send(&mut stub, "P7=04"));
+    assert_eq!(4, stub.debugger.computer.eip);
+    assert_eq!("E01", send(&mut stub, "p9"));
+    Ok(())
+}
+
+#[test]
+fn memory_read_and_write() -> Result<(), String> {
+    let mut stub = stub()?;
+    assert_eq!("6005", send(&mut stub, "m0,2"));
+    assert_eq!("OK", send(&mut stub, "M800020,2:abcd"));
+    assert_eq!("abcd", send(&mut stub, "m800020,2"));
+    assert_eq!("E01", send(&mut stub, "m800100,1"));
+
+    // Lengths that overflow the address are errors, or clamped for qXfer
+    assert_eq!("E01", send(&mut stub, "m10,ffffffffffffffff"));
+    assert_eq!("E01", send(&mut stub, "Mffffffffffffffff,2:abcd"));
+    let xfer = send(&mut stub, "qXfer:memory-map:read::10,ffffffffffffffff");
+    assert!(xfer.starts_with('l'));
+    Ok(())
+}
+
+#[test]
+fn breakpoints_and_halt_reasons() -> Result<(), String> {
+    let mut stub = stub()?;
+    assert_eq!("S05", send(&mut stub, "?"));
+    assert_eq!("OK", send(&mut stub, "Z0,6,2"));
+    assert_eq!("OK", send(&mut stub, "Z2,800010,1"));
+    assert_eq!("T05watch:800010;", send(&mut stub, "c"));
+    assert_eq!("T05swbreak:;", send(&mut stub, "c"));
+    assert_eq!("OK", send(&mut stub, "z0,6,2"));
+    assert_eq!("W00", send(&mut stub, "c"));
+    assert_eq!("W00", send(&mut stub, "?"));
+    Ok(())
+}
+
+#[test]
+fn target_description_is_transferred_in_chunks() -> Result<(), String> {
+    let mut stub = stub()?;
+    let first = send(&mut stub, "qXfer:features:read:target.xml:0,15");
+    assert_eq!("m<?xml version=\"1.0\"?>", first);
+
+    let rest = send(&mut stub, "qXfer:features:read:target.xml:15,1000");
+    assert!(rest.starts_with('l'));
+    assert!(rest.contains("<reg name=\"ip\" bitsize=\"8\""));
+    Ok(())
+}
+
+struct FakeStream {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for FakeStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for FakeStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn serve_frames_packets_and_checks_checksums() -> Result<(), String> {
+    let mut stub = stub()?;
+    let mut stream = FakeStream {
+        input: Cursor::new(b"+$g#00$g#67+$D#44".to_vec()),
+        output: Vec::new(),
+    };
+    stub.serve(&mut stream).map_err(|e| e.to_string())?;
+    assert_eq!(
+        "-+$0000000000000000#00+$OK#9a",
+        String::from_utf8_lossy(&stream.output)
+    );
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use evil_electronic_enigma::read_image;
+use evil_electronic_enigma::write_image;
+use evil_electronic_enigma::ImageFormat;
+use evil_electronic_enigma::CHALLENGE_MEMORY;
+use evil_electronic_enigma::CHALLENGE_PROGRAM;
+use evil_electronic_enigma::IMAGE_FORMATS;
+
+#[test]
+fn images_round_trip_in_every_format() -> Result<(), String> {
+    for format in &IMAGE_FORMATS {
+        for image in &[CHALLENGE_PROGRAM, CHALLENGE_MEMORY, &[]] {
+            assert_eq!(format.parse(&format.write(image))?, *image);
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("leg-image-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    for name in &[
+        "program.bin",
+        "program.hex",
+        "program.json",
+        "program.logisim",
+        "program.mem",
+        "program.ihex",
+    ] {
+        write_image(dir.join(name), CHALLENGE_PROGRAM)?;
+        assert_eq!(read_image(dir.join(name))?, CHALLENGE_PROGRAM);
+    }
+    let hex = std::fs::read_to_string(dir.join("program.hex")).map_err(|e| e.to_string())?;
+    assert!(hex.starts_with("12 00 13 01 b1 02"));
+    std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[test]
+fn text_formats_are_lenient_about_whitespace() -> Result<(), String> {
+    let hex = b"# program\n  12 0\n\n ff\tA\n";
+    assert_eq!(ImageFormat::Hex.parse(hex)?, vec![0x12, 0, 0xff, 0xa]);
+    assert_eq!(ImageFormat::Json.parse(b" [ 1,2 ,\n3 ]\n")?, vec![1, 2, 3]);
+    assert_eq!(ImageFormat::Json.parse(b"[ ]")?, vec![]);
+    assert_eq!(ImageFormat::from_path("a/b.HEX"), ImageFormat::Raw);
+    assert_eq!("json".parse(), Ok(ImageFormat::Json));
+    Ok(())
+}
+
+#[test]
+fn hardware_formats_match_their_tools() -> Result<(), String> {
+    let image = [0x12, 0, 0, 0, 0, 0, 0xb1, 0xb1];
+    assert_eq!(
+        ImageFormat::Logisim.write(&image),
+        b"v2.0 raw\n12 5*0 b1 b1\n".to_vec()
+    );
+    assert_eq!(
+        ImageFormat::IntelHex.write(&image),
+        b":08000000120000000000B1B184\n:00000001FF\n".to_vec()
+    );
+
+    // As written by Logisim-evolution, Verilog tools and objcopy
+    let logisim = b"v2.0 raw\n# rom\n12 3*0\n b1\n";
+    assert_eq!(
+        ImageFormat::Logisim.parse(logisim)?,
+        vec![0x12, 0, 0, 0, 0xb1]
+    );
+    let readmemh = b"// rom\n12 0_0 /* skipped\n ff */ 3\n@6 b1 // end\n";
+    assert_eq!(
+        ImageFormat::Readmemh.parse(readmemh)?,
+        vec![0x12, 0, 3, 0, 0, 0, 0xb1]
+    );
+    let ihex = b":020000040000FA\n:03000400120013D4\n:00000001FF\n";
+    assert_eq!(
+        ImageFormat::IntelHex.parse(ihex)?,
+        vec![0, 0, 0, 0, 0x12, 0, 0x13]
+    );
+
+    // Both are often named .hex
+    assert_eq!(
+        ImageFormat::Hex.parse(ihex)?,
+        ImageFormat::IntelHex.parse(ihex)?
+    );
+    assert_eq!(
+        ImageFormat::Hex.parse(readmemh)?,
+        ImageFormat::Readmemh.parse(readmemh)?
+    );
+    assert_eq!(
+        ImageFormat::Hex.parse(
+            b"@2 12
+"
+        )?,
+        vec![0, 0, 0x12]
+    );
+    Ok(())
+}
+
+#[test]
+fn invalid_images_are_rejected() {
+    assert_eq!(
+        ImageFormat::Hex.parse(b"00 01\n02 100\n"),
+        Err("line 2: Invalid hex byte: 100".to_string())
+    );
+    assert_eq!(
+        ImageFormat::Json.parse(b"[1, 256]"),
+        Err("Invalid byte: 256".to_string())
+    );
+    assert_eq!(
+        ImageFormat::Json.parse(b"{}"),
+        Err("Expected a JSON array".to_string())
+    );
+    assert_eq!(
+        ImageFormat::Logisim.parse(b"12 0\n"),
+        Err("Expected a v2.0 raw header".to_string())
+    );
+    assert_eq!(
+        ImageFormat::Logisim.parse(b"v2.0 raw\n1 18446744073709551615*0\n"),
+        Err("line 2: Image too large".to_string())
+    );
+    assert_eq!(
+        ImageFormat::Readmemh.parse(b"@10000 1\n"),
+        Err("line 1: Address out of range: 0x10000".to_string())
+    );
+    assert_eq!(
+        ImageFormat::IntelHex.parse(b":0100000012EE\n:00000001FF\n"),
+        Err("line 1: Checksum mismatch".to_string())
+    );
+    assert_eq!(
+        ImageFormat::IntelHex.parse(b":0100000012ED\n"),
+        Err("Missing end of file record".to_string())
+    );
+    assert!("elf".parse::<ImageFormat>().is_err());
+}
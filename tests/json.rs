@@ -0,0 +1,84 @@
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::RegisterRef;
+use evil_electronic_enigma::CHALLENGE_MEMORY;
+use evil_electronic_enigma::CHALLENGE_PROGRAM;
+
+#[test]
+fn state_round_trips_through_json() -> Result<(), String> {
+    let mut computer = LegComputer::new(CHALLENGE_PROGRAM.to_vec(), CHALLENGE_MEMORY.to_vec());
+    for _ in 0..500 {
+        let parsed = LegComputer::from_json(&computer.to_json())?;
+        assert_eq!(parsed.program, computer.program);
+        assert_eq!(parsed.memory, computer.memory);
+        assert_eq!(parsed.eip, computer.eip);
+        assert_eq!(parsed.reg_i, computer.reg_i);
+        assert_eq!(parsed.reg_o, computer.reg_o);
+        assert_eq!(parsed.to_json(), computer.to_json());
+        for register in &[RegisterRef::A, RegisterRef::ST, RegisterRef::FL] {
+            assert_eq!(
+                parsed.read_register(register),
+                computer.read_register(register)
+            );
+        }
+        computer.try_step()?;
+    }
+
+    // The parsed state runs on like the original
+    let parsed = LegComputer::from_json(&computer.to_json())?.run();
+    assert_eq!(parsed.to_json(), computer.run().to_json());
+    Ok(())
+}
+
+#[test]
+fn json_state_has_named_fields() -> Result<(), String> {
+    let json = LegComputer::new(CHALLENGE_PROGRAM.to_vec(), vec![0; 4]).to_json();
+    assert!(json.contains("\"eip\": 0,"));
+    assert!(json.contains("\"instruction\": \"LOAD 0 => C\","));
+    assert!(json.contains(
+        "\"registers\": {\"A\": 0, \"B\": 0, \"C\": 0, \"D\": 0, \"ST\": 0, \"BP\": 0},"
+    ));
+    assert!(json.contains("\"greater_or_equal_signed\": false,"));
+    assert!(json.contains("\"memory\": \"00000000\""));
+
+    let halted = LegComputer::new(vec![0x12], vec![]).to_json();
+    assert!(halted.contains("\"instruction\": null,"));
+    Ok(())
+}
+
+#[test]
+fn json_state_parser_accepts_arrays_and_rejects_bad_input() -> Result<(), String> {
+    let json = LegComputer::new(vec![0, 0], vec![0; 4])
+        .to_json()
+        .replace("\"memory\": \"00000000\"", "\"memory\": [1, 2,\n 255]");
+    let computer = LegComputer::from_json(&json)?;
+    assert_eq!(computer.memory, vec![1, 2, 255]);
+
+    for (from, to, error) in &[
+        (
+            "\"memory\": \"00000000\"",
+            "\"memory\": [256]",
+            "memory: Expected bytes",
+        ),
+        (
+            "\"eip\": 0",
+            "\"eip\": -1",
+            "eip: Expected a byte, got Number(-1)",
+        ),
+        ("\"reg_i\": 0,", "", "Missing field: reg_i"),
+        (
+            "\"equal\": false",
+            "\"equal\": 0",
+            "equal: Expected a boolean, got Number(0)",
+        ),
+        ("}", "} x", "JSON at"),
+    ] {
+        let json = LegComputer::new(vec![0, 0], vec![0; 4]).to_json();
+        let err = LegComputer::from_json(&json.replacen(from, to, 1)).err();
+        assert!(
+            err.as_ref().map(|e| e.starts_with(error)) == Some(true),
+            "{:?}",
+            err
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::challenge_source;
+use evil_electronic_enigma::lint;
+use evil_electronic_enigma::LintKind;
+
+fn kinds(source: &str) -> Result<Vec<(u8, LintKind)>, String> {
+    Ok(lint(source)?.iter().map(|l| (l.addr, l.kind)).collect())
+}
+
+#[test]
+fn challenge_is_clean() -> Result<(), String> {
+    assert_eq!(lint(&challenge_source())?, vec![]);
+    Ok(())
+}
+
+#[test]
+fn each_lint_is_reported() -> Result<(), String> {
+    let source = "
+MOVC 1 => A
+MOV A => FL
+ALU ECHO A B => A
+JMPR T ? 6
+HALT
+ALU INCR A A => A
+JMPR EQ ? 3
+CALLR 100
+HALT
+";
+    assert_eq!(
+        kinds(source)?,
+        vec![
+            (2, LintKind::DroppedWrite),
+            (4, LintKind::Uninitialized),
+            (8, LintKind::UnreachableHalt),
+            (10, LintKind::Unreachable),
+            (12, LintKind::OddJump),
+            (14, LintKind::JumpOutOfProgram),
+        ]
+    );
+
+    let lints = lint(source)?;
+    assert_eq!(
+        lints[1].to_string(),
+        "line 4 (004): B may be read before it is written in fn 000 [uninitialized]"
+    );
+    assert_eq!(
+        lints[3].to_string(),
+        "line 7 (010): unreachable code [unreachable]"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn unreachable_code_names_the_jump_before_it() -> Result<(), String> {
+    let lints = lint(
+        "
+MOVC 0 => A
+JMPR T ? 4
+MOVC 1 => A
+RET A
+",
+    )?;
+    assert_eq!(lints.len(), 1);
+    assert_eq!(lints[0].message, "unreachable code after JMPR T ? 4");
+
+    Ok(())
+}
+
+#[test]
+fn any_alu_op_can_be_an_unused_compare() -> Result<(), String> {
+    let source = "
+MOVC 1 => A
+MOVC 2 => B
+ALU SUB A B => C
+ALU SUB A B => C
+JMPR LT ? 2
+ALU SUB A B => D
+STORE D => 0
+ALU AND A A => A
+ALU SUB B A => A
+HALT
+";
+    // The first SUB's result is overwritten unread, the AND leaves A as it
+    // was, and the last SUB's result is never read
+    assert_eq!(
+        kinds(source)?,
+        vec![
+            (4, LintKind::UnusedFlags),
+            (14, LintKind::UnusedFlags),
+            (16, LintKind::UnusedFlags),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn lints_are_suppressible() -> Result<(), String> {
+    let source = "
+# allow-file: unreachable-halt
+MOVC 0 => C
+# allow: unused-flags
+ALU ECHO C C => C
+ALU ECHO C C => C
+HALT
+HALT
+";
+    let lints = lint(source)?;
+    assert_eq!(
+        lints.iter().map(|l| (l.line, l.kind)).collect::<Vec<_>>(),
+        vec![(6, LintKind::UnusedFlags)]
+    );
+
+    assert_eq!(
+        lint("# allow: no-such-lint\nHALT\n"),
+        Err("Line 1: Unknown lint: no-such-lint".to_string())
+    );
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use evil_electronic_enigma::parse_range;
+use evil_electronic_enigma::ImageLoader;
+use evil_electronic_enigma::InputStart;
+use evil_electronic_enigma::CHALLENGE_MEMORY;
+use evil_electronic_enigma::CHALLENGE_PROGRAM;
+
+fn challenge() -> Result<ImageLoader, String> {
+    ImageLoader::new(CHALLENGE_PROGRAM.to_vec(), CHALLENGE_MEMORY.to_vec())
+}
+
+#[test]
+fn default_loader_runs_the_challenge() -> Result<(), String> {
+    let flag = std::fs::read("flag.txt").map_err(|e| e.to_string())?;
+    let loader = challenge()?;
+    let computer = loader.load(&flag)?.run();
+    assert_eq!(loader.output(&computer), b"OK!");
+
+    let mut twice = flag.clone();
+    twice.extend(&flag);
+    let computer = loader.load(&twice)?.run();
+    assert_eq!(loader.output(&computer), b"ERR");
+    Ok(())
+}
+
+#[test]
+fn input_placement_is_configurable() -> Result<(), String> {
+    let mut loader = ImageLoader::new(vec![], vec![7, 0, 200])?;
+    assert_eq!(loader.memory.len(), 256);
+    loader.input_start = InputStart::Pointer(2);
+    loader.end_pointer = Some(3);
+    let memory = loader.memory_with_input(b"ab")?;
+    assert_eq!(memory[200..202], *b"ab");
+    assert_eq!(memory[3], 202);
+
+    loader.input_start = "10".parse()?;
+    loader.end_pointer = None;
+    let memory = loader.memory_with_input(b"ab")?;
+    assert_eq!(memory[10..12], *b"ab");
+    assert_eq!(memory[0..4], [7, 0, 200, 0]);
+
+    // The input fills memory, with nowhere to write its end
+    let memory = loader.memory_with_input(&[1; 246])?;
+    assert_eq!(memory[255], 1);
+    assert_eq!("*2".parse(), Ok(InputStart::Pointer(2)));
+    assert_eq!(parse_range("0-3"), Ok((0, 3)));
+    Ok(())
+}
+
+#[test]
+fn input_that_does_not_fit_is_rejected() -> Result<(), String> {
+    let loader = challenge()?;
+    assert_eq!(
+        loader.memory_with_input(&[0; 212]).err(),
+        Some("Input of 212 bytes does not fit in memory: 045-256 are free".to_string())
+    );
+    assert_eq!(
+        loader.memory_with_input(&[0; 211]).err(),
+        Some(
+            "Input of 211 bytes does not fit in memory: its end 256 does not fit in a word"
+                .to_string()
+        )
+    );
+    assert!(loader.memory_with_input(&[0; 210]).is_ok());
+
+    assert!(ImageLoader::new(vec![0; 258], vec![]).is_err());
+    assert!(ImageLoader::new(vec![], vec![0; 257]).is_err());
+    assert!("x".parse::<InputStart>().is_err());
+    assert!(parse_range("5-300").is_err());
+    Ok(())
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::challenge_memory;
+use common::challenge_source;
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::Obfuscator;
+use evil_electronic_enigma::SymbolicInput;
+use evil_electronic_enigma::Word;
+use evil_electronic_enigma::TECHNIQUES;
+
+fn challenge_obfuscator(seed: u64, memory: &[Word], len: u8) -> Obfuscator {
+    let mut obfuscator = Obfuscator::new(seed);
+    obfuscator.memory = memory.to_vec();
+    obfuscator.inputs = (memory[0]..memory[0] + len)
+        .map(|addr| SymbolicInput {
+            addr,
+            domain: (0x20..0x7f).collect(),
+        })
+        .collect();
+    obfuscator.output = (0, 3);
+    obfuscator.samples = 100;
+    // Between the input list and the stack
+    obfuscator.table = Some(64);
+    obfuscator
+}
+
+#[test]
+fn obfuscated_challenge_still_checks_the_flag() -> Result<(), String> {
+    let original = assemble_program(&challenge_source())?;
+    let flag = b"midnight{s0rt3d}";
+    let memory = challenge_memory(flag, flag);
+    let obfuscator = challenge_obfuscator(7, &memory, flag.len() as u8);
+
+    let (obfuscated, report) = obfuscator.obfuscate(&original)?;
+    assert_ne!(obfuscated, original);
+    assert_eq!(report.bytes_before, 234);
+    assert!(report.bytes_after <= 256);
+    assert_eq!(report.checked, 100);
+
+    let run = |input: &[u8]| {
+        let mut memory = challenge_memory(flag, input);
+        memory[64..64 + report.table.len()].copy_from_slice(&report.table);
+        LegComputer::new(generate_code(&obfuscated), memory).run()
+    };
+    assert_eq!(b"OK!"[..], run(flag).memory[0..3]);
+    assert_eq!(b"ERR"[..], run(b"midnight{d3tr0s}").memory[0..3]);
+
+    // The seed decides everything
+    assert_eq!(obfuscator.obfuscate(&original)?.0, obfuscated);
+    let other = challenge_obfuscator(8, &memory, flag.len() as u8);
+    assert_ne!(other.obfuscate(&original)?.0, obfuscated);
+    Ok(())
+}
+
+#[test]
+fn each_technique_applies_alone() -> Result<(), String> {
+    let original = assemble_program(
+        "
+LOAD 0 => C
+LOAD 1 => D
+ALU SUB D C => D
+PUSH C
+PUSH D
+CALLR sort
+POP A
+POP A
+POP A
+LOAD 0 => C
+PUSH C
+MOVC 8 => D
+PUSH D
+CALLR checksum
+POP A
+POP B
+POP B
+MOVC 5 => B
+ALU XOR A B => C
+ALU ADD A C => D
+MOV D => A
+STORE A => 2
+HALT
+.include sort checksum
+",
+    )?;
+    let mut memory = vec![16, 24];
+    memory.resize(256, 0);
+
+    for technique in &TECHNIQUES {
+        let mut obfuscator = Obfuscator::new(1);
+        obfuscator.techniques = vec![*technique];
+        obfuscator.rate = 0.5;
+        obfuscator.memory = memory.clone();
+        obfuscator.inputs = (16..24)
+            .map(|addr| SymbolicInput {
+                addr,
+                domain: (0..=255).collect(),
+            })
+            .collect();
+        obfuscator.samples = 200;
+        obfuscator.table = Some(100);
+
+        let (obfuscated, report) = obfuscator.obfuscate(&original)?;
+        assert_ne!(obfuscated, original, "{:?}", technique);
+        let applied = report.substituted
+            + report.opaque_predicates
+            + report.junk
+            + report.flattened
+            + report.renamed as usize;
+        assert!(applied > 0, "{:?}: {}", technique, report);
+        assert_eq!(report.checked, 200);
+    }
+    Ok(())
+}
+
+#[test]
+fn programs_that_cannot_move_are_rejected() {
+    let obfuscator = Obfuscator::new(0);
+    let fails = |source: &str| {
+        obfuscator
+            .obfuscate(&assemble_program(source).unwrap())
+            .unwrap_err()
+    };
+    assert_eq!(
+        fails("MOVC 0 => A\nJMPP T ? A"),
+        "002: JMPP T ? A has a computed target"
+    );
+    assert_eq!(
+        fails("MOVC 0 => A\nJMPR T ? 2"),
+        "002: jumps past the end of the program"
+    );
+}
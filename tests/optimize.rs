@@ -0,0 +1,111 @@
+mod common;
+
+use common::challenge_memory;
+use common::challenge_source;
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::optimize;
+use evil_electronic_enigma::Equivalence;
+use evil_electronic_enigma::EquivalenceChecker;
+use evil_electronic_enigma::SymbolicInput;
+
+#[test]
+fn challenge_shrinks_and_still_works() -> Result<(), String> {
+    let original = assemble_program(&challenge_source())?;
+    let (optimized, report) = optimize(&original)?;
+    // Only the NOP before the output loop can go
+    assert_eq!(report.saved(), 2);
+    assert_eq!(report.dead_code, 1);
+
+    let flag = b"midnight{s0rt3d}";
+    let memory = challenge_memory(flag, flag);
+    let start = memory[0];
+    let mut checker = EquivalenceChecker::new(
+        generate_code(&original),
+        generate_code(&optimized),
+        memory,
+        (start..start + flag.len() as u8)
+            .map(|addr| SymbolicInput {
+                addr,
+                domain: (0x20..0x7f).collect(),
+            })
+            .collect(),
+    );
+    checker.output = (0, 3);
+    checker.samples = 300;
+    assert_eq!(
+        checker.check(),
+        Equivalence::Equivalent {
+            runs: 300,
+            exhaustive: false
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn each_pass_applies() -> Result<(), String> {
+    let program = assemble_program(
+        "
+MOVC 3 => A
+MOVC 3 => A
+MOV A => B
+LOAD 20 => C
+LOAD 21 => C
+PUSH C
+POP D
+PUSH D
+POP D
+STORE D => 30
+STORE C => 30
+ALU ECHO C B => C
+JMPR LT ? 4
+NOP
+ALU ECHO C B => C
+JMPR GE ? 4
+JMPR T ? 10
+STOREP C => B
+JMPR T ? 2
+JMPR T ? 6
+MOVC 9 => D
+JMPR T ? 2
+ALU ECHO A B => A
+JMPR EQ ? 4
+STORE A => 0
+HALT
+",
+    )?;
+    let (optimized, report) = optimize(&program)?;
+
+    let listing: Vec<String> = optimized.iter().map(|i| i.to_string()).collect();
+    assert_eq!(
+        listing,
+        vec![
+            "MOVC 3 => B",
+            "LOAD 21 => C",
+            "STORE C => 30",
+            "ALU ECHO C B => C",
+            "JMPR GE ? 4",
+            "JMPR T ? 4",
+            "STORE C => 3",
+            "HALT",
+        ]
+    );
+    assert_eq!(
+        report.to_string(),
+        "52 -> 16 bytes, saved 36, jumps: 7, unreachable: 4, stack: 3, constants: 4, \
+         flags: 1, dead code: 5, dead stores: 1"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn computed_targets_are_rejected() -> Result<(), String> {
+    let program = assemble_program("MOVC 6 => A\nJMPP T ? A\nHALT\nHALT")?;
+    assert_eq!(
+        optimize(&program),
+        Err("002: JMPP T ? A has a computed target".to_string())
+    );
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use evil_electronic_enigma::flag_image;
+use evil_electronic_enigma::Checker;
+use evil_electronic_enigma::FlagProver;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::CHALLENGE_PROGRAM;
+
+#[test]
+fn structure_counts_what_running_every_input_finds() -> Result<(), String> {
+    let image = flag_image(b"dacb", Checker::SortXor)?;
+    let alphabet = b"abcdefg".to_vec();
+    let mut prover = FlagProver::new(CHALLENGE_PROGRAM.to_vec(), image.clone());
+    prover.alphabet = alphabet.clone();
+    prover.lengths = (1, 4);
+    prover.max_solutions = 10_000;
+    let report = prover.prove()?;
+    assert!(report.complete());
+    assert!(report.lengths.iter().all(|l| l.structural));
+
+    let mut expected = Vec::new();
+    let mut inputs = vec![vec![]];
+    for _ in 0..4 {
+        inputs = inputs
+            .iter()
+            .flat_map(|input: &Vec<u8>| {
+                alphabet.iter().map(move |a| {
+                    let mut next = input.clone();
+                    next.push(*a);
+                    next
+                })
+            })
+            .collect();
+        for input in &inputs {
+            let computer = LegComputer::new(CHALLENGE_PROGRAM.to_vec(), image.with_input(input)?);
+            if computer.run().memory[0..3] == *b"OK!" {
+                expected.push(input.clone());
+            }
+        }
+    }
+
+    let mut solutions = report.solutions.clone();
+    solutions.sort();
+    expected.sort();
+    assert_eq!(solutions, expected);
+    assert_eq!(report.total(), expected.len() as u64);
+    assert!(solutions.contains(&b"dacb".to_vec()));
+    assert!(!report.unique());
+    Ok(())
+}
+
+#[test]
+fn challenge_accepts_the_empty_input() -> Result<(), String> {
+    let flag = std::fs::read("flag.txt").map_err(|e| e.to_string())?;
+    let mut prover = FlagProver::new(
+        CHALLENGE_PROGRAM.to_vec(),
+        flag_image(&flag, Checker::SortXor)?,
+    );
+    prover.lengths = (0, 4);
+    let report = prover.prove()?;
+    assert!(report.complete());
+    assert_eq!(report.solutions, vec![Vec::<u8>::new()]);
+    assert_eq!(report.total(), 1);
+    Ok(())
+}
+
+#[test]
+fn search_past_the_solution_length_is_bounded() -> Result<(), String> {
+    let mut prover = FlagProver::new(
+        CHALLENGE_PROGRAM.to_vec(),
+        flag_image(b"ba", Checker::SortXor)?,
+    );
+    prover.alphabet = b"ab".to_vec();
+    prover.lengths = (2, 3);
+    prover.max_runs = 5;
+    let report = prover.prove()?;
+    assert_eq!(report.solutions, vec![b"ba".to_vec()]);
+    assert!(report.lengths[0].complete && report.lengths[0].structural);
+    assert!(!report.lengths[1].complete && !report.lengths[1].structural);
+    assert!(!report.unique());
+    assert!(report
+        .to_string()
+        .ends_with("At least 1 inputs are accepted"));
+
+    // Only the empty input can be made of no characters
+    prover.alphabet = Vec::new();
+    prover.lengths = (0, 3);
+    let report = prover.prove()?;
+    assert!(report.complete());
+    assert_eq!(report.total(), 1);
+    assert_eq!(report.solutions, vec![Vec::<u8>::new()]);
+    Ok(())
+}
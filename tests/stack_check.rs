@@ -0,0 +1,118 @@
+mod common;
+
+use common::challenge_program;
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::check_stack;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::Cfg;
+use evil_electronic_enigma::EdgeKind;
+use evil_electronic_enigma::Instruction;
+use evil_electronic_enigma::RegisterRef;
+use evil_electronic_enigma::StackInstruction;
+use evil_electronic_enigma::StackIssue;
+
+#[test]
+fn challenge_follows_the_calling_convention() -> Result<(), String> {
+    let report = check_stack(&Cfg::recover(&challenge_program()?));
+
+    assert_eq!(report.issues, vec![]);
+    // CALLR 72 to the copy function, after pushing its three arguments
+    assert_eq!(report.depths[&10], 3);
+    // The return value and the arguments are popped again
+    assert_eq!(report.depths[&20], 0);
+    // The xor check keeps a running index and result in two locals
+    assert_eq!(report.max_depths[&106], 2);
+
+    Ok(())
+}
+
+#[test]
+fn convention_violations_are_reported() -> Result<(), String> {
+    let program = generate_code(&assemble_program(
+        "
+MOVC 3 => A
+PUSH A
+CALLR 8
+POP B
+POP B
+HALT
+
+SLOAD 3 => B
+SLOAD -1 => C
+POP D
+PUSH A
+ALU DECR A A => A
+JMPR NE ? -4
+RET A
+",
+    )?);
+    let report = check_stack(&Cfg::recover(&program));
+
+    assert_eq!(
+        report.issues,
+        vec![
+            StackIssue::SloadPastArguments {
+                addr: 12,
+                offset: 3,
+                call_site: 4,
+                pushed: 1,
+            },
+            StackIssue::SloadAboveTop {
+                addr: 14,
+                offset: -1,
+                depth: 0,
+            },
+            StackIssue::PopBelowFrame { addr: 16, depth: 0 },
+            StackIssue::DepthMismatch {
+                addr: 18,
+                expected: -1,
+                found: 0,
+                from: 22,
+            },
+        ]
+    );
+    assert_eq!(
+        report.issues[0].to_string(),
+        "012: SLOAD 3 reads past the arguments of the call at 004, which pushed 1"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn functions_with_unknown_callers_may_read_arguments() -> Result<(), String> {
+    let program = generate_code(&assemble_program(
+        "
+MOVC 12 => A
+PUSH A
+CALLR 8
+POP B
+POP B
+HALT
+
+SLOAD 2 => B
+RET B
+",
+    )?);
+    let mut cfg = Cfg::recover(&program);
+    assert_eq!(check_stack(&cfg).issues, vec![]);
+
+    // As if the function were only called through CALL A, e.g. with its
+    // entry known from a symbol table
+    let block = cfg.blocks.get_mut(&0).ok_or("No entry block")?;
+    block.instructions[2].1 = Instruction::Stack(StackInstruction::Call {
+        addr_reg: RegisterRef::A,
+    });
+    for edge in block.edges.iter_mut().filter(|e| e.kind == EdgeKind::Call) {
+        edge.target = None;
+    }
+    assert_eq!(check_stack(&cfg).issues, vec![]);
+
+    // The entry function still has no caller at all
+    let program = generate_code(&assemble_program("SLOAD 2 => A\nHALT")?);
+    assert_eq!(
+        check_stack(&Cfg::recover(&program)).issues,
+        vec![StackIssue::SloadWithoutCaller { addr: 0, offset: 2 }]
+    );
+    Ok(())
+}
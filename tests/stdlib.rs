@@ -0,0 +1,139 @@
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::Instruction;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::RegisterRef;
+use evil_electronic_enigma::Word;
+use evil_electronic_enigma::STDLIB;
+
+/// Call a library routine with `args`, returning the return value, the
+/// final machine and the values written to GPO.
+fn call(
+    name: &str,
+    args: &[Word],
+    memory: Vec<Word>,
+) -> Result<(Word, LegComputer, Vec<Word>), String> {
+    let mut source = String::new();
+    for arg in args {
+        source += &format!("MOVC {} => A\nPUSH A\n", arg);
+    }
+    source += &format!("CALLR {}\nPOP A\n", name);
+    for _ in args {
+        source += "POP B\n";
+    }
+    source += &format!("HALT\n.include {}\n", name);
+
+    let mut computer = LegComputer::new(generate_code(&assemble_program(&source)?), memory);
+    let mut output = Vec::new();
+    for _ in 0..10_000 {
+        if computer.is_halted() {
+            assert_eq!(computer.read_register(&RegisterRef::ST), 0);
+            return Ok((computer.read_register(&RegisterRef::A), computer, output));
+        }
+        let gpo = matches!(computer.current_instruction()?, Instruction::Gpo { .. });
+        computer.try_step()?;
+        if gpo {
+            output.push(computer.reg_o);
+        }
+    }
+    Err(format!("{}{:?} did not return", name, args))
+}
+
+fn memory(data: &[(usize, &[u8])]) -> Vec<Word> {
+    let mut memory = vec![0; 256];
+    for (addr, bytes) in data {
+        memory[*addr..*addr + bytes.len()].copy_from_slice(bytes);
+    }
+    memory
+}
+
+#[test]
+fn memory_routines() -> Result<(), String> {
+    let data = memory(&[(16, b"midnight"), (32, b"midnigHT")]);
+
+    let (ret, computer, _) = call("memcpy", &[64, 16, 8], data.clone())?;
+    assert_eq!(ret, 64);
+    assert_eq!(&computer.memory[64..73], b"midnight\0");
+    // Copying to the front of the same buffer reads each byte before
+    // overwriting it
+    let (ret, computer, _) = call("memcpy", &[14, 16, 8], data.clone())?;
+    assert_eq!(ret, 14);
+    assert_eq!(&computer.memory[14..24], b"midnightht");
+
+    let (ret, computer, _) = call("memset", &[70, b'!', 3], data.clone())?;
+    assert_eq!(ret, 70);
+    assert_eq!(&computer.memory[69..74], b"\0!!!\0");
+
+    assert_eq!(call("memcmp", &[16, 32, 6], data.clone())?.0, 0);
+    assert_eq!(call("memcmp", &[16, 32, 8], data.clone())?.0, b'h' - b'H');
+    assert_eq!(
+        call("memcmp", &[32, 16, 8], data.clone())?.0,
+        b'H'.wrapping_sub(b'h')
+    );
+    assert_eq!(call("memcmp", &[16, 32, 0], data.clone())?.0, 0);
+
+    let (ret, computer, _) = call("sort", &[16, 8], data.clone())?;
+    assert_eq!(ret, 16);
+    assert_eq!(&computer.memory[15..25], b"\0dghiimnt\0");
+    let (_, computer, _) = call("sort", &[16, 0], data.clone())?;
+    assert_eq!(&computer.memory[16..24], b"midnight");
+
+    let xor = b"midnight".iter().fold(0, |a, b| a ^ b);
+    assert_eq!(call("xor_fold", &[16, 8], data.clone())?.0, xor);
+    let sum = b"midnight".iter().fold(0u8, |a, b| a.wrapping_add(*b));
+    assert_eq!(call("checksum", &[16, 8], data.clone())?.0, sum);
+    assert_eq!(call("checksum", &[16, 0], data)?.0, 0);
+    Ok(())
+}
+
+#[test]
+fn arithmetic_routines() -> Result<(), String> {
+    for a in (0..=255).step_by(17).chain(vec![1, 128, 254, 255]) {
+        for b in (0..=255).step_by(13).chain(vec![1, 2, 128, 255]) {
+            let (a, b) = (a as Word, b as Word);
+            assert_eq!(call("mul", &[a, b], vec![0; 256])?.0, a.wrapping_mul(b));
+            let (div, modulo) = match b {
+                0 => (255, a),
+                _ => (a / b, a % b),
+            };
+            assert_eq!(call("div", &[a, b], vec![0; 256])?.0, div, "{} / {}", a, b);
+            assert_eq!(
+                call("mod", &[a, b], vec![0; 256])?.0,
+                modulo,
+                "{} % {}",
+                a,
+                b
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn hex_output() -> Result<(), String> {
+    for (value, hex) in &[(0x00, b"00"), (0x4a, b"4a"), (0xf9, b"f9"), (0xff, b"ff")] {
+        let (ret, _, output) = call("hex_out", &[*value], vec![0; 256])?;
+        assert_eq!(ret, *value);
+        assert_eq!(&output, hex);
+    }
+    Ok(())
+}
+
+#[test]
+fn routines_assemble_together() -> Result<(), String> {
+    let names: Vec<&str> = STDLIB.iter().map(|(name, _)| *name).collect();
+    let program = assemble_program(&format!(
+        "HALT\n.include {}\n.include sort",
+        names.join(" ")
+    ))?;
+    let length: usize = STDLIB
+        .iter()
+        .map(|(_, source)| assemble_program(source).map(|p| p.len()))
+        .sum::<Result<usize, String>>()?;
+    assert_eq!(program.len(), 1 + length);
+    assert_eq!(
+        assemble_program(".include strlen"),
+        Err("Line 1: Unknown library routine: strlen".to_string())
+    );
+    Ok(())
+}
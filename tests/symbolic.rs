@@ -0,0 +1,129 @@
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::SolveResult;
+use evil_electronic_enigma::Solver;
+use evil_electronic_enigma::SymbolicExecutor;
+use evil_electronic_enigma::SymbolicInput;
+use evil_electronic_enigma::Target;
+
+mod common;
+
+fn printable(addrs: std::ops::Range<u8>) -> Vec<SymbolicInput> {
+    addrs
+        .map(|addr| SymbolicInput {
+            addr,
+            domain: (0x21..=0x7e).collect(),
+        })
+        .collect()
+}
+
+#[test]
+fn solves_arithmetic_checks() -> Result<(), String> {
+    // Writes 1 to address 0 iff in[0] ^ 32 == 'a' and in[0] + in[1] == 100
+    let source = "
+LOAD 16 => A
+LOAD 17 => B
+MOVC 32 => C
+ALU XOR A C => C
+MOVC 97 => D
+ALU ECHO C D => C
+JMPR NE ? 14
+ALU ADD A B => A
+MOVC 100 => D
+ALU ECHO A D => A
+JMPR NE ? 6
+MOVC 1 => A
+STORE A => 0
+HALT
+";
+    let program = generate_code(&assemble_program(source)?);
+    let executor = SymbolicExecutor::new(
+        program,
+        &[0; 256],
+        &printable(16..18),
+        Target::HaltWithMemory {
+            addr: 0,
+            bytes: vec![1],
+        },
+    )?;
+    let report = executor.run();
+
+    assert!(report.errors.is_empty());
+    assert!(!report.incomplete);
+    assert_eq!(3, report.paths);
+    assert_eq!(1, report.solutions.len());
+    assert_eq!(b"A#"[..], report.solutions[0].input[..]);
+    Ok(())
+}
+
+#[test]
+fn finds_a_flag_for_the_challenge() -> Result<(), String> {
+    let correct = b"L3G!";
+    let program = common::challenge_program()?;
+    let memory = common::challenge_memory(correct, correct);
+
+    let mut executor = SymbolicExecutor::new(
+        program.clone(),
+        &memory,
+        &printable(20..24),
+        Target::HaltWithMemory {
+            addr: 0,
+            bytes: b"OK!".to_vec(),
+        },
+    )?;
+    executor.max_solutions = 5;
+    let report = executor.run();
+    assert!(report.errors.is_empty());
+    assert!(!report.solutions.is_empty());
+
+    for solution in report.solutions {
+        let memory = common::challenge_memory(correct, &solution.input);
+        let computer = LegComputer::new(program.clone(), memory).run();
+        assert_eq!(b"OK!"[..], computer.memory[0..3]);
+    }
+    Ok(())
+}
+
+#[test]
+fn solver_reports_unsatisfiable_constraints() -> Result<(), String> {
+    let source = "
+LOAD 16 => A
+MOVC 200 => B
+ALU ECHO A B => A
+JMPR LT ? 6
+MOVC 1 => C
+STORE C => 0
+HALT
+";
+    let program = generate_code(&assemble_program(source)?);
+    let report = SymbolicExecutor::new(
+        program,
+        &[0; 256],
+        &printable(16..17),
+        Target::HaltWithMemory {
+            addr: 0,
+            bytes: vec![1],
+        },
+    )?
+    .run();
+    assert_eq!(1, report.paths);
+    assert!(report.solutions.is_empty());
+    assert!(!report.incomplete);
+
+    assert_eq!(SolveResult::Unsat, Solver::new(vec![vec![]]).solve(&[]));
+
+    let mut inputs = printable(16..18);
+    inputs[1].domain.clear();
+    assert_eq!(
+        SymbolicExecutor::new(
+            generate_code(&assemble_program("HALT")?),
+            &[0; 256],
+            &inputs,
+            Target::Reach(0)
+        )
+        .err(),
+        Some("Input 1 at 017 has an empty domain".to_string())
+    );
+    Ok(())
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::challenge_memory;
+use common::challenge_program;
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::Taint;
+use evil_electronic_enigma::TaintTracker;
+
+#[test]
+fn every_flag_byte_reaches_the_final_check() -> Result<(), String> {
+    let flag = b"midnight{s0rt3d}";
+    let memory = challenge_memory(flag, flag);
+    let start = memory[0];
+    let mut tracker = TaintTracker::new(
+        LegComputer::new(challenge_program()?, memory),
+        &[(start, flag.len())],
+    );
+    tracker.run(100_000)?;
+    let report = &tracker.report;
+
+    assert_eq!(&tracker.computer.memory[0..3], b"OK!");
+    // JMPR Z in main, on the result of the xor check
+    assert_eq!(report.influencing(54), (0..flag.len()).collect::<Taint>());
+    assert_eq!(report.branches[&54].executions, 1);
+    assert_eq!(report.branches[&54].taken, 1);
+    // The copy loop only compares pointers
+    assert_eq!(report.influencing(92), Taint::new());
+    assert_eq!(report.unused(), Vec::<usize>::new());
+
+    Ok(())
+}
+
+#[test]
+fn taint_follows_data_flow() -> Result<(), String> {
+    let program = generate_code(&assemble_program(
+        "
+LOAD 16 => A
+LOAD 17 => B
+LOAD 18 => C
+LOAD 19 => D
+MOVC 0 => D
+PUSH B
+POP B
+ALU XOR B C => B
+STORE B => 40
+LOAD 40 => C
+GPO C =>
+MOVC 5 => D
+ALU ECHO A D => A
+JMPR EQ ? 4
+NOP
+HALT
+",
+    )?);
+    let mut memory = vec![0; 256];
+    memory[16..20].copy_from_slice(&[5, 1, 2, 3]);
+    let mut tracker = TaintTracker::new(LegComputer::new(program, memory), &[(17, 3), (16, 1)]);
+    tracker.run(100)?;
+    let report = &tracker.report;
+
+    assert_eq!(report.inputs, 4);
+    assert_eq!(report.influencing(26), vec![3].into_iter().collect());
+    assert_eq!(report.outputs, vec![0, 1].into_iter().collect());
+    assert_eq!(report.unused(), vec![2]);
+    assert_eq!(
+        report.to_string(),
+        "026: taken 1/1, input 3\noutput: input 0-1\nunused: input 2\n"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use evil_electronic_enigma::assemble_program;
+use evil_electronic_enigma::call_stack;
+use evil_electronic_enigma::generate_code;
+use evil_electronic_enigma::Action;
+use evil_electronic_enigma::Frame;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::Tui;
+
+// Calls a function that calls itself once more, then stores to address 33.
+const PROGRAM: &str = "
+MOVC 2 => A
+PUSH A
+CALLR 4
+HALT
+
+SLOAD 2 => A
+ALU DECR A A => A
+JMPR Z ? 8
+PUSH A
+CALLC 8
+STORE A => 33
+RET A
+";
+
+fn tui() -> Result<Tui, String> {
+    let program = generate_code(&assemble_program(PROGRAM)?);
+    Ok(Tui::new(LegComputer::new(program, vec![0; 256])))
+}
+
+#[test]
+fn call_stack_follows_saved_base_pointers() -> Result<(), String> {
+    let mut tui = tui()?;
+    tui.command("break 20");
+    tui.command("continue");
+    assert_eq!(20, tui.debugger.computer.eip);
+
+    assert_eq!(
+        vec![
+            Frame {
+                bp: 250,
+                call_site: 16,
+                entry: Some(8),
+            },
+            Frame {
+                bp: 253,
+                call_site: 4,
+                entry: Some(8),
+            },
+        ],
+        call_stack(&tui.debugger.computer)
+    );
+    Ok(())
+}
+
+#[test]
+fn render_highlights_recent_writes() -> Result<(), String> {
+    let mut tui = tui()?;
+    tui.command("break 18");
+    assert_eq!(Action::Redraw, tui.handle_key('c'));
+    assert_eq!(Action::Redraw, tui.handle_key('s'));
+
+    let screen = tui.render();
+    assert!(screen.contains("steps 13"));
+    assert!(screen.contains(" 32:   0 \x1b[97;41m   0 \x1b[0m"));
+    assert!(screen.contains(" > 020: RET A"));
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use evil_electronic_enigma::disassemble;
+use evil_electronic_enigma::flag_image;
+use evil_electronic_enigma::Checker;
+use evil_electronic_enigma::LegComputer;
+use evil_electronic_enigma::VariantGenerator;
+use evil_electronic_enigma::CHALLENGE_PROGRAM;
+
+const FLAG: &[u8] = b"midnight{f1D)l3n_w/_M4_bi75~}";
+
+#[test]
+fn variants_differ_but_check_the_same_flag() -> Result<(), String> {
+    let mut generator = VariantGenerator::new(disassemble(CHALLENGE_PROGRAM)?, FLAG);
+    generator.samples = 50;
+    let standard = flag_image(FLAG, Checker::SortXor)?;
+
+    let mut seen = Vec::new();
+    for seed in 0..3 {
+        let variant = generator.generate(seed)?;
+        assert!(variant.program.len() <= 256);
+        assert_ne!(variant.program, CHALLENGE_PROGRAM);
+        assert_ne!(variant.memory, standard.memory);
+        assert!(!seen.contains(&(variant.program.clone(), variant.memory.clone())));
+        seen.push((variant.program.clone(), variant.memory.clone()));
+
+        let run = |input: &[u8]| {
+            let start = variant.layout.input as usize;
+            let mut memory = variant.memory.clone();
+            memory[1] = (start + input.len()) as u8;
+            memory[start..start + input.len()].copy_from_slice(input);
+            LegComputer::new(variant.program.clone(), memory)
+                .run()
+                .memory[0..3]
+                .to_vec()
+        };
+        assert_eq!(run(FLAG), b"OK!");
+        assert_eq!(run(b"midnight{fiddlin_wi_ma_bits}"), b"ERR");
+        // A team's solution for another team's memory doesn't carry over
+        let other = generator.generate(seed + 100)?;
+        assert_ne!(other.layout, variant.layout);
+    }
+
+    // The seed decides everything
+    let variant = generator.generate(3)?;
+    assert_eq!(generator.generate(3)?.program, variant.program);
+    assert!(variant.manifest().starts_with(
+        "seed:     3\nflag:     \"midnight{f1D)l3n_w/_M4_bi75~}\"\nchecker:  SortXor\n"
+    ));
+    Ok(())
+}
+
+#[test]
+fn flags_that_do_not_fit_are_rejected() -> Result<(), String> {
+    let generator = VariantGenerator::new(
+        disassemble(CHALLENGE_PROGRAM)?,
+        b"midnight{this_is_a_bit_too_long}",
+    );
+    assert_eq!(
+        generator.generate(0).unwrap_err(),
+        "Flag of 32 bytes does not fit in memory: 048-097 are free"
+    );
+    Ok(())
+}